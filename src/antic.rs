@@ -1,5 +1,16 @@
 use crate::mem::Mem;
 
+/// Where the normal-width playfield starts in the scanline buffer. The
+/// buffer spans the 384 pixels of the wide playfield.
+pub const NORMAL_PLAYFIELD_START: usize = 32;
+
+/// Color indices of the hi-res modes (text modes 2 and 3, and mode F),
+/// after the background and PF0-PF3: the playfield's background, in
+/// COLPF2, and a set pixel, which GTIA shows in COLPF1's luminance on
+/// COLPF2's hue
+pub const HIRES_BACKGROUND: u8 = 5;
+pub const HIRES_PIXEL: u8 = 6;
+
 /// ANTIC - Alphanumeric Television Interface Controller
 /// Handles display list processing, DMA, and video timing for Atari 8-bit computers.
 ///
@@ -21,7 +32,7 @@ pub struct Antic {
 
     // Display list state
     current_mode: u8,    // Current ANTIC mode being displayed
-    mode_line: u8,       // Which line within the current mode (0-7 for text modes)
+    mode_line: u8,       // Which scanline within the current mode line
     lines_remaining: u8, // How many more lines of this mode instruction
 
     // ANTIC registers
@@ -41,7 +52,7 @@ pub struct Antic {
     nmires: u8,     // $D40F - NMI reset/status
 
     // Scanline buffer (pixels to be displayed)
-    pub scanline_buffer: [u8; 384],  // Color indices for current scanline
+    pub scanline_buffer: [u8; 384],  // Color indices for current scanline (0 = background, 1-4 = PF0-PF3, 5-6 = hi-res)
 }
 
 impl Antic {
@@ -102,7 +113,7 @@ impl Antic {
             0x0B => self.vcount,    // VCOUNT is readable
             0x0C => self.penh,      // Light pen H
             0x0D => self.penv,      // Light pen V
-            0x0F => self.nmires,    // NMI status
+            0x0F => self.nmires | 0x1F,    // NMI status (low bits unused)
             _ => 0xFF,              // Other registers are write-only
         }
     }
@@ -129,11 +140,18 @@ impl Antic {
             0x09 => self.chbase = val,
             0x0A => self.wsync = val,   // CPU write to WSYNC halts until HSYNC
             0x0E => self.nmien = val,
-            0x0F => self.nmires = val,
+            0x0F => self.nmires = 0,   // Any write resets NMI status
             _ => {}
         }
     }
 
+    /// Vertical blank has started: flag it in NMIST and report whether
+    /// NMIEN lets it through as an NMI
+    pub fn begin_vblank(&mut self) -> bool {
+        self.nmires = 0x40;
+        (self.nmien & 0x40) != 0
+    }
+
     fn update_dlist_ptr(&mut self) {
         self.dlist_ptr = (self.dlistl as u16) | ((self.dlisth as u16) << 8);
         self.dlist_index = self.dlist_ptr;  // Reset to start of display list
@@ -156,24 +174,37 @@ impl Antic {
         }
 
         // Generate scanline data based on current mode
-        if self.current_mode == 0x00 {
-            // Blank line - already filled with 0
-        } else if self.current_mode == 0x02 {
-            // Mode 2: 40-column text, 8 scanlines per character row
-            self.render_mode2_scanline(mem);
+        match self.current_mode {
+            // Modes 2 and 3: 40-column text, 8 or 10 scanlines per row
+            0x02 | 0x03 => self.render_text_scanline(mem),
+            // Modes 4 and 5: 40-column multicolor text, 8 or 16 scanlines
+            0x04 | 0x05 => self.render_multicolor_text_scanline(mem),
+            // Modes 6 and 7: 20-column 5-color text, 8 or 16 scanlines
+            0x06 | 0x07 => self.render_large_text_scanline(mem),
+            // Modes 8-E: bitmap graphics
+            0x08..=0x0E => self.render_bitmap_scanline(mem),
+            // Mode F: hi-res bitmap graphics
+            0x0F => self.render_hires_scanline(mem),
+            // Blank lines
+            _ => {}
         }
-        // TODO: Add other modes
 
         // Move to next line
         if self.lines_remaining > 0 {
             self.lines_remaining -= 1;
             self.mode_line += 1;
+
+            // After the last scanline of a mode line, the screen pointer
+            // moves on to the next line's bytes
+            if self.lines_remaining == 0 && self.current_mode >= 0x02 {
+                self.screen_ptr = self.screen_addr(self.bytes_per_line());
+            }
         }
     }
 
     /// Fetch next display list instruction
     fn fetch_display_list_instruction(&mut self, mem: &Mem) {
-        let instruction = mem.get_byte(self.dlist_index);
+        let instruction = mem.antic_read(self.dlist_index);
         self.dlist_index += 1;
 
         // Check for LMS (Load Memory Scan) bit (bit 6)
@@ -188,8 +219,8 @@ impl Antic {
         // Check for JVB (Jump with Vertical Blank) instruction
         if (instruction & 0x0F) == 0x01 {
             // JVB - jump to new display list address
-            let new_addr_lo = mem.get_byte(self.dlist_index);
-            let new_addr_hi = mem.get_byte(self.dlist_index + 1);
+            let new_addr_lo = mem.antic_read(self.dlist_index);
+            let new_addr_hi = mem.antic_read(self.dlist_index + 1);
             self.dlist_index = ((new_addr_hi as u16) << 8) | (new_addr_lo as u16);
 
             // Re-fetch instruction at new location
@@ -202,8 +233,8 @@ impl Antic {
 
         // If LMS bit is set, read screen memory address
         if has_lms {
-            let screen_lo = mem.get_byte(self.dlist_index);
-            let screen_hi = mem.get_byte(self.dlist_index + 1);
+            let screen_lo = mem.antic_read(self.dlist_index);
+            let screen_hi = mem.antic_read(self.dlist_index + 1);
             self.screen_ptr = ((screen_hi as u16) << 8) | (screen_lo as u16);
             self.dlist_index += 2;
         }
@@ -215,48 +246,241 @@ impl Antic {
                 let count = ((instruction >> 4) & 0x07) as u8;
                 count + 1
             }
-            0x02..=0x07 => 8,  // Text modes: 8 scanlines per character row
-            0x08..=0x0F => {
-                // Graphics modes - varies by mode
-                8  // Simplified for now
-            }
+            0x02..=0x0F => mode_geometry(mode).0,
             _ => 1,
         };
     }
 
-    /// Render one scanline of ANTIC mode 2 (40-column text)
-    fn render_mode2_scanline(&mut self, mem: &Mem) {
-        let char_base = if self.chbase == 0 {
+    /// Bytes of screen memory the current mode line takes, at the
+    /// playfield width DMACTL bits 0-1 select: narrow is 4/5 and wide 6/5
+    /// of normal width, and with no playfield nothing is fetched
+    fn bytes_per_line(&self) -> u16 {
+        let normal = mode_geometry(self.current_mode).1;
+        match self.dmactl & 0x03 {
+            0x00 => 0,
+            0x01 => normal * 4 / 5,
+            0x02 => normal,
+            _ => normal * 6 / 5,
+        }
+    }
+
+    /// Address of the screen byte `offset` bytes past the screen pointer.
+    /// ANTIC's memory scan counter only counts in its low 12 bits, so
+    /// screen memory wraps round at each 4K boundary.
+    fn screen_addr(&self, offset: u16) -> u16 {
+        (self.screen_ptr & 0xF000) | (self.screen_ptr.wrapping_add(offset) & 0x0FFF)
+    }
+
+    /// Where the playfield starts in the scanline buffer, which spans the
+    /// wide playfield; the narrow one is centred within the normal one
+    fn playfield_start(&self) -> usize {
+        match self.dmactl & 0x03 {
+            0x01 => NORMAL_PLAYFIELD_START + 32,
+            0x03 => 0,
+            _ => NORMAL_PLAYFIELD_START,
+        }
+    }
+
+    /// Character set address from CHBASE, whose low bits are ignored:
+    /// `mask` keeps the bits that count for the mode's alignment
+    fn char_base(&self, mask: u8) -> u16 {
+        if self.chbase == 0 {
             // CHBASE=0 means use OS ROM character set at $E000
             0xE000u16
         } else {
-            // Otherwise use CHBASE register (bits 7-1 = address bits 15-9)
-            (self.chbase as u16) << 8
-        };
+            ((self.chbase & mask) as u16) << 8
+        }
+    }
 
-        // Render 40 characters
-        for char_col in 0..40 {
-            // Read character code from screen RAM
-            let char_code = mem.get_byte(self.screen_ptr + char_col);
+    /// Offset of glyph row `row` in a character, for modes 2-5: CHACTL
+    /// bit 2 turns the characters upside down
+    fn glyph_row(&self, row: u8) -> u16 {
+        if self.chactl & 0x04 != 0 {
+            (7 - row) as u16
+        } else {
+            row as u16
+        }
+    }
 
-            // Get character bitmap for this scanline
-            // Each character is 8 bytes, mode_line is the current scanline within the character
-            let char_addr = char_base + (char_code as u16) * 8 + (self.mode_line as u16);
-            let char_data = mem.get_byte(char_addr);
+    /// Fill `width` pixels of the scanline buffer with a color index
+    fn put_pixels(&mut self, x: usize, width: usize, color: u8) {
+        self.scanline_buffer[x..x + width].fill(color);
+    }
+
+    /// Render one scanline of ANTIC mode 2 or 3 (40-column text in hi-res
+    /// pixels, which take COLPF1's luminance on a COLPF2 playfield).
+    ///
+    /// Mode 3 rows are 10 scanlines. Characters $60-$7F have descenders:
+    /// their first two scanlines are blank and their top two rows are
+    /// drawn at the bottom instead. The other characters are blank on the
+    /// last two scanlines.
+    fn render_text_scanline(&mut self, mem: &Mem) {
+        // 1K aligned (CHBASE bits 7-2 = address bits 15-10)
+        let char_base = self.char_base(0xFC);
+        let start = self.playfield_start();
+
+        for char_col in 0..self.bytes_per_line() {
+            // Read character code from screen RAM
+            let char_code = mem.antic_read(self.screen_addr(char_col));
+
+            // Which row of the glyph this scanline shows, if any
+            let line = self.mode_line;
+            let row = if self.current_mode == 0x02 {
+                Some(line)
+            } else if char_code & 0x60 == 0x60 {
+                match line {
+                    0..=1 => None,
+                    2..=7 => Some(line),
+                    _ => Some(line - 8),
+                }
+            } else if line < 8 {
+                Some(line)
+            } else {
+                None
+            };
+
+            // Each character is 8 bytes
+            let mut char_data = match row {
+                Some(row) => mem.antic_read(char_base + ((char_code & 0x7F) as u16) * 8 + self.glyph_row(row)),
+                None => 0,
+            };
+
+            // Bit 7 selects inverse video rather than a glyph: CHACTL bit 0
+            // blanks these characters, then bit 1 inverts them, so with
+            // both set they show as solid blocks
+            if char_code & 0x80 != 0 {
+                if self.chactl & 0x01 != 0 {
+                    char_data = 0;
+                }
+                if self.chactl & 0x02 != 0 {
+                    char_data = !char_data;
+                }
+            }
 
             // Convert character bitmap to pixels (8 pixels per character)
             for bit in 0..8 {
                 let pixel_on = (char_data & (1 << (7 - bit))) != 0;
-                let pixel_x = (char_col * 8 + bit) as usize;
+                let pixel_x = start + char_col as usize * 8 + bit;
+                self.put_pixels(pixel_x, 1, if pixel_on { HIRES_PIXEL } else { HIRES_BACKGROUND });
+            }
+        }
+    }
 
-                // Color index: 0 = background, 1 = foreground
-                self.scanline_buffer[pixel_x] = if pixel_on { 1 } else { 0 };
+    /// Render one scanline of ANTIC mode 4 or 5 (40-column multicolor
+    /// text). Each glyph byte is four pixels two bits each: background,
+    /// PF0, PF1, and PF2, which becomes PF3 when the character code has bit
+    /// 7 set, so CHACTL's inverse and blank bits don't apply. Mode 5 shows
+    /// each glyph row on two scanlines.
+    fn render_multicolor_text_scanline(&mut self, mem: &Mem) {
+        let char_base = self.char_base(0xFC);
+        let start = self.playfield_start();
+        let row = if self.current_mode == 0x05 { self.mode_line / 2 } else { self.mode_line };
+
+        for char_col in 0..self.bytes_per_line() {
+            let char_code = mem.antic_read(self.screen_addr(char_col));
+            let char_data = mem.antic_read(char_base + ((char_code & 0x7F) as u16) * 8 + self.glyph_row(row));
+
+            for pixel in 0..4 {
+                let mut color = (char_data >> (6 - pixel * 2)) & 0x03;
+                if color == 3 && char_code & 0x80 != 0 {
+                    color = 4;
+                }
+                self.put_pixels(start + char_col as usize * 8 + pixel * 2, 2, color);
             }
         }
+    }
+
+    /// Render one scanline of ANTIC mode 6 or 7 (20-column text in double
+    /// width pixels). Bits 7-6 of the character code pick its color, PF0 to
+    /// PF3, and bits 5-0 one of 64 glyphs, so the character set is only
+    /// 512 bytes and aligned to match. Mode 7 shows each glyph row on two
+    /// scanlines.
+    fn render_large_text_scanline(&mut self, mem: &Mem) {
+        // 512 byte aligned (CHBASE bits 7-1 = address bits 15-9)
+        let char_base = self.char_base(0xFE);
+        let start = self.playfield_start();
+        let row = if self.current_mode == 0x07 { self.mode_line / 2 } else { self.mode_line };
+
+        for char_col in 0..self.bytes_per_line() {
+            let char_code = mem.antic_read(self.screen_addr(char_col));
+            let char_data = mem.antic_read(char_base + ((char_code & 0x3F) as u16) * 8 + row as u16);
+            let color = (char_code >> 6) + 1;
 
-        // If this was the last scanline of this character row, advance screen pointer
-        if self.mode_line == 7 {
-            self.screen_ptr = self.screen_ptr.wrapping_add(40);
+            for bit in 0..8 {
+                let pixel_on = (char_data & (1 << (7 - bit))) != 0;
+                let pixel_x = start + char_col as usize * 16 + bit * 2;
+                self.put_pixels(pixel_x, 2, if pixel_on { color } else { 0 });
+            }
+        }
+    }
+
+    /// Render one scanline of a bitmap mode, 8 to E. Each screen byte is
+    /// eight 1-bit pixels (background or PF0) or four 2-bit ones
+    /// (background, PF0, PF1 or PF2), leftmost pixel in the high bits.
+    /// The bytes of a normal-width line share its 320 hi-res pixels.
+    fn render_bitmap_scanline(&mut self, mem: &Mem) {
+        let depth = bitmap_depth(self.current_mode);
+        let pixels_per_byte = (8 / depth) as usize;
+        let byte_width = 320 / mode_geometry(self.current_mode).1 as usize;
+        let pixel_width = byte_width / pixels_per_byte;
+        let mask = (1u8 << depth) - 1;
+        let start = self.playfield_start();
+
+        for col in 0..self.bytes_per_line() {
+            let data = mem.antic_read(self.screen_addr(col));
+            for pixel in 0..pixels_per_byte {
+                let shift = 8 - depth as usize * (pixel + 1);
+                let color = (data >> shift) & mask;
+                let pixel_x = start + col as usize * byte_width + pixel * pixel_width;
+                self.put_pixels(pixel_x, pixel_width, color);
+            }
         }
     }
+
+    /// Render one scanline of mode F, one hi-res pixel a bit. The whole
+    /// playfield is COLPF2 rather than the background, and set pixels only
+    /// take COLPF1's luminance.
+    fn render_hires_scanline(&mut self, mem: &Mem) {
+        let start = self.playfield_start();
+
+        for col in 0..self.bytes_per_line() {
+            let data = mem.antic_read(self.screen_addr(col));
+            for bit in 0..8 {
+                let pixel_on = data & (0x80 >> bit) != 0;
+                let pixel_x = start + col as usize * 8 + bit;
+                self.put_pixels(pixel_x, 1, if pixel_on { HIRES_PIXEL } else { HIRES_BACKGROUND });
+            }
+        }
+    }
+}
+
+/// Scanlines per mode line, and bytes of screen memory per line at normal
+/// playfield width, for each display mode
+fn mode_geometry(mode: u8) -> (u8, u16) {
+    match mode {
+        0x02 => (8, 40),
+        0x03 => (10, 40),
+        0x04 => (8, 40),
+        0x05 => (16, 40),
+        0x06 => (8, 20),
+        0x07 => (16, 20),
+        0x08 => (8, 10),
+        0x09 => (4, 10),
+        0x0A => (4, 20),
+        0x0B => (2, 20),
+        0x0C => (1, 20),
+        0x0D => (2, 40),
+        0x0E => (1, 40),
+        0x0F => (1, 40),
+        _ => (1, 0),
+    }
+}
+
+/// Bits per pixel in a bitmap mode: the 2-color modes 9, B and C have
+/// one, the 4-color modes 8, A, D and E two
+fn bitmap_depth(mode: u8) -> u8 {
+    match mode {
+        0x09 | 0x0B | 0x0C => 1,
+        _ => 2,
+    }
 }
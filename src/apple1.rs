@@ -0,0 +1,180 @@
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::mem::{Mem, Region};
+use crate::pia::Pia;
+
+/// Largest monitor ROM accepted ($E000-$FFFF)
+const MAX_ROM_SIZE: usize = 0x2000;
+
+/// Minimal Apple-1 style terminal machine
+///
+/// This machine type provides:
+/// - RAM from $0000 up to the monitor ROM
+/// - 6821 PIA at $D010-$D013 (KBD, KBDCR, DSP, DSPCR), mirrored through $D01F
+/// - A user-supplied monitor ROM (e.g. WozMon) ending at $FFFF
+///
+/// The keyboard and display are wired to the host terminal: bytes read from
+/// stdin become key presses and display output is written to stdout.
+pub struct Apple1 {
+    cpu: Cpu,
+    mem: Mem,
+    pia: Pia,
+
+    // Host terminal
+    input: Option<Receiver<u8>>,
+    output: Box<dyn Write>,
+
+    cycle_count: u64,
+}
+
+impl Apple1 {
+    /// Build a machine around a monitor ROM image. The image is placed so
+    /// that it ends at $FFFF and therefore supplies the CPU vectors.
+    pub fn new(rom: &[u8]) -> io::Result<Apple1> {
+        if rom.is_empty() || rom.len() > MAX_ROM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("monitor ROM must be 1-{} bytes, got {}", MAX_ROM_SIZE, rom.len()),
+            ));
+        }
+
+        let rom_base = (0x10000 - rom.len()) as u16;
+        let mut mem = Mem::blank(rom_base);
+        mem.load(Region::Rom, rom_base, rom)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let mut apple1 = Apple1 {
+            cpu: Cpu::new(),
+            mem,
+            pia: Pia::new(),
+            input: None,
+            output: Box::new(io::stdout()),
+            cycle_count: 0,
+        };
+
+        // The display is always ready to accept a character (PB7 low)
+        apple1.pia.set_portb_input(0x00);
+
+        let mut cpu = std::mem::replace(&mut apple1.cpu, Cpu::new());
+        cpu.reset(&mut apple1);
+        apple1.cpu = cpu;
+
+        Ok(apple1)
+    }
+
+    /// Load a monitor ROM image from a file
+    pub fn from_rom_file(path: &str) -> io::Result<Apple1> {
+        let mut buffer = Vec::new();
+        std::fs::File::open(path)?.read_to_end(&mut buffer)?;
+        Apple1::new(&buffer)
+    }
+
+    /// Feed key presses from the host's stdin. A background thread reads
+    /// bytes so the emulator never blocks waiting for input.
+    pub fn attach_stdin(&mut self) {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                match byte {
+                    Ok(b) => {
+                        if tx.send(b).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+        self.input = Some(rx);
+    }
+
+    /// Redirect display output (stdout by default)
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Press a key. The Apple-1 keyboard only produces upper case ASCII with
+    /// bit 7 set, and RETURN is CR.
+    pub fn key_press(&mut self, ascii: u8) {
+        let ch = match ascii {
+            b'\n' => b'\r',
+            _ => ascii.to_ascii_uppercase(),
+        };
+        self.pia.set_porta_input(ch | 0x80);
+
+        // Strobe CA1 to latch the key-ready flag in KBDCR
+        self.pia.set_ca1(false);
+        self.pia.set_ca1(true);
+    }
+
+    /// Execute one machine cycle
+    pub fn tick(&mut self) {
+        // Only take the next host key once the previous one has been read
+        if !self.pia.ca1_flag() {
+            let key = self.input.as_ref().and_then(|rx| rx.try_recv().ok());
+            if let Some(key) = key {
+                self.key_press(key);
+            }
+        }
+
+        let mut cpu = std::mem::replace(&mut self.cpu, Cpu::new());
+        cpu.tick(self);
+        self.cpu = cpu;
+
+        self.pia.tick();
+        self.cycle_count += 1;
+    }
+
+    /// Run for the given number of machine cycles
+    pub fn run_cycles(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.tick();
+        }
+    }
+
+    /// Send a character written to DSP to the display
+    fn display(&mut self, val: u8) {
+        let ch = val & 0x7F;
+        let result = match ch {
+            b'\r' => self.output.write_all(b"\n"),
+            0x20..=0x7E => self.output.write_all(&[ch]),
+            _ => Ok(()),
+        };
+        if result.is_ok() {
+            self.output.flush().ok();
+        }
+    }
+}
+
+impl Bus for Apple1 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // PIA ($D010-$D01F), A0/A1 wired straight to RS0/RS1
+            0xD010..=0xD01F => self.pia.read((addr & 0x03) as u8),
+
+            _ => self.mem.get_byte(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0xD010..=0xD01F => {
+                self.pia.write((addr & 0x03) as u8, val);
+
+                // A write to DSP with CB2 dropped is a character for the display
+                if (addr & 0x03) == 0x02 && !self.pia.cb2_output() {
+                    self.display(val);
+                    // Display accepted the character - acknowledge on CB1
+                    self.pia.set_cb1(false);
+                    self.pia.set_cb1(true);
+                }
+            }
+
+            _ => self.mem.set_byte(addr, val),
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Assembly failure, with the 1-based source line it happened on
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembled code: the 64K address space with whatever was emitted into
+/// it, plus the symbol table
+pub struct Assembly {
+    memory: Vec<u8>,
+    symbols: HashMap<String, i64>,
+}
+
+impl Assembly {
+    /// Copy out `len` bytes starting at `start`. Bytes nothing was
+    /// assembled into read as $FF, like unprogrammed ROM.
+    pub fn image(&self, start: u16, len: usize) -> Vec<u8> {
+        let start = start as usize;
+        self.memory[start..start + len].to_vec()
+    }
+
+    /// Value of a label or constant
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(&name.to_ascii_uppercase()).map(|&v| v as u16)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Imp,
+    Acc,
+    Imm,
+    Zp,
+    ZpX,
+    ZpY,
+    Abs,
+    AbsX,
+    AbsY,
+    Ind,
+    IzX,
+    IzY,
+    Rel,
+}
+
+/// Official 6502 opcodes
+const OPCODES: &[(&str, Mode, u8)] = &[
+    ("ADC", Mode::Imm, 0x69), ("ADC", Mode::Zp, 0x65), ("ADC", Mode::ZpX, 0x75),
+    ("ADC", Mode::Abs, 0x6D), ("ADC", Mode::AbsX, 0x7D), ("ADC", Mode::AbsY, 0x79),
+    ("ADC", Mode::IzX, 0x61), ("ADC", Mode::IzY, 0x71),
+    ("AND", Mode::Imm, 0x29), ("AND", Mode::Zp, 0x25), ("AND", Mode::ZpX, 0x35),
+    ("AND", Mode::Abs, 0x2D), ("AND", Mode::AbsX, 0x3D), ("AND", Mode::AbsY, 0x39),
+    ("AND", Mode::IzX, 0x21), ("AND", Mode::IzY, 0x31),
+    ("ASL", Mode::Acc, 0x0A), ("ASL", Mode::Zp, 0x06), ("ASL", Mode::ZpX, 0x16),
+    ("ASL", Mode::Abs, 0x0E), ("ASL", Mode::AbsX, 0x1E),
+    ("BCC", Mode::Rel, 0x90), ("BCS", Mode::Rel, 0xB0), ("BEQ", Mode::Rel, 0xF0),
+    ("BMI", Mode::Rel, 0x30), ("BNE", Mode::Rel, 0xD0), ("BPL", Mode::Rel, 0x10),
+    ("BVC", Mode::Rel, 0x50), ("BVS", Mode::Rel, 0x70),
+    ("BIT", Mode::Zp, 0x24), ("BIT", Mode::Abs, 0x2C),
+    ("BRK", Mode::Imp, 0x00),
+    ("CLC", Mode::Imp, 0x18), ("CLD", Mode::Imp, 0xD8), ("CLI", Mode::Imp, 0x58),
+    ("CLV", Mode::Imp, 0xB8),
+    ("CMP", Mode::Imm, 0xC9), ("CMP", Mode::Zp, 0xC5), ("CMP", Mode::ZpX, 0xD5),
+    ("CMP", Mode::Abs, 0xCD), ("CMP", Mode::AbsX, 0xDD), ("CMP", Mode::AbsY, 0xD9),
+    ("CMP", Mode::IzX, 0xC1), ("CMP", Mode::IzY, 0xD1),
+    ("CPX", Mode::Imm, 0xE0), ("CPX", Mode::Zp, 0xE4), ("CPX", Mode::Abs, 0xEC),
+    ("CPY", Mode::Imm, 0xC0), ("CPY", Mode::Zp, 0xC4), ("CPY", Mode::Abs, 0xCC),
+    ("DEC", Mode::Zp, 0xC6), ("DEC", Mode::ZpX, 0xD6), ("DEC", Mode::Abs, 0xCE),
+    ("DEC", Mode::AbsX, 0xDE),
+    ("DEX", Mode::Imp, 0xCA), ("DEY", Mode::Imp, 0x88),
+    ("EOR", Mode::Imm, 0x49), ("EOR", Mode::Zp, 0x45), ("EOR", Mode::ZpX, 0x55),
+    ("EOR", Mode::Abs, 0x4D), ("EOR", Mode::AbsX, 0x5D), ("EOR", Mode::AbsY, 0x59),
+    ("EOR", Mode::IzX, 0x41), ("EOR", Mode::IzY, 0x51),
+    ("INC", Mode::Zp, 0xE6), ("INC", Mode::ZpX, 0xF6), ("INC", Mode::Abs, 0xEE),
+    ("INC", Mode::AbsX, 0xFE),
+    ("INX", Mode::Imp, 0xE8), ("INY", Mode::Imp, 0xC8),
+    ("JMP", Mode::Abs, 0x4C), ("JMP", Mode::Ind, 0x6C),
+    ("JSR", Mode::Abs, 0x20),
+    ("LDA", Mode::Imm, 0xA9), ("LDA", Mode::Zp, 0xA5), ("LDA", Mode::ZpX, 0xB5),
+    ("LDA", Mode::Abs, 0xAD), ("LDA", Mode::AbsX, 0xBD), ("LDA", Mode::AbsY, 0xB9),
+    ("LDA", Mode::IzX, 0xA1), ("LDA", Mode::IzY, 0xB1),
+    ("LDX", Mode::Imm, 0xA2), ("LDX", Mode::Zp, 0xA6), ("LDX", Mode::ZpY, 0xB6),
+    ("LDX", Mode::Abs, 0xAE), ("LDX", Mode::AbsY, 0xBE),
+    ("LDY", Mode::Imm, 0xA0), ("LDY", Mode::Zp, 0xA4), ("LDY", Mode::ZpX, 0xB4),
+    ("LDY", Mode::Abs, 0xAC), ("LDY", Mode::AbsX, 0xBC),
+    ("LSR", Mode::Acc, 0x4A), ("LSR", Mode::Zp, 0x46), ("LSR", Mode::ZpX, 0x56),
+    ("LSR", Mode::Abs, 0x4E), ("LSR", Mode::AbsX, 0x5E),
+    ("NOP", Mode::Imp, 0xEA),
+    ("ORA", Mode::Imm, 0x09), ("ORA", Mode::Zp, 0x05), ("ORA", Mode::ZpX, 0x15),
+    ("ORA", Mode::Abs, 0x0D), ("ORA", Mode::AbsX, 0x1D), ("ORA", Mode::AbsY, 0x19),
+    ("ORA", Mode::IzX, 0x01), ("ORA", Mode::IzY, 0x11),
+    ("PHA", Mode::Imp, 0x48), ("PHP", Mode::Imp, 0x08), ("PLA", Mode::Imp, 0x68),
+    ("PLP", Mode::Imp, 0x28),
+    ("ROL", Mode::Acc, 0x2A), ("ROL", Mode::Zp, 0x26), ("ROL", Mode::ZpX, 0x36),
+    ("ROL", Mode::Abs, 0x2E), ("ROL", Mode::AbsX, 0x3E),
+    ("ROR", Mode::Acc, 0x6A), ("ROR", Mode::Zp, 0x66), ("ROR", Mode::ZpX, 0x76),
+    ("ROR", Mode::Abs, 0x6E), ("ROR", Mode::AbsX, 0x7E),
+    ("RTI", Mode::Imp, 0x40), ("RTS", Mode::Imp, 0x60),
+    ("SBC", Mode::Imm, 0xE9), ("SBC", Mode::Zp, 0xE5), ("SBC", Mode::ZpX, 0xF5),
+    ("SBC", Mode::Abs, 0xED), ("SBC", Mode::AbsX, 0xFD), ("SBC", Mode::AbsY, 0xF9),
+    ("SBC", Mode::IzX, 0xE1), ("SBC", Mode::IzY, 0xF1),
+    ("SEC", Mode::Imp, 0x38), ("SED", Mode::Imp, 0xF8), ("SEI", Mode::Imp, 0x78),
+    ("STA", Mode::Zp, 0x85), ("STA", Mode::ZpX, 0x95), ("STA", Mode::Abs, 0x8D),
+    ("STA", Mode::AbsX, 0x9D), ("STA", Mode::AbsY, 0x99), ("STA", Mode::IzX, 0x81),
+    ("STA", Mode::IzY, 0x91),
+    ("STX", Mode::Zp, 0x86), ("STX", Mode::ZpY, 0x96), ("STX", Mode::Abs, 0x8E),
+    ("STY", Mode::Zp, 0x84), ("STY", Mode::ZpX, 0x94), ("STY", Mode::Abs, 0x8C),
+    ("TAX", Mode::Imp, 0xAA), ("TAY", Mode::Imp, 0xA8), ("TSX", Mode::Imp, 0xBA),
+    ("TXA", Mode::Imp, 0x8A), ("TXS", Mode::Imp, 0x9A), ("TYA", Mode::Imp, 0x98),
+];
+
+fn opcode(mnemonic: &str, mode: Mode) -> Option<u8> {
+    OPCODES
+        .iter()
+        .find(|(m, md, _)| *m == mnemonic && *md == mode)
+        .map(|&(_, _, op)| op)
+}
+
+fn is_mnemonic(word: &str) -> bool {
+    OPCODES.iter().any(|(m, _, _)| *m == word)
+}
+
+/// A small two-pass 6502 assembler, enough to build ROM images from source
+/// kept in this repository.
+///
+/// Syntax:
+/// - `label:` defines a label, `NAME = expr` a constant
+/// - `; comment` to end of line
+/// - `.org expr`, `.byte expr|"text",...`, `.word expr,...`
+/// - `.if expr` / `.else` / `.endif` (conditions must be known in pass 1)
+/// - numbers as `$hex`, `%binary`, decimal or `'c'`; `*` is the current
+///   address
+/// - expressions are evaluated left to right with `+ - * / & | ^`, with an
+///   optional leading `<` (low byte) or `>` (high byte)
+///
+/// Operands resolved to $00-$FF in pass 1 use zero page addressing where
+/// the instruction has it; forward references are always absolute.
+///
+/// `defines` are constants visible to the source, for use with `.if`.
+pub fn assemble(source: &str, defines: &[(&str, i64)]) -> Result<Assembly, AsmError> {
+    let mut asm = Assembler {
+        memory: vec![0xFF; 0x10000],
+        symbols: defines
+            .iter()
+            .map(|&(name, val)| (name.to_ascii_uppercase(), val))
+            .collect(),
+        pc: 0,
+        final_pass: false,
+        zero_page: Vec::new(),
+        instruction: 0,
+    };
+
+    asm.pass(source)?;
+    asm.final_pass = true;
+    asm.pass(source)?;
+
+    Ok(Assembly {
+        memory: asm.memory,
+        symbols: asm.symbols,
+    })
+}
+
+struct Assembler {
+    memory: Vec<u8>,
+    symbols: HashMap<String, i64>,
+    pc: u32,
+    final_pass: bool,
+
+    // Per instruction: whether pass 1 chose a zero page mode, so pass 2
+    // produces the same layout
+    zero_page: Vec<bool>,
+    instruction: usize,
+}
+
+impl Assembler {
+    fn pass(&mut self, source: &str) -> Result<(), AsmError> {
+        self.pc = 0;
+        self.instruction = 0;
+
+        // Whether each enclosing .if is currently assembling
+        let mut conditions: Vec<bool> = Vec::new();
+
+        for (index, raw) in source.lines().enumerate() {
+            let error = |message: String| AsmError { line: index + 1, message };
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let active = conditions.iter().all(|&c| c);
+            let lower = line.to_ascii_lowercase();
+            if let Some(expr) = lower.strip_prefix(".if ") {
+                let val = if active {
+                    self.eval(&line[4..4 + expr.len()]).map_err(error)?
+                } else {
+                    Some(0)
+                };
+                let val = val.ok_or_else(|| error(".if condition must be known".to_string()))?;
+                conditions.push(val != 0);
+                continue;
+            }
+            if lower == ".else" {
+                let last = conditions
+                    .last_mut()
+                    .ok_or_else(|| error(".else without .if".to_string()))?;
+                *last = !*last;
+                continue;
+            }
+            if lower == ".endif" {
+                conditions
+                    .pop()
+                    .ok_or_else(|| error(".endif without .if".to_string()))?;
+                continue;
+            }
+            if !active {
+                continue;
+            }
+
+            self.line(line).map_err(error)?;
+        }
+
+        if !conditions.is_empty() {
+            return Err(AsmError { line: source.lines().count(), message: "missing .endif".to_string() });
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, mut line: &str) -> Result<(), String> {
+        // Label
+        if let Some(colon) = line.find(':') {
+            let name = line[..colon].trim();
+            if is_identifier(name) {
+                self.define(name, self.pc as i64)?;
+                line = line[colon + 1..].trim();
+                if line.is_empty() {
+                    return Ok(());
+                }
+            }
+        }
+
+        // Constant
+        if let Some(eq) = line.find('=') {
+            let name = line[..eq].trim();
+            if is_identifier(name) {
+                if let Some(val) = self.eval(&line[eq + 1..])? {
+                    self.define(name, val)?;
+                } else if self.final_pass {
+                    return Err(format!("can't resolve {}", name));
+                }
+                return Ok(());
+            }
+        }
+
+        let (word, operand) = match line.find(char::is_whitespace) {
+            Some(space) => (&line[..space], line[space..].trim()),
+            None => (line, ""),
+        };
+        let word = word.to_ascii_uppercase();
+
+        match word.as_str() {
+            ".ORG" => {
+                let addr = self.require(operand)?;
+                if !(0..=0x10000).contains(&addr) {
+                    return Err(format!("origin ${:X} out of range", addr));
+                }
+                self.pc = addr as u32;
+                Ok(())
+            }
+            ".BYTE" => {
+                for item in split_list(operand) {
+                    if let Some(text) = item.strip_prefix('"') {
+                        let text = text.strip_suffix('"').ok_or("unterminated string")?;
+                        for b in text.bytes() {
+                            self.emit(b)?;
+                        }
+                    } else {
+                        let val = self.value(item)?;
+                        if self.final_pass && !(-128..=255).contains(&val) {
+                            return Err(format!("byte value {} out of range", val));
+                        }
+                        self.emit(val as u8)?;
+                    }
+                }
+                Ok(())
+            }
+            ".WORD" => {
+                for item in split_list(operand) {
+                    let val = self.value(item)?;
+                    self.emit(val as u8)?;
+                    self.emit((val >> 8) as u8)?;
+                }
+                Ok(())
+            }
+            _ if is_mnemonic(&word) => self.instruction(&word, operand),
+            _ => Err(format!("unknown instruction {}", word)),
+        }
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operand: &str) -> Result<(), String> {
+        let upper = operand.to_ascii_uppercase();
+
+        let (mode, expr) = if operand.is_empty() {
+            if opcode(mnemonic, Mode::Acc).is_some() {
+                (Mode::Acc, "")
+            } else {
+                (Mode::Imp, "")
+            }
+        } else if upper == "A" {
+            (Mode::Acc, "")
+        } else if let Some(expr) = operand.strip_prefix('#') {
+            (Mode::Imm, expr)
+        } else if operand.starts_with('(') && upper.ends_with(",X)") {
+            (Mode::IzX, &operand[1..operand.len() - 3])
+        } else if operand.starts_with('(') && upper.ends_with("),Y") {
+            (Mode::IzY, &operand[1..operand.len() - 3])
+        } else if operand.starts_with('(') && operand.ends_with(')') {
+            (Mode::Ind, &operand[1..operand.len() - 1])
+        } else if upper.ends_with(",X") {
+            (Mode::AbsX, operand[..operand.len() - 2].trim())
+        } else if upper.ends_with(",Y") {
+            (Mode::AbsY, operand[..operand.len() - 2].trim())
+        } else if opcode(mnemonic, Mode::Rel).is_some() {
+            (Mode::Rel, operand)
+        } else {
+            (Mode::Abs, operand)
+        };
+
+        // Zero page if the operand is known to fit now, decided once
+        let mode = match mode {
+            Mode::Abs | Mode::AbsX | Mode::AbsY => {
+                let zp_mode = match mode {
+                    Mode::Abs => Mode::Zp,
+                    Mode::AbsX => Mode::ZpX,
+                    _ => Mode::ZpY,
+                };
+                let use_zp = if self.final_pass {
+                    self.zero_page[self.instruction]
+                } else {
+                    let fits = matches!(self.eval(expr)?, Some(v) if (0..=0xFF).contains(&v));
+                    let use_zp = fits && opcode(mnemonic, zp_mode).is_some();
+                    self.zero_page.push(use_zp);
+                    use_zp
+                };
+                self.instruction += 1;
+                if use_zp { zp_mode } else { mode }
+            }
+            _ => mode,
+        };
+
+        let op = opcode(mnemonic, mode)
+            .ok_or_else(|| format!("{} has no {:?} addressing mode", mnemonic, mode))?;
+        self.emit(op)?;
+
+        match mode {
+            Mode::Imp | Mode::Acc => {}
+            Mode::Imm | Mode::Zp | Mode::ZpX | Mode::ZpY | Mode::IzX | Mode::IzY => {
+                let val = self.value(expr)?;
+                if self.final_pass && !(-128..=255).contains(&val) {
+                    return Err(format!("operand {} doesn't fit in a byte", val));
+                }
+                self.emit(val as u8)?;
+            }
+            Mode::Abs | Mode::AbsX | Mode::AbsY | Mode::Ind => {
+                let val = self.value(expr)?;
+                self.emit(val as u8)?;
+                self.emit((val >> 8) as u8)?;
+            }
+            Mode::Rel => {
+                let target = self.value(expr)?;
+                let offset = target - (self.pc as i64 + 1);
+                if self.final_pass && !(-128..=127).contains(&offset) {
+                    return Err(format!("branch out of range ({} bytes)", offset));
+                }
+                self.emit(offset as u8)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, val: i64) -> Result<(), String> {
+        let key = name.to_ascii_uppercase();
+        if !self.final_pass && self.symbols.contains_key(&key) {
+            return Err(format!("{} defined twice", name));
+        }
+        self.symbols.insert(key, val);
+        Ok(())
+    }
+
+    fn emit(&mut self, val: u8) -> Result<(), String> {
+        if self.pc > 0xFFFF {
+            return Err("code runs past $FFFF".to_string());
+        }
+        if self.final_pass {
+            self.memory[self.pc as usize] = val;
+        }
+        self.pc += 1;
+        Ok(())
+    }
+
+    /// Value of an expression in the final pass; placeholder before then
+    fn value(&self, expr: &str) -> Result<i64, String> {
+        match self.eval(expr)? {
+            Some(val) => Ok(val),
+            None if self.final_pass => Err(format!("undefined symbol in {}", expr.trim())),
+            None => Ok(0),
+        }
+    }
+
+    /// Value of an expression that must be known in pass 1
+    fn require(&self, expr: &str) -> Result<i64, String> {
+        self.eval(expr)?
+            .ok_or_else(|| format!("{} must be known in advance", expr.trim()))
+    }
+
+    /// Evaluate an expression, or None if it uses a symbol not yet defined
+    fn eval(&self, expr: &str) -> Result<Option<i64>, String> {
+        let expr = expr.trim();
+        let (part, expr) = match expr.chars().next() {
+            Some('<') => (Some(false), &expr[1..]),
+            Some('>') => (Some(true), &expr[1..]),
+            _ => (None, expr),
+        };
+
+        let mut chars = expr.trim().chars().peekable();
+        let mut result = Some(0_i64);
+        let mut op = '+';
+        loop {
+            while chars.peek() == Some(&' ') {
+                chars.next();
+            }
+
+            // Term
+            let term = match chars.peek() {
+                Some('*') => {
+                    chars.next();
+                    Some(self.pc as i64)
+                }
+                Some('-') => {
+                    chars.next();
+                    let mut text = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' || c == '$' {
+                            text.push(c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    self.term(&text)?.map(|v| -v)
+                }
+                Some('\'') => {
+                    chars.next();
+                    let c = chars.next().ok_or("bad character literal")?;
+                    if chars.next() != Some('\'') {
+                        return Err("bad character literal".to_string());
+                    }
+                    Some(c as i64)
+                }
+                Some(_) => {
+                    let mut text = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '%' {
+                            text.push(c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    self.term(&text)?
+                }
+                None => return Err("missing operand".to_string()),
+            };
+
+            result = match (result, term) {
+                (Some(a), Some(b)) => Some(match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' if b != 0 => a / b,
+                    '/' => return Err("division by zero".to_string()),
+                    '&' => a & b,
+                    '|' => a | b,
+                    _ => a ^ b,
+                }),
+                _ => None,
+            };
+
+            while chars.peek() == Some(&' ') {
+                chars.next();
+            }
+            match chars.next() {
+                None => break,
+                Some(c) if "+-*/&|^".contains(c) => op = c,
+                Some(c) => return Err(format!("unexpected '{}' in expression", c)),
+            }
+        }
+
+        Ok(result.map(|val| match part {
+            Some(false) => val & 0xFF,
+            Some(true) => (val >> 8) & 0xFF,
+            None => val,
+        }))
+    }
+
+    fn term(&self, text: &str) -> Result<Option<i64>, String> {
+        let parsed = if let Some(hex) = text.strip_prefix('$') {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(bin) = text.strip_prefix('%') {
+            i64::from_str_radix(bin, 2)
+        } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+            text.parse()
+        } else if is_identifier(text) {
+            return Ok(self.symbols.get(&text.to_ascii_uppercase()).copied());
+        } else {
+            return Err(format!("bad term '{}'", text));
+        };
+        parsed.map(Some).map_err(|_| format!("bad number '{}'", text))
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Drop a trailing comment, leaving semicolons inside quotes alone
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut in_char = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' if !in_char => in_quotes = !in_quotes,
+            '\'' if !in_quotes => in_char = !in_char,
+            ';' if !in_quotes && !in_char => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Split a directive's operands on commas outside quotes
+fn split_list(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                items.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(text[start..].trim());
+    items
+}
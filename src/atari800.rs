@@ -1,13 +1,306 @@
+use crate::builtin_os;
 use crate::bus::Bus;
+use crate::cartridge::{CartType, Cartridge};
+use crate::cassette::Cassette;
 use crate::cpu::Cpu;
-use crate::mem::Mem;
+use crate::mem::{Mem, Page, RamFill, Region};
 use crate::debugger::Debugger;
+use crate::disk::{DiskDrive, D1};
+use crate::drive1050::Drive1050;
 use crate::antic::Antic;
 use crate::gtia::Gtia;
+use crate::host::{self, HostDevice, HostEntry, HANDLER_TABLE};
+use crate::interface850::{SerialPort, PORTS, R1};
 use crate::pokey::Pokey;
 use crate::pia::Pia;
+use crate::printer::{Printer, P1};
+use crate::rom::{RomError, RomKind, RomSet};
+use crate::serial_bridge::SerialBridge;
+use crate::sio::{SioBus, STATUS_DEVICE_ERROR, STATUS_OK, STATUS_TIMEOUT};
+use crate::xex::{Segment, Xex, INITAD, RUNAD};
+use std::path::PathBuf;
+
+/// Size of the Atari BASIC ROM ($A000-$BFFF)
+const BASIC_ROM_SIZE: usize = 0x2000;
+
+/// How long OPTION is held down after power-on to disable built-in BASIC
+const BOOT_OPTION_FRAMES: u32 = 60;
+
+/// CONSOL switch bit for OPTION
+const CONSOL_OPTION: u8 = 0x04;
+
+/// NTSC frame: 262 scanlines of 114 machine cycles
+const CYCLES_PER_FRAME: u32 = 262 * 114;
+
+/// The OS jumps through this vector when it has finished booting
+const DOSVEC: u16 = 0x000A;
+
+/// SIOV, the OS's serial I/O entry point
+const SIOV: u16 = 0xE459;
+
+/// Device control block fields read by SIOV
+const DDEVIC: u16 = 0x0300;
+const DUNIT: u16 = 0x0301;
+const DCOMND: u16 = 0x0302;
+const DSTATS: u16 = 0x0303;
+const DBUFLO: u16 = 0x0304;
+const DBYTLO: u16 = 0x0308;
+const DAUX1: u16 = 0x030A;
+const DAUX2: u16 = 0x030B;
+
+/// CIOV, the OS's central I/O entry point
+const CIOV: u16 = 0xE456;
+
+/// Device handler table: 12 entries of a device letter and the address of
+/// the handler's vectors
+const HATABS: u16 = 0x031A;
+const HATABS_SIZE: usize = 36;
+
+/// Page zero copy of the IOCB CIO is working on, read by device handlers
+const ICCOMZ: u16 = 0x0022;
+const ICBALZ: u16 = 0x0024;
+const ICAX1Z: u16 = 0x002A;
+
+/// Longest file specification read from an IOCB buffer, which ends at an
+/// ATASCII EOL
+const MAX_FILESPEC: u16 = 128;
+const EOL: u8 = 0x9B;
+
+/// Return address for INITAD and RUNAD calls made by the binary loader:
+/// the last byte of the CSOPIV vector, which is never executed
+const XEX_RETURN: u16 = 0xE47F;
+
+/// Machine model
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    Atari400,
+    Atari800,
+    Atari600Xl,
+    Atari800Xl,
+    Atari65Xe,
+    Atari130Xe,
+}
+
+impl Model {
+    /// XL/XE machines have PORTB memory control and built-in BASIC
+    pub fn is_xl(&self) -> bool {
+        matches!(
+            self,
+            Model::Atari600Xl | Model::Atari800Xl | Model::Atari65Xe | Model::Atari130Xe
+        )
+    }
+
+    /// RAM fitted at the factory, in kilobytes
+    pub fn standard_ram_kb(&self) -> u16 {
+        match self {
+            Model::Atari400 | Model::Atari600Xl => 16,
+            Model::Atari800 => 48,
+            Model::Atari800Xl | Model::Atari65Xe | Model::Atari130Xe => 64,
+        }
+    }
+
+    /// Extended RAM fitted at the factory
+    pub fn standard_extended_ram(&self) -> ExtendedRam {
+        match self {
+            Model::Atari130Xe => ExtendedRam::Xe130,
+            _ => ExtendedRam::None,
+        }
+    }
+
+    /// Most RAM the memory map can hold, in kilobytes
+    fn max_ram_kb(&self) -> u16 {
+        if self.is_xl() { 64 } else { 48 }
+    }
+
+    /// OS ROM used by this model: 10K for the 400/800, 16K for XL/XE
+    pub fn os_rom_kind(&self) -> RomKind {
+        if self.is_xl() {
+            RomKind::OsXl
+        } else {
+            RomKind::Os800
+        }
+    }
+}
+
+/// Extended RAM banked into $4000-$7FFF through PORTB on XL/XE machines.
+///
+/// Bit 4 low gives the CPU the selected bank. Schemes that leave bit 5
+/// free use it the same way for ANTIC; the others let ANTIC follow the CPU.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExtendedRam {
+    None,
+    /// 130XE: 64K in 4 banks, PORTB bits 2-3
+    Xe130,
+    /// 192K: 128K in 8 banks, PORTB bits 2, 3, 6
+    Compy192,
+    /// 320K RAMBO: 256K in 16 banks, PORTB bits 2, 3, 5, 6
+    Rambo320,
+    /// 320K Compy-Shop: 256K in 16 banks, PORTB bits 2, 3, 6, 7
+    Compy320,
+    /// 576K: 512K in 32 banks, PORTB bits 1, 2, 3, 5, 6
+    Ram576,
+    /// 1088K: 1024K in 64 banks, PORTB bits 1, 2, 3, 5, 6, 7
+    Ram1088,
+}
+
+impl ExtendedRam {
+    /// PORTB bits that select the bank, least significant first
+    fn bank_bits(&self) -> &'static [u8] {
+        match self {
+            ExtendedRam::None => &[],
+            ExtendedRam::Xe130 => &[2, 3],
+            ExtendedRam::Compy192 => &[2, 3, 6],
+            ExtendedRam::Rambo320 => &[2, 3, 5, 6],
+            ExtendedRam::Compy320 => &[2, 3, 6, 7],
+            ExtendedRam::Ram576 => &[2, 3, 1, 5, 6],
+            ExtendedRam::Ram1088 => &[2, 3, 1, 5, 6, 7],
+        }
+    }
+
+    /// Extended RAM size in bytes (16K per bank)
+    pub fn size(&self) -> usize {
+        match self {
+            ExtendedRam::None => 0,
+            _ => (1 << self.bank_bits().len()) * 0x4000,
+        }
+    }
+
+    /// True if PORTB bit 5 gives ANTIC its own access control
+    fn has_antic_access(&self) -> bool {
+        !self.bank_bits().contains(&5)
+    }
+
+    /// Bank selected by a PORTB value
+    fn bank(&self, portb: u8) -> usize {
+        self.bank_bits()
+            .iter()
+            .enumerate()
+            .fold(0, |bank, (i, &bit)| bank | ((((portb >> bit) & 1) as usize) << i))
+    }
+}
+
+/// Machine configuration chosen at power-on
+#[derive(Clone, Debug)]
+pub struct Atari800Config {
+    pub model: Model,
+
+    /// Installed RAM in kilobytes, in 8K steps: 8K to 48K on the 400/800,
+    /// up to 64K on XL/XE machines (not counting extended RAM)
+    pub ram_size_kb: u16,
+
+    /// PORTB-banked memory on top of the 64K of an XL/XE machine
+    pub extended_ram: ExtendedRam,
+
+    /// RAM contents at power-on, extended RAM included
+    pub ram_fill: RamFill,
+
+    /// OS ROM image, 10K or 16K to suit the model. Without one the
+    /// built-in replacement OS is used.
+    pub os_rom: Option<Vec<u8>>,
+
+    /// Self-test ROM image (2K) to use instead of the one in the XL/XE OS.
+    /// Left out on the 400/800, which have no self-test.
+    pub self_test_rom: Option<Vec<u8>>,
+
+    /// Atari BASIC ROM image (8K). On the 400/800 it is plugged in like a
+    /// cartridge; on XL/XE machines it is built in and PORTB bit 1 maps it.
+    pub basic_rom: Option<Vec<u8>>,
+
+    /// Boot with BASIC. On XL/XE machines this is done the usual way, by
+    /// holding OPTION while the OS starts.
+    pub basic_enabled: bool,
+
+    /// Cartridge in the left slot ($8000-$BFFF)
+    pub cartridge: Option<Cartridge>,
+
+    /// Cartridge in the 800's right slot ($8000-$9FFF)
+    pub right_cartridge: Option<Cartridge>,
+
+    /// Serve calls to SIOV straight from the devices instead of running
+    /// the OS's serial I/O routine. Much faster, less accurate.
+    pub sio_patch: bool,
+
+    /// Host directory served as the H: device
+    pub host_dir: Option<PathBuf>,
+}
+
+impl Atari800Config {
+    /// Stock configuration for a model
+    pub fn for_model(model: Model) -> Atari800Config {
+        Atari800Config {
+            model,
+            ram_size_kb: model.standard_ram_kb(),
+            extended_ram: model.standard_extended_ram(),
+            ram_fill: RamFill::Zero,
+            os_rom: None,
+            self_test_rom: None,
+            basic_rom: None,
+            basic_enabled: true,
+            cartridge: None,
+            right_cartridge: None,
+            sio_patch: false,
+            host_dir: None,
+        }
+    }
+}
+
+impl Atari800Config {
+    /// Fill in the OS ROM for the model, plus BASIC and self-test ROMs if
+    /// the set has them. BASIC for the 400/800 is a cartridge, so it's only
+    /// plugged in when its path is given explicitly. With no OS ROM to be
+    /// found the machine falls back to the built-in OS.
+    pub fn load_roms(&mut self, roms: &RomSet) -> Result<(), RomError> {
+        if let Some(os) = roms.load_optional(self.model.os_rom_kind())? {
+            self.os_rom = Some(os.data);
+        }
+        if self.model.is_xl() || roms.basic.is_some() {
+            if let Some(basic) = roms.load_optional(RomKind::Basic)? {
+                self.basic_rom = Some(basic.data);
+            }
+        }
+        if self.model.is_xl() {
+            if let Some(self_test) = roms.load_optional(RomKind::SelfTest)? {
+                self.self_test_rom = Some(self_test.data);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Atari800Config {
+    fn default() -> Atari800Config {
+        Atari800Config::for_model(Model::Atari800)
+    }
+}
+
+/// A binary load file being fed into memory
+struct XexLoad {
+    segments: std::vec::IntoIter<Segment>,
+    first_start: u16,
+
+    // Where the OS went at the end of booting, or None until it gets there
+    dosvec: Option<u16>,
+
+    // Waiting for an INITAD routine to return
+    in_init: bool,
+}
+
+/// A cartridge plugged into a slot, with the location of its image in the
+/// ROM store
+struct CartSlot {
+    cart: Cartridge,
+    rom_base: usize,
+}
+
+/// A disk drive of either kind, off the bus while it changes units
+enum Drive {
+    Image(DiskDrive),
+    Firmware(Box<Drive1050>),
+}
 
 pub struct Atari800 {
+    config: Atari800Config,
+
     // Core components
     cpu: Cpu,
     mem: Mem,
@@ -18,6 +311,25 @@ pub struct Atari800 {
     pokey: Pokey,
     pia: Pia,
 
+    // Serial bus with the disk drives
+    sio: SioBus,
+
+    // H: device, when there is a host directory
+    host: Option<HostDevice>,
+
+    // Cartridge slots
+    left_cart: Option<CartSlot>,
+    right_cart: Option<CartSlot>,
+
+    // Built-in BASIC location in the ROM store (XL/XE)
+    basic_rom_base: Option<usize>,
+
+    // Frames left until OPTION is released after power-on
+    option_held_frames: u32,
+
+    // Binary load in progress
+    xex: Option<XexLoad>,
+
     // Debugger
     debugger: Debugger,
 
@@ -28,18 +340,57 @@ pub struct Atari800 {
 
 impl Atari800 {
     pub fn new() -> Atari800 {
+        Atari800::with_config(Atari800Config::default())
+            .expect("the default machine has no ROM images to get wrong")
+    }
+
+    /// Build and power on a machine. Fails if a ROM image in the
+    /// configuration is the wrong size for it.
+    pub fn with_config(mut config: Atari800Config) -> Result<Atari800, RomError> {
+        let cartridge = config.cartridge.take();
+        let right_cartridge = config.right_cartridge.take();
+        let basic_rom = config.basic_rom.take();
+        let host = config.host_dir.clone().map(HostDevice::new);
+
         let mut atari800 = Atari800 {
+            config,
             cpu: Cpu::new(),
-            mem: Mem::new(0xC000, false),  // ROM at $C000-$FFFF, load OS ROM
+            mem: Mem::blank(0xC000),
             antic: Antic::new(),
             gtia: Gtia::new(),
             pokey: Pokey::new(),
             pia: Pia::new(),
+            sio: SioBus::new(),
+            host,
+            left_cart: None,
+            right_cart: None,
+            basic_rom_base: None,
+            option_held_frames: 0,
+            xex: None,
             debugger: Debugger::new(),
             master_cycle: 0,
             cpu_halted: false,
         };
 
+        let extended_size = atari800.config.extended_ram.size();
+        atari800.mem.ram.resize(0x10000 + extended_size, 0x00);
+        atari800.config.ram_fill.fill(&mut atari800.mem.ram);
+
+        let os_rom = atari800.config.os_rom.take()
+            .unwrap_or_else(|| builtin_os::os_rom(atari800.config.model.is_xl()));
+        atari800.load_os_rom(&os_rom)?;
+        if let Some(self_test_rom) = atari800.config.self_test_rom.take() {
+            atari800.load_self_test_rom(&self_test_rom)?;
+        }
+
+        atari800.update_memory_map();
+        if let Some(basic_rom) = basic_rom {
+            atari800.install_basic(&basic_rom)?;
+        }
+        for cart in cartridge.into_iter().chain(right_cartridge) {
+            atari800.insert_cartridge(cart);
+        }
+
         // Reset CPU after construction to load PC from reset vector
         // Use mem::replace to temporarily take ownership of CPU
         let mut cpu = std::mem::replace(&mut atari800.cpu, Cpu::new());
@@ -49,7 +400,430 @@ impl Atari800 {
         // Set up test pattern
         atari800.setup_test_pattern();
 
-        atari800
+        Ok(atari800)
+    }
+
+    /// How RAM was filled at power-on, including the seed of a random fill
+    /// so the run can be repeated by configuring the same fill. Front ends
+    /// should log it.
+    pub fn ram_fill(&self) -> RamFill {
+        self.config.ram_fill
+    }
+
+    /// Installed RAM in bytes, rounded down to a whole 8K bank and limited
+    /// to what the model's memory map can hold.
+    fn ram_size(&self) -> u32 {
+        let max_kb = self.config.model.max_ram_kb();
+        ((self.config.ram_size_kb.clamp(8, max_kb) & !0x07) as u32) * 1024
+    }
+
+    /// Rebuild the CPU memory map.
+    ///
+    /// 400/800:
+    ///   $0000-$BFFF  RAM up to the installed size, unmapped above it
+    ///   $C000-$CFFF  unmapped
+    ///   $D000-$D7FF  I/O (handled in the Bus implementation), holes unmapped
+    ///   $D800-$FFFF  OS ROM
+    ///
+    /// XL/XE: RAM up to 64K, with PORTB choosing what covers it:
+    ///   bit 0 = 1    OS ROM at $C000-$CFFF and $D800-$FFFF
+    ///   bit 1 = 0    BASIC at $A000-$BFFF
+    ///   bit 7 = 0    self-test ROM at $5000-$57FF (only while the OS is on)
+    ///
+    /// Cartridges overlay $8000-$BFFF wherever they drive RD4/RD5.
+    fn update_memory_map(&mut self) {
+        let ram_top = self.ram_size();
+        self.mem.map(0x0000, (ram_top - 1) as u16, Page::Ram(0x0000));
+        if ram_top < 0x10000 {
+            self.mem.map(ram_top as u16, 0xFFFF, Page::Unmapped);
+        }
+        self.mem.map(0xD000, 0xD7FF, Page::Unmapped);
+
+        if self.config.model.is_xl() {
+            let portb = self.pia.portb_output();
+            let extended = self.map_extended_ram(portb);
+
+            if portb & 0x01 != 0 {
+                self.mem.map(0xC000, 0xCFFF, Page::Rom(0xC000));
+                self.mem.map(0xD800, 0xFFFF, Page::Rom(0xD800));
+
+                // Schemes using bit 7 for banking only free it for the
+                // self-test while extended RAM is off
+                let bit7_banking = self.config.extended_ram.bank_bits().contains(&7);
+                if portb & 0x80 == 0 && !(bit7_banking && extended) {
+                    self.mem.map(0x5000, 0x57FF, Page::Rom(0xD000));
+                }
+            }
+        } else {
+            self.mem.map(0xC000, 0xCFFF, Page::Unmapped);
+            self.mem.map(0xD800, 0xFFFF, Page::Rom(0xD800));
+        }
+
+        // XL/XE built-in BASIC: PORTB bit 1 low maps it at $A000-$BFFF
+        if let Some(base) = self.basic_rom_base {
+            if self.pia.portb_output() & 0x02 == 0 {
+                self.mem.map(0xA000, 0xBFFF, Page::Rom(base));
+            }
+        }
+
+        // Only the 800 has a right slot. It goes first so a left cart
+        // driving $8000 wins.
+        let right_cart = match self.config.model {
+            Model::Atari800 => self.right_cart.as_ref(),
+            _ => None,
+        };
+        for slot in [right_cart, self.left_cart.as_ref()].iter().copied().flatten() {
+            for (i, window) in slot.cart.windows().iter().enumerate() {
+                if let Some(offset) = window {
+                    let start = 0x8000 + (i as u16) * 0x1000;
+                    self.mem.map(start, start + 0x0FFF, Page::Rom(slot.rom_base + offset));
+                }
+            }
+        }
+
+        // TRIG3 reports a cartridge driving $A000-$BFFF
+        let rd5 = self.left_cart.as_ref().is_some_and(|slot| slot.cart.rd5());
+        self.gtia.set_trig(3, rd5 as u8);
+    }
+
+    /// Bank extended RAM into $4000-$7FFF for the CPU (PORTB bit 4 low) and
+    /// ANTIC (bit 5 low, or following the CPU). Returns true if the CPU
+    /// sees extended RAM.
+    fn map_extended_ram(&mut self, portb: u8) -> bool {
+        let ext = self.config.extended_ram;
+        if ext == ExtendedRam::None || self.ram_size() < 0x10000 {
+            return false;
+        }
+
+        let bank = Page::Ram(0x10000 + ext.bank(portb) * 0x4000);
+        let cpu_access = portb & 0x10 == 0;
+        let antic_access = if ext.has_antic_access() {
+            portb & 0x20 == 0
+        } else {
+            cpu_access
+        };
+
+        if cpu_access {
+            self.mem.map_cpu(0x4000, 0x7FFF, bank);
+        }
+        if antic_access {
+            self.mem.map_antic(0x4000, 0x7FFF, bank);
+        }
+        cpu_access
+    }
+
+    /// Install an OS ROM image. Images of the wrong size for the model are
+    /// refused.
+    fn load_os_rom(&mut self, image: &[u8]) -> Result<(), RomError> {
+        let kind = self.config.model.os_rom_kind();
+        if image.len() != kind.size() {
+            return Err(RomError::WrongImageSize { kind, expected: kind.size(), actual: image.len() });
+        }
+        // Ends at $FFFF: $D800 for the 400/800, $C000 for XL/XE (whose
+        // $D000-$D7FF part is the self-test)
+        let addr = (0x10000 - image.len()) as u16;
+        self.mem.load(Region::Rom, addr, image).expect("the OS ROM ends at $FFFF");
+        Ok(())
+    }
+
+    /// Replace the self-test part of an XL/XE OS. The 400/800 have no
+    /// self-test ROM, so there the image is left out.
+    fn load_self_test_rom(&mut self, image: &[u8]) -> Result<(), RomError> {
+        if !self.config.model.is_xl() {
+            return Ok(());
+        }
+        let kind = RomKind::SelfTest;
+        if image.len() != kind.size() {
+            return Err(RomError::WrongImageSize { kind, expected: kind.size(), actual: image.len() });
+        }
+        self.mem.load(Region::Rom, 0xD000, image).expect("the self-test ROM fits below $D800");
+        Ok(())
+    }
+
+    /// Set up the BASIC ROM for the configured model
+    fn install_basic(&mut self, image: &[u8]) -> Result<(), RomError> {
+        if image.len() != BASIC_ROM_SIZE {
+            return Err(RomError::WrongImageSize {
+                kind: RomKind::Basic,
+                expected: BASIC_ROM_SIZE,
+                actual: image.len(),
+            });
+        }
+
+        if self.config.model.is_xl() {
+            self.basic_rom_base = Some(self.mem.add_rom_image(image));
+            if !self.config.basic_enabled {
+                // The XL OS disables BASIC if OPTION is down during boot
+                self.option_held_frames = BOOT_OPTION_FRAMES;
+                self.gtia.set_consol_input(0x07 & !CONSOL_OPTION);
+            }
+            self.update_memory_map();
+        } else if self.config.basic_enabled {
+            if let Ok(cart) = Cartridge::new(CartType::Std8k, image) {
+                self.insert_cartridge(cart);
+            }
+        }
+        Ok(())
+    }
+
+    /// Plug in a cartridge. Right-slot carts go in the right slot, anything
+    /// else replaces the cart in the left slot.
+    pub fn insert_cartridge(&mut self, mut cart: Cartridge) {
+        cart.reset();
+        let right_slot = cart.cart_type().is_right_slot();
+        if right_slot {
+            self.remove_right_cartridge();
+        } else {
+            self.remove_cartridge();
+        }
+        let rom_base = self.mem.add_rom_image(cart.image());
+        let slot = Some(CartSlot { cart, rom_base });
+        if right_slot {
+            self.right_cart = slot;
+        } else {
+            self.left_cart = slot;
+        }
+        self.update_memory_map();
+    }
+
+    /// Remove the left-slot cartridge
+    pub fn remove_cartridge(&mut self) {
+        if let Some(slot) = self.left_cart.take() {
+            self.free_cart_image(&slot);
+        }
+        self.update_memory_map();
+    }
+
+    /// Remove the right-slot cartridge
+    pub fn remove_right_cartridge(&mut self) {
+        if let Some(slot) = self.right_cart.take() {
+            self.free_cart_image(&slot);
+        }
+        self.update_memory_map();
+    }
+
+    /// Give back the ROM store space of a cartridge that has come out, so
+    /// swapping cartridges doesn't keep growing it. Images stored after it
+    /// move down.
+    fn free_cart_image(&mut self, slot: &CartSlot) {
+        let len = slot.cart.image().len();
+        self.mem.remove_rom_image(slot.rom_base, len);
+        let moved = |base: &mut usize| {
+            if *base > slot.rom_base {
+                *base -= len;
+            }
+        };
+        if let Some(base) = self.basic_rom_base.as_mut() {
+            moved(base);
+        }
+        for other in [self.left_cart.as_mut(), self.right_cart.as_mut()].iter_mut().flatten() {
+            moved(&mut other.rom_base);
+        }
+    }
+
+    /// Switch the SIOV patch on or off
+    pub fn set_sio_patch(&mut self, enabled: bool) {
+        self.config.sio_patch = enabled;
+    }
+
+    pub fn sio_patch(&self) -> bool {
+        self.config.sio_patch
+    }
+
+    /// Serve H: from a host directory, or stop serving it. Files open on
+    /// the old directory are closed. Once stopped, an H: entry the OS still
+    /// has in HATABS stays callable until reset and fails every call.
+    pub fn set_host_dir(&mut self, dir: Option<PathBuf>) {
+        self.host = dir.clone().map(HostDevice::new);
+        self.config.host_dir = dir;
+    }
+
+    pub fn host_dir(&self) -> Option<&PathBuf> {
+        self.config.host_dir.as_ref()
+    }
+
+    /// Put a drive on the serial bus as D1:-D8: (`unit` 1-8), replacing any
+    /// drive already there. A drive for any other unit, or for a unit a 1050
+    /// is set to, is handed back.
+    pub fn mount_disk(&mut self, unit: u8, drive: DiskDrive) -> Result<(), DiskDrive> {
+        if !(1..=8).contains(&unit) || self.drive_1050(unit).is_some() {
+            return Err(drive);
+        }
+        self.sio.attach(D1 + unit - 1, Box::new(drive));
+        Ok(())
+    }
+
+    /// Take drive D`unit`: off the bus, handing it back
+    pub fn unmount_disk(&mut self, unit: u8) -> Option<DiskDrive> {
+        if (1..=8).contains(&unit) {
+            self.sio.detach_as(D1 + unit - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Exchange the drives at D`a`: and D`b`:, either of which may be
+    /// empty. A 1050 moves by having its switches reset, so only within
+    /// D1:-D4:. A unit outside 1-8, or one a 1050 can't be set to, is
+    /// handed back and nothing moves.
+    pub fn swap_disks(&mut self, a: u8, b: u8) -> Result<(), u8> {
+        for unit in [a, b] {
+            if !(1..=8).contains(&unit) {
+                return Err(unit);
+            }
+        }
+        if self.drive_1050(a).is_some() && b > 4 {
+            return Err(b);
+        }
+        if self.drive_1050(b).is_some() && a > 4 {
+            return Err(a);
+        }
+        let drive_a = self.take_drive(a);
+        if let Some(drive_b) = self.take_drive(b) {
+            self.put_drive(a, drive_b);
+        }
+        if let Some(drive_a) = drive_a {
+            self.put_drive(b, drive_a);
+        }
+        Ok(())
+    }
+
+    /// Take whatever drive is at D`unit`: off the bus
+    fn take_drive(&mut self, unit: u8) -> Option<Drive> {
+        self.unmount_disk(unit)
+            .map(Drive::Image)
+            .or_else(|| self.detach_1050(unit).map(|drive| Drive::Firmware(Box::new(drive))))
+    }
+
+    /// Put a drive taken off the bus back at D`unit`:, which is free and
+    /// one it can be set to
+    fn put_drive(&mut self, unit: u8, drive: Drive) {
+        match drive {
+            Drive::Image(drive) => self.sio.attach(D1 + unit - 1, Box::new(drive)),
+            Drive::Firmware(mut drive) => {
+                drive.set_unit(unit).expect("a 1050 only moves within D1:-D4:");
+                self.attach_1050(*drive);
+            }
+        }
+    }
+
+    pub fn disk(&self, unit: u8) -> Option<&DiskDrive> {
+        if (1..=8).contains(&unit) {
+            self.sio.device(D1 + unit - 1)
+        } else {
+            None
+        }
+    }
+
+    /// The drive at D`unit`:, to change its write protection
+    pub fn disk_mut(&mut self, unit: u8) -> Option<&mut DiskDrive> {
+        if (1..=8).contains(&unit) {
+            self.sio.device_mut(D1 + unit - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Put a 1050 running its own firmware on the serial bus, at the unit
+    /// its switches are set to, replacing any drive already there. The
+    /// SIOV patch leaves commands for it to the OS, which talks to it over
+    /// the wire.
+    pub fn attach_1050(&mut self, drive: Drive1050) {
+        self.sio.attach_line_device(drive.device_id(), Box::new(drive));
+    }
+
+    /// Take the 1050 at D`unit`: off the bus, handing it back
+    pub fn detach_1050(&mut self, unit: u8) -> Option<Drive1050> {
+        if (1..=8).contains(&unit) {
+            self.sio.detach_line_device_as(D1 + unit - 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn drive_1050(&self, unit: u8) -> Option<&Drive1050> {
+        if (1..=8).contains(&unit) {
+            self.sio.line_device(D1 + unit - 1)
+        } else {
+            None
+        }
+    }
+
+    /// The 1050 at D`unit`:, to change its disk or write protection
+    pub fn drive_1050_mut(&mut self, unit: u8) -> Option<&mut Drive1050> {
+        if (1..=8).contains(&unit) {
+            self.sio.line_device_mut(D1 + unit - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Put a printer on the serial bus as P1:, replacing any already there
+    pub fn connect_printer(&mut self, printer: Printer) {
+        self.sio.attach(P1, Box::new(printer));
+    }
+
+    pub fn disconnect_printer(&mut self) {
+        self.sio.detach(P1);
+    }
+
+    /// Put an 850 interface module on the serial bus, its ports as R1:-R4:
+    /// with port 1 connected through `bridge`. Programs load the R: handler
+    /// from it with its boot sequence.
+    pub fn connect_850(&mut self, bridge: Box<dyn SerialBridge>) {
+        let mut bridge = Some(bridge);
+        for unit in 1..=PORTS {
+            self.sio.attach(R1 + unit - 1, Box::new(SerialPort::new(unit, bridge.take())));
+        }
+    }
+
+    pub fn disconnect_850(&mut self) {
+        for unit in 1..=PORTS {
+            self.sio.detach(R1 + unit - 1);
+        }
+    }
+
+    /// Link the serial bus to an SIO2PC disk server through `bridge`. The
+    /// server answers for any device not on the bus, and the SIOV patch
+    /// leaves commands for those to the OS. The command line is the
+    /// bridge's handshake.
+    pub fn connect_sio2pc(&mut self, bridge: Box<dyn SerialBridge>) {
+        self.sio.connect_sio2pc(bridge);
+    }
+
+    pub fn disconnect_sio2pc(&mut self) -> Option<Box<dyn SerialBridge>> {
+        self.sio.disconnect_sio2pc()
+    }
+
+    /// Put a tape in the program recorder, replacing any already there
+    pub fn insert_tape(&mut self, cassette: Cassette) {
+        self.sio.insert_tape(cassette);
+    }
+
+    pub fn eject_tape(&mut self) -> Option<Cassette> {
+        self.sio.eject_tape()
+    }
+
+    pub fn tape(&self) -> Option<&Cassette> {
+        self.sio.tape()
+    }
+
+    pub fn tape_mut(&mut self) -> Option<&mut Cassette> {
+        self.sio.tape_mut()
+    }
+
+    /// Pass an access in the $D500 page (CCTL) to the cartridges, which may
+    /// switch banks
+    fn cartridge_access(&mut self, addr: u16, val: Option<u8>) {
+        let mut changed = false;
+        for slot in [&mut self.left_cart, &mut self.right_cart].iter_mut() {
+            if let Some(slot) = slot.as_mut() {
+                changed |= slot.cart.access_d5(addr, val);
+            }
+        }
+        if changed {
+            self.update_memory_map();
+        }
     }
 
     pub fn tick(&mut self) {
@@ -70,8 +844,251 @@ impl Atari800 {
         // self.tick_cycle_accurate();
     }
 
+    /// Run one frame without the debugger: 262 scanlines of 114 cycles,
+    /// then render and start the vertical blank
+    pub fn run_frame(&mut self) {
+        for _ in 0..CYCLES_PER_FRAME {
+            self.tick_cycle_accurate();
+        }
+        self.render();
+        self.trigger_vbi();
+    }
+
+    /// Press a key. `code` is the keyboard code POKEY reports in KBCODE,
+    /// with bit 6 for Shift and bit 7 for Control.
+    pub fn press_key(&mut self, code: u8) {
+        self.pokey.key_down(code);
+    }
+
+    pub fn release_key(&mut self) {
+        self.pokey.key_up();
+    }
+
+    pub fn press_break(&mut self) {
+        self.pokey.break_key();
+    }
+
+    /// Press the reset button and restart the OS from its cold start. RAM
+    /// keeps its contents until the OS clears it.
+    pub fn cold_start(&mut self) {
+        self.antic = Antic::new();
+        self.pokey = Pokey::new();
+        self.pia = Pia::new();
+        self.update_memory_map();
+
+        let mut cpu = std::mem::replace(&mut self.cpu, Cpu::new());
+        cpu.reset(self);
+        self.cpu = cpu;
+    }
+
+    /// Boot a binary load file without DOS: the machine is restarted, and
+    /// once the OS has booted the segments are loaded, calling INITAD after
+    /// any segment that sets it and finally RUNAD (or, if no segment sets
+    /// RUNAD, the start of the first segment). BASIC is switched off on
+    /// XL/XE machines, and any cartridge is pulled, as the OS would start
+    /// it instead of getting to DOSVEC. The load happens as frames are run.
+    pub fn load_xex(&mut self, xex: Xex) {
+        self.remove_cartridge();
+        self.remove_right_cartridge();
+        if self.config.model.is_xl() {
+            self.option_held_frames = BOOT_OPTION_FRAMES;
+            self.gtia.set_consol_input(0x07 & !CONSOL_OPTION);
+        }
+        self.xex = Some(XexLoad {
+            first_start: xex.segments.first().map_or(0, |s| s.start),
+            segments: xex.segments.into_iter(),
+            dosvec: None,
+            in_init: false,
+        });
+        self.cold_start();
+    }
+
+    /// Whether a binary load started by `load_xex` is still going
+    pub fn xex_loading(&self) -> bool {
+        self.xex.is_some()
+    }
+
+    /// Carry the binary load forward. Called between instructions.
+    fn advance_xex(&mut self, cpu: &mut Cpu) {
+        let mut load = match self.xex.take() {
+            Some(load) => load,
+            None => return,
+        };
+
+        let dosvec = match load.dosvec {
+            Some(dosvec) => dosvec,
+            None => {
+                // Page zero is always RAM; read it directly so the check
+                // doesn't disturb the data bus
+                let dosvec = u16::from_le_bytes([self.mem.ram[DOSVEC as usize], self.mem.ram[DOSVEC as usize + 1]]);
+                if dosvec == 0 || cpu.pc != dosvec {
+                    self.xex = Some(load);
+                    return;
+                }
+                load.dosvec = Some(dosvec);
+                self.write_word(RUNAD, 0);
+                dosvec
+            }
+        };
+        if load.in_init {
+            if cpu.pc != XEX_RETURN {
+                self.xex = Some(load);
+                return;
+            }
+            load.in_init = false;
+        }
+
+        while let Some(segment) = load.segments.next() {
+            self.write_word(INITAD, 0);
+            for (i, &byte) in segment.data.iter().enumerate() {
+                self.write(segment.start.wrapping_add(i as u16), byte);
+            }
+
+            if segment.covers(INITAD) || segment.covers(INITAD + 1) {
+                let init = self.read_word(INITAD);
+                if init != 0 {
+                    cpu.call(self, init, XEX_RETURN);
+                    load.in_init = true;
+                    self.xex = Some(load);
+                    return;
+                }
+            }
+        }
+
+        // Everything is in: run it, returning to where the OS was going
+        let run = match self.read_word(RUNAD) {
+            0 => load.first_start,
+            run => run,
+        };
+        cpu.call(self, run, dosvec);
+    }
+
+    /// SIO device ID the device control block addresses
+    fn sio_device_id(&mut self) -> u8 {
+        self.read(DDEVIC).wrapping_add(self.read(DUNIT)).wrapping_sub(1)
+    }
+
+    /// Whether the SIOV call about to be made is for a device that only
+    /// answers over the serial lines
+    fn sio_goes_down_the_wire(&mut self) -> bool {
+        let id = self.sio_device_id();
+        self.sio.needs_wire(id)
+    }
+
+    /// Serve a call to SIOV from the device control block, the way the OS
+    /// routine would but all at once, and return to the caller with the
+    /// status in Y and DSTATS
+    fn fast_sio(&mut self, cpu: &mut Cpu) {
+        let id = self.sio_device_id();
+        let command = self.read(DCOMND);
+        let direction = self.read(DSTATS);
+        let buffer = self.read_word(DBUFLO);
+        let len = self.read_word(DBYTLO);
+        let aux1 = self.read(DAUX1);
+        let aux2 = self.read(DAUX2);
+
+        let write = if direction & 0x80 != 0 {
+            Some((0..len).map(|i| self.read(buffer.wrapping_add(i))).collect::<Vec<u8>>())
+        } else {
+            None
+        };
+        let (mut status, data) = self.sio.execute(id, command, aux1, aux2, write.as_deref());
+
+        // The data frame follows COMPLETE or ERROR. The OS would wait in
+        // vain for the rest of a short one.
+        let answered = status == STATUS_OK || status == STATUS_DEVICE_ERROR;
+        if direction & 0x40 != 0 && answered {
+            for (i, &byte) in data.iter().take(len as usize).enumerate() {
+                self.write(buffer.wrapping_add(i as u16), byte);
+            }
+            if data.len() < len as usize {
+                status = STATUS_TIMEOUT;
+            }
+        }
+
+        self.write(DSTATS, status);
+        cpu.y = status;
+        cpu.n = status & 0x80 != 0;
+        cpu.z = status == 0;
+        cpu.rts(self);
+    }
+
+    /// Put H: in HATABS if it isn't there, in the first free entry. The OS
+    /// rebuilds the table on reset, so this is checked on every call to
+    /// CIOV.
+    fn install_host_device(&mut self) {
+        let start = HATABS as usize;
+        let table = &mut self.mem.ram[start..start + HATABS_SIZE];
+        if table.chunks(3).any(|entry| entry[0] == b'H') {
+            return;
+        }
+        if let Some(entry) = table.chunks_mut(3).find(|entry| entry[0] == 0) {
+            entry[0] = b'H';
+            entry[1..].copy_from_slice(&HANDLER_TABLE.to_le_bytes());
+        }
+    }
+
+    /// Whether the $D600 page answers: while H: is served, and after that
+    /// for as long as the OS's HATABS still leads there
+    fn host_table_answers(&self) -> bool {
+        let start = HATABS as usize;
+        self.host.is_some()
+            || self.mem.ram[start..start + HATABS_SIZE]
+                .chunks(3)
+                .any(|entry| entry[0] == b'H' && entry[1..] == HANDLER_TABLE.to_le_bytes())
+    }
+
+    /// Do the work of an H: handler routine CIO has called, and return to
+    /// it with the status in Y (and for GET the byte in A)
+    fn host_call(&mut self, cpu: &mut Cpu, entry: HostEntry) {
+        let channel = (cpu.x >> 4) as usize;
+        let command = self.read(ICCOMZ);
+        let aux1 = self.read(ICAX1Z);
+        let spec = match entry {
+            HostEntry::Open | HostEntry::Special => {
+                let buffer = self.read_word(ICBALZ);
+                let mut spec = Vec::new();
+                for i in 0..MAX_FILESPEC {
+                    let c = self.read(buffer.wrapping_add(i));
+                    spec.push(c);
+                    if c == EOL {
+                        break;
+                    }
+                }
+                spec
+            }
+            _ => Vec::new(),
+        };
+
+        let device = match self.host.as_mut() {
+            Some(device) => device,
+            None => {
+                // H: has been switched off under a program still using it
+                cpu.y = host::STATUS_NO_DEVICE;
+                cpu.n = true;
+                cpu.z = false;
+                cpu.rts(self);
+                return;
+            }
+        };
+        let (status, byte) = match entry {
+            HostEntry::Open => (device.open(channel, &spec, aux1), cpu.a),
+            HostEntry::Close => (device.close(channel), cpu.a),
+            HostEntry::Get => device.get(channel),
+            HostEntry::Put => (device.put(channel, cpu.a), cpu.a),
+            HostEntry::Status => (device.status(channel), cpu.a),
+            HostEntry::Special => (device.special(command, &spec), cpu.a),
+            HostEntry::Init => (host::STATUS_SUCCESS, cpu.a),
+        };
+
+        cpu.a = byte;
+        cpu.y = status;
+        cpu.n = status & 0x80 != 0;
+        cpu.z = status == 0;
+        cpu.rts(self);
+    }
+
     /// Cycle-accurate tick - executes one machine cycle
-    #[allow(dead_code)]
     fn tick_cycle_accurate(&mut self) {
         // ANTIC runs first and decides if it needs DMA
         let dma_active = self.antic.tick(&mut self.mem);
@@ -85,6 +1102,33 @@ impl Atari800 {
 
             // Use mem::replace to temporarily take ownership of CPU
             let mut cpu = std::mem::replace(&mut self.cpu, Cpu::new());
+            if cpu.cycles_remaining == 0 && self.xex.is_some() {
+                self.advance_xex(&mut cpu);
+            }
+            // The SIOV patch only applies while the OS ROM is mapped there
+            if cpu.cycles_remaining == 0 && cpu.pc == SIOV && self.config.sio_patch
+                && matches!(self.mem.page(SIOV), Page::Rom(_))
+                && !self.sio_goes_down_the_wire()
+            {
+                self.fast_sio(&mut cpu);
+            }
+            // H: routines run in the emulator, and CIO calls make sure the
+            // OS can find them
+            if cpu.cycles_remaining == 0 {
+                match HostEntry::at(cpu.pc) {
+                    Some(entry) if self.host_table_answers() => self.host_call(&mut cpu, entry),
+                    _ if cpu.pc == CIOV && self.host.is_some()
+                        && matches!(self.mem.page(CIOV), Page::Rom(_)) =>
+                    {
+                        self.install_host_device()
+                    }
+                    _ => {}
+                }
+            }
+            // POKEY interrupts are taken between instructions
+            if cpu.cycles_remaining == 0 && self.pokey.irq() {
+                cpu.irq(self);
+            }
             cpu.tick(self);  // CPU now tracks its own multi-cycle state
             self.cpu = cpu;
         }
@@ -95,6 +1139,10 @@ impl Atari800 {
         // POKEY runs (sound, timers, serial I/O)
         self.pokey.tick();
 
+        // Serial devices answer POKEY, the PIA's CB2 being the command line
+        // and CA2 the cassette motor (low = on)
+        self.sio.tick(&mut self.pokey, self.pia.cb2_output(), !self.pia.ca2_output());
+
         // PIA runs (joystick input)
         self.pia.tick();
 
@@ -220,6 +1268,19 @@ impl Atari800 {
     /// Should be called after each frame render
     /// This is essential for Atari OS and most software to function
     pub fn trigger_vbi(&mut self) {
+        // Let go of OPTION once the OS has had time to look at it
+        if self.option_held_frames > 0 {
+            self.option_held_frames -= 1;
+            if self.option_held_frames == 0 {
+                self.gtia.set_consol_input(0x07);
+            }
+        }
+
+        // The NMI only reaches the CPU if NMIEN enables it
+        if !self.antic.begin_vblank() {
+            return;
+        }
+
         // Use mem::replace to temporarily take ownership of CPU
         let mut cpu = std::mem::replace(&mut self.cpu, Cpu::new());
         cpu.nmi(self);  // self implements Bus
@@ -229,7 +1290,7 @@ impl Atari800 {
 
 impl Bus for Atari800 {
     fn read(&mut self, addr: u16) -> u8 {
-        match addr {
+        let val = match addr {
             // GTIA registers ($D000-$D01F)
             0xD000..=0xD01F => self.gtia.read_register(addr),
 
@@ -242,12 +1303,28 @@ impl Bus for Atari800 {
             // ANTIC registers ($D400-$D4FF)
             0xD400..=0xD4FF => self.antic.read_register(addr),
 
+            // H: handler table in the unused $D600 page
+            0xD600..=0xD6FF if self.host_table_answers() => host::table_byte(addr),
+
+            // Cartridge control ($D500-$D5FF) - reads can switch banks
+            // but nothing drives the bus
+            0xD500..=0xD5FF => {
+                self.cartridge_access(addr, None);
+                return self.mem.get_byte(addr);
+            }
+
             // Regular memory (RAM/ROM)
-            _ => self.mem.get_byte(addr),
-        }
+            _ => return self.mem.get_byte(addr),
+        };
+
+        // Chip register reads drive the data bus too
+        self.mem.set_data_bus(val);
+        val
     }
 
     fn write(&mut self, addr: u16, val: u8) {
+        self.mem.set_data_bus(val);
+
         match addr {
             // GTIA registers ($D000-$D01F)
             0xD000..=0xD01F => self.gtia.write_register(addr, val),
@@ -256,11 +1333,20 @@ impl Bus for Atari800 {
             0xD200..=0xD2FF => self.pokey.write_register(addr, val),
 
             // PIA registers ($D300-$D3FF)
-            0xD300..=0xD3FF => self.pia.write_register(addr, val),
+            0xD300..=0xD3FF => {
+                self.pia.write_register(addr, val);
+                // PORTB drives memory banking on XL/XE machines
+                if self.config.model.is_xl() {
+                    self.update_memory_map();
+                }
+            }
 
             // ANTIC registers ($D400-$D4FF)
             0xD400..=0xD4FF => self.antic.write_register(addr, val),
 
+            // Cartridge control ($D500-$D5FF)
+            0xD500..=0xD5FF => self.cartridge_access(addr, Some(val)),
+
             // Regular memory (RAM/ROM)
             _ => self.mem.set_byte(addr, val),
         }
@@ -0,0 +1,280 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::disk_image::{DiskImage, ImageFormat};
+
+/// Size of the ATR header
+const HEADER_SIZE: usize = 16;
+
+/// The header's first word: the sum of the letters "NICKATARI"
+const MAGIC: u16 = 0x0296;
+
+/// The first three sectors of a disk are always 128 bytes; the OS boots
+/// from them
+const BOOT_SECTORS: usize = 3;
+
+/// Disk formats the 810 and 1050 drives know
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Density {
+    /// 720 sectors of 128 bytes (810, 1050)
+    Single,
+    /// 1040 sectors of 128 bytes (1050)
+    Enhanced,
+    /// 720 sectors of 256 bytes (1050 with a double density upgrade)
+    Double,
+}
+
+impl Density {
+    pub fn sector_count(&self) -> usize {
+        match self {
+            Density::Single | Density::Double => 720,
+            Density::Enhanced => 1040,
+        }
+    }
+
+    pub fn sector_size(&self) -> usize {
+        match self {
+            Density::Single | Density::Enhanced => 128,
+            Density::Double => 256,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AtrError {
+    Io(io::Error),
+    /// The file doesn't start with the ATR magic number
+    NotAtr,
+    /// Sectors must be 128 or 256 bytes
+    BadSectorSize(u16),
+    /// The header promises more data than the file has
+    Truncated { expected: usize, actual: usize },
+}
+
+impl fmt::Display for AtrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtrError::Io(e) => write!(f, "{}", e),
+            AtrError::NotAtr => write!(f, "not an ATR disk image"),
+            AtrError::BadSectorSize(size) => write!(f, "unsupported sector size {}", size),
+            AtrError::Truncated { expected, actual } => write!(
+                f,
+                "header says {} bytes of sectors, file has {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AtrError {}
+
+impl From<io::Error> for AtrError {
+    fn from(e: io::Error) -> AtrError {
+        AtrError::Io(e)
+    }
+}
+
+/// An ATR disk image: a 16-byte header followed by the sectors in order.
+///
+/// Header: magic $0296, image size in 16-byte paragraphs (low word), sector
+/// size, paragraphs high byte, then 8 bytes that aren't used here. With
+/// 256-byte sectors the three boot sectors are stored as 128 bytes each,
+/// or padded to 256 in some images.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Atr {
+    header: [u8; HEADER_SIZE],
+    sector_size: usize,
+    boot_padded: bool,
+    data: Vec<u8>,
+}
+
+impl Atr {
+    /// A freshly formatted disk
+    pub fn blank(density: Density) -> Atr {
+        Atr::with_sectors(density.sector_size(), density.sector_count())
+    }
+
+    /// An empty disk of any size. `sector_size` is 128 or 256.
+    pub fn with_sectors(sector_size: usize, sectors: usize) -> Atr {
+        let mut atr = Atr {
+            header: [0; HEADER_SIZE],
+            sector_size,
+            boot_padded: false,
+            data: Vec::new(),
+        };
+        atr.data = vec![0; atr.image_size(sectors)];
+        atr.update_header();
+        atr
+    }
+
+    /// A copy of the sectors of a disk in another format
+    pub fn from_image(image: &dyn DiskImage) -> Atr {
+        let sector_size = image.density().sector_size();
+        let mut atr = Atr::with_sectors(sector_size, image.sector_count());
+        for sector in 1..=atr.sector_count() {
+            if let Some(data) = image.read_sector(sector) {
+                atr.write_sector(sector, data);
+            }
+        }
+        atr
+    }
+
+    pub fn parse(image: &[u8]) -> Result<Atr, AtrError> {
+        if image.len() < HEADER_SIZE || u16::from_le_bytes([image[0], image[1]]) != MAGIC {
+            return Err(AtrError::NotAtr);
+        }
+        let mut header = [0; HEADER_SIZE];
+        header.copy_from_slice(&image[..HEADER_SIZE]);
+
+        let sector_size = u16::from_le_bytes([header[4], header[5]]);
+        if sector_size != 128 && sector_size != 256 {
+            return Err(AtrError::BadSectorSize(sector_size));
+        }
+        let paragraphs = u16::from_le_bytes([header[2], header[3]]) as usize
+            | (header[6] as usize) << 16;
+        let expected = paragraphs * 16;
+        let actual = image.len() - HEADER_SIZE;
+        if actual < expected {
+            return Err(AtrError::Truncated { expected, actual });
+        }
+
+        let data = image[HEADER_SIZE..HEADER_SIZE + expected].to_vec();
+        let boot_padded = sector_size == 256 && data.len().is_multiple_of(256);
+        Ok(Atr { header, sector_size: sector_size as usize, boot_padded, data })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Atr, AtrError> {
+        Atr::parse(&fs::read(path)?)
+    }
+
+    /// The whole image, header included
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut image = self.header.to_vec();
+        image.extend_from_slice(&self.data);
+        image
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// The density a drive would report. Anything with 128-byte sectors
+    /// past sector 720 needs enhanced density.
+    pub fn density(&self) -> Density {
+        if self.sector_size == 256 {
+            Density::Double
+        } else if self.sector_count() > Density::Single.sector_count() {
+            Density::Enhanced
+        } else {
+            Density::Single
+        }
+    }
+
+    pub fn sector_count(&self) -> usize {
+        if self.sector_size == 128 || self.boot_padded {
+            self.data.len() / self.sector_size
+        } else {
+            (self.data.len() + BOOT_SECTORS * 128) / 256
+        }
+    }
+
+    /// Size of a sector, numbered from 1
+    pub fn sector_size(&self, sector: usize) -> usize {
+        if sector <= BOOT_SECTORS { 128 } else { self.sector_size }
+    }
+
+    /// Where a sector starts in the data, or None if there is no such
+    /// sector
+    fn sector_offset(&self, sector: usize) -> Option<usize> {
+        if sector == 0 || sector > self.sector_count() {
+            return None;
+        }
+        let index = sector - 1;
+        Some(if self.sector_size == 128 || self.boot_padded {
+            index * self.sector_size
+        } else if sector <= BOOT_SECTORS {
+            index * 128
+        } else {
+            BOOT_SECTORS * 128 + (index - BOOT_SECTORS) * 256
+        })
+    }
+
+    pub fn read_sector(&self, sector: usize) -> Option<&[u8]> {
+        let offset = self.sector_offset(sector)?;
+        Some(&self.data[offset..offset + self.sector_size(sector)])
+    }
+
+    /// Replace a sector's contents. Returns false if there is no such
+    /// sector or `data` is the wrong size for it.
+    pub fn write_sector(&mut self, sector: usize, data: &[u8]) -> bool {
+        match self.sector_offset(sector) {
+            Some(offset) if data.len() == self.sector_size(sector) => {
+                self.data[offset..offset + data.len()].copy_from_slice(data);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Wipe the disk, changing its format to `density`
+    pub fn format(&mut self, density: Density) {
+        self.sector_size = density.sector_size();
+        self.boot_padded = false;
+        self.data = vec![0; self.image_size(density.sector_count())];
+        self.update_header();
+    }
+
+    /// Bytes of sector data in an image of `sectors` sectors
+    fn image_size(&self, sectors: usize) -> usize {
+        if self.sector_size == 128 {
+            sectors * 128
+        } else {
+            sectors * 256 - BOOT_SECTORS * 128
+        }
+    }
+
+    fn update_header(&mut self) {
+        let paragraphs = self.data.len() / 16;
+        self.header[0..2].copy_from_slice(&MAGIC.to_le_bytes());
+        self.header[2..4].copy_from_slice(&(paragraphs as u16).to_le_bytes());
+        self.header[4..6].copy_from_slice(&(self.sector_size as u16).to_le_bytes());
+        self.header[6] = (paragraphs >> 16) as u8;
+    }
+}
+
+impl DiskImage for Atr {
+    fn image_format(&self) -> ImageFormat {
+        ImageFormat::Atr
+    }
+
+    fn density(&self) -> Density {
+        Atr::density(self)
+    }
+
+    fn sector_count(&self) -> usize {
+        Atr::sector_count(self)
+    }
+
+    fn sector_size(&self, sector: usize) -> usize {
+        Atr::sector_size(self, sector)
+    }
+
+    fn read_sector(&self, sector: usize) -> Option<&[u8]> {
+        Atr::read_sector(self, sector)
+    }
+
+    fn write_sector(&mut self, sector: usize, data: &[u8]) -> bool {
+        Atr::write_sector(self, sector, data)
+    }
+
+    fn format(&mut self, density: Density) -> bool {
+        Atr::format(self, density);
+        true
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        Atr::to_bytes(self)
+    }
+}
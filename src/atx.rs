@@ -0,0 +1,404 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::atr::Density;
+use crate::disk_image::{DiskImage, ImageFormat, SectorCopy, TRACKS};
+
+const SIGNATURE: &[u8; 4] = b"AT8X";
+
+/// Size of the file header
+const HEADER_SIZE: usize = 48;
+
+/// Size of a track record's header, and of a chunk's
+const TRACK_HEADER_SIZE: usize = 32;
+const CHUNK_HEADER_SIZE: usize = 8;
+
+/// Track record type for a track (the only one there is)
+const RECORD_TRACK: u16 = 0x0000;
+
+/// Chunk types inside a track record
+const CHUNK_SECTOR_LIST: u8 = 0x01;
+const CHUNK_WEAK_SECTOR: u8 = 0x10;
+
+/// Sector status bit: no sector data in the image (record not found)
+const STATUS_MISSING: u8 = 0x10;
+
+/// Sector status bit the image uses for "has extended data" rather than
+/// for the controller's write protect
+const STATUS_EXTENDED: u8 = 0x40;
+
+/// Angular positions are in 8 microsecond units, 26042 per revolution at
+/// 288 rpm
+pub const ROTATION_UNITS: u32 = 26042;
+
+#[derive(Debug)]
+pub enum AtxError {
+    Io(io::Error),
+    /// The file doesn't start with "AT8X"
+    NotAtx,
+    /// Density code in the header isn't 0-2
+    BadDensity(u8),
+    /// A record runs past the end of the file; `offset` is where it starts
+    Truncated { offset: usize },
+}
+
+impl fmt::Display for AtxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtxError::Io(e) => write!(f, "{}", e),
+            AtxError::NotAtx => write!(f, "not an ATX disk image"),
+            AtxError::BadDensity(code) => write!(f, "unknown density {}", code),
+            AtxError::Truncated { offset } => {
+                write!(f, "file ends inside the record at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AtxError {}
+
+impl From<io::Error> for AtxError {
+    fn from(e: io::Error) -> AtxError {
+        AtxError::Io(e)
+    }
+}
+
+/// One sector as the drive finds it on a track
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtxSector {
+    /// Sector number within the track, from 1
+    pub number: u8,
+
+    /// Floppy controller status when reading it: CRC error ($08), record
+    /// not found ($10), deleted data ($20), lost data ($04)
+    pub status: u8,
+
+    /// Angular position of the sector header, in 8µs units from the index
+    pub position: u16,
+
+    /// None if the controller never finds the data
+    pub data: Option<Vec<u8>>,
+
+    /// Bytes from here on read back differently every time
+    pub weak_offset: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtxTrack {
+    pub number: u8,
+
+    /// In the order they were recorded; numbers may repeat
+    pub sectors: Vec<AtxSector>,
+}
+
+/// An ATX disk image: a copy of the disk's tracks as a drive reads them,
+/// keeping the timing, missing and duplicate sectors, bad CRCs and weak
+/// bits that copy protection checks for.
+///
+/// The 48-byte header gives the density and where the track records
+/// start. Each track record holds chunks, one of which lists the sectors
+/// (number, status, position, offset of the data in the record); weak
+/// sector chunks mark where a sector's data stops being stable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Atx {
+    pub density: Density,
+    pub tracks: Vec<AtxTrack>,
+}
+
+impl Atx {
+    pub fn parse(image: &[u8]) -> Result<Atx, AtxError> {
+        if image.len() < HEADER_SIZE || &image[0..4] != SIGNATURE {
+            return Err(AtxError::NotAtx);
+        }
+        let density = match image[18] {
+            0 => Density::Single,
+            1 => Density::Enhanced,
+            2 => Density::Double,
+            code => return Err(AtxError::BadDensity(code)),
+        };
+        let sector_size = density.sector_size();
+
+        let mut tracks = Vec::new();
+        let mut offset = read_u32(image, 28) as usize;
+        let end = (read_u32(image, 32) as usize).min(image.len());
+        while offset + 8 <= end {
+            let size = read_u32(image, offset) as usize;
+            if size < 8 || offset + size > image.len() {
+                return Err(AtxError::Truncated { offset });
+            }
+            let record = &image[offset..offset + size];
+            if read_u16(record, 4) == RECORD_TRACK {
+                tracks.push(parse_track(record, sector_size).ok_or(AtxError::Truncated { offset })?);
+            }
+            offset += size;
+        }
+
+        Ok(Atx { density, tracks })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Atx, AtxError> {
+        Atx::parse(&fs::read(path)?)
+    }
+
+    /// A copy of a disk in another format. Sectors without a recorded
+    /// position are spread evenly round their track, any further copies of
+    /// one half a turn on.
+    pub fn from_image(image: &dyn DiskImage) -> Atx {
+        let density = image.density();
+        let per_track = image.sectors_per_track();
+        let spacing = ROTATION_UNITS as usize / per_track;
+        let tracks = (0..TRACKS)
+            .map(|track| {
+                let mut sectors = Vec::new();
+                for number in 1..=per_track {
+                    let copies = image.copies(track * per_track + number).unwrap_or_default();
+                    for (i, copy) in copies.into_iter().enumerate() {
+                        let spread = ((number - 1) * spacing + i * ROTATION_UNITS as usize / 2)
+                            % ROTATION_UNITS as usize;
+                        let found = copy.status & STATUS_MISSING == 0;
+                        let mut data = copy.data;
+                        data.resize(density.sector_size(), 0);
+                        sectors.push(AtxSector {
+                            number: number as u8,
+                            status: copy.status,
+                            position: copy.position.unwrap_or(spread as u16),
+                            data: Some(data).filter(|_| found),
+                            weak_offset: copy.weak_offset,
+                        });
+                    }
+                }
+                AtxTrack { number: track as u8, sectors }
+            })
+            .collect();
+        Atx { density, tracks }
+    }
+
+    /// The file image
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sector_size = self.density.sector_size();
+        let mut image = vec![0; HEADER_SIZE];
+        image[0..4].copy_from_slice(SIGNATURE);
+        image[4] = 1;
+        image[18] = match self.density {
+            Density::Single => 0,
+            Density::Enhanced => 1,
+            Density::Double => 2,
+        };
+        image[28..32].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+
+        for track in &self.tracks {
+            let sectors = &track.sectors;
+            let list_size = CHUNK_HEADER_SIZE + 8 * sectors.len();
+            let weak: Vec<(usize, usize)> = sectors
+                .iter()
+                .enumerate()
+                .filter_map(|(index, sector)| Some((index, sector.weak_offset?)))
+                .collect();
+            let data_start = TRACK_HEADER_SIZE + list_size + CHUNK_HEADER_SIZE * (weak.len() + 1);
+            let with_data = sectors.iter().filter(|sector| sector.data.is_some()).count();
+            let size = data_start + with_data * sector_size;
+
+            let mut record = vec![0; TRACK_HEADER_SIZE];
+            record[0..4].copy_from_slice(&(size as u32).to_le_bytes());
+            record[4..6].copy_from_slice(&RECORD_TRACK.to_le_bytes());
+            record[8] = track.number;
+            record[10..12].copy_from_slice(&(sectors.len() as u16).to_le_bytes());
+            record[20..24].copy_from_slice(&(TRACK_HEADER_SIZE as u32).to_le_bytes());
+
+            record.extend_from_slice(&(list_size as u32).to_le_bytes());
+            record.extend_from_slice(&[CHUNK_SECTOR_LIST, 0, 0, 0]);
+            let mut offset = data_start;
+            for sector in sectors {
+                let (status, start) = match sector.data {
+                    Some(_) => (sector.status & !STATUS_MISSING, offset),
+                    None => (sector.status | STATUS_MISSING, 0),
+                };
+                record.extend_from_slice(&[sector.number, status]);
+                record.extend_from_slice(&sector.position.to_le_bytes());
+                record.extend_from_slice(&(start as u32).to_le_bytes());
+                if sector.data.is_some() {
+                    offset += sector_size;
+                }
+            }
+            for (index, weak_offset) in weak {
+                record.extend_from_slice(&(CHUNK_HEADER_SIZE as u32).to_le_bytes());
+                record.extend_from_slice(&[CHUNK_WEAK_SECTOR, index as u8]);
+                record.extend_from_slice(&(weak_offset as u16).to_le_bytes());
+            }
+            record.extend_from_slice(&[0; CHUNK_HEADER_SIZE]);
+            for data in sectors.iter().filter_map(|sector| sector.data.as_ref()) {
+                let mut data = data.clone();
+                data.resize(sector_size, 0);
+                record.extend_from_slice(&data);
+            }
+            image.extend_from_slice(&record);
+        }
+
+        let end = image.len() as u32;
+        image[32..36].copy_from_slice(&end.to_le_bytes());
+        image
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Sectors numbered 1 to this on each track
+    pub fn sectors_per_track(&self) -> usize {
+        match self.density {
+            Density::Single | Density::Double => 18,
+            Density::Enhanced => 26,
+        }
+    }
+
+    pub fn track(&self, number: usize) -> Option<&AtxTrack> {
+        self.tracks.iter().find(|track| track.number as usize == number)
+    }
+
+    /// The copies of a sector, numbered from 1 across the disk
+    fn sectors(&self, sector: usize) -> impl Iterator<Item = &AtxSector> {
+        let per_track = self.sectors_per_track();
+        let number = (sector.wrapping_sub(1) % per_track + 1) as u8;
+        self.track(sector.wrapping_sub(1) / per_track)
+            .into_iter()
+            .flat_map(move |track| track.sectors.iter().filter(move |s| s.number == number))
+    }
+}
+
+/// Read only: the drive can't write the timing back
+impl DiskImage for Atx {
+    fn image_format(&self) -> ImageFormat {
+        ImageFormat::Atx
+    }
+
+    fn density(&self) -> Density {
+        self.density
+    }
+
+    fn sector_count(&self) -> usize {
+        TRACKS * Atx::sectors_per_track(self)
+    }
+
+    /// The file keeps boot sectors full size, but only 128 bytes are read
+    fn sector_size(&self, sector: usize) -> usize {
+        if sector <= 3 {
+            128
+        } else {
+            self.density.sector_size()
+        }
+    }
+
+    fn sectors_per_track(&self) -> usize {
+        Atx::sectors_per_track(self)
+    }
+
+    fn read_sector(&self, sector: usize) -> Option<&[u8]> {
+        if sector == 0 || sector > self.sector_count() {
+            return None;
+        }
+        let size = DiskImage::sector_size(self, sector);
+        self.sectors(sector).find_map(|copy| copy.data.as_deref()).map(|data| &data[..size])
+    }
+
+    fn write_sector(&mut self, _sector: usize, _data: &[u8]) -> bool {
+        false
+    }
+
+    fn sector_status(&self, sector: usize) -> u8 {
+        self.sectors(sector).next().map_or(STATUS_MISSING, |copy| copy.status)
+    }
+
+    fn copies(&self, sector: usize) -> Option<Vec<SectorCopy>> {
+        if sector == 0 || sector > self.sector_count() {
+            return None;
+        }
+        let size = DiskImage::sector_size(self, sector);
+        let copies = self
+            .sectors(sector)
+            .map(|copy| SectorCopy {
+                status: copy.status,
+                position: Some(copy.position),
+                data: copy.data.as_ref().map_or_else(|| vec![0; size], |data| data[..size].to_vec()),
+                weak_offset: copy.weak_offset,
+            })
+            .collect();
+        Some(copies)
+    }
+
+    fn format(&mut self, _density: Density) -> bool {
+        false
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        Atx::to_bytes(self)
+    }
+}
+
+/// Read a track record. None if a chunk or the sector data runs past its
+/// end.
+fn parse_track(record: &[u8], sector_size: usize) -> Option<AtxTrack> {
+    if record.len() < 32 {
+        return None;
+    }
+    let number = record[8];
+    let sector_count = read_u16(record, 10) as usize;
+    let mut sectors = Vec::new();
+    let mut weak = Vec::new();
+
+    let mut chunk = read_u32(record, 20) as usize;
+    while chunk + 8 <= record.len() {
+        let size = read_u32(record, chunk) as usize;
+        if size == 0 {
+            break;
+        }
+        if size < 8 {
+            return None;
+        }
+        let body = record.get(chunk..chunk + size)?;
+        match body[4] {
+            CHUNK_SECTOR_LIST => {
+                for i in 0..sector_count {
+                    let entry = body.get(8 + i * 8..16 + i * 8)?;
+                    let status = entry[1];
+                    let start = read_u32(entry, 4) as usize;
+                    let data = if status & STATUS_MISSING != 0 {
+                        None
+                    } else {
+                        Some(record.get(start..start + sector_size)?.to_vec())
+                    };
+                    sectors.push(AtxSector {
+                        number: entry[0],
+                        status: status & !STATUS_EXTENDED,
+                        position: read_u16(entry, 2),
+                        data,
+                        weak_offset: None,
+                    });
+                }
+            }
+            CHUNK_WEAK_SECTOR => weak.push((body[5] as usize, read_u16(body, 6) as usize)),
+            _ => {}
+        }
+        chunk += size;
+    }
+
+    for (index, offset) in weak {
+        if let Some(sector) = sectors.get_mut(index) {
+            sector.weak_offset = Some(offset);
+        }
+    }
+    Some(AtxTrack { number, sectors })
+}
+
+fn read_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
@@ -0,0 +1,135 @@
+; Character set for the built-in OS: 128 glyphs of 8 bytes in ANTIC's
+; internal order ($20-$5F, the graphics characters, then $60-$7F).
+; Inverse video comes from bit 7 of the screen code, not the font.
+; Letters and digits are drawn with single-pixel strokes on a 5x6 grid.
+
+        .org $E000
+
+        .byte $00, $00, $00, $00, $00, $00, $00, $00    ; $00 space
+        .byte $00, $10, $10, $10, $10, $00, $10, $00    ; $01 !
+        .byte $00, $28, $28, $00, $00, $00, $00, $00    ; $02 "
+        .byte $00, $28, $7C, $28, $7C, $28, $00, $00    ; $03 #
+        .byte $10, $3C, $50, $38, $14, $78, $10, $00    ; $04 $
+        .byte $00, $64, $68, $10, $2C, $4C, $00, $00    ; $05 %
+        .byte $00, $30, $48, $30, $4A, $44, $3A, $00    ; $06 &
+        .byte $00, $10, $10, $00, $00, $00, $00, $00    ; $07 '
+        .byte $00, $08, $10, $10, $10, $10, $08, $00    ; $08 (
+        .byte $00, $20, $10, $10, $10, $10, $20, $00    ; $09 )
+        .byte $00, $00, $54, $38, $7C, $38, $54, $00    ; $0A *
+        .byte $00, $00, $10, $10, $7C, $10, $10, $00    ; $0B +
+        .byte $00, $00, $00, $00, $00, $10, $10, $20    ; $0C ,
+        .byte $00, $00, $00, $00, $7C, $00, $00, $00    ; $0D -
+        .byte $00, $00, $00, $00, $00, $00, $10, $00    ; $0E .
+        .byte $00, $04, $08, $10, $20, $40, $00, $00    ; $0F /
+        .byte $00, $38, $44, $4C, $54, $64, $38, $00    ; $10 0
+        .byte $00, $10, $30, $10, $10, $10, $38, $00    ; $11 1
+        .byte $00, $38, $44, $04, $18, $20, $7C, $00    ; $12 2
+        .byte $00, $78, $04, $38, $04, $04, $78, $00    ; $13 3
+        .byte $00, $08, $18, $28, $48, $7C, $08, $00    ; $14 4
+        .byte $00, $7C, $40, $78, $04, $04, $78, $00    ; $15 5
+        .byte $00, $18, $20, $78, $44, $44, $38, $00    ; $16 6
+        .byte $00, $7C, $04, $08, $10, $10, $10, $00    ; $17 7
+        .byte $00, $38, $44, $38, $44, $44, $38, $00    ; $18 8
+        .byte $00, $38, $44, $44, $3C, $08, $30, $00    ; $19 9
+        .byte $00, $00, $10, $00, $00, $10, $00, $00    ; $1A :
+        .byte $00, $00, $10, $00, $00, $10, $10, $20    ; $1B ;
+        .byte $00, $08, $10, $20, $10, $08, $00, $00    ; $1C <
+        .byte $00, $00, $7C, $00, $7C, $00, $00, $00    ; $1D =
+        .byte $00, $20, $10, $08, $10, $20, $00, $00    ; $1E >
+        .byte $00, $38, $44, $08, $10, $00, $10, $00    ; $1F ?
+        .byte $00, $38, $44, $5C, $54, $58, $20, $1C    ; $20 @
+        .byte $00, $38, $44, $44, $7C, $44, $44, $00    ; $21 A
+        .byte $00, $78, $44, $78, $44, $44, $78, $00    ; $22 B
+        .byte $00, $3C, $40, $40, $40, $40, $3C, $00    ; $23 C
+        .byte $00, $70, $48, $44, $44, $48, $70, $00    ; $24 D
+        .byte $00, $7C, $40, $78, $40, $40, $7C, $00    ; $25 E
+        .byte $00, $7C, $40, $78, $40, $40, $40, $00    ; $26 F
+        .byte $00, $3C, $40, $40, $4C, $44, $3C, $00    ; $27 G
+        .byte $00, $44, $44, $7C, $44, $44, $44, $00    ; $28 H
+        .byte $00, $38, $10, $10, $10, $10, $38, $00    ; $29 I
+        .byte $00, $1C, $08, $08, $08, $48, $30, $00    ; $2A J
+        .byte $00, $44, $48, $70, $48, $44, $44, $00    ; $2B K
+        .byte $00, $40, $40, $40, $40, $40, $7C, $00    ; $2C L
+        .byte $00, $44, $6C, $54, $44, $44, $44, $00    ; $2D M
+        .byte $00, $44, $64, $54, $4C, $44, $44, $00    ; $2E N
+        .byte $00, $38, $44, $44, $44, $44, $38, $00    ; $2F O
+        .byte $00, $78, $44, $44, $78, $40, $40, $00    ; $30 P
+        .byte $00, $38, $44, $44, $54, $48, $34, $00    ; $31 Q
+        .byte $00, $78, $44, $44, $78, $48, $44, $00    ; $32 R
+        .byte $00, $3C, $40, $38, $04, $04, $78, $00    ; $33 S
+        .byte $00, $7C, $10, $10, $10, $10, $10, $00    ; $34 T
+        .byte $00, $44, $44, $44, $44, $44, $38, $00    ; $35 U
+        .byte $00, $44, $44, $44, $28, $28, $10, $00    ; $36 V
+        .byte $00, $44, $44, $44, $54, $6C, $44, $00    ; $37 W
+        .byte $00, $44, $28, $10, $10, $28, $44, $00    ; $38 X
+        .byte $00, $44, $28, $10, $10, $10, $10, $00    ; $39 Y
+        .byte $00, $7C, $08, $10, $20, $40, $7C, $00    ; $3A Z
+        .byte $00, $38, $20, $20, $20, $20, $38, $00    ; $3B [
+        .byte $00, $40, $20, $10, $08, $04, $00, $00    ; $3C \
+        .byte $00, $38, $08, $08, $08, $08, $38, $00    ; $3D ]
+        .byte $00, $10, $28, $44, $00, $00, $00, $00    ; $3E ^
+        .byte $00, $00, $00, $00, $00, $00, $00, $FE    ; $3F _
+        .byte $00, $6C, $FE, $FE, $7C, $38, $10, $00    ; $40 heart
+        .byte $10, $10, $10, $10, $1F, $10, $10, $10    ; $41 right tee
+        .byte $01, $01, $01, $01, $01, $01, $01, $01    ; $42 right bar
+        .byte $10, $10, $10, $10, $F0, $00, $00, $00    ; $43 bottom right corner
+        .byte $10, $10, $10, $10, $F0, $10, $10, $10    ; $44 left tee
+        .byte $00, $00, $00, $00, $F0, $10, $10, $10    ; $45 top right corner
+        .byte $01, $02, $04, $08, $10, $20, $40, $80    ; $46 diagonal /
+        .byte $80, $40, $20, $10, $08, $04, $02, $01    ; $47 diagonal \
+        .byte $01, $03, $07, $0F, $1F, $3F, $7F, $FF    ; $48 lower right triangle
+        .byte $00, $00, $00, $00, $0F, $0F, $0F, $0F    ; $49 lower right quarter
+        .byte $0F, $0F, $0F, $0F, $00, $00, $00, $00    ; $4A upper right quarter
+        .byte $80, $C0, $E0, $F0, $F8, $FC, $FE, $FF    ; $4B lower left triangle
+        .byte $FF, $00, $00, $00, $00, $00, $00, $00    ; $4C top bar
+        .byte $00, $00, $00, $00, $00, $00, $00, $FF    ; $4D bottom bar
+        .byte $00, $00, $00, $00, $F0, $F0, $F0, $F0    ; $4E lower left quarter
+        .byte $10, $38, $10, $54, $FE, $54, $10, $38    ; $4F club
+        .byte $00, $00, $00, $00, $1F, $10, $10, $10    ; $50 top left corner
+        .byte $00, $00, $00, $00, $FF, $00, $00, $00    ; $51 horizontal line
+        .byte $10, $10, $10, $10, $FF, $10, $10, $10    ; $52 cross
+        .byte $00, $38, $7C, $7C, $7C, $38, $00, $00    ; $53 ball
+        .byte $00, $00, $00, $00, $FF, $FF, $FF, $FF    ; $54 bottom half
+        .byte $80, $80, $80, $80, $80, $80, $80, $80    ; $55 left bar
+        .byte $00, $00, $00, $00, $FF, $10, $10, $10    ; $56 bottom tee
+        .byte $10, $10, $10, $10, $FF, $00, $00, $00    ; $57 top tee
+        .byte $F0, $F0, $F0, $F0, $F0, $F0, $F0, $F0    ; $58 left half
+        .byte $10, $10, $10, $10, $1F, $00, $00, $00    ; $59 bottom left corner
+        .byte $00, $02, $04, $48, $30, $20, $00, $00    ; $5A tick
+        .byte $E0, $80, $C0, $80, $EE, $08, $0E, $00    ; $5B escape
+        .byte $00, $10, $38, $54, $10, $10, $10, $00    ; $5C up arrow
+        .byte $00, $10, $10, $10, $54, $38, $10, $00    ; $5D down arrow
+        .byte $00, $00, $20, $40, $FE, $40, $20, $00    ; $5E left arrow
+        .byte $00, $00, $08, $04, $FE, $04, $08, $00    ; $5F right arrow
+        .byte $00, $10, $38, $7C, $FE, $7C, $38, $10    ; $60 diamond
+        .byte $00, $00, $38, $04, $3C, $44, $3C, $00    ; $61 a
+        .byte $00, $40, $40, $78, $44, $44, $78, $00    ; $62 b
+        .byte $00, $00, $38, $40, $40, $40, $38, $00    ; $63 c
+        .byte $00, $04, $04, $3C, $44, $44, $3C, $00    ; $64 d
+        .byte $00, $00, $38, $44, $7C, $40, $38, $00    ; $65 e
+        .byte $00, $18, $20, $78, $20, $20, $20, $00    ; $66 f
+        .byte $00, $00, $3C, $44, $44, $3C, $04, $38    ; $67 g
+        .byte $00, $40, $40, $78, $44, $44, $44, $00    ; $68 h
+        .byte $00, $10, $00, $30, $10, $10, $38, $00    ; $69 i
+        .byte $00, $08, $00, $18, $08, $08, $08, $30    ; $6A j
+        .byte $00, $40, $40, $48, $70, $48, $44, $00    ; $6B k
+        .byte $00, $30, $10, $10, $10, $10, $08, $00    ; $6C l
+        .byte $00, $00, $68, $54, $54, $54, $44, $00    ; $6D m
+        .byte $00, $00, $78, $44, $44, $44, $44, $00    ; $6E n
+        .byte $00, $00, $38, $44, $44, $44, $38, $00    ; $6F o
+        .byte $00, $00, $78, $44, $44, $78, $40, $40    ; $70 p
+        .byte $00, $00, $3C, $44, $44, $3C, $04, $04    ; $71 q
+        .byte $00, $00, $58, $60, $40, $40, $40, $00    ; $72 r
+        .byte $00, $00, $3C, $40, $38, $04, $78, $00    ; $73 s
+        .byte $00, $20, $78, $20, $20, $20, $18, $00    ; $74 t
+        .byte $00, $00, $44, $44, $44, $44, $3C, $00    ; $75 u
+        .byte $00, $00, $44, $44, $28, $28, $10, $00    ; $76 v
+        .byte $00, $00, $44, $44, $54, $54, $28, $00    ; $77 w
+        .byte $00, $00, $44, $28, $10, $28, $44, $00    ; $78 x
+        .byte $00, $00, $44, $44, $44, $3C, $04, $38    ; $79 y
+        .byte $00, $00, $7C, $08, $10, $20, $7C, $00    ; $7A z
+        .byte $00, $10, $38, $7C, $FE, $54, $10, $38    ; $7B spade
+        .byte $10, $10, $10, $10, $10, $10, $10, $10    ; $7C |
+        .byte $00, $F8, $C0, $A0, $90, $08, $04, $00    ; $7D clear
+        .byte $00, $10, $30, $7F, $30, $10, $00, $00    ; $7E backspace
+        .byte $00, $08, $0C, $FE, $0C, $08, $00, $00    ; $7F tab
@@ -0,0 +1,2068 @@
+; Built-in replacement OS for the Atari 400/800 and XL/XE.
+;
+; Written from scratch for atari800-rs so the emulator can boot without
+; Atari's copyrighted ROMs. It keeps the documented entry points, vectors
+; and OS variable locations, so well-behaved software that sticks to them
+; runs. There is no floating point package and no cassette support.
+;
+; Assembled with XL = 1 for the 16K XL/XE image, XL = 0 for the 10K
+; 400/800 image. The font is in builtin_font.asm.
+
+; ---- Page zero ----
+RAMPTR  = $04           ; RAM test and clear pointer
+TRAMSZ  = $06           ; left cartridge present
+TSTDAT  = $07           ; right cartridge present
+WARMST  = $08
+BOOTQ   = $09           ; bit 0 set once booted from disk
+DOSVEC  = $0A
+DOSINI  = $0C
+POKMSK  = $10
+BRKKEY  = $11
+RTCLOK  = $12
+HANDVEC = $1C           ; handler table while CIO calls into it
+PBPNT   = $1E           ; bytes in the printer buffer
+ICHIDZ  = $20
+ICDNOZ  = $21
+ICCOMZ  = $22
+ICSTAZ  = $23
+ICBALZ  = $24
+ICBAHZ  = $25
+ICBLLZ  = $28
+ICBLHZ  = $29
+ICSPRZ  = $2C           ; bytes transferred
+ICIDNO  = $2E
+CIOCHR  = $2F
+STATUS  = $30           ; SIO status
+CHKSUM  = $31           ; SIO frame checksum
+BUFRLO  = $32           ; SIO frame pointer
+BUFRHI  = $33
+BFENLO  = $34           ; SIO bytes left in the frame
+BFENHI  = $35
+CRETRY  = $36           ; SIO command frame retries left
+CRITIC  = $42
+ATRACT  = $4D
+TMPCHR  = $50
+LMARGN  = $52
+RMARGN  = $53
+ROWCRS  = $54
+COLCRS  = $55
+SAVMSC  = $58
+OLDCHR  = $5D
+OLDADR  = $5E
+ADRESS  = $64
+TOADR   = $66
+FRMADR  = $68
+RAMTOP  = $6A
+BUFCNT  = $6B
+BUFSTR  = $6C
+
+; ---- OS variables ----
+VDSLST  = $0200
+VBREAK  = $0206
+VKEYBD  = $0208
+VSERIN  = $020A
+VSEROR  = $020C
+VSEROC  = $020E
+VTIMR1  = $0210
+VTIMR2  = $0212
+VTIMR4  = $0214
+VIMIRQ  = $0216
+CDTMV1  = $0218
+VVBLKI  = $0222
+VVBLKD  = $0224
+CDTMA1  = $0226
+CDTMA2  = $0228
+CDTMF3  = $022A
+CDTMF4  = $022C
+INTEMP  = $022D
+CDTMF5  = $022E
+SDMCTL  = $022F
+SDLSTL  = $0230
+SDLSTH  = $0231
+SSKCTL  = $0232
+CDEVIC  = $023A         ; SIO command frame
+CCOMND  = $023B
+CAUX1   = $023C
+CAUX2   = $023D
+DBSECT  = $0241         ; boot sectors left to read
+BOOTAD  = $0242         ; boot load address
+LINBUF  = $0247
+GPRIOR  = $026F
+STICK0  = $0278
+STICK1  = $0279
+STRIG0  = $0284
+STRIG1  = $0285
+ESCFLG  = $02A2
+INVFLG  = $02B6
+SHFLOK  = $02BE
+PCOLR0  = $02C0
+COLOR0  = $02C4
+RAMSIZ  = $02E4
+MEMTOP  = $02E5
+DVSTAT  = $02EA         ; device status from the last STATUS
+MEMLO   = $02E7
+CRSINH  = $02F0
+CHACT   = $02F3
+CHBAS   = $02F4
+ATACHR  = $02FB
+CH      = $02FC
+DDEVIC  = $0300
+DUNIT   = $0301
+DCOMND  = $0302
+DSTATS  = $0303
+DBUFLO  = $0304
+DBUFHI  = $0305
+DTIMLO  = $0306
+DBYTLO  = $0308
+DBYTHI  = $0309
+DAUX1   = $030A
+DAUX2   = $030B
+SIOTIM  = $0314         ; SIO frames left before a timeout
+SIOCLK  = $0316         ; RTCLOK+2 when SIOTIM last counted
+HATABS  = $031A
+ICHID   = $0340
+ICDNO   = $0341
+ICCOM   = $0342
+ICSTA   = $0343
+ICBAL   = $0344
+ICBAH   = $0345
+ICBLL   = $0348
+ICBLH   = $0349
+ICAX1   = $034A
+ICAX2   = $034B
+PRNBUF  = $03C0         ; printer line buffer
+BOOTBF  = $0400         ; boot sector buffer
+LBUFF   = $0580
+
+; ---- Hardware ----
+TRIG0   = $D010
+TRIG1   = $D011
+COLPM0  = $D012
+PRIOR   = $D01B
+CONSOL  = $D01F
+AUDF3   = $D204
+AUDF4   = $D206
+AUDCTL  = $D208
+KBCODE  = $D209
+SKRES   = $D20A
+SEROUT  = $D20D
+SERIN   = $D20D
+IRQEN   = $D20E
+IRQST   = $D20E
+SKCTL   = $D20F
+PORTA   = $D300
+PORTB   = $D301
+PACTL   = $D302
+PBCTL   = $D303
+DMACTL  = $D400
+CHACTL  = $D401
+DLISTL  = $D402
+DLISTH  = $D403
+CHBASE  = $D409
+NMIEN   = $D40E
+NMIRES  = $D40F
+NMIST   = $D40F
+
+EOL     = $9B
+
+; ==== Tables ====
+
+        .org $D800
+
+; Copied to VDSLST-VVBLKD at start-up
+vector_defaults:
+        .word rti_only          ; VDSLST
+        .word pla_rti           ; VPRCED
+        .word pla_rti           ; VINTER
+        .word pla_rti           ; VBREAK
+        .word key_irq           ; VKEYBD
+        .word pla_rti           ; VSERIN
+        .word pla_rti           ; VSEROR
+        .word pla_rti           ; VSEROC
+        .word pla_rti           ; VTIMR1
+        .word pla_rti           ; VTIMR2
+        .word pla_rti           ; VTIMR4
+        .word irq_dispatch      ; VIMIRQ
+        .word 0, 0, 0, 0, 0     ; CDTMV1-5
+        .word sysvbv            ; VVBLKI
+        .word xitvbv            ; VVBLKD
+vector_defaults_end:
+
+; Copied to the start of HATABS
+hatabs_defaults:
+        .byte 'P'
+        .word printv
+        .byte 'C'
+        .word casetv
+        .byte 'E'
+        .word editrv
+        .byte 'S'
+        .word screnv
+        .byte 'K'
+        .word keybdv
+hatabs_defaults_end:
+
+; COLOR0-COLOR4
+color_defaults:
+        .byte $28, $CA, $94, $46, $00
+
+editor_name:
+        .byte "E:", EOL
+
+banner:
+        .byte "ATARI800-RS BUILT-IN OS", EOL
+
+; Keyboard code to ATASCII: unshifted, Shift, Control. $FF for keys that
+; don't produce a character.
+keymap:
+        .byte $6C, $6A, $3B, $FF, $FF, $6B, $2B, $2A    ; L J ; F1 F2 K + *
+        .byte $6F, $FF, $70, $75, $9B, $69, $2D, $3D    ; O . P U Ret I - =
+        .byte $76, $FF, $63, $FF, $FF, $62, $78, $7A    ; V Help C F3 F4 B X Z
+        .byte $34, $FF, $33, $36, $1B, $35, $32, $31    ; 4 . 3 6 Esc 5 2 1
+        .byte $2C, $20, $2E, $6E, $FF, $6D, $2F, $FF    ; , Space . N . M / Inv
+        .byte $72, $FF, $65, $79, $7F, $74, $77, $71    ; R . E Y Tab T W Q
+        .byte $39, $FF, $30, $37, $7E, $38, $3C, $3E    ; 9 . 0 7 Bksp 8 < >
+        .byte $66, $68, $64, $FF, $FF, $67, $73, $61    ; F H D . Caps G S A
+
+        .byte $4C, $4A, $3A, $FF, $FF, $4B, $5C, $5E
+        .byte $4F, $FF, $50, $55, $9B, $49, $5F, $7C
+        .byte $56, $FF, $43, $FF, $FF, $42, $58, $5A
+        .byte $24, $FF, $23, $26, $1B, $25, $22, $21
+        .byte $5B, $20, $5D, $4E, $FF, $4D, $3F, $FF
+        .byte $52, $FF, $45, $59, $9F, $54, $57, $51
+        .byte $28, $FF, $29, $27, $9C, $40, $7D, $9D
+        .byte $46, $48, $44, $FF, $FF, $47, $53, $41
+
+        .byte $0C, $0A, $7B, $FF, $FF, $0B, $1E, $1F
+        .byte $0F, $FF, $10, $15, $9B, $09, $1C, $1D
+        .byte $16, $FF, $03, $FF, $FF, $02, $18, $1A
+        .byte $FF, $FF, $FF, $FF, $1B, $FF, $FD, $FF
+        .byte $00, $20, $60, $0E, $FF, $0D, $FF, $FF
+        .byte $12, $FF, $05, $19, $9E, $14, $17, $11
+        .byte $FF, $FF, $FF, $FF, $FE, $FF, $7D, $FF
+        .byte $06, $08, $04, $FF, $FF, $07, $13, $01
+
+; ==== Device handler tables ====
+;
+; Each entry is the routine address minus one (handlers are entered with
+; RTS): open, close, get, put, status, special, then a JMP to the init
+; routine.
+
+        .org $E400
+editrv:
+        .word ed_open-1, ok-1, ed_get-1, ed_put-1, ok-1, ok-1
+        jmp rts_only
+        .byte 0
+screnv:
+        .word ed_open-1, ok-1, ed_get-1, ed_put-1, ok-1, ok-1
+        jmp rts_only
+        .byte 0
+keybdv:
+        .word ok-1, ok-1, get_key-1, not_supported-1, ok-1, ok-1
+        jmp rts_only
+        .byte 0
+printv:
+        .word pr_open-1, pr_close-1, not_supported-1, pr_put-1, pr_status-1, ok-1
+        jmp rts_only
+        .byte 0
+casetv:
+        .word no_device-1, no_device-1, no_device-1, no_device-1, no_device-1, no_device-1
+        jmp rts_only
+        .byte 0
+
+; ==== Entry points ====
+
+        .org $E450
+diskiv: jmp rts_only
+dskinv: jmp disk_io
+ciov:   jmp cio
+siov:   jmp sio
+setvbv: jmp set_vbv
+sysvbv: jmp sys_vbv
+xitvbv: jmp exit_vbv
+sioinv: jmp rts_only
+sendev: jmp rts_only
+intinv: jmp rts_only
+cioinv: jmp cio_init
+blkbdv: jmp memo_pad
+warmsv: jmp warm_start
+coldsv: jmp cold_start
+rblokv: jmp no_device
+csopiv: jmp no_device
+
+; ==== Code ====
+
+        .org $E480
+
+rti_only:
+        rti
+
+pla_rti:
+        pla
+        rti
+
+rts_only:
+        rts
+
+ok:
+        ldy #1
+        rts
+
+not_supported:
+        ldy #$92
+        rts
+
+no_device:
+        ldy #$8A
+        rts
+
+; ---- Power-up and reset ----
+
+cold_start:
+        sei
+        cld
+        ldx #$FF
+        txs
+        jsr init_hardware
+
+        ; Clear page zero from WARMST up
+        lda #0
+        ldx #WARMST
+cs_zero_page:
+        sta $00,x
+        inx
+        bne cs_zero_page
+
+        ; Clear RAM a page at a time, stopping at the first page that
+        ; doesn't hold what was written. Writing the complement of the page
+        ; number keeps the floating bus from passing for RAM.
+        sta RAMPTR
+        lda #$01
+        sta RAMPTR+1
+cs_page:
+        ldy #0
+        lda RAMPTR+1
+        eor #$FF
+        sta (RAMPTR),y
+        cmp (RAMPTR),y
+        bne cs_done
+        lda #0
+        sta (RAMPTR),y
+        cmp (RAMPTR),y
+        bne cs_done
+cs_byte:
+        sta (RAMPTR),y
+        iny
+        bne cs_byte
+        inc RAMPTR+1
+        lda RAMPTR+1
+        cmp #$C0
+        bne cs_page
+cs_done:
+        lda RAMPTR+1
+        sta RAMTOP
+        sta RAMSIZ
+
+        lda #<memo_pad
+        sta DOSVEC
+        lda #>memo_pad
+        sta DOSVEC+1
+        lda #<rts_only
+        sta DOSINI
+        lda #>rts_only
+        sta DOSINI+1
+        jmp start_os
+
+warm_start:
+        sei
+        cld
+        ldx #$FF
+        txs
+        jsr init_hardware
+
+        ; Clear the OS variables, keeping RAMTOP
+        lda RAMTOP
+        pha
+        lda #0
+        ldx #$10
+ws_zero_page:
+        sta $00,x
+        inx
+        bpl ws_zero_page
+        tax
+ws_os_pages:
+        sta $0200,x
+        sta $0300,x
+        inx
+        bne ws_os_pages
+        pla
+        sta RAMTOP
+        sta RAMSIZ
+        lda #$FF
+        sta WARMST
+        ; fall through
+
+start_os:
+        ldx #vector_defaults_end-vector_defaults-1
+so_vectors:
+        lda vector_defaults,x
+        sta VDSLST,x
+        dex
+        bpl so_vectors
+
+        ldx #hatabs_defaults_end-hatabs_defaults-1
+so_hatabs:
+        lda hatabs_defaults,x
+        sta HATABS,x
+        dex
+        bpl so_hatabs
+
+        ldx #4
+so_colors:
+        lda color_defaults,x
+        sta COLOR0,x
+        dex
+        bpl so_colors
+
+        lda #$E0
+        sta CHBAS
+        lda #$02
+        sta CHACT
+        lda #$40
+        sta SHFLOK
+        lda #$FF
+        sta CH
+        sta BRKKEY
+        lda #2
+        sta LMARGN
+        lda #39
+        sta RMARGN
+        lda #$00
+        sta MEMLO
+        lda #$07
+        sta MEMLO+1
+        lda #$03
+        sta SSKCTL
+        lda #$C0
+        sta POKMSK
+        sta IRQEN
+
+        jsr cio_init
+        jsr open_editor
+
+        lda #$40
+        sta NMIEN
+        cli
+
+        lda WARMST
+        bne so_warm
+        jsr boot_disk
+        jmp so_boot
+so_warm:
+        jsr call_dosini
+so_boot:
+        jsr check_carts
+        jmp (DOSVEC)
+
+call_dosini:
+        jmp (DOSINI)
+
+; Reset the chips and set up the PIA
+init_hardware:
+        lda #0
+        tax
+ih_clear:
+        sta $D000,x
+        sta $D200,x
+        sta $D400,x
+        inx
+        bne ih_clear
+
+        lda #$03                ; keyboard scan and debounce
+        sta SKCTL
+        lda #$3C                ; cassette motor off, SIO command off
+        sta PACTL
+.if XL
+        ; PORTB: latch OS on, BASIC and self-test off, then make the lines
+        ; outputs
+        sta PBCTL
+        lda #$FF
+        sta PORTB
+        lda #$38
+        sta PBCTL
+        lda #$FF
+        sta PORTB
+        lda #$3C
+        sta PBCTL
+
+        ; BASIC comes on unless OPTION is held
+        lda CONSOL
+        and #$04
+        beq ih_done
+        lda PORTB
+        and #$FD
+        sta PORTB
+ih_done:
+.else
+        sta PBCTL
+.endif
+        rts
+
+open_editor:
+        ldx #0
+        lda #3
+        sta ICCOM
+        lda #<editor_name
+        sta ICBAL
+        lda #>editor_name
+        sta ICBAH
+        lda #$0C
+        sta ICAX1
+        lda #0
+        sta ICAX2
+        jmp ciov
+
+; ---- Cartridges ----
+
+; Initialise any cartridges, then start one if it asks to be run
+check_carts:
+        ldx #$BF
+        jsr cart_present
+        sta TRAMSZ
+        ldx #$9F
+        jsr cart_present
+        sta TSTDAT
+
+        lda TSTDAT
+        beq cc_no_right
+        jsr init_right
+cc_no_right:
+        lda TRAMSZ
+        beq cc_no_left
+        jsr init_left
+cc_no_left:
+        lda TRAMSZ
+        beq cc_try_right
+        lda $BFFD
+        and #$04
+        beq cc_try_right
+        jmp ($BFFA)
+cc_try_right:
+        lda TSTDAT
+        beq cc_none
+        lda $9FFD
+        and #$04
+        beq cc_none
+        jmp ($9FFA)
+cc_none:
+        rts
+
+init_left:
+        jmp ($BFFE)
+
+init_right:
+        jmp ($9FFE)
+
+; A = 1 if there is a cartridge ending in page X: its flag byte at $xFFC
+; reads 0 and isn't RAM
+cart_present:
+        stx RAMPTR+1
+        lda #$FC
+        sta RAMPTR
+        ldy #0
+        lda (RAMPTR),y
+        bne cp_none
+        lda #$FF
+        sta (RAMPTR),y
+        cmp (RAMPTR),y
+        beq cp_ram
+        lda #1
+        rts
+cp_ram:
+        lda #0
+        sta (RAMPTR),y
+cp_none:
+        lda #0
+        rts
+
+; ---- Interrupts ----
+
+nmi:
+        bit NMIST
+        bpl nmi_vbi
+        jmp (VDSLST)
+nmi_vbi:
+        cld
+        pha
+        lda NMIST
+        and #$20
+        beq nmi_not_reset
+        jmp warmsv
+nmi_not_reset:
+        txa
+        pha
+        tya
+        pha
+        sta NMIRES
+        jmp (VVBLKI)
+
+sys_vbv:
+        inc RTCLOK+2
+        bne sv_clock
+        inc RTCLOK+1
+        bne sv_clock
+        inc RTCLOK
+sv_clock:
+        lda CRITIC
+        beq sv_stage2
+        jmp exit_vbv
+sv_stage2:
+        lda SDLSTL
+        sta DLISTL
+        lda SDLSTH
+        sta DLISTH
+        lda SDMCTL
+        sta DMACTL
+        lda CHBAS
+        sta CHBASE
+        lda CHACT
+        sta CHACTL
+        lda GPRIOR
+        sta PRIOR
+        ldx #8
+sv_colors:
+        lda PCOLR0,x
+        sta COLPM0,x
+        dex
+        bpl sv_colors
+
+        ; Timers 1 and 2 call CDTMA1/2, 3-5 clear their flags
+        ldx #0
+        jsr countdown
+        bne sv_timer2
+        jsr call_timer1
+sv_timer2:
+        ldx #2
+        jsr countdown
+        bne sv_timer3
+        jsr call_timer2
+sv_timer3:
+        ldx #4
+        jsr countdown
+        bne sv_timer4
+        sta CDTMF3
+sv_timer4:
+        ldx #6
+        jsr countdown
+        bne sv_timer5
+        sta CDTMF4
+sv_timer5:
+        ldx #8
+        jsr countdown
+        bne sv_joysticks
+        sta CDTMF5
+
+sv_joysticks:
+        lda PORTA
+        and #$0F
+        sta STICK0
+        lda PORTA
+        lsr a
+        lsr a
+        lsr a
+        lsr a
+        sta STICK1
+        lda TRIG0
+        sta STRIG0
+        lda TRIG1
+        sta STRIG1
+        jmp (VVBLKD)
+
+; Count down timer X (offset from CDTMV1). Returns Z set and A = 0 when
+; it has just run out.
+countdown:
+        lda CDTMV1,x
+        ora CDTMV1+1,x
+        beq cd_idle
+        lda CDTMV1,x
+        bne cd_low
+        dec CDTMV1+1,x
+cd_low:
+        dec CDTMV1,x
+        lda CDTMV1,x
+        ora CDTMV1+1,x
+        rts
+cd_idle:
+        lda #1
+        rts
+
+call_timer1:
+        jmp (CDTMA1)
+
+call_timer2:
+        jmp (CDTMA2)
+
+exit_vbv:
+        pla
+        tay
+        pla
+        tax
+        pla
+        rti
+
+; A = 1-5 for CDTMV1-5, 6 for VVBLKI, 7 for VVBLKD; X = high byte,
+; Y = low byte
+set_vbv:
+        asl a
+        sta INTEMP
+        txa
+        ldx INTEMP
+        php
+        sei
+        sta VIMIRQ+1,x
+        tya
+        sta VIMIRQ,x
+        plp
+        rts
+
+irq:
+        cld
+        jmp (VIMIRQ)
+
+; Find the POKEY interrupt (IRQST bits are low when pending), acknowledge
+; it and jump through its vector with A on the stack
+irq_dispatch:
+        pha
+        lda IRQST
+        and #$40
+        bne id_not_key
+        lda #$BF
+        jsr irq_ack
+        jmp (VKEYBD)
+id_not_key:
+        lda IRQST
+        and #$80
+        bne id_not_break
+        lda #$7F
+        jsr irq_ack
+        lda #0
+        sta BRKKEY
+        pla
+        rti
+id_not_break:
+        lda IRQST
+        and #$20
+        bne id_not_serin
+        lda #$DF
+        jsr irq_ack
+        jmp (VSERIN)
+id_not_serin:
+        lda IRQST
+        and #$10
+        bne id_not_seror
+        lda #$EF
+        jsr irq_ack
+        jmp (VSEROR)
+id_not_seror:
+        lda POKMSK
+        and #$08
+        beq id_not_seroc
+        lda IRQST
+        and #$08
+        bne id_not_seroc
+        jmp (VSEROC)
+id_not_seroc:
+        lda IRQST
+        and #$01
+        bne id_not_timer1
+        lda #$FE
+        jsr irq_ack
+        jmp (VTIMR1)
+id_not_timer1:
+        lda IRQST
+        and #$02
+        bne id_not_timer2
+        lda #$FD
+        jsr irq_ack
+        jmp (VTIMR2)
+id_not_timer2:
+        lda IRQST
+        and #$04
+        bne id_not_timer4
+        lda #$FB
+        jsr irq_ack
+        jmp (VTIMR4)
+id_not_timer4:
+        ; BRK instruction: B flag set in the pushed status
+        txa
+        pha
+        tsx
+        lda $0103,x
+        and #$10
+        beq id_other
+        pla
+        tax
+        jmp (VBREAK)
+id_other:
+        pla
+        tax
+        pla
+        rti
+
+; Acknowledge a POKEY interrupt. A = mask with its bit clear.
+irq_ack:
+        and POKMSK
+        sta IRQEN
+        lda POKMSK
+        sta IRQEN
+        rts
+
+key_irq:
+        lda KBCODE
+        sta CH
+        lda #0
+        sta ATRACT
+        pla
+        rti
+
+; ---- SIO ----
+
+; Serial I/O for the request in the device control block. The command
+; frame goes out with the command line (PIA CB2) held low. Once the device
+; has acknowledged it, a data frame is sent if DSTATS bit 7 is set and
+; must be acknowledged too. The device then reports COMPLETE or ERROR and,
+; if DSTATS bit 6 is set, sends its data frame. POKEY is polled with IRQs
+; masked and CRITIC set. Returns the status in Y and DSTATS, with N set
+; on an error.
+sio:
+        php
+        sei
+        lda #1
+        sta CRITIC
+        lda POKMSK              ; let POKEY flag serial events
+        ora #$38
+        sta POKMSK
+        sta IRQEN
+        lda #$28                ; channels 3+4 joined at 1.79MHz
+        sta AUDCTL
+        sta AUDF3               ; 19200 baud
+        lda #0
+        sta AUDF4
+        lda SSKCTL
+        and #$07
+        ora #$10                ; asynchronous receive
+        sta SKCTL
+        sta SKRES
+
+        ; Device ID = DDEVIC + DUNIT - 1
+        lda DDEVIC
+        clc
+        adc DUNIT
+        sec
+        sbc #1
+        sta CDEVIC
+        lda DCOMND
+        sta CCOMND
+        lda DAUX1
+        sta CAUX1
+        lda DAUX2
+        sta CAUX2
+        lda #4
+        sta CRETRY
+sio_command:
+        lda #1
+        sta STATUS
+        lda #<CDEVIC
+        sta BUFRLO
+        lda #>CDEVIC
+        sta BUFRHI
+        lda #4
+        sta BFENLO
+        lda #0
+        sta BFENHI
+        lda #$34                ; command line low
+        sta PBCTL
+        jsr send_frame
+        lda #$3C
+        sta PBCTL
+        jsr get_ack
+        bcc sio_acked
+        dec CRETRY
+        bne sio_command
+        beq sio_done
+
+sio_acked:
+        bit DSTATS
+        bpl sio_complete
+        jsr dcb_buffer
+        jsr send_frame
+        jsr get_ack
+        bcs sio_done
+
+sio_complete:
+        ; DTIMLO counts 64 frames
+        lda #0
+        sta SIOTIM
+        lda DTIMLO
+        lsr a
+        ror SIOTIM
+        lsr a
+        ror SIOTIM
+        sta SIOTIM+1
+        jsr get_byte
+        bcs sio_done
+        cmp #'C'
+        beq sio_data
+        cmp #'E'
+        bne sio_nak
+        lda #$90                ; device error, but the data still comes
+        sta STATUS
+sio_data:
+        bit DSTATS
+        bvc sio_done
+        jsr dcb_buffer
+        jsr get_frame
+        jmp sio_done
+sio_nak:
+        lda #$8B
+        sta STATUS
+
+sio_done:
+        lda POKMSK
+        and #$C7
+        sta POKMSK
+        sta IRQEN
+        lda #0
+        sta CRITIC
+        plp
+        ldy STATUS
+        sty DSTATS
+        rts
+
+; Point the frame at the DCB's buffer
+dcb_buffer:
+        lda DBUFLO
+        sta BUFRLO
+        lda DBUFHI
+        sta BUFRHI
+        lda DBYTLO
+        sta BFENLO
+        lda DBYTHI
+        sta BFENHI
+        rts
+
+; Send the frame and its checksum, and wait for the last bit to go out
+send_frame:
+        lda #0
+        sta CHKSUM
+sf_byte:
+        ldy #0
+        lda (BUFRLO),y
+        jsr put_byte
+        jsr next_byte
+        bne sf_byte
+        lda CHKSUM
+        jsr put_byte
+sf_wait:
+        lda IRQST               ; bit 3 low: output complete
+        and #$08
+        bne sf_wait
+        rts
+
+; Send A, adding it to the checksum. Returns once POKEY can take the next
+; byte.
+put_byte:
+        sta SEROUT
+        jsr add_checksum
+pb_wait:
+        lda IRQST
+        and #$10
+        bne pb_wait
+        lda #$EF
+        jmp irq_ack
+
+; Receive a frame into the buffer and check its checksum. STATUS is set
+; on a timeout or a bad checksum.
+get_frame:
+        lda #0
+        sta CHKSUM
+gf_byte:
+        jsr byte_timeout
+        jsr get_byte
+        bcs gf_done
+        ldy #0
+        sta (BUFRLO),y
+        jsr add_checksum
+        jsr next_byte
+        bne gf_byte
+        jsr byte_timeout
+        jsr get_byte
+        bcs gf_done
+        cmp CHKSUM
+        beq gf_done
+        lda #$8F
+        sta STATUS
+gf_done:
+        rts
+
+; Wait for ACK. Carry set, with STATUS set, on a NAK or a timeout.
+get_ack:
+        jsr byte_timeout
+        jsr get_byte
+        bcs ga_done
+        cmp #'A'
+        clc
+        beq ga_done
+        lda #$8B
+        sta STATUS
+        sec
+ga_done:
+        rts
+
+; Allow one or two frames for the next byte
+byte_timeout:
+        lda #2
+        sta SIOTIM
+        lda #0
+        sta SIOTIM+1
+        rts
+
+; Wait for a byte, counting SIOTIM down each frame. Returns it in A with
+; carry clear, or carry set and STATUS = $8A on a timeout.
+get_byte:
+        lda RTCLOK+2
+        sta SIOCLK
+gb_poll:
+        lda IRQST
+        and #$20
+        beq gb_ready
+        lda RTCLOK+2
+        cmp SIOCLK
+        beq gb_poll
+        sta SIOCLK
+        lda SIOTIM
+        bne gb_count
+        dec SIOTIM+1
+gb_count:
+        dec SIOTIM
+        lda SIOTIM
+        ora SIOTIM+1
+        bne gb_poll
+        lda #$8A
+        sta STATUS
+        sec
+        rts
+gb_ready:
+        lda #$DF
+        jsr irq_ack
+        lda SERIN
+        clc
+        rts
+
+; Add A to CHKSUM, wrapping the carry round
+add_checksum:
+        clc
+        adc CHKSUM
+        adc #0
+        sta CHKSUM
+        rts
+
+; Move the frame pointer on and count a byte off. Z set at the end of the
+; frame.
+next_byte:
+        inc BUFRLO
+        bne nb_count
+        inc BUFRHI
+nb_count:
+        lda BFENLO
+        bne nb_low
+        dec BFENHI
+nb_low:
+        dec BFENLO
+        lda BFENLO
+        ora BFENHI
+        rts
+
+; Disk I/O through the DCB: sector DAUX1/2 of drive DUNIT with DCOMND
+; 'R', 'W', 'P', 'S', '!' or '"'. Single density sectors only.
+disk_io:
+        lda #$31
+        sta DDEVIC
+        lda #$07
+        sta DTIMLO
+        lda #128
+        sta DBYTLO
+        lda #0
+        sta DBYTHI
+        ldx #$40                ; data from the drive
+        lda DCOMND
+        cmp #'S'
+        bne di_data
+        lda #4
+        sta DBYTLO
+        bne di_go
+di_data:
+        cmp #'W'
+        beq di_write
+        cmp #'P'
+        bne di_go
+di_write:
+        ldx #$80                ; data to the drive
+di_go:
+        stx DSTATS
+        jsr siov
+        ldy DSTATS
+        rts
+
+; ---- P: printer ----
+
+; PUT collects a line in PRNBUF, which goes to the printer (SIO device
+; $40) 40 bytes at a time with command 'W'. After an EOL the rest of the
+; line is padded with spaces.
+
+pr_open:
+        lda #0
+        sta PBPNT
+pr_status:
+        lda #'S'
+        sta DCOMND
+        lda #<DVSTAT
+        sta DBUFLO
+        lda #>DVSTAT
+        sta DBUFHI
+        lda #4
+        ldx #$40                ; data from the printer
+        bne pr_sio
+
+pr_put:
+        ldx PBPNT
+        sta PRNBUF,x
+        inx
+        stx PBPNT
+        cmp #EOL
+        beq pr_line
+        cpx #40
+        beq pr_line
+        ldy #1
+        rts
+
+; Print anything left in the buffer
+pr_close:
+        ldy #1
+        lda PBPNT
+        bne pr_line
+        rts
+
+pr_line:
+        ldx PBPNT
+        lda #' '
+pl_pad:
+        cpx #40
+        beq pl_send
+        sta PRNBUF,x
+        inx
+        bne pl_pad
+pl_send:
+        lda #0
+        sta PBPNT
+        lda #'W'
+        sta DCOMND
+        lda #<PRNBUF
+        sta DBUFLO
+        lda #>PRNBUF
+        sta DBUFHI
+        lda #40
+        ldx #$80                ; data to the printer
+
+; SIO to the printer with DCOMND and DBUF set up, A = the byte count and
+; X = the direction
+pr_sio:
+        sta DBYTLO
+        stx DSTATS
+        lda #$40
+        sta DDEVIC
+        lda #1
+        sta DUNIT
+        lda #0
+        sta DBYTHI
+        sta DAUX2
+        lda #'N'                ; normal width
+        sta DAUX1
+        lda #$07
+        sta DTIMLO
+        jsr siov
+        ldy DSTATS
+        rts
+
+; ---- Disk boot ----
+
+; Boot from D1: if a disk answers. Sector 1 starts with a flags byte, the
+; number of sectors to load, the load address and the init address. The
+; sectors are loaded there and the code 6 bytes in is called. If it
+; returns with carry clear the disk has booted, and DOSINI is called.
+boot_disk:
+        lda #1
+        sta DUNIT
+        sta DAUX1
+        lda #0
+        sta DAUX2
+        lda #'R'
+        sta DCOMND
+        lda #<BOOTBF
+        sta DBUFLO
+        lda #>BOOTBF
+        sta DBUFHI
+        jsr dskinv
+        bmi bd_done
+
+        lda BOOTBF+1
+        sta DBSECT
+        lda BOOTBF+2
+        sta BOOTAD
+        sta RAMPTR
+        sta DBUFLO
+        lda BOOTBF+3
+        sta BOOTAD+1
+        sta RAMPTR+1
+        sta DBUFHI
+        lda BOOTBF+4
+        sta DOSINI
+        lda BOOTBF+5
+        sta DOSINI+1
+        ldy #127
+bd_copy:
+        lda BOOTBF,y
+        sta (RAMPTR),y
+        dey
+        bpl bd_copy
+
+bd_sector:
+        dec DBSECT
+        beq bd_run
+        clc
+        lda DBUFLO
+        adc #128
+        sta DBUFLO
+        bcc bd_read
+        inc DBUFHI
+bd_read:
+        inc DAUX1
+        jsr dskinv
+        bpl bd_sector
+        bmi bd_failed
+
+bd_run:
+        jsr run_boot
+        bcs bd_failed
+        lda BOOTQ
+        ora #$01
+        sta BOOTQ
+        jmp call_dosini
+bd_failed:
+        lda #<rts_only
+        sta DOSINI
+        lda #>rts_only
+        sta DOSINI+1
+bd_done:
+        rts
+
+run_boot:
+        clc
+        lda BOOTAD
+        adc #6
+        sta RAMPTR
+        lda BOOTAD+1
+        adc #0
+        sta RAMPTR+1
+        jmp (RAMPTR)
+
+; ---- CIO ----
+
+cio_init:
+        ldx #0
+ci_loop:
+        lda #$FF
+        sta ICHID,x
+        txa
+        clc
+        adc #$10
+        tax
+        cmp #$80
+        bne ci_loop
+        rts
+
+; X = IOCB number * 16. Returns Y = status (N set on error) and A = the
+; byte read for single byte transfers.
+cio:
+        sta CIOCHR
+        stx ICIDNO
+        txa
+        and #$8F
+        beq cio_valid
+        lda CIOCHR
+        ldy #$86                ; bad IOCB number
+        rts
+cio_valid:
+        ldy #0
+cio_copy:
+        lda ICHID,x
+        sta ICHIDZ,y
+        inx
+        iny
+        cpy #12
+        bne cio_copy
+        lda #0
+        sta ICSPRZ
+        sta ICSPRZ+1
+
+        lda ICCOMZ
+        cmp #3
+        beq cio_open
+        ldy ICHIDZ
+        cpy #$FF
+        bne cio_is_open
+        ldy #1                  ; closing a closed IOCB is fine
+        cmp #12
+        beq cio_done_jmp
+        cmp #14
+        bcs cio_special_closed
+        ldy #$85                ; not open
+cio_done_jmp:
+        jmp cio_done
+cio_is_open:
+        cmp #12
+        beq cio_close
+        cmp #13
+        beq cio_status
+        bcs cio_special
+        cmp #5
+        bcc cio_bad_command
+        cmp #9
+        bcs cio_put_jmp
+        jmp cio_get
+cio_put_jmp:
+        jmp cio_put
+cio_bad_command:
+        ldy #$84
+        jmp cio_done
+
+cio_close:
+        ldy #2
+        jsr call_handler
+        lda #$FF
+        sta ICHIDZ
+        jmp cio_done
+
+cio_status:
+        ldy #8
+        jsr call_handler
+        jmp cio_done
+
+cio_special:
+        ldy #10
+        jsr call_handler
+        jmp cio_done
+
+; Special commands on a closed IOCB (rename, delete and so on) go to the
+; device named in the buffer, and the IOCB stays closed
+cio_special_closed:
+        jsr find_device
+        bcs cio_done_jmp
+        ldy #10
+        jsr call_handler
+        lda #$FF
+        sta ICHIDZ
+        jmp cio_done
+
+cio_open:
+        ldy #$81                ; already open
+        lda ICHIDZ
+        cmp #$FF
+        bne cio_done_jmp
+
+        jsr find_device
+        bcs cio_done_jmp
+        ldy #0
+        jsr call_handler
+        cpy #$80
+        bcc cio_done_jmp
+        lda #$FF                ; failed, leave it closed
+        sta ICHIDZ
+        bne cio_done_jmp
+
+; GET RECORD (5) and GET CHARACTERS (7)
+cio_get:
+        lda ICBLLZ
+        ora ICBLHZ
+        bne cg_loop
+        ldy #4                  ; no buffer: one byte in A
+        jsr call_handler
+        sta CIOCHR
+        jmp cio_done
+cg_loop:
+        jsr buffer_full
+        bcc cg_more
+        lda ICCOMZ
+        cmp #7
+        bcs cio_success
+cg_skip:
+        ldy #4                  ; record too long: skip to its end
+        jsr call_handler
+        cpy #$80
+        bcs cio_done
+        cmp #EOL
+        bne cg_skip
+        ldy #$89                ; truncated record
+        bne cio_done
+cg_more:
+        ldy #4
+        jsr call_handler
+        cpy #$80
+        bcs cio_done
+        ldy #0
+        sta (ICBALZ),y
+        jsr advance
+        ldy ICCOMZ
+        cpy #7
+        bcs cg_loop
+        cmp #EOL
+        bne cg_loop
+cio_success:
+        ldy #1
+
+cio_done:
+        sty ICSTAZ
+        ldx ICIDNO
+        lda ICHIDZ
+        sta ICHID,x
+        lda ICDNOZ
+        sta ICDNO,x
+        lda ICSTAZ
+        sta ICSTA,x
+        lda ICCOMZ
+        cmp #5
+        bcc cd_no_count
+        cmp #12
+        bcs cd_no_count
+        lda ICSPRZ
+        sta ICBLL,x
+        lda ICSPRZ+1
+        sta ICBLH,x
+cd_no_count:
+        lda CIOCHR
+        ldy ICSTAZ
+        rts
+
+; PUT RECORD (9) and PUT CHARACTERS (11)
+cio_put:
+        lda ICBLLZ
+        ora ICBLHZ
+        bne cp_loop
+        ldy #6                  ; no buffer: send the byte in A
+        jsr call_handler
+        jmp cio_done
+cp_loop:
+        jsr buffer_full
+        bcc cp_more
+        lda ICCOMZ
+        cmp #11
+        bcs cio_success
+        lda #EOL                ; record without an EOL: end it anyway
+        sta CIOCHR
+        ldy #6
+        jsr call_handler
+        jmp cio_done
+cp_more:
+        ldy #0
+        lda (ICBALZ),y
+        sta CIOCHR
+        ldy #6
+        jsr call_handler
+        cpy #$80
+        bcs cio_done
+        jsr advance
+        lda ICCOMZ
+        cmp #11
+        bcs cp_loop
+        lda CIOCHR
+        cmp #EOL
+        bne cp_loop
+        beq cio_success
+
+; C set once the transfer count has reached the buffer length
+buffer_full:
+        lda ICSPRZ+1
+        cmp ICBLHZ
+        bne bf_done
+        lda ICSPRZ
+        cmp ICBLLZ
+bf_done:
+        rts
+
+; Step the buffer pointer and transfer count, keeping A
+advance:
+        inc ICBALZ
+        bne adv_count
+        inc ICBAHZ
+adv_count:
+        inc ICSPRZ
+        bne adv_done
+        inc ICSPRZ+1
+adv_done:
+        rts
+
+; Look up the device named in the buffer and set ICHIDZ and ICDNOZ.
+; Returns C set and Y = $82 if there is no such device.
+find_device:
+        ; Search HATABS from the end, so later entries win
+        ldy #0
+        lda (ICBALZ),y
+        ldx #33
+fd_find:
+        cmp HATABS,x
+        beq fd_found
+        dex
+        dex
+        dex
+        bpl fd_find
+        ldy #$82                ; no such device
+        sec
+        rts
+fd_found:
+        stx ICHIDZ
+
+        ; Unit number after the letter, default 1
+        ldy #1
+        lda (ICBALZ),y
+        sec
+        sbc #'1'
+        cmp #9
+        bcc fd_unit
+        lda #0
+fd_unit:
+        clc
+        adc #1
+        sta ICDNOZ
+        clc
+        rts
+
+; Enter the handler routine at offset Y in the device's table, with
+; A = CIOCHR and X = the IOCB
+call_handler:
+        ldx ICHIDZ
+        lda HATABS+1,x
+        sta HANDVEC
+        lda HATABS+2,x
+        sta HANDVEC+1
+        lda (HANDVEC),y
+        tax
+        iny
+        lda (HANDVEC),y
+        pha
+        txa
+        pha
+        lda CIOCHR
+        ldx ICIDNO
+        rts
+
+; ---- K: keyboard ----
+
+; Wait for a key and return its ATASCII code, or status $80 for BREAK
+get_key:
+        lda BRKKEY
+        bne gk_wait
+        lda #$FF
+        sta BRKKEY
+        ldy #$80
+        rts
+gk_wait:
+        lda CH
+        cmp #$FF
+        beq get_key
+        tay
+        lda #$FF
+        sta CH
+
+        tya
+        and #$3F
+        cmp #$3C                ; CAPS: lower case, Shift locks caps,
+        bne gk_not_caps         ; Control locks control
+        tya
+        and #$C0
+        sta SHFLOK
+        jmp get_key
+gk_not_caps:
+        cmp #$27                ; Atari key toggles inverse video
+        bne gk_not_inverse
+        lda INVFLG
+        eor #$80
+        sta INVFLG
+        jmp get_key
+gk_not_inverse:
+        cpy #$C0                ; Shift+Control combinations
+        bcs get_key
+        lda keymap,y
+        cmp #$FF
+        beq get_key
+
+        cmp #'a'
+        bcc gk_inverse
+        cmp #'z'+1
+        bcs gk_inverse
+        ldx SHFLOK
+        beq gk_inverse
+        cpx #$40
+        bne gk_control
+        and #$DF
+        jmp gk_inverse
+gk_control:
+        and #$1F
+gk_inverse:
+        ldx INVFLG
+        beq gk_done
+        cmp #$20
+        bcc gk_done
+        cmp #$7D
+        bcs gk_done
+        eor #$80
+gk_done:
+        ldy #1
+        rts
+
+; ---- E: screen editor ----
+
+; Text mode 0 screen just below RAMTOP: display list at RAMTOP-$3E0,
+; screen memory from RAMTOP-$3C0
+ed_open:
+        lda RAMTOP
+        sec
+        sbc #4
+        sta SAVMSC+1
+        sta SDLSTH
+        sta MEMTOP+1
+        sta TOADR+1
+        lda #$40
+        sta SAVMSC
+        lda #$20
+        sta SDLSTL
+        sta TOADR
+        lda #$1F
+        sta MEMTOP
+
+        ldy #0
+        lda #$70                ; 24 blank lines
+        sta (TOADR),y
+        iny
+        sta (TOADR),y
+        iny
+        sta (TOADR),y
+        iny
+        lda #$42                ; mode 2 with LMS
+        sta (TOADR),y
+        iny
+        lda SAVMSC
+        sta (TOADR),y
+        iny
+        lda SAVMSC+1
+        sta (TOADR),y
+        iny
+        lda #$02
+eo_lines:
+        sta (TOADR),y
+        iny
+        cpy #29
+        bne eo_lines
+        lda #$41                ; JVB
+        sta (TOADR),y
+        iny
+        lda SDLSTL
+        sta (TOADR),y
+        iny
+        lda SDLSTH
+        sta (TOADR),y
+
+        lda #0
+        sta OLDADR+1
+        jsr clear_screen
+        lda #$22
+        sta SDMCTL
+        ldy #1
+        rts
+
+ed_put:
+        sta ATACHR
+        jsr hide_cursor
+        jsr put_char
+        jsr show_cursor
+        ldy #1
+        rts
+
+; Read a line typed at the keyboard, then return it a byte at a time
+ed_get:
+        lda BUFCNT
+        bne eg_next
+eg_key:
+        jsr get_key
+        cpy #$80
+        bcs eg_exit
+        cmp #EOL
+        beq eg_return
+        sta ATACHR
+        jsr hide_cursor
+        jsr put_char
+        jsr show_cursor
+        jmp eg_key
+eg_return:
+        jsr hide_cursor
+        jsr row_addr
+        ldy RMARGN
+eg_end:
+        lda (ADRESS),y
+        bne eg_found
+        dey
+        cpy LMARGN
+        bcs eg_end
+eg_found:
+        iny
+        sty BUFSTR
+        ldx #0
+        ldy LMARGN
+eg_copy:
+        cpy BUFSTR
+        bcs eg_copied
+        lda (ADRESS),y
+        jsr int_to_ata
+        sta LINBUF,x
+        inx
+        iny
+        bne eg_copy
+eg_copied:
+        lda #EOL
+        sta LINBUF,x
+        inx
+        stx BUFCNT
+        lda #0
+        sta BUFSTR
+        lda #EOL
+        sta ATACHR
+        jsr put_char
+        jsr show_cursor
+eg_next:
+        ldx BUFSTR
+        lda LINBUF,x
+        inc BUFSTR
+        dec BUFCNT
+        ldy #1
+eg_exit:
+        rts
+
+; Display ATACHR or carry out the control code
+put_char:
+        lda ATACHR
+        ldx ESCFLG
+        beq pc_control
+        ldx #0
+        stx ESCFLG
+        jmp pc_print
+pc_control:
+        cmp #EOL
+        beq newline
+        cmp #$1B
+        bne pc_not_escape
+        sta ESCFLG
+        rts
+pc_not_escape:
+        cmp #$7D
+        bne pc_not_clear
+        jmp clear_screen
+pc_not_clear:
+        cmp #$7E
+        bne pc_not_backspace
+        jmp backspace
+pc_not_backspace:
+        cmp #$1C
+        beq cursor_up
+        cmp #$1D
+        beq cursor_down
+        cmp #$1E
+        beq cursor_left
+        cmp #$1F
+        beq cursor_right
+        cmp #$7F                ; tab and the other editing codes are
+        beq pc_done             ; ignored
+        cmp #$FD
+        bcs pc_done
+        cmp #$9C
+        bcc pc_print
+        cmp #$A0
+        bcc pc_done
+pc_print:
+        jsr ata_to_int
+        pha
+        jsr row_addr
+        pla
+        ldy COLCRS
+        sta (ADRESS),y
+        inc COLCRS
+        lda RMARGN
+        cmp COLCRS
+        bcs pc_done
+newline:
+        lda LMARGN
+        sta COLCRS
+        inc ROWCRS
+        lda ROWCRS
+        cmp #24
+        bcc pc_done
+        jsr scroll
+        lda #23
+        sta ROWCRS
+pc_done:
+        rts
+
+cursor_up:
+        dec ROWCRS
+        bpl cm_done
+        lda #23
+        sta ROWCRS
+cm_done:
+        rts
+
+cursor_down:
+        inc ROWCRS
+        lda ROWCRS
+        cmp #24
+        bcc cm_done
+        lda #0
+        sta ROWCRS
+        rts
+
+cursor_left:
+        lda COLCRS
+        cmp LMARGN
+        beq cl_wrap
+        dec COLCRS
+        rts
+cl_wrap:
+        lda RMARGN
+        sta COLCRS
+        rts
+
+cursor_right:
+        lda COLCRS
+        cmp RMARGN
+        bcs cr_wrap
+        inc COLCRS
+        rts
+cr_wrap:
+        lda LMARGN
+        sta COLCRS
+        rts
+
+backspace:
+        lda COLCRS
+        cmp LMARGN
+        beq bs_done
+        bcc bs_done
+        dec COLCRS
+        jsr row_addr
+        ldy COLCRS
+        lda #0
+        sta (ADRESS),y
+bs_done:
+        rts
+
+clear_screen:
+        lda SAVMSC
+        sta TOADR
+        lda SAVMSC+1
+        sta TOADR+1
+        lda #0
+        ldx #3
+cls_page:
+        ldy #0
+cls_byte:
+        sta (TOADR),y
+        iny
+        bne cls_byte
+        inc TOADR+1
+        dex
+        bne cls_page
+        ldy #191                ; 960 - 3 * 256 bytes left
+cls_tail:
+        sta (TOADR),y
+        dey
+        bne cls_tail
+        sta (TOADR),y
+        sta ROWCRS
+        sta COLCRS+1
+        lda LMARGN
+        sta COLCRS
+        rts
+
+; Move lines 1-23 up one and blank the bottom line
+scroll:
+        lda SAVMSC
+        sta TOADR
+        clc
+        adc #40
+        sta FRMADR
+        lda SAVMSC+1
+        sta TOADR+1
+        adc #0
+        sta FRMADR+1
+        ldx #23
+sc_line:
+        ldy #39
+sc_byte:
+        lda (FRMADR),y
+        sta (TOADR),y
+        dey
+        bpl sc_byte
+        lda FRMADR
+        sta TOADR
+        lda FRMADR+1
+        sta TOADR+1
+        lda FRMADR
+        clc
+        adc #40
+        sta FRMADR
+        bcc sc_next
+        inc FRMADR+1
+sc_next:
+        dex
+        bne sc_line
+        ldy #39
+        lda #0
+sc_clear:
+        sta (TOADR),y
+        dey
+        bpl sc_clear
+        rts
+
+; ADRESS = start of cursor row in screen memory (SAVMSC + ROWCRS * 40)
+row_addr:
+        lda #0
+        sta ADRESS+1
+        lda ROWCRS
+        asl a
+        asl a
+        clc
+        adc ROWCRS
+        asl a
+        asl a
+        rol ADRESS+1
+        asl a
+        rol ADRESS+1
+        clc
+        adc SAVMSC
+        sta ADRESS
+        lda ADRESS+1
+        adc SAVMSC+1
+        sta ADRESS+1
+        rts
+
+; The cursor is the character under it shown in inverse video
+show_cursor:
+        lda CRSINH
+        bne sh_done
+        jsr row_addr
+        lda ADRESS
+        clc
+        adc COLCRS
+        sta OLDADR
+        lda ADRESS+1
+        adc #0
+        sta OLDADR+1
+        ldy #0
+        lda (OLDADR),y
+        sta OLDCHR
+        eor #$80
+        sta (OLDADR),y
+sh_done:
+        rts
+
+hide_cursor:
+        lda OLDADR+1
+        beq hd_done
+        ldy #0
+        lda OLDCHR
+        sta (OLDADR),y
+        sty OLDADR+1
+hd_done:
+        rts
+
+; ATASCII to screen code, keeping the inverse bit
+ata_to_int:
+        pha
+        and #$80
+        sta TMPCHR
+        pla
+        and #$7F
+        cmp #$60
+        bcs ai_done
+        cmp #$20
+        bcc ai_control
+        sbc #$20
+        bcs ai_done
+ai_control:
+        adc #$40
+ai_done:
+        ora TMPCHR
+        rts
+
+; Screen code to ATASCII, keeping the inverse bit
+int_to_ata:
+        pha
+        and #$80
+        sta TMPCHR
+        pla
+        and #$7F
+        cmp #$60
+        bcs ia_done
+        cmp #$40
+        bcc ia_low
+        sbc #$40
+        bcs ia_done
+ia_low:
+        adc #$20
+ia_done:
+        ora TMPCHR
+        rts
+
+; ---- Memo pad ----
+
+; Where the machine ends up with nothing to boot: the editor, taking
+; lines and doing nothing with them
+memo_pad:
+        ldx #0
+        lda #9
+        sta ICCOM
+        lda #<banner
+        sta ICBAL
+        lda #>banner
+        sta ICBAH
+        lda #40
+        sta ICBLL
+        lda #0
+        sta ICBLH
+        jsr ciov
+mp_loop:
+        ldx #0
+        lda #5
+        sta ICCOM
+        lda #<LBUFF
+        sta ICBAL
+        lda #>LBUFF
+        sta ICBAH
+        lda #120
+        sta ICBLL
+        lda #0
+        sta ICBLH
+        jsr ciov
+        jmp mp_loop
+
+; ==== 6502 vectors ====
+
+        .org $FFFA
+        .word nmi, cold_start, irq
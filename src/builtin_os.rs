@@ -0,0 +1,30 @@
+use std::sync::OnceLock;
+
+use crate::asm::{self, AsmError};
+
+const OS_SOURCE: &str = include_str!("builtin_os.asm");
+const FONT_SOURCE: &str = include_str!("builtin_font.asm");
+
+/// The replacement OS, assembled for the 400/800 (10K, $D800-$FFFF) or
+/// for XL/XE machines (16K, $C000-$FFFF). Either can be loaded in place of
+/// the matching Atari ROM. Each is only assembled the first time it's
+/// asked for.
+pub fn os_rom(xl: bool) -> Vec<u8> {
+    static OS_800: OnceLock<Vec<u8>> = OnceLock::new();
+    static OS_XL: OnceLock<Vec<u8>> = OnceLock::new();
+    let cache = if xl { &OS_XL } else { &OS_800 };
+    cache
+        .get_or_init(|| assemble(xl).unwrap_or_else(|e| panic!("built-in OS doesn't assemble: {}", e)))
+        .clone()
+}
+
+/// Assemble the replacement OS from its source
+pub fn assemble(xl: bool) -> Result<Vec<u8>, AsmError> {
+    let source = format!("{}\n{}", OS_SOURCE, FONT_SOURCE);
+    let assembly = asm::assemble(&source, &[("XL", xl as i64)])?;
+    Ok(if xl {
+        assembly.image(0xC000, 0x4000)
+    } else {
+        assembly.image(0xD800, 0x2800)
+    })
+}
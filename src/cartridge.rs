@@ -0,0 +1,402 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Size of a .CAR file header
+const CAR_HEADER_SIZE: usize = 16;
+
+/// Cartridge hardware types, numbered as in the .CAR header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CartType {
+    Std8k,              // 1
+    Std16k,             // 2
+    Oss034m16k,         // 3
+    Williams64k,        // 8
+    Sdx64k,             // 11
+    Xegs32k,            // 12
+    Xegs64k,            // 13
+    Xegs128k,           // 14
+    Oss091m16k,         // 15
+    Right8k,            // 21
+    Williams32k,        // 22
+    Xegs256k,           // 23
+    Xegs512k,           // 24
+    Xegs1024k,          // 25
+    Mega16k,            // 26
+    Mega32k,            // 27
+    Mega64k,            // 28
+    Mega128k,           // 29
+    Mega256k,           // 30
+    Mega512k,           // 31
+    Mega1024k,          // 32
+    SwXegs32k,          // 33
+    SwXegs64k,          // 34
+    SwXegs128k,         // 35
+    SwXegs256k,         // 36
+    SwXegs512k,         // 37
+    SwXegs1024k,        // 38
+    AtariMax128k,       // 41
+    AtariMax1024k,      // 42
+    Sdx128k,            // 43
+}
+
+impl CartType {
+    /// Look up a .CAR header type number
+    pub fn from_car_type(num: u32) -> Option<CartType> {
+        let cart_type = match num {
+            1 => CartType::Std8k,
+            2 => CartType::Std16k,
+            3 => CartType::Oss034m16k,
+            8 => CartType::Williams64k,
+            11 => CartType::Sdx64k,
+            12 => CartType::Xegs32k,
+            13 => CartType::Xegs64k,
+            14 => CartType::Xegs128k,
+            15 => CartType::Oss091m16k,
+            21 => CartType::Right8k,
+            22 => CartType::Williams32k,
+            23 => CartType::Xegs256k,
+            24 => CartType::Xegs512k,
+            25 => CartType::Xegs1024k,
+            26 => CartType::Mega16k,
+            27 => CartType::Mega32k,
+            28 => CartType::Mega64k,
+            29 => CartType::Mega128k,
+            30 => CartType::Mega256k,
+            31 => CartType::Mega512k,
+            32 => CartType::Mega1024k,
+            33 => CartType::SwXegs32k,
+            34 => CartType::SwXegs64k,
+            35 => CartType::SwXegs128k,
+            36 => CartType::SwXegs256k,
+            37 => CartType::SwXegs512k,
+            38 => CartType::SwXegs1024k,
+            41 => CartType::AtariMax128k,
+            42 => CartType::AtariMax1024k,
+            43 => CartType::Sdx128k,
+            _ => return None,
+        };
+        Some(cart_type)
+    }
+
+    /// Guess the type of a raw .ROM/.BIN image from its size. Ambiguous
+    /// sizes of 32K and up are assumed to be XEGS carts.
+    pub fn from_size(size: usize) -> Option<CartType> {
+        let cart_type = match size {
+            0x2000 => CartType::Std8k,
+            0x4000 => CartType::Std16k,
+            0x8000 => CartType::Xegs32k,
+            0x10000 => CartType::Xegs64k,
+            0x20000 => CartType::Xegs128k,
+            0x40000 => CartType::Xegs256k,
+            0x80000 => CartType::Xegs512k,
+            0x100000 => CartType::Xegs1024k,
+            _ => return None,
+        };
+        Some(cart_type)
+    }
+
+    /// Image size in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            CartType::Std8k | CartType::Right8k => 0x2000,
+            CartType::Std16k | CartType::Oss034m16k | CartType::Oss091m16k
+            | CartType::Mega16k => 0x4000,
+            CartType::Williams32k | CartType::Xegs32k | CartType::SwXegs32k
+            | CartType::Mega32k => 0x8000,
+            CartType::Williams64k | CartType::Sdx64k | CartType::Xegs64k
+            | CartType::SwXegs64k | CartType::Mega64k => 0x10000,
+            CartType::Xegs128k | CartType::SwXegs128k | CartType::Mega128k
+            | CartType::AtariMax128k | CartType::Sdx128k => 0x20000,
+            CartType::Xegs256k | CartType::SwXegs256k | CartType::Mega256k => 0x40000,
+            CartType::Xegs512k | CartType::SwXegs512k | CartType::Mega512k => 0x80000,
+            CartType::Xegs1024k | CartType::SwXegs1024k | CartType::Mega1024k
+            | CartType::AtariMax1024k => 0x100000,
+        }
+    }
+
+    /// True for carts that plug into the 800's right slot
+    pub fn is_right_slot(&self) -> bool {
+        *self == CartType::Right8k
+    }
+}
+
+/// Errors from loading a cartridge image
+#[derive(Debug)]
+pub enum CartError {
+    Io(io::Error),
+    UnknownType(u32),
+    UnknownSize(usize),
+    WrongSize { cart_type: CartType, expected: usize, actual: usize },
+    BadChecksum { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for CartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartError::Io(e) => write!(f, "{}", e),
+            CartError::UnknownType(num) => write!(f, "unsupported .CAR cartridge type {}", num),
+            CartError::UnknownSize(size) => {
+                write!(f, "can't detect cartridge type of a {} byte raw image", size)
+            }
+            CartError::WrongSize { cart_type, expected, actual } => write!(
+                f,
+                "{:?} cartridge should be {} bytes, image is {} bytes",
+                cart_type, expected, actual
+            ),
+            CartError::BadChecksum { expected, actual } => write!(
+                f,
+                ".CAR checksum mismatch: header says {:08X}, data sums to {:08X}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CartError {}
+
+impl From<io::Error> for CartError {
+    fn from(e: io::Error) -> CartError {
+        CartError::Io(e)
+    }
+}
+
+/// A cartridge image plus its bank-switching state.
+///
+/// Bank switching is driven by accesses to the $D500-$D5FF page (the CCTL
+/// area). The cart's current contents are described by `windows()`, which
+/// the machine uses to rebuild its memory map.
+#[derive(Clone)]
+pub struct Cartridge {
+    cart_type: CartType,
+    image: Vec<u8>,
+
+    // Selected bank, or None when the cart has switched itself off
+    bank: Option<usize>,
+}
+
+impl fmt::Debug for Cartridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cartridge")
+            .field("cart_type", &self.cart_type)
+            .field("size", &self.image.len())
+            .field("bank", &self.bank)
+            .finish()
+    }
+}
+
+impl Cartridge {
+    /// Build a cartridge of a known type from a raw image
+    pub fn new(cart_type: CartType, image: &[u8]) -> Result<Cartridge, CartError> {
+        if image.len() != cart_type.size() {
+            return Err(CartError::WrongSize {
+                cart_type,
+                expected: cart_type.size(),
+                actual: image.len(),
+            });
+        }
+
+        let mut cart = Cartridge {
+            cart_type,
+            image: image.to_vec(),
+            bank: None,
+        };
+        cart.reset();
+        Ok(cart)
+    }
+
+    /// Build a cartridge from a .CAR file or a raw image, detecting which
+    pub fn from_bytes(data: &[u8]) -> Result<Cartridge, CartError> {
+        if data.len() >= CAR_HEADER_SIZE && &data[0..4] == b"CART" {
+            let type_num = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+            let expected = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+            let cart_type = CartType::from_car_type(type_num)
+                .ok_or(CartError::UnknownType(type_num))?;
+
+            let image = &data[CAR_HEADER_SIZE..];
+            let actual = image.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+            if actual != expected {
+                return Err(CartError::BadChecksum { expected, actual });
+            }
+
+            Cartridge::new(cart_type, image)
+        } else {
+            let cart_type = CartType::from_size(data.len())
+                .ok_or(CartError::UnknownSize(data.len()))?;
+            Cartridge::new(cart_type, data)
+        }
+    }
+
+    /// Load a .CAR, .ROM or .BIN cartridge file
+    pub fn from_file(path: &str) -> Result<Cartridge, CartError> {
+        let mut buffer = Vec::new();
+        File::open(path)?.read_to_end(&mut buffer)?;
+        Cartridge::from_bytes(&buffer)
+    }
+
+    pub fn cart_type(&self) -> CartType {
+        self.cart_type
+    }
+
+    pub fn image(&self) -> &[u8] {
+        &self.image
+    }
+
+    fn bank_count(&self, bank_size: usize) -> usize {
+        self.image.len() / bank_size
+    }
+
+    /// Return to the power-on bank. Every supported board starts with
+    /// bank 0 selected and the cart enabled.
+    pub fn reset(&mut self) {
+        self.bank = Some(0);
+    }
+
+    /// Handle a read (val = None) or write (val = Some) in the $D500 page.
+    /// Returns true if the memory map needs to be rebuilt.
+    pub fn access_d5(&mut self, addr: u16, val: Option<u8>) -> bool {
+        let lo = (addr & 0xFF) as usize;
+        let old_bank = self.bank;
+
+        match self.cart_type {
+            CartType::Oss034m16k => {
+                // Reference: retrobits OSS cartridge notes
+                self.bank = if lo & 0x08 != 0 {
+                    None
+                } else {
+                    match lo & 0x07 {
+                        0x00 => Some(0),
+                        0x03 | 0x07 => Some(1),
+                        0x04 => Some(2),
+                        _ => None,
+                    }
+                };
+            }
+            CartType::Oss091m16k => {
+                self.bank = match lo & 0x09 {
+                    0x00 => Some(1),
+                    0x01 => Some(3),
+                    0x08 => None,
+                    _ => Some(2),
+                };
+            }
+            CartType::Williams32k | CartType::Williams64k => {
+                if lo & 0xF0 == 0 {
+                    self.bank = if lo & 0x08 != 0 {
+                        None
+                    } else {
+                        Some(lo & (self.bank_count(0x2000) - 1))
+                    };
+                }
+            }
+            CartType::Sdx64k => {
+                if lo & 0xF0 == 0xE0 {
+                    self.bank = if lo & 0x08 != 0 { None } else { Some(!lo & 0x07) };
+                }
+            }
+            CartType::Sdx128k => {
+                if lo & 0xE0 == 0xE0 {
+                    self.bank = if lo & 0x08 != 0 {
+                        None
+                    } else {
+                        Some((((lo & 0x10) ^ 0x10) >> 1) | (!lo & 0x07))
+                    };
+                }
+            }
+            CartType::AtariMax128k => {
+                if lo < 0x20 {
+                    self.bank = if lo & 0x10 != 0 { None } else { Some(lo & 0x0F) };
+                }
+            }
+            CartType::AtariMax1024k => {
+                self.bank = if lo & 0x80 != 0 { None } else { Some(lo & 0x7F) };
+            }
+            CartType::Xegs32k | CartType::Xegs64k | CartType::Xegs128k
+            | CartType::Xegs256k | CartType::Xegs512k | CartType::Xegs1024k => {
+                if let Some(v) = val {
+                    self.bank = Some(v as usize & (self.bank_count(0x2000) - 1));
+                }
+            }
+            CartType::SwXegs32k | CartType::SwXegs64k | CartType::SwXegs128k
+            | CartType::SwXegs256k | CartType::SwXegs512k | CartType::SwXegs1024k => {
+                if let Some(v) = val {
+                    self.bank = if v & 0x80 != 0 {
+                        None
+                    } else {
+                        Some(v as usize & (self.bank_count(0x2000) - 1))
+                    };
+                }
+            }
+            CartType::Mega16k | CartType::Mega32k | CartType::Mega64k
+            | CartType::Mega128k | CartType::Mega256k | CartType::Mega512k
+            | CartType::Mega1024k => {
+                if let Some(v) = val {
+                    self.bank = if v & 0x80 != 0 {
+                        None
+                    } else {
+                        Some(v as usize & (self.bank_count(0x4000) - 1))
+                    };
+                }
+            }
+            CartType::Std8k | CartType::Std16k | CartType::Right8k => {}
+        }
+
+        self.bank != old_bank
+    }
+
+    /// What the cart currently shows in each 4K window from $8000 to
+    /// $BFFF, as an offset into the image. None means the window is not
+    /// driven by the cart (RAM or nothing shows through).
+    pub fn windows(&self) -> [Option<usize>; 4] {
+        let bank = match self.bank {
+            Some(bank) => bank,
+            None => return [None; 4],
+        };
+
+        match self.cart_type {
+            CartType::Std8k => [None, None, Some(0x0000), Some(0x1000)],
+            CartType::Right8k => [Some(0x0000), Some(0x1000), None, None],
+            CartType::Std16k => [Some(0x0000), Some(0x1000), Some(0x2000), Some(0x3000)],
+            CartType::Oss034m16k => {
+                // Switchable 4K at $A000, last 4K fixed at $B000
+                [None, None, Some(bank * 0x1000), Some(0x3000)]
+            }
+            CartType::Oss091m16k => {
+                // Switchable 4K at $A000, first 4K fixed at $B000
+                [None, None, Some(bank * 0x1000), Some(0x0000)]
+            }
+            CartType::Williams32k | CartType::Williams64k | CartType::Sdx64k
+            | CartType::Sdx128k | CartType::AtariMax128k | CartType::AtariMax1024k => {
+                let base = bank * 0x2000;
+                [None, None, Some(base), Some(base + 0x1000)]
+            }
+            CartType::Xegs32k | CartType::Xegs64k | CartType::Xegs128k
+            | CartType::Xegs256k | CartType::Xegs512k | CartType::Xegs1024k
+            | CartType::SwXegs32k | CartType::SwXegs64k | CartType::SwXegs128k
+            | CartType::SwXegs256k | CartType::SwXegs512k | CartType::SwXegs1024k => {
+                // Switchable 8K at $8000, last bank fixed at $A000
+                let base = bank * 0x2000;
+                let last = self.image.len() - 0x2000;
+                [Some(base), Some(base + 0x1000), Some(last), Some(last + 0x1000)]
+            }
+            CartType::Mega16k | CartType::Mega32k | CartType::Mega64k
+            | CartType::Mega128k | CartType::Mega256k | CartType::Mega512k
+            | CartType::Mega1024k => {
+                let base = bank * 0x4000;
+                [Some(base), Some(base + 0x1000), Some(base + 0x2000), Some(base + 0x3000)]
+            }
+        }
+    }
+
+    /// RD4 line: cart is driving $8000-$9FFF
+    pub fn rd4(&self) -> bool {
+        let w = self.windows();
+        w[0].is_some() || w[1].is_some()
+    }
+
+    /// RD5 line: cart is driving $A000-$BFFF
+    pub fn rd5(&self) -> bool {
+        let w = self.windows();
+        w[2].is_some() || w[3].is_some()
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Chunk types
+const CHUNK_DESCRIPTION: &[u8; 4] = b"FUJI";
+const CHUNK_BAUD: &[u8; 4] = b"baud";
+const CHUNK_DATA: &[u8; 4] = b"data";
+
+/// Each chunk starts with its type, length and two aux bytes
+const CHUNK_HEADER_SIZE: usize = 8;
+
+/// The standard tape speed, for data before any baud chunk
+pub const DEFAULT_BAUD: u16 = 600;
+
+#[derive(Debug)]
+pub enum CasError {
+    Io(io::Error),
+    /// The file doesn't start with a "FUJI" chunk
+    NotCas,
+    /// A chunk runs past the end of the file; `offset` is where it starts
+    Truncated { offset: usize },
+}
+
+impl fmt::Display for CasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CasError::Io(e) => write!(f, "{}", e),
+            CasError::NotCas => write!(f, "not a CAS tape image"),
+            CasError::Truncated { offset } => {
+                write!(f, "file ends inside the chunk at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CasError {}
+
+impl From<io::Error> for CasError {
+    fn from(e: io::Error) -> CasError {
+        CasError::Io(e)
+    }
+}
+
+/// A record on the tape: the bytes and the blank stretch before them
+#[derive(Clone, Debug, PartialEq)]
+pub struct CasBlock {
+    pub baud: u16,
+
+    /// Inter-record gap before the block, in milliseconds
+    pub gap_ms: u16,
+
+    pub data: Vec<u8>,
+}
+
+/// A CAS tape image: the records on a cassette as the computer receives
+/// them, with the speed they were recorded at and the gaps between them.
+///
+/// The file is a list of chunks, each with a four-letter type, a 16-bit
+/// length and two aux bytes. "FUJI" comes first and holds a description;
+/// "baud" sets the speed (aux) of the records that follow; "data" is one
+/// record, with the gap before it in aux. Other chunk types are skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cas {
+    pub description: String,
+    pub blocks: Vec<CasBlock>,
+}
+
+impl Cas {
+    pub fn parse(image: &[u8]) -> Result<Cas, CasError> {
+        if image.len() < CHUNK_HEADER_SIZE || &image[0..4] != CHUNK_DESCRIPTION {
+            return Err(CasError::NotCas);
+        }
+
+        let mut cas = Cas::default();
+        let mut baud = DEFAULT_BAUD;
+        let mut offset = 0;
+        while offset < image.len() {
+            let header = image
+                .get(offset..offset + CHUNK_HEADER_SIZE)
+                .ok_or(CasError::Truncated { offset })?;
+            let len = u16::from_le_bytes([header[4], header[5]]) as usize;
+            let aux = u16::from_le_bytes([header[6], header[7]]);
+            let start = offset + CHUNK_HEADER_SIZE;
+            let body = image
+                .get(start..start + len)
+                .ok_or(CasError::Truncated { offset })?;
+
+            match &header[0..4] {
+                b"FUJI" => cas.description = String::from_utf8_lossy(body).into_owned(),
+                b"baud" => baud = aux,
+                b"data" => cas.blocks.push(CasBlock { baud, gap_ms: aux, data: body.to_vec() }),
+                _ => {}
+            }
+            offset = start + len;
+        }
+
+        Ok(cas)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Cas, CasError> {
+        Cas::parse(&fs::read(path)?)
+    }
+
+    /// The file image, with a baud chunk wherever the speed changes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut image = Vec::new();
+        push_chunk(&mut image, CHUNK_DESCRIPTION, 0, self.description.as_bytes());
+        let mut baud = None;
+        for block in &self.blocks {
+            if baud != Some(block.baud) {
+                push_chunk(&mut image, CHUNK_BAUD, block.baud, &[]);
+                baud = Some(block.baud);
+            }
+            push_chunk(&mut image, CHUNK_DATA, block.gap_ms, &block.data);
+        }
+        image
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CasError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
+
+fn push_chunk(image: &mut Vec<u8>, kind: &[u8; 4], aux: u16, body: &[u8]) {
+    image.extend_from_slice(kind);
+    image.extend_from_slice(&(body.len() as u16).to_le_bytes());
+    image.extend_from_slice(&aux.to_le_bytes());
+    image.extend_from_slice(body);
+}
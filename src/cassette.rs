@@ -0,0 +1,197 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cas::{Cas, CasBlock, CasError};
+
+/// Machine cycles per second and per millisecond (NTSC)
+const CYCLES_PER_SECOND: u64 = 1_789_790;
+const CYCLES_PER_MS: u64 = 1_790;
+
+/// Bits on the tape per byte: start, eight data bits, stop
+const BITS_PER_BYTE: u64 = 10;
+
+/// An Atari 410/1010 program recorder.
+///
+/// It sits on the serial connector but isn't addressed by SIO: the tape
+/// only moves while the PIA's CA2 motor line is low. Playing, it sends the
+/// tape's records to POKEY's serial input at the speed they were recorded
+/// at, leaving the recorded gap before each one. Recording, it takes the
+/// bytes POKEY sends while the motor runs and starts a new record after a
+/// pause or a change of speed. A tape recorded to a file is saved whenever
+/// the motor stops; if that fails, the reason waits in `take_write_error`.
+pub struct Cassette {
+    tape: Cas,
+    path: Option<PathBuf>,
+    recording: bool,
+    motor: bool,
+
+    // Playback position: the next byte, and the cycles of tape before it
+    // has come in
+    block: usize,
+    offset: usize,
+    wait: u64,
+
+    // Cycles the motor has run, and where the last recorded byte ended
+    position: u64,
+    last_end: u64,
+
+    // Recorded since the last save
+    changed: bool,
+
+    // Why the tape last failed to go to its file
+    write_error: Option<io::Error>,
+}
+
+impl Cassette {
+    /// A tape to play, from memory
+    pub fn new(tape: Cas) -> Cassette {
+        let mut cassette = Cassette {
+            tape,
+            path: None,
+            recording: false,
+            motor: false,
+            block: 0,
+            offset: 0,
+            wait: 0,
+            position: 0,
+            last_end: 0,
+            changed: false,
+            write_error: None,
+        };
+        cassette.rewind();
+        cassette
+    }
+
+    /// A tape to play, from a .CAS file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Cassette, CasError> {
+        Ok(Cassette::new(Cas::from_file(path)?))
+    }
+
+    /// A blank tape with PLAY and RECORD pressed
+    pub fn record() -> Cassette {
+        Cassette { recording: true, ..Cassette::new(Cas::default()) }
+    }
+
+    /// As `record`, saving the recording as a new .CAS file
+    pub fn record_to<P: AsRef<Path>>(path: P) -> Cassette {
+        Cassette { path: Some(path.as_ref().to_path_buf()), ..Cassette::record() }
+    }
+
+    pub fn tape(&self) -> &Cas {
+        &self.tape
+    }
+
+    /// The file a recording is saved to, if there is one
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Why saving the recording when the motor last stopped failed, if it
+    /// has since this was last asked. The next stop tries again.
+    pub fn take_write_error(&mut self) -> Option<io::Error> {
+        self.write_error.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Whether everything on the tape has been played
+    pub fn at_end(&self) -> bool {
+        self.block >= self.tape.blocks.len()
+    }
+
+    /// Back to the start of the tape
+    pub fn rewind(&mut self) {
+        self.block = 0;
+        self.offset = 0;
+        self.position = 0;
+        self.last_end = 0;
+        self.cue();
+    }
+
+    /// Run one machine cycle with the motor on or off. Returns a byte when
+    /// one has finished coming off the tape.
+    pub fn tick(&mut self, motor: bool) -> Option<u8> {
+        if motor != self.motor {
+            self.motor = motor;
+            if !motor {
+                if let Err(e) = self.save() {
+                    self.write_error = Some(e);
+                }
+            }
+        }
+        if !motor {
+            return None;
+        }
+        self.position += 1;
+        if self.recording || self.at_end() {
+            return None;
+        }
+
+        self.wait = self.wait.saturating_sub(1);
+        if self.wait > 0 {
+            return None;
+        }
+        let block = &self.tape.blocks[self.block];
+        let byte = block.data[self.offset];
+        self.offset += 1;
+        if self.offset == block.data.len() {
+            self.block += 1;
+            self.offset = 0;
+        }
+        self.cue();
+        Some(byte)
+    }
+
+    /// A byte POKEY has finished sending, at `bit_cycles` machine cycles a
+    /// bit. Only goes on the tape while recording with the motor running.
+    pub fn record_byte(&mut self, byte: u8, bit_cycles: u32) {
+        if !self.recording || !self.motor {
+            return;
+        }
+        let byte_cycles = BITS_PER_BYTE * bit_cycles as u64;
+        let baud = ((CYCLES_PER_SECOND + bit_cycles as u64 / 2) / bit_cycles as u64) as u16;
+        let start = self.position.saturating_sub(byte_cycles);
+        let gap = start.saturating_sub(self.last_end);
+        self.last_end = self.position;
+        self.changed = true;
+
+        // Bytes of a record follow each other without a break
+        match self.tape.blocks.last_mut() {
+            Some(block) if gap < byte_cycles && block.baud == baud => block.data.push(byte),
+            _ => self.tape.blocks.push(CasBlock {
+                baud,
+                gap_ms: (gap / CYCLES_PER_MS).min(u16::MAX as u64) as u16,
+                data: vec![byte],
+            }),
+        }
+    }
+
+    /// Set the wait for the next byte: a byte's time at the block's speed,
+    /// plus the gap if it starts a block. Empty blocks are passed over.
+    fn cue(&mut self) {
+        while self.tape.blocks.get(self.block).is_some_and(|block| block.data.is_empty()) {
+            self.block += 1;
+        }
+        self.wait = match self.tape.blocks.get(self.block) {
+            Some(block) => {
+                let byte = BITS_PER_BYTE * CYCLES_PER_SECOND / block.baud.max(1) as u64;
+                let gap = if self.offset == 0 { block.gap_ms as u64 * CYCLES_PER_MS } else { 0 };
+                gap + byte
+            }
+            None => 0,
+        };
+    }
+
+    /// Write anything recorded since the last save to the tape's file, if
+    /// it has one
+    pub fn save(&mut self) -> io::Result<()> {
+        if let (Some(path), true) = (&self.path, self.changed) {
+            fs::write(path, self.tape.to_bytes())?;
+            self.changed = false;
+        }
+        Ok(())
+    }
+}
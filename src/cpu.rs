@@ -396,6 +396,40 @@ impl Cpu {
         self.cycles_remaining = 7;
     }
 
+    /// Maskable interrupt: same sequence as NMI through $FFFE, ignored
+    /// while the I flag is set. Returns whether it was taken.
+    pub fn irq(&mut self, bus: &mut dyn Bus) -> bool {
+        if self.i {
+            return false;
+        }
+
+        self.stack_push_byte(bus, (self.pc >> 8) as u8);
+        self.stack_push_byte(bus, (self.pc & 0xFF) as u8);
+        let status = self.get_status(false);
+        self.stack_push_byte(bus, status);
+        self.i = true;
+        self.pc = bus.read_word(VECTOR_IRQBRK);
+        self.cycles_remaining = 7;
+        true
+    }
+
+    /// Enter a subroutine from outside the program, as if a JSR had been
+    /// executed with the given return address
+    pub fn call(&mut self, bus: &mut dyn Bus, addr: u16, return_to: u16) {
+        let ret = return_to.wrapping_sub(1);
+        self.stack_push_byte(bus, (ret >> 8) as u8);
+        self.stack_push_byte(bus, (ret & 0xFF) as u8);
+        self.pc = addr;
+        self.cycles_remaining = 0;
+    }
+
+    /// Leave a subroutine from outside the program, as if an RTS had been
+    /// executed
+    pub fn rts(&mut self, bus: &mut dyn Bus) {
+        self.pc = self.stack_pop_word(bus).wrapping_add(1);
+        self.cycles_remaining = 0;
+    }
+
     pub fn tick(&mut self, bus: &mut dyn Bus) -> u8 {
         if self.cycles_remaining == 0 {
             // Start new instruction
@@ -1967,60 +2001,63 @@ impl Cpu {
 
     fn bcs(&mut self, bus: &mut dyn Bus, addr: u16) {
         if self.c {
-            self.check_addr(addr);
-            self.pc = addr;
+            self.take_branch(addr);
         }
     }
 
     fn bcc(&mut self, bus: &mut dyn Bus, addr: u16) {
         if !self.c {
-            self.check_addr(addr);
-            self.pc = addr;
+            self.take_branch(addr);
         }
     }
 
     fn beq(&mut self, bus: &mut dyn Bus, addr: u16) {
         if self.z {
-            self.check_addr(addr);
-            self.pc = addr;
+            self.take_branch(addr);
         }
     }
 
     fn bmi(&mut self, bus: &mut dyn Bus, addr: u16) {
         if self.n {
-            self.check_addr(addr);
-            self.pc = addr;
+            self.take_branch(addr);
         }
     }
 
     fn bne(&mut self, bus: &mut dyn Bus, addr: u16) {
         if !self.z {
-            self.check_addr(addr);
-            self.pc = addr;
+            self.take_branch(addr);
         }
     }
 
     fn bpl(&mut self, bus: &mut dyn Bus, addr: u16) {
         if !self.n {
-            self.check_addr(addr);
-            self.pc = addr;
+            self.take_branch(addr);
         }
     }
 
     fn bvc(&mut self, bus: &mut dyn Bus, addr: u16) {
         if !self.v {
-            self.check_addr(addr);
-            self.pc = addr;
+            self.take_branch(addr);
         }
     }
 
     fn bvs(&mut self, bus: &mut dyn Bus, addr: u16) {
         if self.v {
-            self.check_addr(addr);
-            self.pc = addr;
+            self.take_branch(addr);
         }
     }
 
+    /// Branch to `addr`: a cycle more than not branching, and another if
+    /// it is on a different page
+    fn take_branch(&mut self, addr: u16) {
+        self.check_addr(addr);
+        self.cycles_remaining += 1;
+        if self.branch_crosses_page(addr.wrapping_sub(self.pc) as i8) {
+            self.cycles_remaining += 1;
+        }
+        self.pc = addr;
+    }
+
     fn check_addr(&self, addr: u16) {
         // TODO : Remove this. This is used for running tests and detecting
         // failure.
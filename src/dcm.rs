@@ -0,0 +1,376 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::atr::{Atr, Density};
+use crate::disk_image::{DiskImage, ImageFormat};
+
+/// Pass header types: a single-file archive, or one of several
+const PASS_SINGLE: u8 = 0xFA;
+const PASS_MULTI: u8 = 0xF9;
+
+/// Pass header flags: last pass, density, pass number
+const FLAG_LAST_PASS: u8 = 0x80;
+const FLAG_DENSITY: u8 = 0x60;
+const FLAG_PASS: u8 = 0x1F;
+
+/// Block types. With bit 7 set the next block is for the following
+/// sector, otherwise its number comes after the block.
+const BLOCK_MODIFY_BEGIN: u8 = 0x41;
+const BLOCK_DOS: u8 = 0x42;
+const BLOCK_COMPRESSED: u8 = 0x43;
+const BLOCK_MODIFY_END: u8 = 0x44;
+const BLOCK_END_OF_PASS: u8 = 0x45;
+const BLOCK_SAME: u8 = 0x46;
+const BLOCK_UNCOMPRESSED: u8 = 0x47;
+const BLOCK_SEQUENTIAL: u8 = 0x80;
+
+/// Shortest stretch of one byte worth a fill in a compressed sector
+const MIN_FILL: usize = 4;
+
+#[derive(Debug)]
+pub enum DcmError {
+    Io(io::Error),
+    /// The file doesn't start with a pass header
+    NotDcm,
+    /// A block runs past the end of the file; `offset` is where it starts
+    Truncated { offset: usize },
+    /// An unknown block type, or one that doesn't fit its sector
+    BadBlock { offset: usize, kind: u8 },
+}
+
+impl fmt::Display for DcmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DcmError::Io(e) => write!(f, "{}", e),
+            DcmError::NotDcm => write!(f, "not a DCM disk image"),
+            DcmError::Truncated { offset } => {
+                write!(f, "file ends inside the block at offset {}", offset)
+            }
+            DcmError::BadBlock { offset, kind } => {
+                write!(f, "bad block type ${:02X} at offset {}", kind, offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DcmError {}
+
+impl From<io::Error> for DcmError {
+    fn from(e: io::Error) -> DcmError {
+        DcmError::Io(e)
+    }
+}
+
+/// A DCM (DiskComm) disk image: the disk's sectors compressed, in one or
+/// more passes.
+///
+/// A pass starts with a header: type $FA, flags (bit 7 last pass, bits 6-5
+/// density, bits 4-0 pass number) and the first sector. Then come blocks,
+/// each giving a sector's contents from the one before it:
+///
+/// - $41 change the start: offset N, then bytes N down to 0
+/// - $42 DOS 2 sector: a byte for 0-122, then bytes 123-127
+/// - $43 compressed: alternately the offset a run of bytes ends at and the
+///   bytes, and the offset a fill ends at and the byte to fill with
+/// - $44 change the end: offset N, then bytes N to the end
+/// - $46 the same as the last sector
+/// - $47 the whole sector
+///
+/// $45 ends the pass. Sectors no block mentions are zero.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dcm {
+    sectors: Atr,
+}
+
+impl Dcm {
+    pub fn blank(density: Density) -> Dcm {
+        Dcm { sectors: Atr::blank(density) }
+    }
+
+    /// Whether the data starts like a DCM archive
+    pub fn is_dcm(image: &[u8]) -> bool {
+        image.len() >= 4
+            && (image[0] == PASS_SINGLE || image[0] == PASS_MULTI)
+            && image[1] & FLAG_DENSITY != FLAG_DENSITY
+            && image[1] & FLAG_PASS == 1
+    }
+
+    pub fn parse(image: &[u8]) -> Result<Dcm, DcmError> {
+        if !Dcm::is_dcm(image) {
+            return Err(DcmError::NotDcm);
+        }
+        let density = density_from_flags(image[1]);
+        let mut sectors = Atr::blank(density);
+        let mut reader = Reader { image, offset: 0 };
+        let mut buffer = [0u8; 256];
+
+        loop {
+            let header = reader.take(4)?;
+            if header[0] != PASS_SINGLE && header[0] != PASS_MULTI {
+                return Err(DcmError::NotDcm);
+            }
+            let last_pass = header[1] & FLAG_LAST_PASS != 0;
+            let mut sector = u16::from_le_bytes([header[2], header[3]]) as usize;
+
+            loop {
+                let offset = reader.offset;
+                let kind = reader.byte()?;
+                if kind & !BLOCK_SEQUENTIAL == BLOCK_END_OF_PASS {
+                    break;
+                }
+                let size = sectors.sector_size(sector);
+                decode_block(&mut reader, kind & !BLOCK_SEQUENTIAL, &mut buffer[..size])?;
+                if !sectors.write_sector(sector, &buffer[..size]) {
+                    return Err(DcmError::BadBlock { offset, kind });
+                }
+                sector = if kind & BLOCK_SEQUENTIAL != 0 {
+                    sector + 1
+                } else {
+                    let next = reader.take(2)?;
+                    u16::from_le_bytes([next[0], next[1]]) as usize
+                };
+            }
+
+            if last_pass || reader.offset == image.len() {
+                break;
+            }
+        }
+
+        Ok(Dcm { sectors })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Dcm, DcmError> {
+        Dcm::parse(&fs::read(path)?)
+    }
+
+    pub fn from_image(image: &dyn DiskImage) -> Dcm {
+        Dcm { sectors: Atr::from_image(image) }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, DiskImage::to_bytes(self))
+    }
+}
+
+fn density_from_flags(flags: u8) -> Density {
+    match flags & FLAG_DENSITY {
+        0x20 => Density::Double,
+        0x40 => Density::Enhanced,
+        _ => Density::Single,
+    }
+}
+
+fn density_flags(density: Density) -> u8 {
+    match density {
+        Density::Single => 0x00,
+        Density::Double => 0x20,
+        Density::Enhanced => 0x40,
+    }
+}
+
+struct Reader<'a> {
+    image: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DcmError> {
+        let bytes = self
+            .image
+            .get(self.offset..self.offset + len)
+            .ok_or(DcmError::Truncated { offset: self.offset })?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, DcmError> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+/// Apply a block to the sector buffer, which holds the last sector. The
+/// reader is just past the block type.
+fn decode_block(reader: &mut Reader, kind: u8, sector: &mut [u8]) -> Result<(), DcmError> {
+    let size = sector.len();
+    let bad = DcmError::BadBlock { offset: reader.offset - 1, kind };
+    match kind {
+        BLOCK_MODIFY_BEGIN => {
+            let end = reader.byte()? as usize;
+            if end >= size {
+                return Err(bad);
+            }
+            let bytes = reader.take(end + 1)?;
+            for (i, &byte) in bytes.iter().enumerate() {
+                sector[end - i] = byte;
+            }
+        }
+        BLOCK_DOS => {
+            if size != 128 {
+                return Err(bad);
+            }
+            let fill = reader.byte()?;
+            sector[..123].fill(fill);
+            sector[123..].copy_from_slice(reader.take(5)?);
+        }
+        BLOCK_COMPRESSED => {
+            let mut at = 0;
+            while at < size {
+                // Offsets are a byte: 0 stands for 256 past the start
+                let end = match reader.byte()? as usize {
+                    0 if at > 0 => 256,
+                    end => end,
+                };
+                if end < at || end > size {
+                    return Err(bad);
+                }
+                sector[at..end].copy_from_slice(reader.take(end - at)?);
+                at = end;
+                if at == size {
+                    break;
+                }
+                let end = match reader.byte()? as usize {
+                    0 => 256,
+                    end => end,
+                };
+                if end <= at || end > size {
+                    return Err(bad);
+                }
+                let fill = reader.byte()?;
+                sector[at..end].fill(fill);
+                at = end;
+            }
+        }
+        BLOCK_MODIFY_END => {
+            let start = reader.byte()? as usize;
+            if start >= size {
+                return Err(bad);
+            }
+            sector[start..].copy_from_slice(reader.take(size - start)?);
+        }
+        BLOCK_SAME => {}
+        BLOCK_UNCOMPRESSED => sector.copy_from_slice(reader.take(size)?),
+        _ => return Err(bad),
+    }
+    Ok(())
+}
+
+/// The shortest block that turns `previous` into `sector`, type first
+fn encode_block(previous: &[u8], sector: &[u8]) -> Vec<u8> {
+    let size = sector.len();
+    if previous == sector {
+        return vec![BLOCK_SAME];
+    }
+
+    let mut best = vec![BLOCK_UNCOMPRESSED];
+    best.extend_from_slice(sector);
+
+    let mut candidates = vec![compress(sector)];
+    if let Some(last) = (0..size).rev().find(|&i| previous[i] != sector[i]) {
+        let mut block = vec![BLOCK_MODIFY_BEGIN, last as u8];
+        block.extend(sector[..=last].iter().rev());
+        candidates.push(block);
+    }
+    if let Some(first) = (0..size).find(|&i| previous[i] != sector[i]) {
+        let mut block = vec![BLOCK_MODIFY_END, first as u8];
+        block.extend_from_slice(&sector[first..]);
+        candidates.push(block);
+    }
+    if size == 128 && sector[..123].iter().all(|&byte| byte == sector[0]) {
+        let mut block = vec![BLOCK_DOS, sector[0]];
+        block.extend_from_slice(&sector[123..]);
+        candidates.push(block);
+    }
+
+    for block in candidates {
+        if block.len() < best.len() {
+            best = block;
+        }
+    }
+    best
+}
+
+/// A $43 block: literal stretches and fills, alternately. A 256-byte
+/// sector with no fills comes out longer than $47, so never needs its
+/// first offset to be 256.
+fn compress(sector: &[u8]) -> Vec<u8> {
+    let size = sector.len();
+    let mut block = vec![BLOCK_COMPRESSED];
+    let mut at = 0;
+    while at < size {
+        // Copy up to the next fill worth having
+        let fill_start = (at..size)
+            .find(|&i| i + MIN_FILL <= size && sector[i..i + MIN_FILL].iter().all(|&b| b == sector[i]))
+            .unwrap_or(size);
+        block.push(fill_start as u8);
+        block.extend_from_slice(&sector[at..fill_start]);
+        at = fill_start;
+        if at == size {
+            break;
+        }
+        let fill_end = (at..size).find(|&i| sector[i] != sector[at]).unwrap_or(size);
+        block.push(fill_end as u8);
+        block.push(sector[at]);
+        at = fill_end;
+    }
+    block
+}
+
+impl DiskImage for Dcm {
+    fn image_format(&self) -> ImageFormat {
+        ImageFormat::Dcm
+    }
+
+    fn density(&self) -> Density {
+        self.sectors.density()
+    }
+
+    fn sector_count(&self) -> usize {
+        self.sectors.sector_count()
+    }
+
+    fn sector_size(&self, sector: usize) -> usize {
+        self.sectors.sector_size(sector)
+    }
+
+    fn read_sector(&self, sector: usize) -> Option<&[u8]> {
+        self.sectors.read_sector(sector)
+    }
+
+    fn write_sector(&mut self, sector: usize, data: &[u8]) -> bool {
+        self.sectors.write_sector(sector, data)
+    }
+
+    fn format(&mut self, density: Density) -> bool {
+        self.sectors.format(density);
+        true
+    }
+
+    /// One pass holding every sector that isn't all zero
+    fn to_bytes(&self) -> Vec<u8> {
+        let used: Vec<usize> = (1..=self.sectors.sector_count())
+            .filter(|&n| self.sectors.read_sector(n).is_some_and(|data| data.iter().any(|&b| b != 0)))
+            .collect();
+        let flags = FLAG_LAST_PASS | density_flags(self.density()) | 1;
+        let first = used.first().copied().unwrap_or(1) as u16;
+        let mut image = vec![PASS_SINGLE, flags];
+        image.extend_from_slice(&first.to_le_bytes());
+
+        let mut previous = vec![0u8; 256];
+        for (i, &sector) in used.iter().enumerate() {
+            let data = self.sectors.read_sector(sector).unwrap();
+            let mut block = encode_block(&previous[..data.len()], data);
+            match used.get(i + 1) {
+                Some(&next) if next != sector + 1 => {
+                    block.extend_from_slice(&(next as u16).to_le_bytes());
+                }
+                _ => block[0] |= BLOCK_SEQUENTIAL,
+            }
+            image.extend_from_slice(&block);
+            previous[..data.len()].copy_from_slice(data);
+        }
+        image.push(BLOCK_END_OF_PASS);
+        image
+    }
+}
@@ -0,0 +1,406 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::atr::{Atr, AtrError, Density};
+use crate::atx::{Atx, AtxError, ROTATION_UNITS};
+use crate::dcm::DcmError;
+use crate::disk_image::{self, DiskImage, SectorCopy};
+use crate::pro::ProError;
+use crate::sio::{SioDevice, SioResponse};
+use crate::xfd::XfdError;
+
+/// SIO device ID of D1:; D2:-D8: follow
+pub const D1: u8 = 0x31;
+
+/// Drive status byte 0
+const STATUS_FAILED: u8 = 0x04;
+const STATUS_WRITE_PROTECT: u8 = 0x08;
+const STATUS_MOTOR_ON: u8 = 0x10;
+const STATUS_DOUBLE: u8 = 0x20;
+const STATUS_ENHANCED: u8 = 0x80;
+
+/// Floppy controller status bits. Drive status byte 1 is this, inverted.
+const FDC_LOST_DATA: u8 = 0x04;
+const FDC_CRC_ERROR: u8 = 0x08;
+const FDC_NOT_FOUND: u8 = 0x10;
+const FDC_DELETED: u8 = 0x20;
+const FDC_WRITE_PROTECT: u8 = 0x40;
+
+/// Controller status bits that make a read fail
+const FDC_READ_ERRORS: u8 = FDC_LOST_DATA | FDC_CRC_ERROR | FDC_NOT_FOUND | FDC_DELETED;
+
+/// Drive status byte 2: the format command's timeout in seconds
+const FORMAT_TIMEOUT: u8 = 0xE0;
+
+/// Machine cycles per thousand 8µs units of rotation
+const CYCLES_PER_KILO_UNIT: u64 = 14_318;
+
+/// 810 head movement: 5.3ms per track, then 10ms to settle
+const STEP_CYCLES: u64 = 9_486;
+const SETTLE_CYCLES: u64 = 17_898;
+
+/// Revolutions the drive spends looking for a sector that isn't there
+const NOT_FOUND_REVOLUTIONS: u64 = 2;
+
+#[derive(Debug)]
+pub enum DiskError {
+    Atr(AtrError),
+    Atx(AtxError),
+    Xfd(XfdError),
+    Dcm(DcmError),
+    Pro(ProError),
+}
+
+impl fmt::Display for DiskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiskError::Atr(e) => write!(f, "{}", e),
+            DiskError::Atx(e) => write!(f, "{}", e),
+            DiskError::Xfd(e) => write!(f, "{}", e),
+            DiskError::Dcm(e) => write!(f, "{}", e),
+            DiskError::Pro(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DiskError {}
+
+impl From<AtrError> for DiskError {
+    fn from(e: AtrError) -> DiskError {
+        DiskError::Atr(e)
+    }
+}
+
+impl From<AtxError> for DiskError {
+    fn from(e: AtxError) -> DiskError {
+        DiskError::Atx(e)
+    }
+}
+
+impl From<XfdError> for DiskError {
+    fn from(e: XfdError) -> DiskError {
+        DiskError::Xfd(e)
+    }
+}
+
+impl From<DcmError> for DiskError {
+    fn from(e: DcmError) -> DiskError {
+        DiskError::Dcm(e)
+    }
+}
+
+impl From<ProError> for DiskError {
+    fn from(e: ProError) -> DiskError {
+        DiskError::Pro(e)
+    }
+}
+
+/// An Atari 810/1050 disk drive with a disk image in it, in any of the
+/// formats `DiskImage` covers.
+///
+/// Handles read ($52), write ($57), put ($50, write without verify),
+/// status ($53), format ($21) and the 1050's enhanced density format
+/// ($22). A disk opened from a file has its changes written back, in the
+/// file's format, as they are made; if that fails the command fails with
+/// it, and the reason waits in `take_write_error`.
+///
+/// Reads report the controller status the image records for the sector.
+/// Where the image keeps sector positions (ATX) the disk spins: the drive
+/// steps to the sector's track and waits for the next copy of the sector
+/// to pass the head, so timing checks, duplicate and missing sectors, bad
+/// CRCs and weak bits behave as on the original disk. Copies without a
+/// position (PRO phantoms) come back in turn.
+pub struct DiskDrive {
+    disk: Box<dyn DiskImage>,
+    path: Option<PathBuf>,
+    write_protected: bool,
+
+    // Sector a write command is waiting to receive
+    write_sector: Option<usize>,
+
+    // Bus time of the latest command frame, and how long it kept the drive
+    // busy
+    clock: u64,
+    busy: u64,
+
+    // Track under the head
+    head: usize,
+
+    // Controller status and success of the last read or write
+    fdc_status: u8,
+    failed: bool,
+
+    // Reads so far, to take turns among unpositioned copies of a sector
+    reads: usize,
+
+    // Why the disk last failed to go back to its file
+    write_error: Option<io::Error>,
+}
+
+impl DiskDrive {
+    /// A drive holding an image that only lives in memory
+    pub fn new(atr: Atr) -> DiskDrive {
+        DiskDrive::with_image(Box::new(atr))
+    }
+
+    /// A drive holding a copy-protected disk, which can't be written
+    pub fn from_atx(atx: Atx) -> DiskDrive {
+        DiskDrive::with_image(Box::new(atx))
+    }
+
+    /// A drive holding a disk in any format. Read-only formats are write
+    /// protected.
+    pub fn with_image(disk: Box<dyn DiskImage>) -> DiskDrive {
+        DiskDrive {
+            write_protected: disk.is_read_only(),
+            disk,
+            path: None,
+            write_sector: None,
+            clock: 0,
+            busy: 0,
+            head: 0,
+            fdc_status: 0,
+            failed: false,
+            reads: 0,
+            write_error: None,
+        }
+    }
+
+    /// A drive holding the image in a file, whose format is told by its
+    /// contents. Changes go back to the file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<DiskDrive, DiskError> {
+        let disk = disk_image::open_image(&path)?;
+        Ok(DiskDrive { path: Some(path.as_ref().to_path_buf()), ..DiskDrive::with_image(disk) })
+    }
+
+    /// A drive holding a new blank ATR file of `density` at `path`. A file
+    /// already there is left alone, failing with `AlreadyExists`.
+    pub fn create<P: AsRef<Path>>(path: P, density: Density) -> Result<DiskDrive, DiskError> {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&path).map_err(AtrError::from)?;
+        file.write_all(&Atr::blank(density).to_bytes()).map_err(AtrError::from)?;
+        DiskDrive::open(path)
+    }
+
+    pub fn image(&self) -> &dyn DiskImage {
+        self.disk.as_ref()
+    }
+
+    /// The file changes are written back to, if there is one
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Why writing the disk back to its file last failed, if it has since
+    /// this was last asked. The next change to the disk tries again.
+    pub fn take_write_error(&mut self) -> Option<io::Error> {
+        self.write_error.take()
+    }
+
+    pub fn write_protected(&self) -> bool {
+        self.write_protected
+    }
+
+    /// Set or clear the write protect tab. Read-only formats stay
+    /// protected.
+    pub fn set_write_protected(&mut self, protected: bool) {
+        self.write_protected = protected || self.disk.is_read_only();
+    }
+
+    fn density(&self) -> Density {
+        self.disk.density()
+    }
+
+    fn status(&self) -> Vec<u8> {
+        let mut drive = STATUS_MOTOR_ON;
+        let mut fdc = self.fdc_status;
+        match self.density() {
+            Density::Single => {}
+            Density::Enhanced => drive |= STATUS_ENHANCED,
+            Density::Double => drive |= STATUS_DOUBLE,
+        }
+        if self.failed {
+            drive |= STATUS_FAILED;
+        }
+        if self.write_protected {
+            drive |= STATUS_WRITE_PROTECT;
+            fdc |= FDC_WRITE_PROTECT;
+        }
+        vec![drive, !fdc, FORMAT_TIMEOUT, 0x00]
+    }
+
+    fn read(&mut self, sector: usize) -> SioResponse {
+        let copies = match self.disk.copies(sector) {
+            Some(copies) => copies,
+            None => return SioResponse::Nak,
+        };
+        // A sector that isn't there takes the drive a while to give up on
+        let (status, data) = if copies.is_empty() || copies.iter().any(|copy| copy.position.is_some()) {
+            self.read_spinning(sector, &copies)
+        } else {
+            let copy = &copies[self.reads % copies.len()];
+            self.reads += 1;
+            (copy.status, self.sector_data(copy))
+        };
+        self.fdc_status = status;
+        self.failed = status & FDC_READ_ERRORS != 0;
+        if self.failed {
+            SioResponse::Error(data)
+        } else {
+            SioResponse::Complete(data)
+        }
+    }
+
+    /// Find a sector on a disk that keeps the sector positions, keeping
+    /// track of the time it takes. Returns the controller status and the
+    /// data.
+    fn read_spinning(&mut self, sector: usize, copies: &[SectorCopy]) -> (u8, Vec<u8>) {
+        let track = (sector - 1) / self.disk.sectors_per_track();
+        let steps = (track as i64 - self.head as i64).unsigned_abs();
+        let mut time = self.clock;
+        if steps > 0 {
+            time += steps * STEP_CYCLES + SETTLE_CYCLES;
+        }
+        self.head = track;
+
+        // The next copy of the sector to come round
+        let angle = rotation_units(time);
+        let wait = |copy: &SectorCopy| {
+            let position = copy.position.unwrap_or(0) as u64;
+            (position + ROTATION_UNITS as u64 - angle) % ROTATION_UNITS as u64
+        };
+        let result = match copies.iter().min_by_key(|copy| wait(copy)) {
+            Some(found) => {
+                time += units_to_cycles(wait(found) + read_units(self.density()));
+                (found.status, self.sector_data(found))
+            }
+            None => {
+                time += units_to_cycles(NOT_FOUND_REVOLUTIONS * ROTATION_UNITS as u64);
+                (FDC_NOT_FOUND, vec![0; self.disk.sector_size(sector)])
+            }
+        };
+        self.busy = time - self.clock;
+        result
+    }
+
+    /// A sector's bytes as read this time, weak bits included
+    fn sector_data(&self, copy: &SectorCopy) -> Vec<u8> {
+        let mut data = copy.data.clone();
+        if let Some(offset) = copy.weak_offset {
+            let mut noise = self.clock as u32 | 1;
+            for byte in data.iter_mut().skip(offset) {
+                noise ^= noise << 13;
+                noise ^= noise >> 17;
+                noise ^= noise << 5;
+                *byte = noise as u8;
+            }
+        }
+        data
+    }
+
+    /// Wipe the disk. The drive answers with a sector listing the bad
+    /// sectors, ended by $FFFF; there never are any.
+    fn format(&mut self, density: Density) -> SioResponse {
+        let bad_sectors = vec![0xFF; density.sector_size()];
+        if self.write_protected || !self.disk.format(density) {
+            self.fdc_status = FDC_WRITE_PROTECT;
+            self.failed = true;
+            return SioResponse::Error(bad_sectors);
+        }
+        self.fdc_status = 0;
+        self.failed = !self.write_back();
+        if self.failed {
+            return SioResponse::Error(bad_sectors);
+        }
+        SioResponse::Complete(bad_sectors)
+    }
+
+    /// Save the image to the file it came from. Returns false, keeping the
+    /// reason, if it couldn't be written.
+    fn write_back(&mut self) -> bool {
+        if let Some(path) = &self.path {
+            if let Err(e) = fs::write(path, self.disk.to_bytes()) {
+                self.write_error = Some(e);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Angular position of the disk at a bus time, in 8µs units from the index
+fn rotation_units(cycle: u64) -> u64 {
+    cycle * 1000 / CYCLES_PER_KILO_UNIT % ROTATION_UNITS as u64
+}
+
+fn units_to_cycles(units: u64) -> u64 {
+    units * CYCLES_PER_KILO_UNIT / 1000
+}
+
+/// Time for a sector to pass under the head: 8µs a bit in single density
+/// (FM), 4µs in enhanced and double (MFM)
+fn read_units(density: Density) -> u64 {
+    let bits = density.sector_size() as u64 * 8;
+    match density {
+        Density::Single => bits,
+        Density::Enhanced | Density::Double => bits / 2,
+    }
+}
+
+impl SioDevice for DiskDrive {
+    fn set_clock(&mut self, cycle: u64) {
+        self.clock = cycle;
+        self.busy = 0;
+    }
+
+    fn busy_cycles(&self) -> u64 {
+        self.busy
+    }
+
+    fn command(&mut self, command: u8, aux1: u8, aux2: u8) -> SioResponse {
+        let sector = u16::from_le_bytes([aux1, aux2]) as usize;
+        match command {
+            b'R' => self.read(sector),
+            b'W' | b'P' => {
+                if sector == 0 || sector > self.disk.sector_count() {
+                    return SioResponse::Nak;
+                }
+                self.write_sector = Some(sector);
+                SioResponse::Receive(self.disk.sector_size(sector))
+            }
+            b'S' => SioResponse::Complete(self.status()),
+            b'!' => {
+                // An 810 formats in single density; a double density disk
+                // stays that way
+                let density = match self.density() {
+                    Density::Double => Density::Double,
+                    _ => Density::Single,
+                };
+                self.format(density)
+            }
+            b'"' => self.format(Density::Enhanced),
+            _ => SioResponse::Nak,
+        }
+    }
+
+    fn data(&mut self, data: &[u8]) -> SioResponse {
+        let sector = match self.write_sector.take() {
+            Some(sector) => sector,
+            None => return SioResponse::Nak,
+        };
+        if self.write_protected {
+            self.fdc_status = FDC_WRITE_PROTECT;
+            self.failed = true;
+            return SioResponse::Error(Vec::new());
+        }
+        let written = self.disk.write_sector(sector, data);
+        self.fdc_status = 0;
+        self.failed = !written;
+        if !written || !self.write_back() {
+            self.failed = true;
+            return SioResponse::Error(Vec::new());
+        }
+        SioResponse::Complete(Vec::new())
+    }
+}
@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::Path;
+
+use crate::atr::{Atr, AtrError, Density};
+use crate::atx::Atx;
+use crate::dcm::Dcm;
+use crate::disk::DiskError;
+use crate::pro::Pro;
+use crate::xfd::Xfd;
+
+/// Tracks on a 5.25" disk
+pub const TRACKS: usize = 40;
+
+/// File formats a disk can be kept in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    /// Header and sectors
+    Atr,
+    /// Sectors only
+    Xfd,
+    /// DiskComm compressed
+    Dcm,
+    /// APE image with each sector's status
+    Pro,
+    /// Tracks with sector timing, for copy-protected disks
+    Atx,
+}
+
+impl ImageFormat {
+    /// The format a file name's extension suggests
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "atr" => Some(ImageFormat::Atr),
+            "xfd" => Some(ImageFormat::Xfd),
+            "dcm" => Some(ImageFormat::Dcm),
+            "pro" => Some(ImageFormat::Pro),
+            "atx" => Some(ImageFormat::Atx),
+            _ => None,
+        }
+    }
+}
+
+/// A sector as the drive finds it on the disk
+#[derive(Clone, Debug, PartialEq)]
+pub struct SectorCopy {
+    /// Floppy controller status on reading it (see `DiskImage::sector_status`)
+    pub status: u8,
+
+    /// Angular position in 8µs units from the index, for images that keep
+    /// the timing
+    pub position: Option<u16>,
+
+    pub data: Vec<u8>,
+
+    /// Bytes from here on read back differently every time
+    pub weak_offset: Option<usize>,
+}
+
+/// A disk in some file format. The drive works through this and doesn't
+/// need to know which format it has.
+///
+/// Sectors are numbered from 1. Disks have 40 tracks of
+/// `sectors_per_track` sectors, the first three sectors being 128 bytes
+/// whatever the density.
+pub trait DiskImage {
+    fn image_format(&self) -> ImageFormat;
+
+    fn density(&self) -> Density;
+
+    fn sector_count(&self) -> usize;
+
+    /// Size of a sector, numbered from 1
+    fn sector_size(&self, sector: usize) -> usize;
+
+    fn sectors_per_track(&self) -> usize {
+        match self.density() {
+            Density::Single | Density::Double => 18,
+            Density::Enhanced => 26,
+        }
+    }
+
+    /// None if there is no such sector, or the disk has no data for it
+    fn read_sector(&self, sector: usize) -> Option<&[u8]>;
+
+    /// Replace a sector's contents. Returns false if there is no such
+    /// sector, `data` is the wrong size or the format can't be changed.
+    fn write_sector(&mut self, sector: usize, data: &[u8]) -> bool;
+
+    /// Floppy controller status reading a sector: lost data ($04), CRC
+    /// error ($08), record not found ($10), deleted ($20). Zero for a good
+    /// sector.
+    fn sector_status(&self, _sector: usize) -> u8 {
+        0
+    }
+
+    /// Every copy of a sector on the disk, in the order they were recorded.
+    /// Empty if the controller can't find the sector; None if the number is
+    /// out of range.
+    fn copies(&self, sector: usize) -> Option<Vec<SectorCopy>> {
+        if sector == 0 || sector > self.sector_count() {
+            return None;
+        }
+        let data = self
+            .read_sector(sector)
+            .map_or_else(|| vec![0; self.sector_size(sector)], |data| data.to_vec());
+        Some(vec![SectorCopy { status: self.sector_status(sector), position: None, data, weak_offset: None }])
+    }
+
+    /// Wipe the disk, changing its format to `density`. Returns false if
+    /// the file format can't hold that density or can't be changed.
+    fn format(&mut self, density: Density) -> bool;
+
+    /// Whether changes can be written back to the file
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// The file image
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+/// Read a disk image in any of the formats, which is told by its contents
+pub fn open_image<P: AsRef<Path>>(path: P) -> Result<Box<dyn DiskImage>, DiskError> {
+    let data = fs::read(&path).map_err(AtrError::Io)?;
+    parse_image(&data, ImageFormat::from_path(&path))
+}
+
+/// Read a disk image from its bytes. Most formats are known by their
+/// header; XFD has none, and is taken to be the format when nothing else
+/// matches.
+pub fn parse_image(data: &[u8], hint: Option<ImageFormat>) -> Result<Box<dyn DiskImage>, DiskError> {
+    let format = if data.starts_with(&[0x96, 0x02]) {
+        ImageFormat::Atr
+    } else if data.starts_with(b"AT8X") {
+        ImageFormat::Atx
+    } else if Dcm::is_dcm(data) && hint != Some(ImageFormat::Xfd) {
+        ImageFormat::Dcm
+    } else if Pro::is_pro(data) {
+        ImageFormat::Pro
+    } else {
+        ImageFormat::Xfd
+    };
+
+    Ok(match format {
+        ImageFormat::Atr => Box::new(Atr::parse(data)?),
+        ImageFormat::Xfd => Box::new(Xfd::parse(data)?),
+        ImageFormat::Dcm => Box::new(Dcm::parse(data)?),
+        ImageFormat::Pro => Box::new(Pro::parse(data)?),
+        ImageFormat::Atx => Box::new(Atx::parse(data)?),
+    })
+}
+
+/// A copy of a disk in another format, or None if the format can't hold
+/// it (PRO only has single and enhanced density). Sector status and
+/// timing go only where the format can keep them.
+pub fn convert(image: &dyn DiskImage, format: ImageFormat) -> Option<Box<dyn DiskImage>> {
+    Some(match format {
+        ImageFormat::Atr => Box::new(Atr::from_image(image)),
+        ImageFormat::Xfd => Box::new(Xfd::from_image(image)),
+        ImageFormat::Dcm => Box::new(Dcm::from_image(image)),
+        ImageFormat::Pro => Box::new(Pro::from_image(image)?),
+        ImageFormat::Atx => Box::new(Atx::from_image(image)),
+    })
+}
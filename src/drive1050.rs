@@ -0,0 +1,334 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::disk::{DiskError, D1};
+use crate::disk_image::{self, DiskImage};
+use crate::riot::Riot;
+use crate::rom::RomKind;
+use crate::sio::SerialLineDevice;
+use crate::wd2793::{Floppy, Wd2793};
+
+/// Drive and computer clocks, in cycles per second
+const DRIVE_HZ: u64 = 1_000_000;
+const MACHINE_HZ: u64 = 1_789_790;
+
+/// Size of the drive ROM. A 2K ROM is mirrored to fill the space.
+pub const ROM_SIZE: usize = 0x1000;
+
+/// Furthest track the head can be stepped to
+const LAST_TRACK: usize = 41;
+
+/// RIOT port A: drive select switches (unit - 1), motor (low = on), write
+/// protect sensor (low = protected), recording mode (high = FM), and the
+/// controller's DRQ and INTRQ
+const PA_UNIT: u8 = 0x03;
+const PA_MOTOR: u8 = 0x08;
+const PA_WRITE_PROTECT: u8 = 0x10;
+const PA_FM: u8 = 0x20;
+const PA_DRQ: u8 = 0x40;
+const PA_INTRQ: u8 = 0x80;
+
+/// RIOT port B: data to the computer, the four stepper motor phases, the
+/// command line (low = asserted) and data from the computer
+const PB_DATA_OUT: u8 = 0x01;
+const PB_PHASES: u8 = 0x1E;
+const PB_COMMAND: u8 = 0x40;
+const PB_DATA_IN: u8 = 0x80;
+
+#[derive(Debug, PartialEq)]
+pub enum Drive1050Error {
+    /// The drive select switches only go from D1: to D4:
+    NoSuchUnit(u8),
+    /// The ROM isn't 2K or 4K; its size
+    WrongRomSize(usize),
+}
+
+impl fmt::Display for Drive1050Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Drive1050Error::NoSuchUnit(unit) => write!(f, "a 1050 can't be D{}:", unit),
+            Drive1050Error::WrongRomSize(size) => {
+                write!(f, "the 1050 ROM should be 2K or 4K, not {} bytes", size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Drive1050Error {}
+
+/// The 1050's address space, 13 bits wide on the 6507
+struct DriveBus {
+    ram: [u8; 128],
+    rom: Vec<u8>,
+    riot: Riot,
+    fdc: Wd2793,
+    floppy: Floppy,
+
+    // Stepper phase last energized, 0-3
+    phase: u8,
+}
+
+impl DriveBus {
+    /// Set the RIOT's input pins from the lines outside it
+    fn update_inputs(&mut self, unit: u8, command_line: bool, data_in: bool) {
+        let mut port_a = !(PA_UNIT | PA_WRITE_PROTECT | PA_DRQ | PA_INTRQ) | (unit - 1) & PA_UNIT;
+        if !self.floppy.write_protected() {
+            port_a |= PA_WRITE_PROTECT;
+        }
+        if self.fdc.drq() {
+            port_a |= PA_DRQ;
+        }
+        if self.fdc.intrq() {
+            port_a |= PA_INTRQ;
+        }
+        self.riot.set_port_a_input(port_a);
+
+        let mut port_b = !(PB_COMMAND | PB_DATA_IN);
+        if command_line {
+            port_b |= PB_COMMAND;
+        }
+        if data_in {
+            port_b |= PB_DATA_IN;
+        }
+        self.riot.set_port_b_input(port_b);
+    }
+
+    /// Follow the RIOT's outputs: the motor, the recording mode, and the
+    /// head, which steps a track each time the next phase round is
+    /// energized on its own
+    fn update_outputs(&mut self) {
+        let port_a = self.riot.port_a();
+        self.floppy.motor = port_a & PA_MOTOR == 0;
+        self.floppy.mfm = port_a & PA_FM == 0;
+
+        let phases = (self.riot.port_b() & PB_PHASES) >> 1;
+        if phases.count_ones() == 1 {
+            let phase = phases.trailing_zeros() as u8;
+            if phase == (self.phase + 1) % 4 && self.floppy.head < LAST_TRACK {
+                self.floppy.head += 1;
+            } else if phase == (self.phase + 3) % 4 && self.floppy.head > 0 {
+                self.floppy.head -= 1;
+            }
+            self.phase = phase;
+        }
+    }
+}
+
+impl Bus for DriveBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let addr = addr & 0x1FFF;
+        if addr & 0x1000 != 0 {
+            self.rom[(addr as usize & 0x0FFF) % self.rom.len()]
+        } else if addr & 0x0400 != 0 {
+            self.fdc.read(addr, &self.floppy)
+        } else if addr & 0x0080 == 0 {
+            self.ram[(addr & 0x7F) as usize]
+        } else if addr & 0x0200 != 0 {
+            self.riot.read_register(addr)
+        } else {
+            self.riot.read_ram(addr)
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        let addr = addr & 0x1FFF;
+        if addr & 0x1000 != 0 {
+            // ROM
+        } else if addr & 0x0400 != 0 {
+            self.fdc.write(addr, val, &mut self.floppy);
+        } else if addr & 0x0080 == 0 {
+            self.ram[(addr & 0x7F) as usize] = val;
+        } else if addr & 0x0200 != 0 {
+            self.riot.write_register(addr, val);
+            self.update_outputs();
+        } else {
+            self.riot.write_ram(addr, val);
+        }
+    }
+}
+
+/// An Atari 1050 disk drive emulated at the chip level, running its own
+/// firmware. It answers on the serial bus as D`unit`: just as the real drive
+/// does, so copy protection checks that time the drive, and modified
+/// firmware such as the Happy or US Doubler upgrades, behave as on the
+/// hardware.
+///
+/// The drive has a 6507 at 1MHz, 128 bytes of RAM in a 6810, a 6532 RIOT
+/// and a WD2793 floppy controller. Its memory map, mirrored through the 6507's
+/// 8K address space:
+///
+/// - $0000-$007F: 6810 RAM
+/// - $0080-$00FF: RIOT RAM, also at $0180-$01FF for the stack
+/// - $0280-$029F: RIOT ports and timer
+/// - $0400-$0403: WD2793 status/command, track, sector and data
+/// - $1000-$1FFF: the drive ROM
+///
+/// The RIOT's port A reads the drive select switches (bits 0-1, unit - 1)
+/// and the write protect sensor (bit 4, low when protected), switches the
+/// motor (bit 3, low for on) and the recording mode (bit 5, high for FM),
+/// and sees the controller's DRQ (bit 6) and INTRQ (bit 7). Port B sends
+/// data to the computer (bit 0), drives the four stepper phases (bits
+/// 1-4), and reads the command line (bit 6) and the computer's data (bit
+/// 7).
+///
+/// The drive runs in step with the computer, a cycle or so of its own for
+/// each machine cycle, and works the serial lines a bit at a time.
+pub struct Drive1050 {
+    cpu: Cpu,
+    bus: DriveBus,
+    unit: u8,
+    path: Option<PathBuf>,
+
+    // Why the disk last failed to go back to its file
+    write_error: Option<io::Error>,
+
+    // Drive cycles owed, in machine cycles times DRIVE_HZ
+    owed: u64,
+}
+
+impl Drive1050 {
+    /// A drive set to unit `unit` (1-4) running `rom`, which must be 2K or
+    /// 4K
+    pub fn new(rom: &[u8], unit: u8) -> Result<Drive1050, Drive1050Error> {
+        if !(1..=4).contains(&unit) {
+            return Err(Drive1050Error::NoSuchUnit(unit));
+        }
+        if !RomKind::Drive1050.fits(rom.len()) {
+            return Err(Drive1050Error::WrongRomSize(rom.len()));
+        }
+        let bus = DriveBus {
+            ram: [0; 128],
+            rom: rom.to_vec(),
+            riot: Riot::new(),
+            fdc: Wd2793::new(),
+            floppy: Floppy::new(),
+            phase: 0,
+        };
+        let mut drive = Drive1050 {
+            cpu: Cpu::new(),
+            bus,
+            unit,
+            path: None,
+            write_error: None,
+            owed: 0,
+        };
+        drive.bus.update_inputs(unit, true, true);
+        drive.cpu.reset(&mut drive.bus);
+        Ok(drive)
+    }
+
+    pub fn unit(&self) -> u8 {
+        self.unit
+    }
+
+    /// Set the drive select switches to D`unit`: (1-4). The firmware sees
+    /// the change at its next command.
+    pub fn set_unit(&mut self, unit: u8) -> Result<(), Drive1050Error> {
+        if !(1..=4).contains(&unit) {
+            return Err(Drive1050Error::NoSuchUnit(unit));
+        }
+        self.unit = unit;
+        Ok(())
+    }
+
+    /// SIO device ID the drive answers to
+    pub fn device_id(&self) -> u8 {
+        D1 + self.unit - 1
+    }
+
+    /// Put in a disk that only lives in memory, replacing any already
+    /// there
+    pub fn insert_disk(&mut self, disk: Box<dyn DiskImage>) {
+        self.bus.floppy.insert(disk);
+        self.path = None;
+    }
+
+    /// Put in the disk image in a file, whose format is told by its
+    /// contents. Changes go back to the file.
+    pub fn open_disk<P: AsRef<Path>>(&mut self, path: P) -> Result<(), DiskError> {
+        let disk = disk_image::open_image(&path)?;
+        self.bus.floppy.insert(disk);
+        self.path = Some(path.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    pub fn eject_disk(&mut self) -> Option<Box<dyn DiskImage>> {
+        self.path = None;
+        self.bus.floppy.eject()
+    }
+
+    pub fn disk(&self) -> Option<&dyn DiskImage> {
+        self.bus.floppy.disk()
+    }
+
+    /// The file changes are written back to, if there is one
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Why writing the disk back to its file last failed, if it has since
+    /// this was last asked. The next change to the disk tries again.
+    pub fn take_write_error(&mut self) -> Option<io::Error> {
+        self.write_error.take()
+    }
+
+    pub fn write_protected(&self) -> bool {
+        self.bus.floppy.write_protected()
+    }
+
+    /// Set or clear the write protect tab. Read-only formats stay
+    /// protected.
+    pub fn set_write_protected(&mut self, protected: bool) {
+        self.bus.floppy.set_write_protected(protected);
+    }
+
+    /// Track under the head
+    pub fn head_track(&self) -> usize {
+        self.bus.floppy.head
+    }
+
+    pub fn motor_on(&self) -> bool {
+        self.bus.floppy.motor
+    }
+
+    /// The drive's CPU, for looking at where the firmware is
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Run one drive cycle
+    fn step(&mut self) {
+        self.cpu.tick(&mut self.bus);
+        self.bus.riot.tick();
+        self.bus.floppy.clock += 1;
+        self.bus.fdc.tick(&mut self.bus.floppy);
+        if self.bus.floppy.take_changed() {
+            self.save();
+        }
+    }
+
+    /// Write the disk back to its file
+    fn save(&mut self) {
+        if let (Some(path), Some(disk)) = (&self.path, self.bus.floppy.disk()) {
+            if let Err(e) = fs::write(path, disk.to_bytes()) {
+                self.write_error = Some(e);
+            }
+        }
+    }
+}
+
+impl SerialLineDevice for Drive1050 {
+    fn tick(&mut self, command_line: bool, data_out: bool) -> bool {
+        self.owed += DRIVE_HZ;
+        while self.owed >= MACHINE_HZ {
+            self.owed -= MACHINE_HZ;
+            self.bus.update_inputs(self.unit, command_line, data_out);
+            self.step();
+        }
+        self.bus.riot.port_b() & PB_DATA_OUT != 0
+    }
+}
@@ -7,7 +7,8 @@
 
 use crate::bus::Bus;
 use crate::cpu::Cpu;
-use crate::mem::Mem;
+use crate::mem::{LoadError, Mem, Region};
+use std::path::Path;
 
 pub struct FunctionalTest {
     cpu: Cpu,
@@ -16,10 +17,24 @@ pub struct FunctionalTest {
 }
 
 impl FunctionalTest {
-    pub fn new() -> FunctionalTest {
+    /// Build the machine around a test image, loaded into RAM from $0000
+    pub fn new(image: &[u8]) -> Result<FunctionalTest, LoadError> {
+        let mut mem = Mem::blank(0);  // split=0 means all RAM
+        mem.load(Region::Ram, 0x0000, image)?;
+        Ok(FunctionalTest::with_mem(mem))
+    }
+
+    /// Load the test image from a file (normally 6502_functional_test.bin)
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<FunctionalTest, LoadError> {
+        let mut mem = Mem::blank(0);
+        mem.load_file(Region::Ram, 0x0000, path)?;
+        Ok(FunctionalTest::with_mem(mem))
+    }
+
+    fn with_mem(mem: Mem) -> FunctionalTest {
         let mut test = FunctionalTest {
             cpu: Cpu::new(),
-            mem: Mem::new(0, true),  // split=0 means all RAM, load_test=true
+            mem,
             cycle_count: 0,
         };
 
@@ -1,3 +1,4 @@
+use crate::antic::{HIRES_BACKGROUND, HIRES_PIXEL, NORMAL_PLAYFIELD_START};
 use crate::framebuffer::Framebuffer;
 
 /// GTIA - Graphics Television Interface Adaptor
@@ -17,7 +18,8 @@ pub struct Gtia {
     vdelay: u8,         // $D01C - Vertical delay
     gractl: u8,         // $D01D - Graphics control
     hitclr: u8,         // $D01E - Clear collision registers
-    consol: u8,         // $D01F - Console switches
+    consol: u8,         // $D01F - Console switches (write: speaker, switch pull-downs)
+    consol_input: u8,   // Switch state: bit 0 START, 1 SELECT, 2 OPTION (0 = pressed)
 
     // Collision detection (read-only)
     m0pf: u8,           // $D000 - Missile 0 to playfield
@@ -59,6 +61,7 @@ impl Gtia {
             gractl: 0,
             hitclr: 0,
             consol: 0,
+            consol_input: 0x07,
             m0pf: 0,
             m1pf: 0,
             m2pf: 0,
@@ -127,7 +130,7 @@ impl Gtia {
             0x12 => self.trig[2],
             0x13 => self.trig[3],
             // Console switches
-            0x1F => self.consol,
+            0x1F => self.consol_input & !self.consol & 0x07,
             // Color registers (0x16-0x1A) and other write-only registers return 0xFF
             _ => 0xFF,
         }
@@ -176,6 +179,18 @@ impl Gtia {
         }
     }
 
+    /// Set the console switch inputs (bit 0 START, 1 SELECT, 2 OPTION;
+    /// 0 = pressed)
+    pub fn set_consol_input(&mut self, switches: u8) {
+        self.consol_input = switches & 0x07;
+    }
+
+    /// Set a trigger input (1 = not pressed). On XL/XE machines TRIG3
+    /// reports whether a cartridge is driving $A000-$BFFF.
+    pub fn set_trig(&mut self, index: usize, val: u8) {
+        self.trig[index] = val & 0x01;
+    }
+
     /// Convert Atari color value to RGB
     /// Atari color format: bits 7-4 = hue (0-15), bits 3-1 = luminance (0-7), bit 0 ignored
     pub fn color_to_rgb(&self, atari_color: u8) -> (u8, u8, u8) {
@@ -196,23 +211,27 @@ impl Gtia {
     }
 
     /// Colorize an ANTIC scanline and write to framebuffer
-    /// Called once per scanline during frame rendering
+    /// Called once per scanline during frame rendering. The screen shows
+    /// the normal-width playfield out of ANTIC's wide scanline.
     pub fn render_scanline(&mut self, scanline_y: usize, antic_pixels: &[u8; 384]) {
         for x in 0..320 {
-            let color_index = antic_pixels[x];
+            let color_index = antic_pixels[NORMAL_PLAYFIELD_START + x];
             let (r, g, b) = self.get_color_for_index(color_index);
             self.framebuffer.set_pixel(x, scanline_y, r, g, b);
         }
     }
 
-    /// Get RGB color for a color index (0-3)
-    /// Private method - accesses color registers directly without read_register() hack
-    fn get_color_for_index(&self, index: u8) -> (u8, u8, u8) {
+    /// Get RGB color for a color index from ANTIC's scanline buffer (0-4,
+    /// or a hi-res one), from the color registers as they are now
+    pub fn get_color_for_index(&self, index: u8) -> (u8, u8, u8) {
         let atari_color = match index {
             0 => self.colbk,           // Background
             1 => self.colpf[0],        // Playfield 0
             2 => self.colpf[1],        // Playfield 1
             3 => self.colpf[2],        // Playfield 2
+            4 => self.colpf[3],        // Playfield 3
+            HIRES_BACKGROUND => self.colpf[2],
+            HIRES_PIXEL => (self.colpf[2] & 0xF0) | (self.colpf[1] & 0x0F),
             _ => self.colbk,           // Fallback
         };
         self.color_to_rgb(atari_color)
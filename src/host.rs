@@ -0,0 +1,412 @@
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Where the H: handler table appears, in the unused $D600 page of the I/O
+/// area. CIO finds it through HATABS and reads the vectors from here.
+pub const HANDLER_TABLE: u16 = 0xD600;
+
+/// Entry points the vectors lead to. The CPU is stopped on arriving at one
+/// and the emulator does the work instead.
+const ENTRY_POINTS: u16 = HANDLER_TABLE + 0x10;
+
+/// Status codes the handler returns in Y
+pub const STATUS_SUCCESS: u8 = 0x01;
+pub const STATUS_NO_DEVICE: u8 = 0x82;
+pub const STATUS_WRITE_ONLY: u8 = 0x83;
+pub const STATUS_NOT_OPEN: u8 = 0x85;
+pub const STATUS_OUTPUT_NOT_ALLOWED: u8 = 0x87;
+pub const STATUS_END_OF_FILE: u8 = 0x88;
+pub const STATUS_DEVICE_ERROR: u8 = 0x90;
+pub const STATUS_NOT_SUPPORTED: u8 = 0x92;
+pub const STATUS_FILE_EXISTS: u8 = 0x97;
+pub const STATUS_BAD_NAME: u8 = 0xA5;
+pub const STATUS_LOCKED: u8 = 0xA7;
+pub const STATUS_NOT_FOUND: u8 = 0xAA;
+
+/// OPEN modes, in AUX1
+const MODE_READ: u8 = 0x04;
+const MODE_DIRECTORY: u8 = 0x06;
+const MODE_WRITE: u8 = 0x08;
+const MODE_APPEND: u8 = 0x09;
+const MODE_UPDATE: u8 = 0x0C;
+
+/// XIO commands
+const COMMAND_RENAME: u8 = 32;
+const COMMAND_DELETE: u8 = 33;
+const COMMAND_LOCK: u8 = 35;
+const COMMAND_UNLOCK: u8 = 36;
+
+const EOL: u8 = 0x9B;
+
+/// Bytes in a DOS 2 data sector, for the sizes in a directory listing
+const SECTOR_DATA: u64 = 125;
+
+/// Handler routines, in the order of their vectors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HostEntry {
+    Open,
+    Close,
+    Get,
+    Put,
+    Status,
+    Special,
+    Init,
+}
+
+impl HostEntry {
+    const ALL: [HostEntry; 7] = [
+        HostEntry::Open,
+        HostEntry::Close,
+        HostEntry::Get,
+        HostEntry::Put,
+        HostEntry::Status,
+        HostEntry::Special,
+        HostEntry::Init,
+    ];
+
+    /// The routine that starts at `pc`, if any
+    pub fn at(pc: u16) -> Option<HostEntry> {
+        HostEntry::ALL.get(pc.checked_sub(ENTRY_POINTS)? as usize).copied()
+    }
+}
+
+/// A byte of the $D600 page: the handler table, six vectors (address
+/// minus one) and a JMP to the init routine, then the entry points, which
+/// read as RTS
+pub fn table_byte(addr: u16) -> u8 {
+    let offset = addr.wrapping_sub(HANDLER_TABLE) as usize;
+    match offset {
+        0..=11 => {
+            let entry = ENTRY_POINTS + (offset / 2) as u16 - 1;
+            entry.to_le_bytes()[offset % 2]
+        }
+        12 => 0x4C,
+        13..=14 => (ENTRY_POINTS + 6).to_le_bytes()[offset - 13],
+        _ => 0x60,
+    }
+}
+
+/// An 8.3 file name, upper case and padded with spaces. In a pattern '?'
+/// stands for any character, and '*' has been filled out with them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FileName([u8; 11]);
+
+impl FileName {
+    /// Parse a name as the Atari gives it, wildcards allowed or not
+    fn parse(name: &[u8], wildcards: bool) -> Option<FileName> {
+        let mut parts = name.splitn(2, |&c| c == b'.');
+        let base = parts.next()?;
+        let ext = parts.next().unwrap_or(&[]);
+        if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+            return None;
+        }
+
+        let mut padded = [b' '; 11];
+        let (base_field, ext_field) = padded.split_at_mut(8);
+        for (part, field) in [(base, base_field), (ext, ext_field)] {
+            for (i, &c) in part.iter().enumerate() {
+                match c.to_ascii_uppercase() {
+                    b'*' if wildcards => {
+                        field[i..].fill(b'?');
+                        break;
+                    }
+                    b'?' if wildcards => field[i] = b'?',
+                    c if c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_' => field[i] = c,
+                    _ => return None,
+                }
+            }
+        }
+        Some(FileName(padded))
+    }
+
+    /// The name a host file goes by on the Atari, if it has a usable one
+    fn from_host(name: &str) -> Option<FileName> {
+        FileName::parse(name.as_bytes(), false)
+    }
+
+    fn matches(&self, name: &FileName) -> bool {
+        self.0.iter().zip(name.0).all(|(&p, c)| p == b'?' || p == c)
+    }
+
+    /// A rename's new name for `old`: wildcards keep old's characters
+    fn rename(&self, old: &FileName) -> FileName {
+        let mut name = *self;
+        for (c, &o) in name.0.iter_mut().zip(&old.0) {
+            if *c == b'?' {
+                *c = o;
+            }
+        }
+        name
+    }
+
+    /// NAME.EXT as the host file is called when the Atari creates it
+    fn to_host(self) -> String {
+        let base = String::from_utf8_lossy(&self.0[..8]).trim_end().to_string();
+        let ext = String::from_utf8_lossy(&self.0[8..]).trim_end().to_string();
+        if ext.is_empty() { base } else { format!("{}.{}", base, ext) }
+    }
+}
+
+/// An open IOCB
+enum Channel {
+    File { file: fs::File, read: bool, write: bool },
+    Listing { data: Vec<u8>, pos: usize },
+}
+
+/// The H: device: a directory on the host that programs reach through CIO
+/// as if it were a DOS disk. OPEN, GET, PUT, CLOSE and STATUS work on
+/// files, reading a directory listing with OPEN mode 6; XIO 32 renames,
+/// 33 deletes, 35 and 36 lock and unlock.
+///
+/// Names are DOS 2 style, up to eight letters, digits or underscores with
+/// an extension of up to three, matching host files whatever their case.
+/// Host files with other names can't be seen.
+pub struct HostDevice {
+    dir: PathBuf,
+    channels: [Option<Channel>; 8],
+}
+
+impl HostDevice {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> HostDevice {
+        HostDevice { dir: dir.into(), channels: Default::default() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// OPEN on IOCB `channel`. `spec` is the file specification from the
+    /// IOCB buffer, "H:NAME.EXT" or "Hn:NAME.EXT".
+    pub fn open(&mut self, channel: usize, spec: &[u8], aux1: u8) -> u8 {
+        let name = match file_part(spec) {
+            Some(name) => name,
+            None => return STATUS_BAD_NAME,
+        };
+        let opened = if aux1 == MODE_DIRECTORY {
+            let pattern = if name.is_empty() { b"*.*" } else { name };
+            FileName::parse(pattern, true).ok_or(STATUS_BAD_NAME).map(|pattern| self.listing(&pattern))
+        } else {
+            self.open_file(name, aux1)
+        };
+        match opened {
+            Ok(opened) => {
+                self.channels[channel & 7] = Some(opened);
+                STATUS_SUCCESS
+            }
+            Err(status) => status,
+        }
+    }
+
+    fn open_file(&self, name: &[u8], mode: u8) -> Result<Channel, u8> {
+        let read = mode & MODE_READ != 0;
+        let write = mode & MODE_WRITE != 0;
+        if !matches!(mode, MODE_READ | MODE_WRITE | MODE_APPEND | MODE_UPDATE) {
+            return Err(STATUS_NOT_SUPPORTED);
+        }
+
+        let path = if read {
+            // Reading takes the first match of a pattern, as DOS does
+            let pattern = FileName::parse(name, true).ok_or(STATUS_BAD_NAME)?;
+            self.find(&pattern)?.into_iter().next().ok_or(STATUS_NOT_FOUND)?.1
+        } else {
+            let name = FileName::parse(name, false).ok_or(STATUS_BAD_NAME)?;
+            self.path_for(&name)?
+        };
+
+        let mut options = fs::OpenOptions::new();
+        match mode {
+            MODE_READ => options.read(true),
+            MODE_WRITE => options.write(true).create(true).truncate(true),
+            MODE_APPEND => options.append(true).create(true),
+            _ => options.read(true).write(true),
+        };
+        let file = options.open(path).map_err(io_status)?;
+        Ok(Channel::File { file, read, write })
+    }
+
+    pub fn close(&mut self, channel: usize) -> u8 {
+        match self.channels[channel & 7].take() {
+            Some(Channel::File { mut file, write: true, .. }) => match file.flush() {
+                Ok(()) => STATUS_SUCCESS,
+                Err(e) => io_status(e),
+            },
+            _ => STATUS_SUCCESS,
+        }
+    }
+
+    /// GET a byte: the status and the byte
+    pub fn get(&mut self, channel: usize) -> (u8, u8) {
+        match &mut self.channels[channel & 7] {
+            Some(Channel::File { file, read: true, .. }) => {
+                let mut byte = [0];
+                match file.read(&mut byte) {
+                    Ok(1) => (STATUS_SUCCESS, byte[0]),
+                    Ok(_) => (STATUS_END_OF_FILE, 0),
+                    Err(e) => (io_status(e), 0),
+                }
+            }
+            Some(Channel::Listing { data, pos }) => match data.get(*pos) {
+                Some(&byte) => {
+                    *pos += 1;
+                    (STATUS_SUCCESS, byte)
+                }
+                None => (STATUS_END_OF_FILE, 0),
+            },
+            Some(_) => (STATUS_WRITE_ONLY, 0),
+            None => (STATUS_NOT_OPEN, 0),
+        }
+    }
+
+    pub fn put(&mut self, channel: usize, byte: u8) -> u8 {
+        match &mut self.channels[channel & 7] {
+            Some(Channel::File { file, write: true, .. }) => match file.write_all(&[byte]) {
+                Ok(()) => STATUS_SUCCESS,
+                Err(e) => io_status(e),
+            },
+            Some(_) => STATUS_OUTPUT_NOT_ALLOWED,
+            None => STATUS_NOT_OPEN,
+        }
+    }
+
+    pub fn status(&mut self, channel: usize) -> u8 {
+        match &mut self.channels[channel & 7] {
+            Some(Channel::File { file, read: true, .. }) => {
+                // Whether there is anything left to read
+                let pos = file.stream_position();
+                let len = file.metadata().map(|m| m.len());
+                match (pos, len) {
+                    (Ok(pos), Ok(len)) if pos < len => STATUS_SUCCESS,
+                    _ => STATUS_END_OF_FILE,
+                }
+            }
+            Some(_) => STATUS_SUCCESS,
+            None => STATUS_NOT_OPEN,
+        }
+    }
+
+    /// XIO `command` naming files in `spec`: "H:OLD,NEW" to rename,
+    /// otherwise a name or pattern
+    pub fn special(&mut self, command: u8, spec: &[u8]) -> u8 {
+        let names = match file_part(spec) {
+            Some(names) => names,
+            None => return STATUS_BAD_NAME,
+        };
+        let mut names = names.splitn(2, |&c| c == b',');
+        let pattern = match FileName::parse(names.next().unwrap_or(&[]), true) {
+            Some(pattern) => pattern,
+            None => return STATUS_BAD_NAME,
+        };
+        let new_name = names.next().map(|name| FileName::parse(name, true));
+
+        let result = match (command, new_name) {
+            (COMMAND_RENAME, Some(Some(new_name))) => self.rename(&pattern, &new_name),
+            (COMMAND_RENAME, _) => Err(STATUS_BAD_NAME),
+            (COMMAND_DELETE, _) => self.each(&pattern, |path| fs::remove_file(path)),
+            (COMMAND_LOCK | COMMAND_UNLOCK, _) => {
+                let locked = command == COMMAND_LOCK;
+                self.each(&pattern, |path| {
+                    let mut permissions = fs::metadata(path)?.permissions();
+                    permissions.set_readonly(locked);
+                    fs::set_permissions(path, permissions)
+                })
+            }
+            _ => Err(STATUS_NOT_SUPPORTED),
+        };
+        result.err().unwrap_or(STATUS_SUCCESS)
+    }
+
+    fn rename(&self, pattern: &FileName, new_name: &FileName) -> Result<(), u8> {
+        let found = self.find(pattern)?;
+        if found.is_empty() {
+            return Err(STATUS_NOT_FOUND);
+        }
+        for (old, path) in found {
+            let new_name = new_name.rename(&old);
+            if new_name == old {
+                continue;
+            }
+            if !self.find(&new_name)?.is_empty() {
+                return Err(STATUS_FILE_EXISTS);
+            }
+            fs::rename(&path, self.dir.join(new_name.to_host())).map_err(io_status)?;
+        }
+        Ok(())
+    }
+
+    /// Do something to every file matching a pattern
+    fn each(&self, pattern: &FileName, f: impl Fn(&Path) -> io::Result<()>) -> Result<(), u8> {
+        let found = self.find(pattern)?;
+        if found.is_empty() {
+            return Err(STATUS_NOT_FOUND);
+        }
+        for (_, path) in found {
+            f(&path).map_err(io_status)?;
+        }
+        Ok(())
+    }
+
+    /// The host files matching a pattern, sorted by name
+    fn find(&self, pattern: &FileName) -> Result<Vec<(FileName, PathBuf)>, u8> {
+        let mut found: Vec<(FileName, PathBuf)> = fs::read_dir(&self.dir)
+            .map_err(io_status)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                if !entry.file_type().ok()?.is_file() {
+                    return None;
+                }
+                let name = FileName::from_host(entry.file_name().to_str()?)?;
+                Some((name, entry.path())).filter(|_| pattern.matches(&name))
+            })
+            .collect();
+        found.sort_by_key(|(name, _)| name.0);
+        Ok(found)
+    }
+
+    /// The host file for a name, existing or to be created
+    fn path_for(&self, name: &FileName) -> Result<PathBuf, u8> {
+        Ok(match self.find(name)?.into_iter().next() {
+            Some((_, path)) => path,
+            None => self.dir.join(name.to_host()),
+        })
+    }
+
+    /// A DOS 2 style listing: a line for each file with its size in
+    /// sectors, '*' marking locked ones, then the free sectors
+    fn listing(&self, pattern: &FileName) -> Channel {
+        let mut data = Vec::new();
+        for (name, path) in self.find(pattern).unwrap_or_default() {
+            let metadata = fs::metadata(&path).ok();
+            let locked = metadata.as_ref().is_some_and(|m| m.permissions().readonly());
+            let sectors = metadata.map_or(0, |m| m.len().div_ceil(SECTOR_DATA).min(999));
+            data.push(if locked { b'*' } else { b' ' });
+            data.push(b' ');
+            data.extend_from_slice(&name.0[..8]);
+            data.extend_from_slice(&name.0[8..]);
+            data.extend_from_slice(format!(" {:03}", sectors).as_bytes());
+            data.push(EOL);
+        }
+        data.extend_from_slice(b"999 FREE SECTORS");
+        data.push(EOL);
+        Channel::Listing { data, pos: 0 }
+    }
+}
+
+/// The part of a file specification after the device, up to the EOL.
+/// None if there is no device.
+fn file_part(spec: &[u8]) -> Option<&[u8]> {
+    let end = spec.iter().position(|&c| c == EOL || c == 0 || c == b' ').unwrap_or(spec.len());
+    let spec = &spec[..end];
+    let colon = spec.iter().position(|&c| c == b':')?;
+    Some(&spec[colon + 1..])
+}
+
+/// The status for a host error
+fn io_status(e: io::Error) -> u8 {
+    match e.kind() {
+        io::ErrorKind::NotFound => STATUS_NOT_FOUND,
+        io::ErrorKind::PermissionDenied => STATUS_LOCKED,
+        io::ErrorKind::AlreadyExists => STATUS_FILE_EXISTS,
+        _ => STATUS_DEVICE_ERROR,
+    }
+}
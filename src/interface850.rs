@@ -0,0 +1,298 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::asm::{self, AsmError};
+use crate::serial_bridge::SerialBridge;
+use crate::sio::{SioDevice, SioResponse};
+
+/// SIO device ID of the 850's first serial port, R1:; R2:-R4: follow
+pub const R1: u8 = 0x50;
+
+/// Ports on the interface
+pub const PORTS: u8 = 4;
+
+const HANDLER_SOURCE: &str = include_str!("r_handler.asm");
+
+/// Where the bootstrap loads, and where it is entered
+const BOOTSTRAP_START: u16 = 0x0500;
+
+/// Bytes in a block sent with 'W'
+const BLOCK_SIZE: usize = 32;
+
+/// Machine cycles per second
+const CLOCK_HZ: f64 = 1_789_790.0;
+
+/// Baud rates for the rate codes in 'B' AUX1 bits 0-3
+const BAUD_RATES: [f64; 16] = [
+    300.0, 45.5, 50.0, 56.875, 75.0, 110.0, 134.5, 150.0, 300.0, 600.0, 1200.0, 1800.0, 2400.0,
+    4800.0, 9600.0, 9600.0,
+];
+
+/// 'B' AUX1: two stop bits
+const TWO_STOP_BITS: u8 = 0x80;
+
+/// 'B' AUX2: lines that must be on for the command to succeed
+const CHECK_DSR: u8 = 0x04;
+const CHECK_CTS: u8 = 0x02;
+const CHECK_CRX: u8 = 0x01;
+
+/// 'A' AUX1: which of DTR, RTS and XMT to set, and what to
+const SET_DTR: u8 = 0x80;
+const DTR_ON: u8 = 0x40;
+const SET_RTS: u8 = 0x20;
+const RTS_ON: u8 = 0x10;
+const SET_XMT: u8 = 0x02;
+const XMT_ON: u8 = 0x01;
+
+/// Status byte 0: errors since the last status
+const ERROR_LINE_CHECK: u8 = 0x04;
+
+/// Status byte 1: DSR (bits 7-6), CTS (5-4) and carrier (3-2), each on
+/// and on since the last status
+const LINES_ON: u8 = 0xFC;
+
+/// Why the R: handler or its bootstrap couldn't be built
+#[derive(Clone, Debug, PartialEq)]
+pub enum HandlerError {
+    Assembly(AsmError),
+    /// The source doesn't define a symbol the build needs
+    MissingSymbol(&'static str),
+    /// The handler wouldn't fit between page zero and the end of memory
+    /// if loaded at this address
+    NoRoom(u16),
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandlerError::Assembly(error) => write!(f, "R: handler doesn't assemble: {}", error),
+            HandlerError::MissingSymbol(name) => write!(f, "R: handler source has no {}", name),
+            HandlerError::NoRoom(base) => write!(f, "no room for the R: handler at ${:04X}", base),
+        }
+    }
+}
+
+impl std::error::Error for HandlerError {}
+
+/// One serial port of an 850 interface module, as its own device on the
+/// serial bus.
+///
+/// The computer sets the port up with 'B' (baud rate and word size) and
+/// 'A' (control lines), reads it with 'S' and writes blocks with 'W'. 'X'
+/// puts it in concurrent mode, where bytes go straight between the bus and
+/// the port at the port's baud rate until the next command frame.
+///
+/// Port 1 also answers the boot sequence: '?' gives a device control
+/// block for fetching the bootstrap, '!' the bootstrap and '&' the R:
+/// handler, built for the address in AUX1/AUX2.
+pub struct SerialPort {
+    unit: u8,
+    bridge: Option<Box<dyn SerialBridge>>,
+    baud: f64,
+    word_bits: u8,
+    stop_bits: u8,
+    dtr: bool,
+    rts: bool,
+    xmt: bool,
+    errors: u8,
+    block_count: usize,
+    concurrent: bool,
+}
+
+impl SerialPort {
+    /// Port `unit` (1-4), connected through `bridge` if there is one
+    pub fn new(unit: u8, bridge: Option<Box<dyn SerialBridge>>) -> SerialPort {
+        SerialPort {
+            unit,
+            bridge,
+            baud: 300.0,
+            word_bits: 8,
+            stop_bits: 1,
+            dtr: true,
+            rts: true,
+            xmt: true,
+            errors: 0,
+            block_count: 0,
+            concurrent: false,
+        }
+    }
+
+    pub fn baud(&self) -> f64 {
+        self.baud
+    }
+
+    pub fn word_bits(&self) -> u8 {
+        self.word_bits
+    }
+
+    pub fn dtr(&self) -> bool {
+        self.dtr
+    }
+
+    pub fn rts(&self) -> bool {
+        self.rts
+    }
+
+    pub fn concurrent(&self) -> bool {
+        self.concurrent
+    }
+
+    fn connected(&mut self) -> bool {
+        self.bridge.as_mut().is_some_and(|bridge| bridge.connected())
+    }
+
+    fn word_mask(&self) -> u8 {
+        (0xFFu16 >> (8 - self.word_bits)) as u8
+    }
+
+    fn send(&mut self, byte: u8) {
+        let byte = byte & self.word_mask();
+        if let (Some(bridge), true) = (self.bridge.as_mut(), self.xmt) {
+            bridge.send(byte);
+        }
+    }
+
+    /// 'B': AUX1 bits 0-3 the rate, 4-5 the word size less 5, bit 7 two
+    /// stop bits. Fails if a line AUX2 checks is off.
+    fn configure(&mut self, aux1: u8, aux2: u8) -> SioResponse {
+        self.baud = BAUD_RATES[(aux1 & 0x0F) as usize];
+        self.word_bits = 5 + ((aux1 >> 4) & 0x03);
+        self.stop_bits = if aux1 & TWO_STOP_BITS != 0 { 2 } else { 1 };
+        if aux2 & (CHECK_DSR | CHECK_CTS | CHECK_CRX) != 0 && !self.connected() {
+            self.errors |= ERROR_LINE_CHECK;
+            return SioResponse::Error(Vec::new());
+        }
+        SioResponse::Complete(Vec::new())
+    }
+
+    /// 'A': set the control lines. Dropping DTR hangs up.
+    fn control(&mut self, aux1: u8) -> SioResponse {
+        if aux1 & SET_DTR != 0 {
+            self.dtr = aux1 & DTR_ON != 0;
+            if !self.dtr {
+                if let Some(bridge) = self.bridge.as_mut() {
+                    bridge.hang_up();
+                }
+            }
+        }
+        if aux1 & SET_RTS != 0 {
+            self.rts = aux1 & RTS_ON != 0;
+        }
+        if aux1 & SET_XMT != 0 {
+            self.xmt = aux1 & XMT_ON != 0;
+        }
+        SioResponse::Complete(Vec::new())
+    }
+
+    fn status(&mut self) -> Vec<u8> {
+        let lines = if self.connected() { LINES_ON } else { 0 };
+        vec![std::mem::take(&mut self.errors), lines]
+    }
+}
+
+impl SioDevice for SerialPort {
+    fn command(&mut self, command: u8, aux1: u8, aux2: u8) -> SioResponse {
+        match command {
+            b'B' => self.configure(aux1, aux2),
+            b'A' => self.control(aux1),
+            b'S' => SioResponse::Complete(self.status()),
+            b'W' => {
+                self.block_count = (aux1 as usize).min(BLOCK_SIZE);
+                SioResponse::Receive(BLOCK_SIZE)
+            }
+            b'X' => {
+                self.concurrent = true;
+                SioResponse::Complete(Vec::new())
+            }
+            b'?' if self.unit == 1 => poll_block().map_or(SioResponse::Nak, SioResponse::Complete),
+            b'!' if self.unit == 1 => bootstrap().map_or(SioResponse::Nak, SioResponse::Complete),
+            b'&' if self.unit == 1 => handler(u16::from_le_bytes([aux1, aux2]))
+                .map_or(SioResponse::Nak, SioResponse::Complete),
+            _ => SioResponse::Nak,
+        }
+    }
+
+    fn data(&mut self, data: &[u8]) -> SioResponse {
+        for &byte in &data[..self.block_count.min(data.len())] {
+            self.send(byte);
+        }
+        SioResponse::Complete(Vec::new())
+    }
+
+    fn concurrent_byte_cycles(&self) -> Option<u32> {
+        let bits = 1 + self.word_bits + self.stop_bits;
+        Some((CLOCK_HZ * bits as f64 / self.baud) as u32).filter(|_| self.concurrent)
+    }
+
+    fn concurrent_write(&mut self, byte: u8) {
+        self.send(byte);
+    }
+
+    fn concurrent_read(&mut self) -> Option<u8> {
+        let mask = self.word_mask();
+        self.bridge.as_mut()?.receive().map(|byte| byte & mask)
+    }
+
+    fn end_concurrent(&mut self) {
+        self.concurrent = false;
+    }
+}
+
+fn assemble(defines: &[(&str, i64)]) -> Result<asm::Assembly, HandlerError> {
+    asm::assemble(HANDLER_SOURCE, defines).map_err(HandlerError::Assembly)
+}
+
+fn symbol(assembly: &asm::Assembly, name: &'static str) -> Result<u16, HandlerError> {
+    assembly.symbol(name).ok_or(HandlerError::MissingSymbol(name))
+}
+
+/// What every boot needs: the memory the handler takes up with its
+/// buffers, and the bootstrap
+struct BootImages {
+    span: u16,
+    bootstrap: Vec<u8>,
+}
+
+/// Built the first time the 850 is asked to boot, and kept
+fn boot_images() -> Result<&'static BootImages, HandlerError> {
+    static BOOT_IMAGES: OnceLock<Result<BootImages, HandlerError>> = OnceLock::new();
+    BOOT_IMAGES
+        .get_or_init(|| {
+            // The handler's size doesn't depend on where it goes, as long
+            // as that's past page zero
+            let trial = assemble(&[("HANDLER", 1), ("BASE", 0x1000)])?;
+            let span = symbol(&trial, "handler_end")?.wrapping_sub(0x1000);
+            let size = symbol(&trial, "handler_size")?;
+            let assembly = assemble(&[("HANDLER", 0), ("HANDLER_SIZE", size as i64)])?;
+            let end = symbol(&assembly, "bootstrap_end")?;
+            let bootstrap = assembly.image(BOOTSTRAP_START, end.wrapping_sub(BOOTSTRAP_START) as usize);
+            Ok(BootImages { span, bootstrap })
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// The R: handler built to load at `base`. It has to fit between page
+/// zero and the end of memory with its buffers after it.
+pub fn handler(base: u16) -> Result<Vec<u8>, HandlerError> {
+    let span = boot_images()?.span;
+    if base < 0x0100 || base as usize + span as usize > 0x10000 {
+        return Err(HandlerError::NoRoom(base));
+    }
+    let assembly = assemble(&[("HANDLER", 1), ("BASE", base as i64)])?;
+    let size = symbol(&assembly, "handler_size")?;
+    Ok(assembly.image(base, size as usize))
+}
+
+/// The bootstrap, with the boot header in front of it
+pub fn bootstrap() -> Result<Vec<u8>, HandlerError> {
+    Ok(boot_images()?.bootstrap.clone())
+}
+
+/// The device control block for fetching the bootstrap, which the poll
+/// sends for the computer to copy to DDEVIC and pass to SIOV
+fn poll_block() -> Result<Vec<u8>, HandlerError> {
+    let [start_lo, start_hi] = BOOTSTRAP_START.to_le_bytes();
+    let [len_lo, len_hi] = (boot_images()?.bootstrap.len() as u16).to_le_bytes();
+    Ok(vec![R1, 1, b'!', 0x40, start_lo, start_hi, 0x08, 0, len_lo, len_hi, 0, 0])
+}
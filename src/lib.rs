@@ -1,11 +1,34 @@
+pub mod apple1;
+pub mod asm;
 pub mod atari800;
+pub mod atr;
+pub mod atx;
+pub mod builtin_os;
 pub mod bus;
+pub mod cartridge;
+pub mod cas;
+pub mod cassette;
 pub mod cpu;
+pub mod dcm;
 pub mod debugger;
+pub mod disk;
+pub mod disk_image;
+pub mod drive1050;
 pub mod framebuffer;
 pub mod functional_test;
+pub mod host;
+pub mod interface850;
 pub mod mem;
+pub mod printer;
+pub mod pro;
+pub mod riot;
+pub mod rom;
+pub mod serial_bridge;
+pub mod sio;
 pub mod antic;
 pub mod gtia;
 pub mod pokey;
 pub mod pia;
+pub mod wd2793;
+pub mod xex;
+pub mod xfd;
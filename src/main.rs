@@ -1,63 +1,478 @@
-use atari800_rs::atari800::Atari800;
+use atari800_rs::apple1::Apple1;
+use atari800_rs::atr::{AtrError, Density};
+use atari800_rs::atari800::{Atari800, Atari800Config};
+use atari800_rs::cartridge::Cartridge;
+use atari800_rs::cassette::Cassette;
+use atari800_rs::disk::{DiskDrive, DiskError};
+use atari800_rs::drive1050::Drive1050;
+use atari800_rs::disk_image::{self, ImageFormat};
 use atari800_rs::functional_test::FunctionalTest;
+use atari800_rs::mem::RamFill;
+use atari800_rs::printer::Printer;
+use atari800_rs::rom::{RomKind, RomSet};
+#[cfg(unix)]
+use atari800_rs::serial_bridge::{ModemLine, PtyBridge, TtyBridge};
+use atari800_rs::serial_bridge::{SerialBridge, TcpBridge};
+use atari800_rs::xex::Xex;
 use std::env;
+use std::io::{self, BufRead};
+use std::sync::mpsc;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("✗ {}", e);
+            return;
+        }
+    };
+
+    // System ROMs come from ./roms unless another directory is given, and
+    // have to be known dumps unless --allow-unknown-roms is given
+    let roms = RomSet {
+        allow_unknown: options.allow_unknown_roms,
+        basic: options.basic.as_ref().map(Into::into),
+        ..RomSet::with_search_dir(options.roms_dir.as_deref().unwrap_or("roms"))
+    };
+
+    match &options.mode {
+        Mode::FunctionalTest => {
+            // Run the 6502 functional test suite
+            match FunctionalTest::from_file("6502_functional_test.bin") {
+                Ok(mut test) => test.run(),
+                Err(e) => println!("✗ Error loading functional test: {}", e),
+            }
+        }
+        Mode::Render => {
+            // Render test pattern and save as image
+            println!("Rendering Atari 800 test pattern...");
+            let mut atari800 = Atari800::new();
+
+            // Render the screen
+            atari800.render();
+
+            // Save as PPM image
+            match atari800.save_framebuffer("atari800_output.ppm") {
+                Ok(_) => println!("✓ Saved framebuffer to atari800_output.ppm"),
+                Err(e) => println!("✗ Error saving framebuffer: {}", e),
+            }
 
-    // Check for flags
-    let run_functional_test = args.len() > 1 && (args[1] == "--test" || args[1] == "-t");
-    let render_test = args.len() > 1 && (args[1] == "--render" || args[1] == "-r");
-    let debugger_mode = args.len() > 1 && (args[1] == "--debug" || args[1] == "-d");
-    let animate_mode = args.len() > 1 && (args[1] == "--animate" || args[1] == "-a");
-
-    if run_functional_test {
-        // Run the 6502 functional test suite
-        let mut test = FunctionalTest::new();
-        test.run();
-    } else if render_test {
-        // Render test pattern and save as image
-        println!("Rendering Atari 800 test pattern...");
-        let mut atari800 = Atari800::new();
-
-        // Render the screen
-        atari800.render();
+            // Convert to PNG using ImageMagick if available
+            println!("\nTo view the image:");
+            println!("  convert atari800_output.ppm atari800_output.png");
+            println!("  open atari800_output.png");
+        }
+        Mode::Debugger => {
+            // Run the Atari 800 emulator with debugger
+            println!("Starting Atari 800 with debugger");
+            let mut config = match atari_config(&options) {
+                Some(config) => config,
+                None => return,
+            };
+            if let Err(e) = config.load_roms(&roms) {
+                println!("✗ Error loading ROMs: {}", e);
+                return;
+            }
+            let mut atari800 = match Atari800::with_config(config) {
+                Ok(atari800) => atari800,
+                Err(e) => {
+                    println!("✗ Error loading ROMs: {}", e);
+                    return;
+                }
+            };
+
+            loop {
+                atari800.tick();
+            }
+        }
+        Mode::Apple1(rom_path) => {
+            // Run the Apple-1 terminal machine with the given monitor ROM
+            run_apple1(rom_path);
+        }
+        Mode::Convert(input, output) => {
+            // Copy a disk image into the format of the output file's extension
+            convert_disk(input, output);
+        }
+        Mode::Animate => {
+            // Run color cycling animation test
+            run_animated_test();
+        }
+        Mode::Sdl => {
+            // Run with SDL display and CPU execution, with whatever the
+            // options plug in
+            let config = match atari_config(&options) {
+                Some(config) => config,
+                None => return,
+            };
+            if let Some(launch) = open_launch(&options, &roms) {
+                run_with_sdl(config, &roms, launch);
+            }
+        }
+    }
+}
+
+/// What the emulator does
+#[derive(Default)]
+enum Mode {
+    /// Run the Atari with an SDL display (the default)
+    #[default]
+    Sdl,
+    FunctionalTest,
+    Render,
+    Debugger,
+    Animate,
+    /// Run the Apple-1 with a monitor ROM
+    Apple1(String),
+    /// Convert a disk image from one file to another
+    Convert(String, String),
+}
+
+/// The command line. Options can come in any order; a mode option given
+/// twice takes the last one.
+#[derive(Default)]
+struct Options {
+    mode: Mode,
+
+    // The machine
+    roms_dir: Option<String>,
+    allow_unknown_roms: bool,
+    basic: Option<String>,
+    ram_fill: Option<RamFill>,
+    cart: Option<String>,
+    host_dir: Option<String>,
+
+    // What is plugged in or loaded once it's running. Disks go in D1:,
+    // D2:, ... in order, with `true` for a 1050 running its firmware.
+    xex: Option<String>,
+    disks: Vec<(String, bool)>,
+    tape: Option<String>,
+    record_tape: Option<String>,
+    printer: Option<String>,
+    port_850: Option<String>,
+    sio2pc: Option<String>,
+}
+
+/// Parse the command line arguments after the program name
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--test" | "-t" => options.mode = Mode::FunctionalTest,
+            "--render" | "-r" => options.mode = Mode::Render,
+            "--debug" | "-d" => options.mode = Mode::Debugger,
+            "--animate" | "-a" => options.mode = Mode::Animate,
+            "--apple1" | "-1" => options.mode = Mode::Apple1(value()?),
+            "--convert" | "-k" => options.mode = Mode::Convert(value()?, value()?),
+            "--roms" | "-R" => options.roms_dir = Some(value()?),
+            "--allow-unknown-roms" => options.allow_unknown_roms = true,
+            "--basic" | "-b" => options.basic = Some(value()?),
+            "--ram-fill" | "-F" => {
+                let spec = value()?;
+                options.ram_fill = Some(parse_ram_fill(&spec).ok_or_else(|| {
+                    format!("Unknown RAM fill {}: use zero, pattern, random, random:SEED or a byte value", spec)
+                })?);
+            }
+            "--cart" | "-c" => options.cart = Some(value()?),
+            "--host" | "-H" => options.host_dir = Some(value()?),
+            "--xex" | "-x" => options.xex = Some(value()?),
+            "--disk" | "-D" | "--1050" | "-5" => {
+                // Every file up to the next option
+                let firmware = arg == "--1050" || arg == "-5";
+                let first = value()?;
+                options.disks.push((first, firmware));
+                while let Some(path) = args.next_if(|arg| !arg.starts_with('-')) {
+                    options.disks.push((path.clone(), firmware));
+                }
+            }
+            "--tape" | "-T" => options.tape = Some(value()?),
+            "--record-tape" | "-W" => options.record_tape = Some(value()?),
+            "--printer" | "-P" => options.printer = Some(value()?),
+            "--850" | "-8" => options.port_850 = Some(value()?),
+            "--sio2pc" | "-S" => options.sio2pc = Some(value()?),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+    if options.tape.is_some() && options.record_tape.is_some() {
+        return Err("--tape and --record-tape both want the program recorder".to_string());
+    }
+    Ok(options)
+}
+
+/// The machine the options ask for, or None if the cartridge can't be
+/// loaded
+fn atari_config(options: &Options) -> Option<Atari800Config> {
+    let mut config = Atari800Config {
+        host_dir: options.host_dir.as_ref().map(Into::into),
+        ..Atari800Config::default()
+    };
+    if let Some(ram_fill) = options.ram_fill {
+        config.ram_fill = ram_fill;
+    }
+    if let Some(host_dir) = &options.host_dir {
+        println!("H: {}", host_dir);
+    }
+    if let Some(path) = &options.cart {
+        // A cartridge (.CAR, .ROM or .BIN)
+        match Cartridge::from_file(path) {
+            Ok(cart) => {
+                println!("Inserted {:?} cartridge {}", cart.cart_type(), path);
+                config.cartridge = Some(cart);
+            }
+            Err(e) => {
+                println!("✗ Error loading cartridge {}: {}", path, e);
+                return None;
+            }
+        }
+    }
+    Some(config)
+}
+
+/// Open everything the options plug in or load, or None, having said
+/// why, if something can't be
+fn open_launch(options: &Options, roms: &RomSet) -> Option<Launch> {
+    let mut launch = Launch::default();
+
+    // A binary load file (.XEX) to boot
+    if let Some(path) = &options.xex {
+        match Xex::from_file(path) {
+            Ok(xex) => {
+                println!("Loading {} ({} segments)", path, xex.segments.len());
+                launch.xex = Some(xex);
+            }
+            Err(e) => {
+                println!("✗ Error loading {}: {}", path, e);
+                return None;
+            }
+        }
+    }
+
+    // Disk images in D1:, D2:, ..., with 1050s running the drive ROM
+    let mut rom_1050 = None;
+    for (unit, (path, firmware)) in (1..).zip(&options.disks) {
+        if !firmware {
+            match DiskDrive::open(path) {
+                Ok(drive) => {
+                    println!("D{}: {}", unit, path);
+                    launch.disks.push(Drive::Image(drive));
+                }
+                Err(e) => {
+                    println!("✗ Error loading disk {}: {}", path, e);
+                    return None;
+                }
+            }
+            continue;
+        }
+        if rom_1050.is_none() {
+            match roms.load(RomKind::Drive1050) {
+                Ok(rom) => rom_1050 = Some(rom),
+                Err(e) => {
+                    println!("✗ Error loading the 1050 ROM: {}", e);
+                    return None;
+                }
+            }
+        }
+        let rom = rom_1050.as_ref()?;
+        let mut drive = match Drive1050::new(&rom.data, unit) {
+            Ok(drive) => drive,
+            Err(e) => {
+                println!("✗ {}", e);
+                return None;
+            }
+        };
+        if let Err(e) = drive.open_disk(path) {
+            println!("✗ Error loading disk {}: {}", path, e);
+            return None;
+        }
+        println!("D{}: {} (1050)", unit, path);
+        launch.disks.push(Drive::Firmware(Box::new(drive)));
+    }
+
+    // A CAS image in the program recorder, or a blank tape recording to
+    // a new CAS file
+    if let Some(path) = &options.tape {
+        match Cassette::open(path) {
+            Ok(cassette) => {
+                println!("C: {} ({} records)", path, cassette.tape().blocks.len());
+                launch.tape = Some(cassette);
+            }
+            Err(e) => {
+                println!("✗ Error loading tape {}: {}", path, e);
+                return None;
+            }
+        }
+    }
+    if let Some(path) = &options.record_tape {
+        println!("C: recording to {}", path);
+        launch.tape = Some(Cassette::record_to(path));
+    }
 
-        // Save as PPM image
-        match atari800.save_framebuffer("atari800_output.ppm") {
-            Ok(_) => println!("✓ Saved framebuffer to atari800_output.ppm"),
-            Err(e) => println!("✗ Error saving framebuffer: {}", e),
+    // A printer writing to a text file
+    if let Some(path) = &options.printer {
+        match Printer::create(path) {
+            Ok(printer) => {
+                println!("P: printing to {}", path);
+                launch.printer = Some(printer);
+            }
+            Err(e) => {
+                println!("✗ Error creating {}: {}", path, e);
+                return None;
+            }
         }
+    }
 
-        // Convert to PNG using ImageMagick if available
-        println!("\nTo view the image:");
-        println!("  convert atari800_output.ppm atari800_output.png");
-        println!("  open atari800_output.png");
-    } else if debugger_mode {
-        // Run the Atari 800 emulator with debugger
-        println!("Starting Atari 800 with debugger");
-        let mut atari800 = Atari800::new();
+    // An 850 interface with its port 1, and the serial bus itself for an
+    // SIO2PC disk server, each on a TCP port, a serial device or, given
+    // "pty", a pseudo-terminal
+    for (target, name, bridge) in [
+        (&options.port_850, "R1:", &mut launch.port_850),
+        (&options.sio2pc, "SIO2PC", &mut launch.sio2pc),
+    ] {
+        if let Some(target) = target {
+            match open_serial_bridge(target, name) {
+                Ok(opened) => *bridge = Some(opened),
+                Err(e) => {
+                    println!("✗ Error opening {}: {}", target, e);
+                    return None;
+                }
+            }
+        }
+    }
+    Some(launch)
+}
+
+/// RAM fill named on the command line. Seeds and values are in hex, the
+/// way the seed of a random fill is logged.
+fn parse_ram_fill(spec: &str) -> Option<RamFill> {
+    match spec {
+        "zero" => Some(RamFill::Zero),
+        "pattern" => Some(RamFill::Pattern),
+        "random" => Some(RamFill::random()),
+        _ => match spec.strip_prefix("random:") {
+            Some(seed) => u64::from_str_radix(seed, 16).ok().map(RamFill::Random),
+            None => u8::from_str_radix(spec.trim_start_matches('$'), 16).ok().map(RamFill::Value),
+        },
+    }
+}
 
-        loop {
-            atari800.tick();
+/// A bridge to "pty", a serial device (/dev/..., its handshake on RTS, or
+/// on DTR given /dev/...:dtr) or a TCP address to listen on
+fn open_serial_bridge(target: &str, name: &str) -> std::io::Result<Box<dyn SerialBridge>> {
+    #[cfg(unix)]
+    {
+        if target == "pty" {
+            let pty = PtyBridge::open()?;
+            println!("{} {}", name, pty.path().display());
+            return Ok(Box::new(pty));
+        }
+        if target.starts_with("/dev/") {
+            let (path, line) = match target.strip_suffix(":dtr") {
+                Some(path) => (path, ModemLine::Dtr),
+                None => (target.strip_suffix(":rts").unwrap_or(target), ModemLine::Rts),
+            };
+            let tty = TtyBridge::open(path, line)?;
+            println!("{} {} (handshake on {:?})", name, path, line);
+            return Ok(Box::new(tty));
         }
-    } else if animate_mode {
-        // Run color cycling animation test
-        run_animated_test();
-    } else {
-        // Run with SDL display and CPU execution (default)
-        run_with_sdl();
     }
+    let tcp = TcpBridge::listen(target)?;
+    println!("{} listening on {}", name, tcp.local_addr()?);
+    Ok(Box::new(tcp))
 }
 
-fn run_with_sdl() {
+fn convert_disk(input: &str, output: &str) {
+    let format = match ImageFormat::from_path(output) {
+        Some(format) => format,
+        None => {
+            println!("✗ {}: use .atr, .xfd, .dcm, .pro or .atx", output);
+            return;
+        }
+    };
+    let image = match disk_image::open_image(input) {
+        Ok(image) => image,
+        Err(e) => {
+            println!("✗ Error loading disk {}: {}", input, e);
+            return;
+        }
+    };
+    match disk_image::convert(image.as_ref(), format) {
+        Some(converted) => match std::fs::write(output, converted.to_bytes()) {
+            Ok(()) => println!("✓ {:?} {} -> {:?} {}", image.image_format(), input, format, output),
+            Err(e) => println!("✗ Error writing {}: {}", output, e),
+        },
+        None => println!("✗ A {:?} density disk doesn't fit in {:?}", image.density(), format),
+    }
+}
+
+fn run_apple1(rom_path: &str) {
+    let mut apple1 = match Apple1::from_rom_file(rom_path) {
+        Ok(apple1) => apple1,
+        Err(e) => {
+            println!("✗ Error loading monitor ROM {}: {}", rom_path, e);
+            return;
+        }
+    };
+
+    println!("Starting Apple-1 with monitor ROM {}", rom_path);
+    println!("Press Ctrl-C to quit");
+    println!();
+
+    apple1.attach_stdin();
+    loop {
+        apple1.run_cycles(1000);
+    }
+}
+
+/// A drive to start up with
+enum Drive {
+    /// Answering a command frame at a time
+    Image(DiskDrive),
+    /// Running its own firmware
+    Firmware(Box<Drive1050>),
+}
+
+/// What to plug in or load once the Atari is running
+#[derive(Default)]
+struct Launch {
+    xex: Option<Xex>,
+    disks: Vec<Drive>,
+    tape: Option<Cassette>,
+    printer: Option<Printer>,
+
+    // Serial bridges on the host: port 1 of an 850 interface, and the
+    // serial bus itself for a disk server
+    port_850: Option<Box<dyn SerialBridge>>,
+    sio2pc: Option<Box<dyn SerialBridge>>,
+}
+
+fn run_with_sdl(mut config: Atari800Config, roms: &RomSet, launch: Launch) {
+    if let Err(e) = config.load_roms(roms) {
+        println!("✗ Error loading ROMs: {}", e);
+        return;
+    }
+
     println!("Starting Atari 800 with SDL display");
-    println!("Press ESC to quit");
+    println!("Press ESC to quit, F8 to switch fast SIO on or off");
+    println!("Disk commands on the console: mount N FILE, unmount N, swap N M,");
+    println!("  protect N, unprotect N, new N FILE [single|enhanced|double], disks");
     println!();
 
+    // Disk commands are read on their own thread and carried out between
+    // frames
+    let (command_tx, command_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if command_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
     // Initialize SDL2
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -78,7 +493,41 @@ fn run_with_sdl() {
         .unwrap();
 
     // Create Atari800 instance
-    let mut atari800 = Atari800::new();
+    let mut atari800 = match Atari800::with_config(config) {
+        Ok(atari800) => atari800,
+        Err(e) => {
+            println!("✗ Error loading ROMs: {}", e);
+            return;
+        }
+    };
+    if let RamFill::Random(seed) = atari800.ram_fill() {
+        println!("RAM filled from random seed {:016X} (--ram-fill random:{:X} repeats it)", seed, seed);
+    }
+    for (unit, drive) in (1..).zip(launch.disks) {
+        match drive {
+            Drive::Image(drive) => {
+                if atari800.mount_disk(unit, drive).is_err() {
+                    println!("✗ No drive D{}:", unit);
+                }
+            }
+            Drive::Firmware(drive) => atari800.attach_1050(*drive),
+        }
+    }
+    if let Some(printer) = launch.printer {
+        atari800.connect_printer(printer);
+    }
+    if let Some(bridge) = launch.port_850 {
+        atari800.connect_850(bridge);
+    }
+    if let Some(bridge) = launch.sio2pc {
+        atari800.connect_sio2pc(bridge);
+    }
+    if let Some(xex) = launch.xex {
+        atari800.load_xex(xex);
+    }
+    if let Some(cassette) = launch.tape {
+        atari800.insert_tape(cassette);
+    }
 
     // Event loop
     let mut event_pump = sdl_context.event_pump().unwrap();
@@ -93,21 +542,64 @@ fn run_with_sdl() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Pause),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => atari800.press_break(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } => {
+                    let enabled = !atari800.sio_patch();
+                    atari800.set_sio_patch(enabled);
+                    println!("Fast SIO {}", if enabled { "on" } else { "off" });
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(code) = atari_key_code(keycode, keymod) {
+                        atari800.press_key(code);
+                    }
+                }
+                Event::KeyUp { .. } => atari800.release_key(),
                 _ => {}
             }
         }
 
-        // Execute CPU for one frame (~29850 cycles at 1.79 MHz, 60 FPS)
-        // This allows the OS and software to run between frames
-        for _ in 0..29850 {
-            atari800.tick();  // Execute one CPU instruction cycle
+        while let Ok(command) = command_rx.try_recv() {
+            disk_command(&mut atari800, &command);
         }
 
-        // Render frame
-        atari800.render();
+        // Run one frame: CPU and chips, then render and the vertical blank
+        atari800.run_frame();
 
-        // Trigger Vertical Blank Interrupt (VBI) - essential for OS and software
-        atari800.trigger_vbi();
+        // Drives write their disks back as they change
+        for unit in 1..=8 {
+            if let Some(drive) = atari800.disk_mut(unit) {
+                if let Some(e) = drive.take_write_error() {
+                    report_write_error(unit, drive.path(), &e);
+                }
+            }
+        }
+        for unit in 1..=4 {
+            if let Some(drive) = atari800.drive_1050_mut(unit) {
+                if let Some(e) = drive.take_write_error() {
+                    report_write_error(unit, drive.path(), &e);
+                }
+            }
+        }
+        if let Some(cassette) = atari800.tape_mut() {
+            if let Some(e) = cassette.take_write_error() {
+                report_tape_error(cassette.path(), &e);
+            }
+        }
 
         // Copy framebuffer to SDL texture
         texture
@@ -125,9 +617,198 @@ fn run_with_sdl() {
         frame_count = frame_count.wrapping_add(1);
     }
 
+    // A recording the motor is still running for hasn't been saved yet
+    if let Some(mut cassette) = atari800.eject_tape() {
+        if let Err(e) = cassette.save() {
+            report_tape_error(cassette.path(), &e);
+        }
+    }
+
     println!("Shutting down...");
 }
 
+/// Tell the user a drive couldn't write its disk back to the file
+fn report_write_error(unit: u8, path: Option<&std::path::Path>, e: &io::Error) {
+    let path = path.map_or(String::new(), |path| path.display().to_string());
+    println!("✗ Error writing D{}: {}: {}", unit, path, e);
+}
+
+/// Tell the user a tape recording couldn't be saved to its file
+fn report_tape_error(path: Option<&std::path::Path>, e: &io::Error) {
+    let path = path.map_or(String::new(), |path| path.display().to_string());
+    println!("✗ Error writing C: {}: {}", path, e);
+}
+
+/// Carry out a disk command typed on the console
+fn disk_command(atari800: &mut Atari800, command: &str) {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    // Drives as 1 or D1:
+    let unit = |index: usize| -> Option<u8> {
+        let word = words.get(index)?.trim_start_matches(['D', 'd']).trim_end_matches(':');
+        word.parse().ok().filter(|unit| (1..=8).contains(unit))
+    };
+    match (words.first().copied(), unit(1)) {
+        (None, _) => {}
+        (Some("disks"), _) => {
+            for unit in 1..=8 {
+                if let Some(drive) = atari800.disk(unit) {
+                    let file = drive.path().map_or("(in memory)".into(), |path| path.display().to_string());
+                    let protected = if drive.write_protected() { ", write protected" } else { "" };
+                    println!("D{}: {} ({:?}{})", unit, file, drive.image().density(), protected);
+                }
+                if let Some(drive) = atari800.drive_1050(unit) {
+                    let file = drive.path().map_or("(in memory)".into(), |path| path.display().to_string());
+                    let file = if drive.disk().is_some() { file } else { "empty".into() };
+                    let protected = if drive.write_protected() { ", write protected" } else { "" };
+                    println!("D{}: {} (1050, track {}{})", unit, file, drive.head_track(), protected);
+                }
+            }
+        }
+        (Some("mount"), Some(unit)) if words.len() == 3 && atari800.drive_1050(unit).is_some() => {
+            // A 1050 stays put and takes the disk
+            if let Some(drive) = atari800.drive_1050_mut(unit) {
+                match drive.open_disk(words[2]) {
+                    Ok(()) => println!("D{}: {} (1050)", unit, words[2]),
+                    Err(e) => println!("✗ Error loading disk {}: {}", words[2], e),
+                }
+            }
+        }
+        (Some("mount"), Some(unit)) if words.len() == 3 => match DiskDrive::open(words[2]) {
+            Ok(drive) => match atari800.mount_disk(unit, drive) {
+                Ok(()) => println!("D{}: {}", unit, words[2]),
+                Err(_) => println!("✗ No drive D{}:", unit),
+            },
+            Err(e) => println!("✗ Error loading disk {}: {}", words[2], e),
+        },
+        (Some("unmount"), Some(unit)) => {
+            let removed = match atari800.drive_1050_mut(unit) {
+                Some(drive) => drive.eject_disk().is_some(),
+                None => atari800.unmount_disk(unit).is_some(),
+            };
+            if removed {
+                println!("D{}: empty", unit);
+            } else {
+                println!("D{}: was already empty", unit);
+            }
+        }
+        (Some("swap"), Some(a)) => match unit(2) {
+            Some(b) => match atari800.swap_disks(a, b) {
+                Ok(()) => println!("Swapped D{}: and D{}:", a, b),
+                Err(unit) if unit <= 8 => println!("✗ A 1050 can't be D{}:", unit),
+                Err(unit) => println!("✗ No drive D{}:", unit),
+            },
+            None => println!("✗ swap needs two drives, 1-8"),
+        },
+        (Some(tab @ ("protect" | "unprotect")), Some(unit)) => {
+            let protected = if let Some(drive) = atari800.disk_mut(unit) {
+                drive.set_write_protected(tab == "protect");
+                drive.write_protected()
+            } else if let Some(drive) = atari800.drive_1050_mut(unit) {
+                drive.set_write_protected(tab == "protect");
+                drive.write_protected()
+            } else {
+                println!("✗ No disk in D{}:", unit);
+                return;
+            };
+            println!("D{}: {}", unit, if protected { "write protected" } else { "writable" });
+        }
+        (Some("new"), Some(unit)) if words.len() == 3 || words.len() == 4 => {
+            let density = match words.get(3).copied().unwrap_or("single") {
+                "single" => Density::Single,
+                "enhanced" => Density::Enhanced,
+                "double" => Density::Double,
+                other => {
+                    println!("✗ Unknown density {}", other);
+                    return;
+                }
+            };
+            match DiskDrive::create(words[2], density) {
+                Ok(drive) => match atari800.drive_1050_mut(unit) {
+                    Some(drive_1050) => match drive_1050.open_disk(words[2]) {
+                        Ok(()) => println!("D{}: {} (new, {:?}, 1050)", unit, words[2], density),
+                        Err(e) => println!("✗ Error loading disk {}: {}", words[2], e),
+                    },
+                    None => match atari800.mount_disk(unit, drive) {
+                        Ok(()) => println!("D{}: {} (new, {:?})", unit, words[2], density),
+                        Err(_) => println!("✗ No drive D{}:", unit),
+                    },
+                },
+                Err(DiskError::Atr(AtrError::Io(e))) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    println!("✗ {} already exists; mount it or pick another name", words[2])
+                }
+                Err(e) => println!("✗ Error creating {}: {}", words[2], e),
+            }
+        }
+        _ => println!("✗ Unknown disk command: {}", command.trim()),
+    }
+}
+
+/// Atari keyboard code for a host key, with Shift and Control folded in as
+/// bits 6 and 7. The arrow keys are Control plus - = + *, as on the Atari,
+/// and ` stands in for Esc since that quits the emulator.
+fn atari_key_code(keycode: Keycode, keymod: Mod) -> Option<u8> {
+    let mut ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+
+    let code = match keycode {
+        Keycode::L => 0x00,
+        Keycode::J => 0x01,
+        Keycode::Semicolon => 0x02,
+        Keycode::K => 0x05,
+        Keycode::O => 0x08,
+        Keycode::P => 0x0A,
+        Keycode::U => 0x0B,
+        Keycode::Return => 0x0C,
+        Keycode::I => 0x0D,
+        Keycode::Minus => 0x0E,
+        Keycode::Equals => 0x0F,
+        Keycode::V => 0x10,
+        Keycode::C => 0x12,
+        Keycode::B => 0x15,
+        Keycode::X => 0x16,
+        Keycode::Z => 0x17,
+        Keycode::Num4 => 0x18,
+        Keycode::Num3 => 0x1A,
+        Keycode::Num6 => 0x1B,
+        Keycode::Backquote => 0x1C,
+        Keycode::Num5 => 0x1D,
+        Keycode::Num2 => 0x1E,
+        Keycode::Num1 => 0x1F,
+        Keycode::Comma => 0x20,
+        Keycode::Space => 0x21,
+        Keycode::Period => 0x22,
+        Keycode::N => 0x23,
+        Keycode::M => 0x25,
+        Keycode::Slash => 0x26,
+        Keycode::RAlt | Keycode::LAlt => 0x27,
+        Keycode::R => 0x28,
+        Keycode::E => 0x2A,
+        Keycode::Y => 0x2B,
+        Keycode::Tab => 0x2C,
+        Keycode::T => 0x2D,
+        Keycode::W => 0x2E,
+        Keycode::Q => 0x2F,
+        Keycode::Num9 => 0x30,
+        Keycode::Num0 => 0x32,
+        Keycode::Num7 => 0x33,
+        Keycode::Backspace => 0x34,
+        Keycode::Num8 => 0x35,
+        Keycode::F => 0x38,
+        Keycode::H => 0x39,
+        Keycode::D => 0x3A,
+        Keycode::CapsLock => 0x3C,
+        Keycode::G => 0x3D,
+        Keycode::S => 0x3E,
+        Keycode::A => 0x3F,
+        Keycode::Up => { ctrl = true; 0x0E }
+        Keycode::Down => { ctrl = true; 0x0F }
+        Keycode::Left => { ctrl = true; 0x06 }
+        Keycode::Right => { ctrl = true; 0x07 }
+        _ => return None,
+    };
+    Some(code | if shift { 0x40 } else { 0 } | if ctrl { 0x80 } else { 0 })
+}
+
 fn run_animated_test() {
     println!("Starting Atari 800 with animated color test");
     println!("Press ESC to quit");
@@ -196,5 +877,12 @@ fn run_animated_test() {
         frame_count = frame_count.wrapping_add(1);
     }
 
+    // A recording the motor is still running for hasn't been saved yet
+    if let Some(mut cassette) = atari800.eject_tape() {
+        if let Err(e) = cassette.save() {
+            report_tape_error(cassette.path(), &e);
+        }
+    }
+
     println!("Shutting down...");
 }
@@ -1,64 +1,236 @@
-use std::fs::File;
-use std::io::Read;
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
+/// What a 256-byte page of the address space is connected to.
+/// The offset is the index of the page's first byte in the backing store.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Page {
+    Ram(usize),
+    Rom(usize),
+    Unmapped,
+}
+
+/// What RAM holds at power-on. Real machines come up with junk, so
+/// anything other than `Zero` helps catch software that forgets to clear
+/// memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RamFill {
+    #[default]
+    Zero,
+    Value(u8),
+    /// Alternating 64-byte runs of $00 and $FF, like many DRAM chips
+    Pattern,
+    /// Pseudo-random bytes. The same seed gives the same contents.
+    Random(u64),
+}
+
+impl RamFill {
+    /// Random fill with a seed taken from the clock
+    pub fn random() -> RamFill {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        RamFill::Random(nanos)
+    }
+
+    pub fn fill(&self, ram: &mut [u8]) {
+        match *self {
+            RamFill::Zero => ram.fill(0x00),
+            RamFill::Value(val) => ram.fill(val),
+            RamFill::Pattern => {
+                for (i, byte) in ram.iter_mut().enumerate() {
+                    *byte = if i & 0x40 == 0 { 0x00 } else { 0xFF };
+                }
+            }
+            RamFill::Random(seed) => {
+                // SplitMix64: eight bytes per step
+                let mut state = seed;
+                for chunk in ram.chunks_mut(8) {
+                    state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                    z ^= z >> 31;
+                    chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
+
+/// Which backing store an image is loaded into. Loading doesn't change
+/// the page tables: the image only appears where a matching page is mapped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    Ram,
+    Rom,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io { path: PathBuf, error: io::Error },
+    /// The image runs past $FFFF from its load address
+    TooLarge { addr: u16, len: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            LoadError::TooLarge { addr, len } => {
+                write!(f, "{} bytes at ${:04X} runs past $FFFF", len, addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Memory subsystem: RAM and ROM backing stores plus a page table that
+/// decides which of them (if any) answers at each address.
+///
+/// - Writes to ROM pages are ignored, as on real hardware.
+/// - Reads from unmapped pages return the last value seen on the data bus.
+/// - ANTIC has its own page table, since banked machines can show it
+///   different memory than the CPU sees.
 pub struct Mem {
-    pub ram: [u8; 0x10000], // 64K // 1024],
-    pub rom: [u8; 0x10000],
-    pub split: u16,
+    pub ram: Vec<u8>,   // 64K by address, then any extended banks
+    pub rom: Vec<u8>,   // $0000-$FFFF by address, then added images
+    pages: [Page; 256],
+    antic_pages: [Page; 256],
+
+    // Last value driven on the data bus (floating bus reads)
+    data_bus: Cell<u8>,
 }
 
 impl Mem {
-    pub fn new(split: u16, run_func_tests: bool) -> Mem {
-        let mut new_mem = Mem {
-            ram: [0x00_u8; 0x10000],
-            rom: [0x00_u8; 0x10000],
-            split: split,
+    /// Create memory with zeroed RAM below `split` and empty ROM above it.
+    /// A split of 0 makes the whole address space RAM.
+    pub fn blank(split: u16) -> Mem {
+        let mut mem = Mem {
+            ram: vec![0x00_u8; 0x10000],
+            rom: vec![0x00_u8; 0x10000],
+            pages: [Page::Unmapped; 256],
+            antic_pages: [Page::Unmapped; 256],
+            data_bus: Cell::new(0xFF),
         };
 
-        // Initialize with test code.
-        if run_func_tests {
-            let f = File::open("6502_functional_test.bin");
-            let mut f = match f {
-                Ok(file) => file,
-                Err(_error) => panic!("Could not open test file"),
-            };
+        if split == 0 {
+            mem.map(0x0000, 0xFFFF, Page::Ram(0x0000));
+        } else {
+            mem.map(0x0000, split - 1, Page::Ram(0x0000));
+            mem.map(split, 0xFFFF, Page::Rom((split & 0xFF00) as usize));
+        }
 
-            let mut buffer = Vec::new();
-            f.read_to_end(&mut buffer).expect("Failed to read test file");
+        mem
+    }
 
-            new_mem.ram[0..0x10000].copy_from_slice(&buffer);
-        } else {
-            // Load Atari OS ROM at $C000-$FFFF (16KB)
-            new_mem.load_os_rom();
+    /// Connect the pages covering `start..=end` to a backing store, for
+    /// both the CPU and ANTIC. Successive pages take successive 256-byte
+    /// blocks starting at the given offset.
+    pub fn map(&mut self, start: u16, end: u16, page: Page) {
+        Self::fill_pages(&mut self.pages, start, end, page);
+        Self::fill_pages(&mut self.antic_pages, start, end, page);
+    }
+
+    /// Like `map`, but only changes what the CPU sees
+    pub fn map_cpu(&mut self, start: u16, end: u16, page: Page) {
+        Self::fill_pages(&mut self.pages, start, end, page);
+    }
+
+    /// Like `map`, but only changes what ANTIC sees
+    pub fn map_antic(&mut self, start: u16, end: u16, page: Page) {
+        Self::fill_pages(&mut self.antic_pages, start, end, page);
+    }
+
+    fn fill_pages(pages: &mut [Page; 256], start: u16, end: u16, page: Page) {
+        let first = (start >> 8) as usize;
+        let last = (end >> 8) as usize;
+        for (i, p) in (first..=last).enumerate() {
+            pages[p] = match page {
+                Page::Ram(offset) => Page::Ram(offset + i * 0x100),
+                Page::Rom(offset) => Page::Rom(offset + i * 0x100),
+                Page::Unmapped => Page::Unmapped,
+            };
         }
+    }
 
-        new_mem
+    /// What the page containing `addr` is currently connected to
+    pub fn page(&self, addr: u16) -> Page {
+        self.pages[(addr >> 8) as usize]
     }
 
-    /// Load Atari OS ROM into memory at $C000-$FFFF
-    fn load_os_rom(&mut self) {
-        println!("ROM loading...");
-        if let Ok(mut f) = File::open("roms/Atari OS-B NTSC.ROM") {
-            let mut buffer = Vec::new();
-            if f.read_to_end(&mut buffer).is_ok() && buffer.len() == 0x2800 {
-                // Atari 800 OS B ROM is 10240 bytes, load at $D800-$FFFF
-                self.rom[0xD800..0x10000].copy_from_slice(&buffer[0..0x2800]);
-                println!("ROM loaded");
-            } else {
-                println!("Buffer issue loading ROM, buffer len was {}", buffer.len());
-            }
-        } else {
-            println!("Failed to load ROM");
+    /// Copy a raw image into RAM or ROM at its address
+    pub fn load(&mut self, region: Region, addr: u16, data: &[u8]) -> Result<(), LoadError> {
+        let start = addr as usize;
+        if start + data.len() > 0x10000 {
+            return Err(LoadError::TooLarge { addr, len: data.len() });
         }
-        // If ROM file not found, continue with empty ROM (font rendering will fail gracefully)
+        let store = match region {
+            Region::Ram => &mut self.ram,
+            Region::Rom => &mut self.rom,
+        };
+        store[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Load a raw binary file, returning its length
+    pub fn load_file<P: AsRef<Path>>(
+        &mut self,
+        region: Region,
+        addr: u16,
+        path: P,
+    ) -> Result<usize, LoadError> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|error| LoadError::Io { path: path.to_path_buf(), error })?;
+        self.load(region, addr, &data)?;
+        Ok(data.len())
+    }
+
+    /// Append an image (e.g. a cartridge) to the ROM store and return its
+    /// offset, for use with `Page::Rom`
+    pub fn add_rom_image(&mut self, data: &[u8]) -> usize {
+        let offset = self.rom.len();
+        self.rom.extend_from_slice(data);
+        offset
+    }
+
+    /// Take an image added with `add_rom_image` back out of the ROM store.
+    /// Images added after it move down by its length, so their offsets
+    /// need adjusting and their pages mapping again.
+    pub fn remove_rom_image(&mut self, offset: usize, len: usize) {
+        assert!(offset >= 0x10000, "${:X} isn't an added image", offset);
+        self.rom.drain(offset..offset + len);
+    }
+
+    /// Record a value driven onto the data bus by another device (e.g. I/O
+    /// chip reads), so later unmapped reads see it.
+    pub fn set_data_bus(&self, val: u8) {
+        self.data_bus.set(val);
     }
 
     pub fn get_byte(&self, addr: u16) -> u8 {
-        if addr < self.split || self.split == 0 {
-            self.ram[addr as usize]
-        } else {
-            self.rom[addr as usize]
-        }
+        self.read_page(self.pages[(addr >> 8) as usize], addr)
+    }
+
+    /// Read a byte through ANTIC's page table (display list and screen DMA)
+    pub fn antic_read(&self, addr: u16) -> u8 {
+        self.read_page(self.antic_pages[(addr >> 8) as usize], addr)
+    }
+
+    fn read_page(&self, page: Page, addr: u16) -> u8 {
+        let offset = (addr & 0xFF) as usize;
+        let val = match page {
+            Page::Ram(base) => self.ram[base + offset],
+            Page::Rom(base) => self.rom[base + offset],
+            Page::Unmapped => self.data_bus.get(),
+        };
+        self.data_bus.set(val);
+        val
     }
 
     pub fn get_word(&self, addr: u16) -> u16 {
@@ -68,11 +240,12 @@ impl Mem {
     }
 
     pub fn set_byte(&mut self, addr: u16, val: u8) {
-        if addr >= self.split && self.split != 0 {
-            panic!("Can't write to ROM");
-        }
+        self.data_bus.set(val);
 
-        self.ram[addr as usize] = val;
+        // Writes to ROM and unmapped pages go nowhere
+        if let Page::Ram(base) = self.pages[(addr >> 8) as usize] {
+            self.ram[base + (addr & 0xFF) as usize] = val;
+        }
     }
 
     pub fn set_word(&mut self, addr: u16, val: u16) {
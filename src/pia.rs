@@ -1,65 +1,168 @@
-/// PIA - Peripheral Interface Adapter (6520)
-/// Handles joystick input, console switches, and OS ROM banking.
+/// PIA - Peripheral Interface Adapter (6520/6821)
+/// Handles joystick input, console switches, and OS ROM banking on the Atari,
+/// and the keyboard/display on the Apple-1.
 ///
-/// Memory map: $D300-$D3FF
+/// The chip has four register select positions (RS1/RS0):
+///   0 - Port A data / data direction (selected by CRA bit 2)
+///   1 - Control register A (CRA)
+///   2 - Port B data / data direction (selected by CRB bit 2)
+///   3 - Control register B (CRB)
+///
+/// Control register layout:
+///   bit 7   - C1 interrupt flag (read only)
+///   bit 6   - C2 interrupt flag (read only, C2 as input)
+///   bits 5-3 - C2 control
+///   bit 2   - 0 = data direction register, 1 = port data register
+///   bit 1   - C1 active edge (0 = falling, 1 = rising)
+///   bit 0   - C1 interrupt enable
+///
+/// Memory map (Atari): $D300-$D3FF
 pub struct Pia {
     // Port A - Joystick ports 1 & 2, console switches
     porta: u8,          // $D300 - Port A data
-    ddra: u8,           // $D301 - Port A data direction (0=input, 1=output)
+    ddra: u8,           // $D300 - Port A data direction (0=input, 1=output)
+    cra: u8,            // $D302 - PACTL
 
     // Port B - Joystick ports 3 & 4, OS ROM control
-    portb: u8,          // $D302 - Port B data
-    ddrb: u8,           // $D303 - Port B data direction
+    portb: u8,          // $D301 - Port B data
+    ddrb: u8,           // $D301 - Port B data direction
+    crb: u8,            // $D303 - PBCTL
 
     // Input state (what's actually on the pins)
     porta_input: u8,
     portb_input: u8,
+
+    // Control line state
+    ca1: bool,
+    cb1: bool,
+    ca2: bool,          // Output level when CA2 is an output
+    cb2: bool,          // Output level when CB2 is an output
 }
 
+const CR_IRQ1: u8 = 0x80;
+const CR_IRQ2: u8 = 0x40;
+const CR_PORT_SELECT: u8 = 0x04;
+const CR_C1_RISING: u8 = 0x02;
+const CR_C1_IRQ_ENABLE: u8 = 0x01;
+
 impl Pia {
     pub fn new() -> Pia {
         Pia {
             porta: 0xFF,
             ddra: 0,
+            cra: 0,
             portb: 0xFF,
             ddrb: 0,
+            crb: 0,
             porta_input: 0xFF,  // No joystick input
             portb_input: 0xFF,  // No joystick input
+            ca1: true,
+            cb1: true,
+            ca2: true,
+            cb2: true,
         }
     }
 
     /// Execute one machine cycle of PIA operation
     pub fn tick(&mut self) {
-        // PIA is mostly passive, responding to reads/writes
-        // Could add joystick scanning logic here in the future
+        // Pulse output mode (C2 control = 101) holds C2 low for one cycle
+        // after the triggering port access.
+        if (self.cra & 0x38) == 0x28 {
+            self.ca2 = true;
+        }
+        if (self.crb & 0x38) == 0x28 {
+            self.cb2 = true;
+        }
     }
 
-    /// Read from a PIA register
-    pub fn read_register(&self, addr: u16) -> u8 {
-        match addr & 0x03 {
+    /// Read from a PIA register using the Atari's address wiring.
+    /// The Atari connects A0 to RS1 and A1 to RS0, so $D300/$D301 are the
+    /// ports and $D302/$D303 are PACTL/PBCTL.
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        self.read(Self::atari_register_select(addr))
+    }
+
+    /// Write to a PIA register using the Atari's address wiring
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        self.write(Self::atari_register_select(addr), val)
+    }
+
+    fn atari_register_select(addr: u16) -> u8 {
+        (((addr & 0x01) << 1) | ((addr >> 1) & 0x01)) as u8
+    }
+
+    /// Read from a register by chip register select (RS1/RS0)
+    pub fn read(&mut self, rs: u8) -> u8 {
+        match rs & 0x03 {
             0x00 => {
+                if self.cra & CR_PORT_SELECT == 0 {
+                    return self.ddra;
+                }
+                // Reading the port clears the interrupt flags
+                self.cra &= !(CR_IRQ1 | CR_IRQ2);
+                // Handshake/pulse mode drops CA2 on a port A read
+                if (self.cra & 0x30) == 0x20 {
+                    self.ca2 = false;
+                }
                 // PORTA - bits set as input (0 in DDRA) read from porta_input
                 // bits set as output (1 in DDRA) read from porta
                 (self.porta & self.ddra) | (self.porta_input & !self.ddra)
             }
-            0x01 => self.ddra,
+            0x01 => self.cra,
             0x02 => {
+                if self.crb & CR_PORT_SELECT == 0 {
+                    return self.ddrb;
+                }
+                self.crb &= !(CR_IRQ1 | CR_IRQ2);
                 // PORTB - same logic as PORTA
                 (self.portb & self.ddrb) | (self.portb_input & !self.ddrb)
             }
-            0x03 => self.ddrb,
-            _ => 0xFF,
+            _ => self.crb,
         }
     }
 
-    /// Write to a PIA register
-    pub fn write_register(&mut self, addr: u16, val: u8) {
-        match addr & 0x03 {
-            0x00 => self.porta = val,
-            0x01 => self.ddra = val,
-            0x02 => self.portb = val,
-            0x03 => self.ddrb = val,
-            _ => {}
+    /// Write to a register by chip register select (RS1/RS0)
+    pub fn write(&mut self, rs: u8, val: u8) {
+        match rs & 0x03 {
+            0x00 => {
+                if self.cra & CR_PORT_SELECT == 0 {
+                    self.ddra = val;
+                } else {
+                    self.porta = val;
+                }
+            }
+            0x01 => {
+                self.cra = (self.cra & 0xC0) | (val & 0x3F);
+                self.ca2 = Self::c2_output_level(self.cra, self.ca2);
+            }
+            0x02 => {
+                if self.crb & CR_PORT_SELECT == 0 {
+                    self.ddrb = val;
+                } else {
+                    self.portb = val;
+                    // Handshake/pulse mode drops CB2 on a port B write
+                    if (self.crb & 0x30) == 0x20 {
+                        self.cb2 = false;
+                    }
+                }
+            }
+            _ => {
+                self.crb = (self.crb & 0xC0) | (val & 0x3F);
+                self.cb2 = Self::c2_output_level(self.crb, self.cb2);
+            }
+        }
+    }
+
+    /// C2 level after a control register write. In manual output mode
+    /// (bits 5-4 = 11) bit 3 drives the line directly.
+    fn c2_output_level(cr: u8, current: bool) -> bool {
+        if (cr & 0x30) == 0x30 {
+            (cr & 0x08) != 0
+        } else if (cr & 0x20) == 0 {
+            // C2 is an input; the line floats high
+            true
+        } else {
+            current
         }
     }
 
@@ -71,4 +174,67 @@ impl Pia {
     pub fn set_portb_input(&mut self, val: u8) {
         self.portb_input = val;
     }
+
+    /// Drive the CA1 interrupt input
+    pub fn set_ca1(&mut self, level: bool) {
+        if Self::is_active_edge(self.cra, self.ca1, level) {
+            self.cra |= CR_IRQ1;
+            // Handshake mode releases CA2 on the CA1 active edge
+            if (self.cra & 0x38) == 0x20 {
+                self.ca2 = true;
+            }
+        }
+        self.ca1 = level;
+    }
+
+    /// Drive the CB1 interrupt input
+    pub fn set_cb1(&mut self, level: bool) {
+        if Self::is_active_edge(self.crb, self.cb1, level) {
+            self.crb |= CR_IRQ1;
+            if (self.crb & 0x38) == 0x20 {
+                self.cb2 = true;
+            }
+        }
+        self.cb1 = level;
+    }
+
+    fn is_active_edge(cr: u8, old: bool, new: bool) -> bool {
+        if cr & CR_C1_RISING != 0 {
+            !old && new
+        } else {
+            old && !new
+        }
+    }
+
+    /// Current level of the port B pins as seen by external hardware.
+    /// Lines configured as inputs are pulled high.
+    pub fn portb_output(&self) -> u8 {
+        (self.portb & self.ddrb) | !self.ddrb
+    }
+
+    /// Current level of the port A pins as seen by external hardware
+    pub fn porta_output(&self) -> u8 {
+        (self.porta & self.ddra) | !self.ddra
+    }
+
+    /// CA2 line level (Atari: cassette motor control, low = on)
+    pub fn ca2_output(&self) -> bool {
+        self.ca2
+    }
+
+    /// CB2 line level (Atari: SIO command line, low = asserted)
+    pub fn cb2_output(&self) -> bool {
+        self.cb2
+    }
+
+    /// True if CA1 has latched an interrupt since port A was last read
+    pub fn ca1_flag(&self) -> bool {
+        self.cra & CR_IRQ1 != 0
+    }
+
+    /// IRQA and IRQB outputs combined (active when flagged and enabled)
+    pub fn irq(&self) -> bool {
+        (self.cra & CR_IRQ1 != 0 && self.cra & CR_C1_IRQ_ENABLE != 0)
+            || (self.crb & CR_IRQ1 != 0 && self.crb & CR_C1_IRQ_ENABLE != 0)
+    }
 }
@@ -30,6 +30,14 @@ pub struct Pokey {
     // Internal state
     timers: [u16; 4],   // Internal timer counters
     random_seed: u8,    // For random number generation
+
+    // Serial output: SEROUT holds the next byte while the shift register
+    // sends the current one
+    serout_full: bool,
+    shift_byte: u8,
+    shift_cycles: u32,          // Cycles until the shift register is empty
+    shift_bit_cycles: u32,      // Bit time the byte is being sent at
+    serial_out: Option<u8>,     // Last byte sent, until the bus takes it
 }
 
 impl Pokey {
@@ -49,10 +57,15 @@ impl Pokey {
             kbcode: 0xFF,
             random: 0,
             serin: 0,
-            irqst: 0,
-            skstat: 0,
+            irqst: 0xFF,
+            skstat: 0xFF,
             timers: [0; 4],
             random_seed: 0xFF,
+            serout_full: false,
+            shift_byte: 0,
+            shift_cycles: 0,
+            shift_bit_cycles: 0,
+            serial_out: None,
         }
     }
 
@@ -73,7 +86,71 @@ impl Pokey {
         self.random_seed = ((self.random_seed << 1) | ((self.random_seed >> 7) ^ (self.random_seed >> 5) & 1)) & 0xFF;
         self.random = self.random_seed;
 
-        // TODO: Handle keyboard scanning, serial I/O, etc.
+        // Serial output: when a byte has been shifted out, the next one
+        // moves in from SEROUT
+        if self.shift_cycles > 0 {
+            self.shift_cycles -= 1;
+            if self.shift_cycles == 0 {
+                self.serial_out = Some(self.shift_byte);
+                if self.serout_full {
+                    self.start_shift();
+                }
+            }
+        }
+
+        // TODO: Handle keyboard scanning, etc.
+    }
+
+    /// Move SEROUT into the shift register, asking for the next byte
+    fn start_shift(&mut self) {
+        self.shift_byte = self.serout;
+        self.serout_full = false;
+        self.shift_bit_cycles = self.serial_bit_cycles();
+        self.shift_cycles = 10 * self.shift_bit_cycles;
+        if self.irqen & 0x10 != 0 {
+            self.irqst &= !0x10;
+        }
+    }
+
+    /// Machine cycles per serial bit. A bit lasts two periods of channel 4,
+    /// which SIO joins to channel 3 and clocks at 1.79MHz.
+    pub fn serial_bit_cycles(&self) -> u32 {
+        let base = if self.audctl & 0x01 != 0 { 114 } else { 28 };
+        let period = if self.audctl & 0x08 != 0 {
+            let divisor = self.audf[2] as u32 | (self.audf[3] as u32) << 8;
+            if self.audctl & 0x20 != 0 {
+                divisor + 7
+            } else {
+                (divisor + 1) * base
+            }
+        } else {
+            (self.audf[3] as u32 + 1) * base
+        };
+        2 * period
+    }
+
+    /// Level of the serial data output: a low start bit, the data bits
+    /// lowest first and a high stop bit, and high while idle
+    pub fn serial_output_level(&self) -> bool {
+        if self.shift_cycles == 0 {
+            return true;
+        }
+        let elapsed = 10 * self.shift_bit_cycles - self.shift_cycles;
+        match elapsed / self.shift_bit_cycles {
+            0 => false,
+            bit @ 1..=8 => self.shift_byte >> (bit - 1) & 1 != 0,
+            _ => true,
+        }
+    }
+
+    /// IRQST as read. Bit 3 isn't latched: it is low while the serial
+    /// output is idle.
+    fn irq_status(&self) -> u8 {
+        if self.serout_full || self.shift_cycles > 0 {
+            self.irqst
+        } else {
+            self.irqst & !0x08
+        }
     }
 
     /// Read from a POKEY register
@@ -91,7 +168,7 @@ impl Pokey {
             0x09 => self.kbcode,
             0x0A => self.random,
             0x0D => self.serin,
-            0x0E => self.irqst,
+            0x0E => self.irq_status(),
             0x0F => self.skstat,
             _ => 0xFF,
         }
@@ -116,12 +193,70 @@ impl Pokey {
                     self.timers[i] = self.audf[i] as u16;
                 }
             }
-            0x0A => self.skrest = val,
+            0x0A => {
+                // SKRES clears the serial error bits in SKSTAT
+                self.skrest = val;
+                self.skstat |= 0xE0;
+            }
             0x0B => self.potgo = val,
-            0x0D => self.serout = val,
-            0x0E => self.irqen = val,
+            0x0D => {
+                self.serout = val;
+                self.serout_full = true;
+                if self.shift_cycles == 0 {
+                    self.start_shift();
+                }
+            }
+            0x0E => {
+                // Disabling an interrupt also clears its pending status
+                self.irqen = val;
+                self.irqst |= !val;
+            }
             0x0F => self.skctl = val,
             _ => {}
         }
     }
+
+    /// A key is pressed: latch its code (bit 6 Shift, bit 7 Control) and
+    /// raise the keyboard interrupt if enabled
+    pub fn key_down(&mut self, code: u8) {
+        self.kbcode = code;
+        self.skstat &= !0x04;
+        if self.irqen & 0x40 != 0 {
+            self.irqst &= !0x40;
+        }
+    }
+
+    /// All keys released
+    pub fn key_up(&mut self) {
+        self.skstat |= 0x04;
+    }
+
+    /// The BREAK key has its own interrupt rather than a key code
+    pub fn break_key(&mut self) {
+        if self.irqen & 0x80 != 0 {
+            self.irqst &= !0x80;
+        }
+    }
+
+    /// Take the last byte sent through SEROUT, once all 10 bits are out
+    pub fn take_serial_output(&mut self) -> Option<u8> {
+        self.serial_out.take()
+    }
+
+    /// A byte arrives on the serial input. If the last one hasn't been
+    /// acknowledged yet it is lost, and SKSTAT reports the overrun.
+    pub fn serial_input(&mut self, byte: u8) {
+        if self.irqst & 0x20 == 0 {
+            self.skstat &= !0x20;
+        }
+        self.serin = byte;
+        if self.irqen & 0x20 != 0 {
+            self.irqst &= !0x20;
+        }
+    }
+
+    /// Whether POKEY is holding the IRQ line low
+    pub fn irq(&self) -> bool {
+        !self.irq_status() & self.irqen != 0
+    }
 }
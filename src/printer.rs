@@ -0,0 +1,71 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::sio::{SioDevice, SioResponse};
+
+/// SIO device ID of the printer, P1:
+pub const P1: u8 = 0x40;
+
+const EOL: u8 = 0x9B;
+
+/// Line lengths for the print modes the OS gives in AUX1: normal,
+/// sideways and double width
+const LINE_NORMAL: usize = 40;
+const LINE_SIDEWAYS: usize = 29;
+const LINE_DOUBLE: usize = 20;
+
+/// Status byte 2: how many seconds the OS should wait for a line
+const PRINT_TIMEOUT: u8 = 0x10;
+
+/// An 820/1025 style printer on the serial bus, printing to a host text
+/// file.
+///
+/// The OS sends a line at a time with the 'W' command, padded with spaces
+/// after an EOL. The printer prints up to the EOL, which becomes a newline,
+/// or the whole line if there isn't one. Inverse video characters print as
+/// their normal ones. Each line goes to the file as it is printed.
+pub struct Printer {
+    file: fs::File,
+}
+
+impl Printer {
+    /// Print to a new file, replacing any already there
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Printer> {
+        Ok(Printer { file: fs::File::create(path)? })
+    }
+
+    /// The text a line from the OS prints
+    fn text(line: &[u8]) -> Vec<u8> {
+        let mut text = Vec::with_capacity(line.len());
+        for &byte in line {
+            if byte == EOL {
+                text.push(b'\n');
+                break;
+            }
+            text.push(byte & 0x7F);
+        }
+        text
+    }
+}
+
+impl SioDevice for Printer {
+    fn command(&mut self, command: u8, aux1: u8, _aux2: u8) -> SioResponse {
+        match command {
+            b'W' => SioResponse::Receive(match aux1 {
+                b'S' => LINE_SIDEWAYS,
+                b'D' => LINE_DOUBLE,
+                _ => LINE_NORMAL,
+            }),
+            b'S' => SioResponse::Complete(vec![0x00, 0x00, PRINT_TIMEOUT, 0x00]),
+            _ => SioResponse::Nak,
+        }
+    }
+
+    fn data(&mut self, data: &[u8]) -> SioResponse {
+        match self.file.write_all(&Printer::text(data)) {
+            Ok(()) => SioResponse::Complete(Vec::new()),
+            Err(_) => SioResponse::Error(Vec::new()),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::atr::Density;
+use crate::disk_image::{DiskImage, ImageFormat, SectorCopy};
+
+/// File header: record count (big-endian), "P2", then unused bytes
+const HEADER_SIZE: usize = 16;
+
+/// Each record is a 12-byte header and a 128-byte sector
+const RECORD_HEADER_SIZE: usize = 12;
+const SECTOR_SIZE: usize = 128;
+const RECORD_SIZE: usize = RECORD_HEADER_SIZE + SECTOR_SIZE;
+
+/// Record header: the drive and controller status bytes the drive reports
+/// after reading the sector, the number of phantom copies and where they are
+const RECORD_DRIVE_STATUS: usize = 0;
+const RECORD_FDC_STATUS: usize = 1;
+const RECORD_TIMEOUT: usize = 3;
+const RECORD_PHANTOMS: usize = 5;
+const RECORD_PHANTOM_LIST: usize = 6;
+const MAX_PHANTOMS: usize = 5;
+
+/// Controller status bits kept for a sector (see `DiskImage::sector_status`)
+const FDC_READ_STATUS: u8 = 0x3C;
+
+#[derive(Debug)]
+pub enum ProError {
+    Io(io::Error),
+    /// The file doesn't have the "P2" header
+    NotPro,
+    /// The header promises more records than the file has
+    Truncated { expected: usize, actual: usize },
+}
+
+impl fmt::Display for ProError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProError::Io(e) => write!(f, "{}", e),
+            ProError::NotPro => write!(f, "not a PRO disk image"),
+            ProError::Truncated { expected, actual } => {
+                write!(f, "header says {} records, file has {}", expected, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProError {}
+
+impl From<io::Error> for ProError {
+    fn from(e: io::Error) -> ProError {
+        ProError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Record {
+    header: [u8; RECORD_HEADER_SIZE],
+    data: Vec<u8>,
+}
+
+impl Record {
+    fn new(status: u8, data: Vec<u8>) -> Record {
+        let mut header = [0; RECORD_HEADER_SIZE];
+        header[RECORD_DRIVE_STATUS] = if status & FDC_READ_STATUS != 0 { 0x14 } else { 0x10 };
+        header[RECORD_FDC_STATUS] = !status;
+        header[RECORD_TIMEOUT] = 0xE0;
+        Record { header, data }
+    }
+
+    fn status(&self) -> u8 {
+        !self.header[RECORD_FDC_STATUS] & FDC_READ_STATUS
+    }
+
+    /// Phantom numbers: n is the nth record after the disk's sectors
+    fn phantoms(&self) -> &[u8] {
+        let count = (self.header[RECORD_PHANTOMS] as usize).min(MAX_PHANTOMS);
+        &self.header[RECORD_PHANTOM_LIST..RECORD_PHANTOM_LIST + count]
+    }
+}
+
+/// A PRO disk image, as the APE interface keeps copy-protected single
+/// density disks: each sector with the status the drive gave reading it,
+/// and up to five phantom copies a read may return instead.
+///
+/// The records for the disk's sectors come first, in order, then the
+/// phantom copies. A record's header holds the drive status frame for the
+/// sector (drive status, inverted controller status, timeout), the number
+/// of phantoms and their numbers counting from the end of the sectors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pro {
+    records: Vec<Record>,
+    sector_count: usize,
+}
+
+impl Pro {
+    pub fn blank() -> Pro {
+        let count = Density::Single.sector_count();
+        Pro {
+            records: vec![Record::new(0, vec![0; SECTOR_SIZE]); count],
+            sector_count: count,
+        }
+    }
+
+    /// Whether the data starts like a PRO image
+    pub fn is_pro(image: &[u8]) -> bool {
+        image.len() >= HEADER_SIZE && &image[2..4] == b"P2"
+    }
+
+    pub fn parse(image: &[u8]) -> Result<Pro, ProError> {
+        if !Pro::is_pro(image) {
+            return Err(ProError::NotPro);
+        }
+        let expected = u16::from_be_bytes([image[0], image[1]]) as usize;
+        let actual = (image.len() - HEADER_SIZE) / RECORD_SIZE;
+        if actual < expected {
+            return Err(ProError::Truncated { expected, actual });
+        }
+
+        let records: Vec<Record> = image[HEADER_SIZE..]
+            .chunks_exact(RECORD_SIZE)
+            .take(expected)
+            .map(|record| {
+                let mut header = [0; RECORD_HEADER_SIZE];
+                header.copy_from_slice(&record[..RECORD_HEADER_SIZE]);
+                Record { header, data: record[RECORD_HEADER_SIZE..].to_vec() }
+            })
+            .collect();
+        let phantoms = records
+            .iter()
+            .flat_map(|record| record.phantoms().iter().copied())
+            .max()
+            .unwrap_or(0) as usize;
+        let sector_count = records.len().saturating_sub(phantoms);
+        Ok(Pro { records, sector_count })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Pro, ProError> {
+        Pro::parse(&fs::read(path)?)
+    }
+
+    /// A copy of a disk in another format, or None if it has sectors longer
+    /// than 128 bytes. Sectors with more than six copies keep the first six,
+    /// and there is room for 255 phantoms in all.
+    pub fn from_image(image: &dyn DiskImage) -> Option<Pro> {
+        if image.density().sector_size() != SECTOR_SIZE {
+            return None;
+        }
+        let sector_count = image.sector_count();
+        let mut records = Vec::with_capacity(sector_count);
+        let mut phantoms = Vec::new();
+        for sector in 1..=sector_count {
+            let copies = image.copies(sector).unwrap_or_default();
+            let mut copies = copies.into_iter().map(|copy| Record::new(copy.status, copy.data));
+            let mut record = copies.next().unwrap_or_else(|| Record::new(0x10, vec![0; SECTOR_SIZE]));
+            for phantom in copies.take(MAX_PHANTOMS) {
+                if phantoms.len() == u8::MAX as usize {
+                    break;
+                }
+                phantoms.push(phantom);
+                let count = record.header[RECORD_PHANTOMS] as usize;
+                record.header[RECORD_PHANTOM_LIST + count] = phantoms.len() as u8;
+                record.header[RECORD_PHANTOMS] += 1;
+            }
+            records.push(record);
+        }
+        records.extend(phantoms);
+        Some(Pro { records, sector_count })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, DiskImage::to_bytes(self))
+    }
+}
+
+impl DiskImage for Pro {
+    fn image_format(&self) -> ImageFormat {
+        ImageFormat::Pro
+    }
+
+    fn density(&self) -> Density {
+        if self.sector_count > Density::Single.sector_count() {
+            Density::Enhanced
+        } else {
+            Density::Single
+        }
+    }
+
+    fn sector_count(&self) -> usize {
+        self.sector_count
+    }
+
+    fn sector_size(&self, _sector: usize) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn read_sector(&self, sector: usize) -> Option<&[u8]> {
+        if sector == 0 || sector > self.sector_count {
+            return None;
+        }
+        Some(&self.records[sector - 1].data)
+    }
+
+    /// The sector becomes good, without phantoms
+    fn write_sector(&mut self, sector: usize, data: &[u8]) -> bool {
+        if sector == 0 || sector > self.sector_count || data.len() != SECTOR_SIZE {
+            return false;
+        }
+        self.records[sector - 1] = Record::new(0, data.to_vec());
+        true
+    }
+
+    fn sector_status(&self, sector: usize) -> u8 {
+        match self.read_sector(sector) {
+            Some(_) => self.records[sector - 1].status(),
+            None => 0,
+        }
+    }
+
+    fn copies(&self, sector: usize) -> Option<Vec<SectorCopy>> {
+        if sector == 0 || sector > self.sector_count {
+            return None;
+        }
+        let record = &self.records[sector - 1];
+        let phantoms = record
+            .phantoms()
+            .iter()
+            .filter(|&&n| n > 0)
+            .filter_map(|&n| self.records.get(self.sector_count + n as usize - 1));
+        let copies = Some(record)
+            .into_iter()
+            .chain(phantoms)
+            .map(|record| SectorCopy {
+                status: record.status(),
+                position: None,
+                data: record.data.clone(),
+                weak_offset: None,
+            })
+            .collect();
+        Some(copies)
+    }
+
+    /// Only single and enhanced density fit
+    fn format(&mut self, density: Density) -> bool {
+        if density.sector_size() != SECTOR_SIZE {
+            return false;
+        }
+        self.sector_count = density.sector_count();
+        self.records = vec![Record::new(0, vec![0; SECTOR_SIZE]); self.sector_count];
+        true
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut image = vec![0; HEADER_SIZE];
+        image[0..2].copy_from_slice(&(self.records.len() as u16).to_be_bytes());
+        image[2..4].copy_from_slice(b"P2");
+        for record in &self.records {
+            image.extend_from_slice(&record.header);
+            image.extend_from_slice(&record.data);
+        }
+        image
+    }
+}
@@ -0,0 +1,657 @@
+; R: handler for the 850 interface module, and the bootstrap that loads it.
+;
+; The emulated 850 (interface850.rs) assembles this on request. With
+; HANDLER = 0 it is the bootstrap the 850 sends for command '!', loaded at
+; $0500 and entered six bytes in. The bootstrap asks for the handler with
+; command '&', giving MEMLO in AUX1/AUX2, and the 850 assembles it with
+; HANDLER = 1 and BASE = MEMLO, so it needs no relocating. The handler's
+; install routine at BASE puts R: in HATABS and raises MEMLO past it.
+;
+; A port opened without concurrent mode can only be written, a block of up
+; to 32 bytes at a time with command 'W'. XIO 40 starts concurrent mode:
+; POKEY is set to the port's baud rate and bytes go both ways under
+; interrupts until the port is closed.
+
+DOSINI  = $0C
+POKMSK  = $10
+BRKKEY  = $11
+ICDNOZ  = $21
+ICCOMZ  = $22
+ICAX1Z  = $2A
+ICAX2Z  = $2B
+VSERIN  = $020A
+VSEROR  = $020C
+SSKCTL  = $0232
+MEMLO   = $02E7
+DVSTAT  = $02EA
+DDEVIC  = $0300
+DUNIT   = $0301
+DCOMND  = $0302
+DSTATS  = $0303
+DBUFLO  = $0304
+DBUFHI  = $0305
+DTIMLO  = $0306
+DBYTLO  = $0308
+DBYTHI  = $0309
+DAUX1   = $030A
+DAUX2   = $030B
+HATABS  = $031A
+SIOV    = $E459
+
+AUDF3   = $D204
+AUDF4   = $D206
+AUDCTL  = $D208
+SKRES   = $D20A
+SERIN   = $D20D
+SEROUT  = $D20D
+IRQEN   = $D20E
+IRQST   = $D20E
+SKCTL   = $D20F
+
+EOL     = $9B
+CR      = $0D
+LF      = $0A
+
+; XIO 38 translation bits in AUX1
+TR_HEAVY = $10
+TR_NONE  = $20
+TR_LF    = $40
+
+IN_SIZE  = 64
+OUT_SIZE = 32
+BLOCK_SIZE = 32
+
+.if HANDLER
+
+        .org BASE
+
+; Entered once by the bootstrap. Returns with carry clear.
+install:
+        lda DOSINI
+        sta old_dosini
+        lda DOSINI+1
+        sta old_dosini+1
+        lda #<reset
+        sta DOSINI
+        lda #>reset
+        sta DOSINI+1
+        jsr add_device
+        clc
+        rts
+
+; A reset clears the OS variables, so the handler is put back each time
+reset:
+        jsr call_dosini
+        lda #0
+        sta unit
+        sta concurrent
+add_device:
+        lda #<handler_end
+        sta MEMLO
+        lda #>handler_end
+        sta MEMLO+1
+        ldx #0
+ad_find:
+        lda HATABS,x
+        cmp #'R'
+        beq ad_done
+        cmp #0
+        beq ad_free
+        inx
+        inx
+        inx
+        cpx #36
+        bcc ad_find
+        rts
+ad_free:
+        lda #'R'
+        sta HATABS,x
+        lda #<table
+        sta HATABS+1,x
+        lda #>table
+        sta HATABS+2,x
+ad_done:
+        rts
+
+call_dosini:
+        jmp (old_dosini)
+
+table:
+        .word open-1, close-1, get-1, put-1, status-1, special-1
+        jmp add_device
+
+; One port can be open at a time
+open:
+        lda ICDNOZ
+        cmp #5
+        bcc op_unit
+        ldy #$82                ; no such device
+        rts
+op_unit:
+        lda unit
+        beq op_free
+        ldy #$81                ; already open
+        rts
+op_free:
+        lda ICDNOZ
+        sta unit
+        lda #0
+        sta translate
+        sta block_count
+        sta errors
+        sta concurrent
+        ldy #1
+        rts
+
+close:
+        lda concurrent
+        beq cl_block
+        jsr end_concurrent
+        ldy #1
+        bne cl_done
+cl_block:
+        jsr flush_block
+cl_done:
+        lda #0
+        sta unit
+        rts
+
+; Bytes only come in concurrent mode
+get:
+        lda concurrent
+        bne gt_wait
+        ldy #$92                ; not supported
+        rts
+gt_wait:
+        lda BRKKEY
+        beq gt_break
+        lda in_count
+        beq gt_wait
+        sei
+        ldx in_tail
+        lda in_buffer,x
+        inx
+        cpx #IN_SIZE
+        bcc gt_take
+        ldx #0
+gt_take:
+        stx in_tail
+        dec in_count
+        cli
+        jsr in_translate
+        ldy #1
+        rts
+gt_break:
+        ldy #$80
+        rts
+
+put:
+        sta char
+        jsr out_translate
+        bcs pt_ok
+        jsr write_byte
+        cpy #$80
+        bcs pt_done
+        lda char
+        cmp #EOL
+        bne pt_ok
+        lda translate
+        and #TR_NONE
+        bne pt_eol
+        lda translate
+        and #TR_LF
+        beq pt_eol
+        lda #LF
+        jsr write_byte
+        cpy #$80
+        bcs pt_done
+pt_eol:
+        lda concurrent
+        bne pt_ok
+        jmp flush_block         ; an EOL sends the block
+pt_ok:
+        ldy #1
+pt_done:
+        rts
+
+; Concurrent mode: errors, then bytes waiting to be read and to be sent.
+; Otherwise the 850's own status.
+status:
+        lda concurrent
+        beq st_port
+        lda errors
+        sta DVSTAT
+        lda #0
+        sta errors
+        sta DVSTAT+2
+        lda in_count
+        sta DVSTAT+1
+        lda out_count
+        sta DVSTAT+3
+        ldy #1
+        rts
+st_port:
+        lda #'S'
+        jmp port_sio
+
+; XIO 34 sets the control lines, 36 the baud rate and word size, 38 the
+; translation and 40 starts concurrent mode
+special:
+        lda ICCOMZ
+        cmp #34
+        beq xio_control
+        cmp #36
+        beq xio_baud
+        cmp #38
+        beq xio_translate
+        cmp #40
+        beq xio_concurrent
+        ldy #$92
+        rts
+xio_control:
+        lda #'A'
+        jmp port_sio
+xio_baud:
+        lda ICAX1Z
+        and #$0F
+        sta baud
+        lda #'B'
+        jmp port_sio
+xio_translate:
+        lda ICAX1Z
+        sta translate
+        lda ICAX2Z
+        sta untranslatable
+        ldy #1
+        rts
+xio_concurrent:
+        ldy #1
+        lda concurrent
+        beq xc_start
+        rts
+xc_start:
+        jsr flush_block
+        lda #'X'
+        jsr port_sio
+        cpy #$80
+        bcc xc_pokey
+        rts
+
+xc_pokey:
+        sei
+        ldx baud
+        lda audf_low,x
+        sta AUDF3
+        lda audf_high,x
+        sta AUDF4
+        lda #$28                ; channels 3+4 joined at 1.79MHz
+        sta AUDCTL
+        lda SSKCTL
+        and #$07
+        ora #$10                ; asynchronous receive
+        sta SKCTL
+        sta SKRES
+
+        lda VSERIN
+        sta old_vserin
+        lda VSERIN+1
+        sta old_vserin+1
+        lda VSEROR
+        sta old_vseror
+        lda VSEROR+1
+        sta old_vseror+1
+        lda #<serial_in
+        sta VSERIN
+        lda #>serial_in
+        sta VSERIN+1
+        lda #<serial_out
+        sta VSEROR
+        lda #>serial_out
+        sta VSEROR+1
+
+        lda #0
+        sta in_head
+        sta in_tail
+        sta in_count
+        sta out_head
+        sta out_tail
+        sta out_count
+        sta out_busy
+        lda #1
+        sta concurrent
+        lda POKMSK
+        ora #$30
+        sta POKMSK
+        sta IRQEN
+        cli
+        ldy #1
+        rts
+
+; Wait for the last bytes to go, then give POKEY and its vectors back
+end_concurrent:
+        lda out_busy
+        bne end_concurrent
+ec_shift:
+        lda IRQST
+        and #$08                ; low once the last bit is out
+        bne ec_shift
+        sei
+        lda POKMSK
+        and #$C7
+        sta POKMSK
+        sta IRQEN
+        lda old_vserin
+        sta VSERIN
+        lda old_vserin+1
+        sta VSERIN+1
+        lda old_vseror
+        sta VSEROR
+        lda old_vseror+1
+        sta VSEROR+1
+        lda #0
+        sta concurrent
+        cli
+        rts
+
+; Send A: to POKEY in concurrent mode, else into the block, which goes to
+; the 850 when full. Returns the status in Y.
+write_byte:
+        ldx concurrent
+        bne wb_concurrent
+        ldx block_count
+        sta block,x
+        inx
+        stx block_count
+        ldy #1
+        cpx #BLOCK_SIZE
+        bcs flush_block
+        rts
+wb_concurrent:
+        sta out_char
+wb_wait:
+        lda BRKKEY
+        beq wb_break
+        lda out_count
+        cmp #OUT_SIZE
+        bcs wb_wait
+        sei
+        lda out_busy
+        bne wb_queue
+        ; Nothing going out, so no interrupt will come for this byte
+        lda #1
+        sta out_busy
+        lda out_char
+        sta SEROUT
+        cli
+        ldy #1
+        rts
+wb_queue:
+        ldx out_head
+        lda out_char
+        sta out_buffer,x
+        inx
+        cpx #OUT_SIZE
+        bcc wb_head
+        ldx #0
+wb_head:
+        stx out_head
+        inc out_count
+        cli
+        ldy #1
+        rts
+wb_break:
+        ldy #$80
+        rts
+
+; Send what is in the block with command 'W', AUX1 the byte count
+flush_block:
+        ldy #1
+        lda block_count
+        beq fb_done
+        lda #'W'
+        jsr port_sio
+        lda #0
+        sta block_count
+fb_done:
+        rts
+
+; SIO command A to the port in ICDNOZ. 'W' sends the block and 'S' reads
+; the status into DVSTAT. AUX1/AUX2 come from the IOCB, except for 'W'.
+; Returns the status in Y.
+port_sio:
+        sta DCOMND
+        lda #$50
+        sta DDEVIC
+        lda ICDNOZ
+        sta DUNIT
+        lda ICAX1Z
+        sta DAUX1
+        lda ICAX2Z
+        sta DAUX2
+        lda #$08
+        sta DTIMLO
+        lda #0
+        sta DSTATS
+        sta DBYTLO
+        sta DBYTHI
+        lda DCOMND
+        cmp #'W'
+        bne ps_status
+        lda block_count
+        sta DAUX1
+        lda #$80                ; data to the 850
+        sta DSTATS
+        lda #<block
+        sta DBUFLO
+        lda #>block
+        sta DBUFHI
+        lda #BLOCK_SIZE
+        sta DBYTLO
+        bne ps_go
+ps_status:
+        cmp #'S'
+        bne ps_go
+        lda #$40                ; data from the 850
+        sta DSTATS
+        lda #<DVSTAT
+        sta DBUFLO
+        lda #>DVSTAT
+        sta DBUFHI
+        lda #2
+        sta DBYTLO
+ps_go:
+        jsr SIOV
+        ldy DSTATS
+        rts
+
+; A = byte to send. Unless translation is off, EOL becomes CR and bit 7 is
+; cleared; heavy translation also drops what isn't printable ASCII.
+; Returns with carry set if nothing is to be sent.
+out_translate:
+        lda translate
+        and #TR_NONE
+        bne ot_raw
+        lda char
+        cmp #EOL
+        bne ot_char
+        lda #CR
+        clc
+        rts
+ot_char:
+        and #$7F
+        tax
+        lda translate
+        and #TR_HEAVY
+        beq ot_send
+        cpx #$20
+        bcc ot_drop
+        cpx #$7D
+        bcs ot_drop
+ot_send:
+        txa
+        clc
+        rts
+ot_raw:
+        lda char
+        clc
+        rts
+ot_drop:
+        sec
+        rts
+
+; A = byte received. Unless translation is off, CR becomes EOL and bit 7
+; is cleared; heavy translation also replaces what isn't printable ASCII
+; with the character given to XIO 38.
+in_translate:
+        tax
+        lda translate
+        and #TR_NONE
+        bne it_raw
+        txa
+        and #$7F
+        cmp #CR
+        bne it_char
+        lda #EOL
+        rts
+it_char:
+        tax
+        lda translate
+        and #TR_HEAVY
+        beq it_raw
+        cpx #$20
+        bcc it_replace
+        cpx #$7D
+        bcc it_raw
+it_replace:
+        ldx untranslatable
+it_raw:
+        txa
+        rts
+
+; POKEY has a byte in
+serial_in:
+        txa
+        pha
+        lda SERIN
+        ldx in_count
+        cpx #IN_SIZE
+        bcs si_overrun
+        ldx in_head
+        sta in_buffer,x
+        inx
+        cpx #IN_SIZE
+        bcc si_head
+        ldx #0
+si_head:
+        stx in_head
+        inc in_count
+        jmp si_done
+si_overrun:
+        lda errors
+        ora #$10
+        sta errors
+si_done:
+        pla
+        tax
+        pla
+        rti
+
+; POKEY is ready for the next byte to go out
+serial_out:
+        txa
+        pha
+        lda out_count
+        beq se_idle
+        ldx out_tail
+        lda out_buffer,x
+        sta SEROUT
+        inx
+        cpx #OUT_SIZE
+        bcc se_tail
+        ldx #0
+se_tail:
+        stx out_tail
+        dec out_count
+        jmp se_done
+se_idle:
+        lda #0
+        sta out_busy
+se_done:
+        pla
+        tax
+        pla
+        rti
+
+; AUDF3/AUDF4 for the baud rates of XIO 36 AUX1 bits 0-3
+audf_low:
+        .byte <$0BA0, <$4CCD, <$45E3, <$3D6F, <$2E95, <$1FC0, <$19F6, <$1747
+        .byte <$0BA0, <$05CC, <$02E3, <$01EA, <$016E, <$00B3, <$0056, <$0056
+audf_high:
+        .byte >$0BA0, >$4CCD, >$45E3, >$3D6F, >$2E95, >$1FC0, >$19F6, >$1747
+        .byte >$0BA0, >$05CC, >$02E3, >$01EA, >$016E, >$00B3, >$0056, >$0056
+
+old_dosini:     .word 0
+old_vserin:     .word 0
+old_vseror:     .word 0
+unit:           .byte 0         ; port open, 0 if none
+translate:      .byte 0         ; XIO 38 AUX1
+untranslatable: .byte 0
+baud:           .byte 0
+concurrent:     .byte 0
+errors:         .byte 0
+char:           .byte 0
+out_char:       .byte 0
+block_count:    .byte 0
+in_head:        .byte 0
+in_tail:        .byte 0
+in_count:       .byte 0
+out_head:       .byte 0
+out_tail:       .byte 0
+out_count:      .byte 0
+out_busy:       .byte 0
+handler_size = * - BASE
+
+; Buffers, which don't need loading
+in_buffer = *
+out_buffer = in_buffer + IN_SIZE
+block = out_buffer + OUT_SIZE
+handler_end = block + BLOCK_SIZE
+
+.else
+
+        .org $0500
+
+        .byte 0, 1
+        .word $0500, bs_done
+; Fetch the handler to MEMLO and install it
+bootstrap:
+        lda #$50
+        sta DDEVIC
+        lda #1
+        sta DUNIT
+        lda #'&'
+        sta DCOMND
+        lda #$40
+        sta DSTATS
+        lda MEMLO
+        sta DBUFLO
+        sta DAUX1
+        lda MEMLO+1
+        sta DBUFHI
+        sta DAUX2
+        lda #<HANDLER_SIZE
+        sta DBYTLO
+        lda #>HANDLER_SIZE
+        sta DBYTHI
+        lda #$08
+        sta DTIMLO
+        jsr SIOV
+        bmi bs_failed
+        jmp (MEMLO)
+bs_failed:
+        sec
+bs_done:
+        rts
+bootstrap_end:
+
+.endif
@@ -0,0 +1,189 @@
+/// Timer interrupt flag, in the interrupt flag register
+const FLAG_TIMER: u8 = 0x80;
+
+/// PA7 edge flag, in the interrupt flag register
+const FLAG_PA7: u8 = 0x40;
+
+/// Timer prescale for each interval select (A1-A0 on a timer write)
+const PRESCALE: [u16; 4] = [1, 8, 64, 1024];
+
+/// 6532 RIOT: 128 bytes of RAM, two 8-bit I/O ports and an interval timer.
+///
+/// The RAM and the I/O registers are separate chip selects, so the bus
+/// decides which an access is for and calls `read_ram` or `read_register`
+/// with the low address bits.
+///
+/// With A2 low the registers are the port A and B data and direction
+/// registers. With A2 high a write sets the timer (A4 high: A1-A0 select
+/// the interval, A3 enables its interrupt) or the PA7 edge detect (A4 low:
+/// bit 0 picks the rising edge, bit 1 enables its interrupt). A read with
+/// A2 high gives the timer (A0 low) or the interrupt flags (A0 high).
+///
+/// Pins set as inputs read whatever the outside world drives them to;
+/// pins set as outputs read back their output latch.
+pub struct Riot {
+    ram: [u8; 128],
+
+    ora: u8,
+    ddra: u8,
+    orb: u8,
+    ddrb: u8,
+
+    // Levels driven onto the port pins from outside
+    port_a_input: u8,
+    port_b_input: u8,
+
+    // Timer count, its prescale, cycles until the next count, and whether
+    // it has run out and is now counting every cycle
+    timer: u8,
+    prescale: u16,
+    prescale_count: u16,
+    timer_expired: bool,
+    timer_irq_enabled: bool,
+
+    // PA7 edge detect: rising rather than falling edge, and the level last
+    // cycle
+    pa7_rising: bool,
+    pa7_irq_enabled: bool,
+    pa7_level: bool,
+
+    flags: u8,
+}
+
+impl Riot {
+    pub fn new() -> Riot {
+        Riot {
+            ram: [0; 128],
+            ora: 0,
+            ddra: 0,
+            orb: 0,
+            ddrb: 0,
+            port_a_input: 0xFF,
+            port_b_input: 0xFF,
+            timer: 0xFF,
+            prescale: 1024,
+            prescale_count: 1024,
+            timer_expired: false,
+            timer_irq_enabled: false,
+            pa7_rising: false,
+            pa7_irq_enabled: false,
+            pa7_level: true,
+            flags: 0,
+        }
+    }
+
+    pub fn read_ram(&self, addr: u16) -> u8 {
+        self.ram[(addr & 0x7F) as usize]
+    }
+
+    pub fn write_ram(&mut self, addr: u16, val: u8) {
+        self.ram[(addr & 0x7F) as usize] = val;
+    }
+
+    /// Read an I/O or timer register. Reading the timer clears the timer
+    /// flag; reading the flags clears the PA7 flag.
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        if addr & 0x04 == 0 {
+            return match addr & 0x03 {
+                0 => self.port_a(),
+                1 => self.ddra,
+                2 => self.port_b(),
+                _ => self.ddrb,
+            };
+        }
+        if addr & 0x01 == 0 {
+            self.timer_irq_enabled = addr & 0x08 != 0;
+            if self.flags & FLAG_TIMER != 0 {
+                self.flags &= !FLAG_TIMER;
+                self.timer_expired = false;
+            }
+            self.timer
+        } else {
+            let flags = self.flags;
+            self.flags &= !FLAG_PA7;
+            flags
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        if addr & 0x04 == 0 {
+            match addr & 0x03 {
+                0 => self.ora = val,
+                1 => self.ddra = val,
+                2 => self.orb = val,
+                _ => self.ddrb = val,
+            }
+        } else if addr & 0x10 != 0 {
+            self.timer = val;
+            self.prescale = PRESCALE[(addr & 0x03) as usize];
+            self.prescale_count = self.prescale;
+            self.timer_expired = false;
+            self.timer_irq_enabled = addr & 0x08 != 0;
+            self.flags &= !FLAG_TIMER;
+        } else {
+            self.pa7_rising = addr & 0x01 != 0;
+            self.pa7_irq_enabled = addr & 0x02 != 0;
+        }
+    }
+
+    /// Levels on the port A pins
+    pub fn port_a(&self) -> u8 {
+        self.ora & self.ddra | self.port_a_input & !self.ddra
+    }
+
+    /// Levels on the port B pins
+    pub fn port_b(&self) -> u8 {
+        self.orb & self.ddrb | self.port_b_input & !self.ddrb
+    }
+
+    /// Drive the port A pins from outside. Only pins set as inputs take
+    /// the level.
+    pub fn set_port_a_input(&mut self, val: u8) {
+        self.port_a_input = val;
+    }
+
+    pub fn set_port_b_input(&mut self, val: u8) {
+        self.port_b_input = val;
+    }
+
+    /// Whether the chip is pulling its IRQ output low
+    pub fn irq(&self) -> bool {
+        self.flags & FLAG_TIMER != 0 && self.timer_irq_enabled
+            || self.flags & FLAG_PA7 != 0 && self.pa7_irq_enabled
+    }
+
+    /// Run one cycle: count the timer down, and watch PA7 for the edge
+    pub fn tick(&mut self) {
+        if self.timer_expired {
+            self.timer = self.timer.wrapping_sub(1);
+        } else {
+            self.prescale_count -= 1;
+            if self.prescale_count == 0 {
+                self.prescale_count = self.prescale;
+                if self.timer == 0 {
+                    // Out of time: the flag goes up and the count carries
+                    // on down from $FF a cycle at a time
+                    self.timer = 0xFF;
+                    self.timer_expired = true;
+                    self.flags |= FLAG_TIMER;
+                } else {
+                    self.timer -= 1;
+                }
+            }
+        }
+
+        let pa7 = self.port_a() & 0x80 != 0;
+        if pa7 != self.pa7_level {
+            self.pa7_level = pa7;
+            if pa7 == self.pa7_rising {
+                self.flags |= FLAG_PA7;
+            }
+        }
+    }
+}
+
+impl Default for Riot {
+    fn default() -> Riot {
+        Riot::new()
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The system ROMs a machine may need
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RomKind {
+    /// 10K OS for the 400/800 ($D800-$FFFF)
+    Os800,
+    /// 16K OS for XL/XE machines ($C000-$FFFF, self-test included)
+    OsXl,
+    /// 8K Atari BASIC
+    Basic,
+    /// 2K XL/XE self-test, replacing the one inside the XL OS image
+    SelfTest,
+    /// 2K Atari 5200 BIOS
+    Bios5200,
+    /// 4K firmware for a 1050 disk drive, stock or upgraded, or a 2K dump
+    /// that the drive mirrors
+    Drive1050,
+}
+
+impl RomKind {
+    /// Image size in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            RomKind::Os800 => 0x2800,
+            RomKind::OsXl => 0x4000,
+            RomKind::Basic => 0x2000,
+            RomKind::SelfTest | RomKind::Bios5200 => 0x0800,
+            RomKind::Drive1050 => 0x1000,
+        }
+    }
+
+    /// Whether an image of `len` bytes is the right size for this ROM
+    pub fn fits(&self, len: usize) -> bool {
+        len == self.size() || *self == RomKind::Drive1050 && len == self.size() / 2
+    }
+
+    /// Whether images have to be in the known list. Drive upgrades each
+    /// come with their own firmware, and self-test images are cut out of
+    /// whichever XL/XE OS they came from, so those are taken as they are.
+    fn checked(&self) -> bool {
+        !matches!(self, RomKind::SelfTest | RomKind::Drive1050)
+    }
+
+    /// File names tried, in order, when looking in a search directory
+    fn file_names(&self) -> &'static [&'static str] {
+        match self {
+            RomKind::Os800 => &["Atari OS-B NTSC.ROM", "ATARIOSB.ROM", "ATARIOSA.ROM"],
+            RomKind::OsXl => &["Atari XL-XE OS.ROM", "ATARIXL.ROM"],
+            RomKind::Basic => &["Atari BASIC.ROM", "ATARIBAS.ROM"],
+            RomKind::SelfTest => &["Atari Self-Test.ROM", "SELFTEST.ROM"],
+            RomKind::Bios5200 => &["Atari 5200 BIOS.ROM", "5200.ROM"],
+            RomKind::Drive1050 => &["Atari 1050.ROM", "ATARI1050.ROM", "1050.ROM"],
+        }
+    }
+}
+
+/// A ROM dump we know the checksum of
+#[derive(Debug, PartialEq)]
+pub struct KnownRom {
+    pub kind: RomKind,
+    pub crc32: u32,
+    pub name: &'static str,
+}
+
+const KNOWN_ROMS: &[KnownRom] = &[
+    KnownRom { kind: RomKind::Os800, crc32: 0xC1B3BB02, name: "OS rev. A NTSC" },
+    KnownRom { kind: RomKind::Os800, crc32: 0x72B3FED4, name: "OS rev. A PAL" },
+    KnownRom { kind: RomKind::Os800, crc32: 0x0E86D61D, name: "OS rev. B NTSC" },
+    KnownRom { kind: RomKind::OsXl, crc32: 0x643BCC98, name: "XL OS rev. 1" },
+    KnownRom { kind: RomKind::OsXl, crc32: 0x1F9CD270, name: "XL/XE OS rev. 2" },
+    KnownRom { kind: RomKind::OsXl, crc32: 0x29F133F7, name: "XL/XE OS rev. 3" },
+    KnownRom { kind: RomKind::OsXl, crc32: 0x1EAF4002, name: "XL/XE OS rev. 4" },
+    KnownRom { kind: RomKind::Basic, crc32: 0x4BEC4DE2, name: "BASIC rev. A" },
+    KnownRom { kind: RomKind::Basic, crc32: 0xF0202FB3, name: "BASIC rev. B" },
+    KnownRom { kind: RomKind::Basic, crc32: 0x7D684184, name: "BASIC rev. C" },
+    KnownRom { kind: RomKind::Bios5200, crc32: 0x4248D3E3, name: "5200 BIOS" },
+    KnownRom { kind: RomKind::Bios5200, crc32: 0xC2BA2613, name: "5200 BIOS (4-port)" },
+];
+
+/// Look up a ROM image by its CRC32
+pub fn identify(kind: RomKind, data: &[u8]) -> Option<&'static KnownRom> {
+    let crc = crc32(data);
+    KNOWN_ROMS.iter().find(|rom| rom.kind == kind && rom.crc32 == crc)
+}
+
+/// CRC-32 (IEEE 802.3 polynomial, as used by zip and most ROM databases)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    /// No path given and nothing suitable in the search directory, or the
+    /// file doesn't exist
+    Missing { kind: RomKind, path: Option<PathBuf> },
+    Io { path: PathBuf, error: io::Error },
+    WrongSize { kind: RomKind, path: PathBuf, expected: usize, actual: usize },
+    /// An image handed over in memory, e.g. in a machine configuration, is
+    /// the wrong size
+    WrongImageSize { kind: RomKind, expected: usize, actual: usize },
+    UnknownChecksum { kind: RomKind, path: PathBuf, crc32: u32 },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Missing { kind, path: Some(path) } => {
+                write!(f, "{:?} ROM {} not found", kind, path.display())
+            }
+            RomError::Missing { kind, path: None } => write!(f, "no {:?} ROM configured", kind),
+            RomError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            RomError::WrongSize { kind, path, expected, actual } => write!(
+                f,
+                "{:?} ROM {} should be {} bytes, file is {} bytes",
+                kind,
+                path.display(),
+                expected,
+                actual
+            ),
+            RomError::WrongImageSize { kind, expected, actual } => {
+                write!(f, "{:?} ROM image should be {} bytes, got {}", kind, expected, actual)
+            }
+            RomError::UnknownChecksum { kind, path, crc32 } => write!(
+                f,
+                "{:?} ROM {} has unknown CRC32 {:08X}",
+                kind,
+                path.display(),
+                crc32
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// A ROM image read from disk and checked
+#[derive(Clone, Debug)]
+pub struct RomImage {
+    pub kind: RomKind,
+    pub data: Vec<u8>,
+    pub crc32: u32,
+    /// The dump it matched, if any
+    pub known: Option<&'static KnownRom>,
+}
+
+/// Where to find the system ROMs.
+///
+/// Each ROM comes from its explicit path if one is set, otherwise from the
+/// first of the usual file names found in the search directory.
+#[derive(Clone, Debug, Default)]
+pub struct RomSet {
+    pub search_dir: Option<PathBuf>,
+    pub os_800: Option<PathBuf>,
+    pub os_xl: Option<PathBuf>,
+    pub basic: Option<PathBuf>,
+    pub self_test: Option<PathBuf>,
+    pub bios_5200: Option<PathBuf>,
+    pub drive_1050: Option<PathBuf>,
+
+    /// Accept images whose checksum isn't in the known list (patched or
+    /// third-party ROMs)
+    pub allow_unknown: bool,
+}
+
+impl RomSet {
+    /// ROMs looked up in a directory
+    pub fn with_search_dir<P: AsRef<Path>>(dir: P) -> RomSet {
+        RomSet {
+            search_dir: Some(dir.as_ref().to_path_buf()),
+            ..RomSet::default()
+        }
+    }
+
+    fn explicit_path(&self, kind: RomKind) -> Option<&PathBuf> {
+        match kind {
+            RomKind::Os800 => self.os_800.as_ref(),
+            RomKind::OsXl => self.os_xl.as_ref(),
+            RomKind::Basic => self.basic.as_ref(),
+            RomKind::SelfTest => self.self_test.as_ref(),
+            RomKind::Bios5200 => self.bios_5200.as_ref(),
+            RomKind::Drive1050 => self.drive_1050.as_ref(),
+        }
+    }
+
+    /// Path a ROM will be loaded from, if there is one
+    pub fn path(&self, kind: RomKind) -> Option<PathBuf> {
+        if let Some(path) = self.explicit_path(kind) {
+            return Some(path.clone());
+        }
+        let dir = self.search_dir.as_ref()?;
+        kind.file_names()
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// Load and check a ROM
+    pub fn load(&self, kind: RomKind) -> Result<RomImage, RomError> {
+        let path = self.path(kind).ok_or(RomError::Missing { kind, path: None })?;
+        let data = fs::read(&path).map_err(|error| {
+            if error.kind() == io::ErrorKind::NotFound {
+                RomError::Missing { kind, path: Some(path.clone()) }
+            } else {
+                RomError::Io { path: path.clone(), error }
+            }
+        })?;
+
+        if !kind.fits(data.len()) {
+            return Err(RomError::WrongSize {
+                kind,
+                path,
+                expected: kind.size(),
+                actual: data.len(),
+            });
+        }
+
+        let crc32 = crc32(&data);
+        let known = identify(kind, &data);
+        if known.is_none() && kind.checked() && !self.allow_unknown {
+            return Err(RomError::UnknownChecksum { kind, path, crc32 });
+        }
+
+        Ok(RomImage { kind, data, crc32, known })
+    }
+
+    /// Load a ROM the machine can do without: Ok(None) if none is
+    /// configured or found, but any other problem is still an error
+    pub fn load_optional(&self, kind: RomKind) -> Result<Option<RomImage>, RomError> {
+        match self.load(kind) {
+            Ok(image) => Ok(Some(image)),
+            Err(RomError::Missing { path: None, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
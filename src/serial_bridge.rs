@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+/// Bytes read from the host in one go
+const READ_CHUNK: usize = 256;
+
+/// The far end of an emulated serial port: something on the host that
+/// bytes from the port go to and come from.
+pub trait SerialBridge {
+    /// Send a byte to the other end. Bytes with nowhere to go are lost,
+    /// as on a line with nothing plugged in.
+    fn send(&mut self, byte: u8);
+
+    /// A byte from the other end, if one is waiting
+    fn receive(&mut self) -> Option<u8>;
+
+    /// Whether anything is at the other end, which the port reports as
+    /// its DSR, CTS and carrier lines
+    fn connected(&mut self) -> bool;
+
+    /// Drop the connection, as a modem does when DTR goes off
+    fn hang_up(&mut self) {}
+
+    /// Raise or drop the handshake line the bridge drives at the other
+    /// end. Bridges without modem lines have nowhere to signal it.
+    fn set_handshake(&mut self, _on: bool) {}
+}
+
+/// A port bridged to a TCP connection. The bridge listens on a local
+/// address and takes one connection at a time; a telnet client or another
+/// emulator can connect to it.
+pub struct TcpBridge {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    incoming: VecDeque<u8>,
+    outgoing: Vec<u8>,
+}
+
+impl TcpBridge {
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<TcpBridge> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(TcpBridge { listener, stream: None, incoming: VecDeque::new(), outgoing: Vec::new() })
+    }
+
+    /// The address the bridge is listening on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Take a waiting connection if there isn't one
+    fn accept(&mut self) {
+        if self.stream.is_none() {
+            if let Ok((stream, _)) = self.listener.accept() {
+                if stream.set_nonblocking(true).is_ok() {
+                    let _ = stream.set_nodelay(true);
+                    self.stream = Some(stream);
+                }
+            }
+        }
+    }
+
+    /// Move bytes both ways without blocking. The connection is dropped
+    /// when the other end closes it or it fails.
+    fn poll(&mut self) {
+        self.accept();
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+        let open = write_pending(stream, &mut self.outgoing).is_ok()
+            && read_available(stream, &mut self.incoming).is_ok();
+        if !open {
+            self.hang_up();
+        }
+    }
+}
+
+impl SerialBridge for TcpBridge {
+    fn send(&mut self, byte: u8) {
+        self.accept();
+        if self.stream.is_some() {
+            self.outgoing.push(byte);
+        }
+        self.poll();
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        if self.incoming.is_empty() {
+            self.poll();
+        }
+        self.incoming.pop_front()
+    }
+
+    fn connected(&mut self) -> bool {
+        self.poll();
+        self.stream.is_some()
+    }
+
+    fn hang_up(&mut self) {
+        self.stream = None;
+        self.outgoing.clear();
+    }
+}
+
+/// A port bridged to a pseudo-terminal, which a terminal program or
+/// another emulator on the host can open as a serial device. The far end
+/// is always taken to be connected.
+#[cfg(unix)]
+pub struct PtyBridge {
+    master: std::fs::File,
+    // Held open so reads don't fail while nothing else has the device open
+    _slave: std::fs::File,
+    path: std::path::PathBuf,
+    incoming: VecDeque<u8>,
+    outgoing: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl PtyBridge {
+    pub fn open() -> io::Result<PtyBridge> {
+        use std::ffi::CStr;
+        use std::os::unix::fs::OpenOptionsExt;
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        let fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let master = unsafe { std::fs::File::from_raw_fd(fd) };
+        let path = unsafe {
+            if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            std::path::PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned())
+        };
+        let slave = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(&path)?;
+
+        unsafe {
+            // Raw, so bytes pass through untouched
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(slave.as_raw_fd(), &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(PtyBridge { master, _slave: slave, path, incoming: VecDeque::new(), outgoing: Vec::new() })
+    }
+
+    /// The device for the other end to open, such as /dev/pts/3
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(unix)]
+impl SerialBridge for PtyBridge {
+    fn send(&mut self, byte: u8) {
+        self.outgoing.push(byte);
+        if write_pending(&mut self.master, &mut self.outgoing).is_err() {
+            self.outgoing.clear();
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        if self.incoming.is_empty() {
+            let _ = read_available(&mut self.master, &mut self.incoming);
+        }
+        self.incoming.pop_front()
+    }
+
+    fn connected(&mut self) -> bool {
+        true
+    }
+}
+
+/// The modem line a `TtyBridge` drives as its handshake. Through a null
+/// modem cable RTS arrives at the other end as CTS, and DTR as DSR.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModemLine {
+    Rts,
+    Dtr,
+}
+
+/// A port bridged to a serial device on the host, such as a USB serial
+/// adapter or one end of a null modem pair. Bytes go through untouched at
+/// 19200 baud, 8 data bits and no parity, and the handshake is signalled
+/// on a modem line, so a disk server on the other side can watch for it
+/// on CTS or DSR. The far end counts as connected while it asserts DSR or
+/// carrier, or always if the device has no modem lines.
+#[cfg(unix)]
+pub struct TtyBridge {
+    device: std::fs::File,
+    line: ModemLine,
+    incoming: VecDeque<u8>,
+    outgoing: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl TtyBridge {
+    pub fn open<P: AsRef<std::path::Path>>(path: P, line: ModemLine) -> io::Result<TtyBridge> {
+        use std::os::unix::fs::OpenOptionsExt;
+        use std::os::unix::io::AsRawFd;
+
+        let device = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(path)?;
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(device.as_raw_fd(), &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+            if libc::cfsetspeed(&mut termios, libc::B19200) != 0
+                || libc::tcsetattr(device.as_raw_fd(), libc::TCSANOW, &termios) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let mut bridge = TtyBridge { device, line, incoming: VecDeque::new(), outgoing: Vec::new() };
+        bridge.set_handshake(false);
+        Ok(bridge)
+    }
+
+    /// Set or clear modem lines. Devices without them are left alone.
+    fn set_modem_lines(&mut self, set: bool, bits: libc::c_int) {
+        use std::os::unix::io::AsRawFd;
+        let request = if set { libc::TIOCMBIS } else { libc::TIOCMBIC };
+        unsafe {
+            libc::ioctl(self.device.as_raw_fd(), request, &bits);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl SerialBridge for TtyBridge {
+    fn send(&mut self, byte: u8) {
+        self.outgoing.push(byte);
+        if write_pending(&mut self.device, &mut self.outgoing).is_err() {
+            self.outgoing.clear();
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        if self.incoming.is_empty() {
+            let _ = read_available(&mut self.device, &mut self.incoming);
+        }
+        self.incoming.pop_front()
+    }
+
+    fn connected(&mut self) -> bool {
+        use std::os::unix::io::AsRawFd;
+        let mut bits: libc::c_int = 0;
+        let got = unsafe { libc::ioctl(self.device.as_raw_fd(), libc::TIOCMGET, &mut bits) == 0 };
+        !got || bits & (libc::TIOCM_DSR | libc::TIOCM_CAR) != 0
+    }
+
+    fn set_handshake(&mut self, on: bool) {
+        let bit = match self.line {
+            ModemLine::Rts => libc::TIOCM_RTS,
+            ModemLine::Dtr => libc::TIOCM_DTR,
+        };
+        self.set_modem_lines(on, bit);
+    }
+}
+
+/// Write as much of `pending` as goes without blocking
+fn write_pending<W: Write>(writer: &mut W, pending: &mut Vec<u8>) -> io::Result<()> {
+    while !pending.is_empty() {
+        match writer.write(pending) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(len) => {
+                pending.drain(..len);
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Read whatever has arrived without blocking. The end of the stream is an
+/// error, as the connection has gone.
+fn read_available<R: Read>(reader: &mut R, incoming: &mut VecDeque<u8>) -> io::Result<()> {
+    let mut buffer = [0; READ_CHUNK];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(len) => incoming.extend(&buffer[..len]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
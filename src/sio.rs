@@ -0,0 +1,528 @@
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::cassette::Cassette;
+use crate::pokey::Pokey;
+use crate::serial_bridge::SerialBridge;
+
+/// Machine cycles for a device to send one byte at 19200 baud
+const BYTE_CYCLES: u32 = 932;
+
+/// From the command line going high to the device's ACK (about 1ms)
+const ACK_DELAY: u32 = 1_800;
+
+/// From the ACK to COMPLETE or ERROR, while the device does the work
+const COMPLETE_DELAY: u32 = 3_600;
+
+/// Device ID the OS gives the program recorder in DDEVIC
+pub const CASSETTE: u8 = 0x60;
+
+/// Status codes SIOV returns
+pub const STATUS_OK: u8 = 0x01;
+pub const STATUS_TIMEOUT: u8 = 0x8A;
+pub const STATUS_NAK: u8 = 0x8B;
+pub const STATUS_DEVICE_ERROR: u8 = 0x90;
+
+const ACK: u8 = b'A';
+const NAK: u8 = b'N';
+const COMPLETE: u8 = b'C';
+const ERROR: u8 = b'E';
+
+/// How a device answers a command frame, or the data frame after one
+pub enum SioResponse {
+    /// Refuse it
+    Nak,
+    /// Acknowledge it and wait for a data frame of this many bytes
+    Receive(usize),
+    /// Acknowledge it and report success, followed by a data frame unless
+    /// the data is empty
+    Complete(Vec<u8>),
+    /// As `Complete`, but reporting an error
+    Error(Vec<u8>),
+}
+
+/// Lets the bus hand a device back as its own type
+pub trait AsAny: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// The device side of the SIO protocol, one frame at a time
+pub trait SioDevice: AsAny {
+    /// A command frame addressed to this device, with a valid checksum
+    fn command(&mut self, command: u8, aux1: u8, aux2: u8) -> SioResponse;
+
+    /// The data frame following a command answered with `Receive`
+    fn data(&mut self, data: &[u8]) -> SioResponse;
+
+    /// The bus clock in machine cycles, given before each frame. Devices
+    /// with moving parts keep time with it.
+    fn set_clock(&mut self, _cycle: u64) {}
+
+    /// Cycles the device spent on the frame it has just answered, from
+    /// the end of the frame to being ready with COMPLETE or ERROR
+    fn busy_cycles(&self) -> u64 {
+        0
+    }
+
+    /// Machine cycles per byte while the device is in concurrent mode,
+    /// swapping bytes with the computer freely outside command frames.
+    /// None when it isn't.
+    fn concurrent_byte_cycles(&self) -> Option<u32> {
+        None
+    }
+
+    /// A byte from the computer in concurrent mode
+    fn concurrent_write(&mut self, _byte: u8) {}
+
+    /// The next byte for the computer in concurrent mode, if one has come
+    fn concurrent_read(&mut self) -> Option<u8> {
+        None
+    }
+
+    /// The command line has been asserted, which ends concurrent mode
+    fn end_concurrent(&mut self) {}
+}
+
+/// A device that works the serial lines itself a bit at a time, as a drive
+/// running its own firmware does, rather than being handed whole frames
+pub trait SerialLineDevice: AsAny {
+    /// Run for one machine cycle, seeing the command line and the level of
+    /// the computer's data output. Returns the level the device puts on
+    /// the data input line, high when it isn't sending.
+    fn tick(&mut self, command_line: bool, data_out: bool) -> bool;
+}
+
+/// SIO frame checksum: the bytes added up with the carry wrapped around
+pub fn checksum(data: &[u8]) -> u8 {
+    let sum = data.iter().fold(0u16, |sum, &byte| {
+        let sum = sum + byte as u16;
+        (sum & 0xFF) + (sum >> 8)
+    });
+    sum as u8
+}
+
+/// The serial bus and the peripherals on it.
+///
+/// The computer starts a command by pulling the command line (PIA CB2) low
+/// and sending a five-byte frame through POKEY: device ID, command, two aux
+/// bytes and the checksum. When the line goes high again the addressed
+/// device answers with ACK or NAK; for a write it then takes a data frame
+/// and ACKs that, and finally sends COMPLETE or ERROR, followed by a data
+/// frame if it has one. Bytes go back to POKEY at the drive's baud rate.
+///
+/// A device left in concurrent mode after a command (the 850 interface)
+/// has the data lines to itself until the command line is next asserted.
+///
+/// Devices that work the lines themselves see every bit the computer sends,
+/// and what they send is framed back into bytes for POKEY at its baud rate.
+///
+/// The bus can also be linked to a disk server on the host, as an SIO2PC
+/// cable links a real machine to one. Every byte the computer sends goes
+/// down the link, and the server's answers come back at the line's baud
+/// rate. As on the cable, the command line is signalled out of band, as
+/// the bridge's handshake: a serial device raises a modem line for it
+/// while it is asserted, which the server watches on CTS or DSR. A pty or
+/// TCP stream has no modem lines, so a server there has to pick out
+/// command frames by their checksums. Devices on the bus still answer
+/// their own IDs, so the server should be left to serve the others.
+///
+/// The program recorder shares the data lines but not the protocol: it
+/// plays or records whenever the cassette motor runs.
+pub struct SioBus {
+    devices: BTreeMap<u8, Box<dyn SioDevice>>,
+    line_devices: BTreeMap<u8, Box<dyn SerialLineDevice>>,
+    cassette: Option<Cassette>,
+
+    // Byte coming in from the line devices: machine cycles until the next
+    // bit is sampled, the bits so far and how many there are
+    line_byte: Option<(u32, u8, u8)>,
+
+    // Disk server at the far end of an SIO2PC link, and the cycles until
+    // it is next asked for a byte
+    sio2pc: Option<Box<dyn SerialBridge>>,
+    sio2pc_wait: u32,
+
+    // Device in concurrent mode, and the cycles until it is next asked
+    // for a byte
+    concurrent: Option<u8>,
+    concurrent_wait: u32,
+
+    // Machine cycles since power-on
+    clock: u64,
+
+    // Command line level last cycle (high = idle)
+    command_line: bool,
+
+    // Bytes received in the current frame
+    frame: Vec<u8>,
+
+    // Device expecting a data frame, and its length without checksum
+    receiving: Option<(u8, usize)>,
+
+    // Bytes on their way to POKEY, each with the cycles to wait before it
+    // arrives
+    outgoing: VecDeque<(u32, u8)>,
+}
+
+impl SioBus {
+    pub fn new() -> SioBus {
+        SioBus {
+            devices: BTreeMap::new(),
+            line_devices: BTreeMap::new(),
+            cassette: None,
+            line_byte: None,
+            sio2pc: None,
+            sio2pc_wait: 0,
+            concurrent: None,
+            concurrent_wait: 0,
+            clock: 0,
+            command_line: true,
+            frame: Vec::new(),
+            receiving: None,
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    /// Put a device on the bus, replacing any with the same ID ($31-$38
+    /// for D1:-D8:)
+    pub fn attach(&mut self, id: u8, device: Box<dyn SioDevice>) {
+        self.line_devices.remove(&id);
+        self.devices.insert(id, device);
+    }
+
+    /// Put a device that works the lines itself on the bus, replacing any
+    /// device with the same ID
+    pub fn attach_line_device(&mut self, id: u8, device: Box<dyn SerialLineDevice>) {
+        self.detach(id);
+        self.line_devices.insert(id, device);
+    }
+
+    pub fn line_device<T: SerialLineDevice>(&self, id: u8) -> Option<&T> {
+        self.line_devices.get(&id).and_then(|device| (**device).as_any().downcast_ref())
+    }
+
+    pub fn line_device_mut<T: SerialLineDevice>(&mut self, id: u8) -> Option<&mut T> {
+        self.line_devices.get_mut(&id).and_then(|device| (**device).as_any_mut().downcast_mut())
+    }
+
+    /// Take the line device with ID `id` off the bus if it is a `T`
+    pub fn detach_line_device_as<T: SerialLineDevice>(&mut self, id: u8) -> Option<T> {
+        self.line_device::<T>(id)?;
+        let device = self.line_devices.remove(&id)?;
+        device.into_any().downcast().ok().map(|device| *device)
+    }
+
+    pub fn detach(&mut self, id: u8) -> Option<Box<dyn SioDevice>> {
+        if self.concurrent == Some(id) {
+            self.concurrent = None;
+        }
+        self.devices.remove(&id)
+    }
+
+    /// The device with ID `id`, if there is one and it is a `T`
+    pub fn device<T: SioDevice>(&self, id: u8) -> Option<&T> {
+        self.devices.get(&id).and_then(|device| (**device).as_any().downcast_ref())
+    }
+
+    pub fn device_mut<T: SioDevice>(&mut self, id: u8) -> Option<&mut T> {
+        self.devices.get_mut(&id).and_then(|device| (**device).as_any_mut().downcast_mut())
+    }
+
+    /// Take the device with ID `id` off the bus if it is a `T`, handing it
+    /// back. Anything else stays attached.
+    pub fn detach_as<T: SioDevice>(&mut self, id: u8) -> Option<T> {
+        self.device::<T>(id)?;
+        let device = self.detach(id)?;
+        device.into_any().downcast().ok().map(|device| *device)
+    }
+
+    /// Link the bus to a disk server through `bridge`, replacing any link
+    /// already there
+    pub fn connect_sio2pc(&mut self, bridge: Box<dyn SerialBridge>) {
+        self.sio2pc = Some(bridge);
+        self.sio2pc_wait = 0;
+    }
+
+    pub fn disconnect_sio2pc(&mut self) -> Option<Box<dyn SerialBridge>> {
+        self.sio2pc.take()
+    }
+
+    /// Whether commands for device `id` have to go down the wire, as it
+    /// works the lines itself or only the disk server can answer it. The
+    /// program recorder has no command frames at all: only the OS's own
+    /// tape routine can read and write it.
+    pub fn needs_wire(&self, id: u8) -> bool {
+        id == CASSETTE
+            || self.line_devices.contains_key(&id)
+            || self.sio2pc.is_some() && !self.devices.contains_key(&id)
+    }
+
+    /// Put a tape in the program recorder, replacing any already there
+    pub fn insert_tape(&mut self, cassette: Cassette) {
+        self.cassette = Some(cassette);
+    }
+
+    pub fn eject_tape(&mut self) -> Option<Cassette> {
+        self.cassette.take()
+    }
+
+    pub fn tape(&self) -> Option<&Cassette> {
+        self.cassette.as_ref()
+    }
+
+    pub fn tape_mut(&mut self) -> Option<&mut Cassette> {
+        self.cassette.as_mut()
+    }
+
+    /// Carry out a whole command at once, skipping the serial protocol, for
+    /// a patched SIOV. `write` is the data frame to send if the device asks
+    /// for one. Returns the SIO status and the device's data frame.
+    pub fn execute(
+        &mut self,
+        id: u8,
+        command: u8,
+        aux1: u8,
+        aux2: u8,
+        write: Option<&[u8]>,
+    ) -> (u8, Vec<u8>) {
+        self.end_concurrent();
+        let device = match self.devices.get_mut(&id) {
+            Some(device) => device,
+            None => return (STATUS_TIMEOUT, Vec::new()),
+        };
+        device.set_clock(self.clock);
+        let mut response = device.command(command, aux1, aux2);
+        if let SioResponse::Receive(_) = response {
+            response = match write {
+                Some(data) => {
+                    device.set_clock(self.clock);
+                    device.data(data)
+                }
+                None => return (STATUS_TIMEOUT, Vec::new()),
+            };
+        }
+        let result = match response {
+            SioResponse::Nak => (STATUS_NAK, Vec::new()),
+            SioResponse::Receive(_) => (STATUS_TIMEOUT, Vec::new()),
+            SioResponse::Complete(data) => (STATUS_OK, data),
+            SioResponse::Error(data) => (STATUS_DEVICE_ERROR, data),
+        };
+        self.start_concurrent(id);
+        result
+    }
+
+    /// Run one machine cycle. `command_line` is the level of PIA CB2 and
+    /// `motor` whether the cassette motor is on.
+    pub fn tick(&mut self, pokey: &mut Pokey, command_line: bool, motor: bool) {
+        self.clock += 1;
+        let tape_byte = self.cassette.as_mut().and_then(|cassette| cassette.tick(motor));
+
+        if command_line != self.command_line {
+            self.command_line = command_line;
+            if let Some(bridge) = self.sio2pc.as_mut() {
+                bridge.set_handshake(!command_line);
+            }
+            if !command_line {
+                // A new command abandons whatever was going on
+                self.frame.clear();
+                self.receiving = None;
+                self.outgoing.clear();
+                self.end_concurrent();
+            } else {
+                self.end_command();
+            }
+        }
+
+        if let Some(byte) = pokey.take_serial_output() {
+            if let Some(bridge) = self.sio2pc.as_mut() {
+                bridge.send(byte);
+            }
+            if !command_line {
+                self.frame.push(byte);
+            } else if let Some((id, len)) = self.receiving {
+                self.frame.push(byte);
+                if self.frame.len() == len + 1 {
+                    self.receiving = None;
+                    self.end_data(id);
+                }
+            } else if let Some(id) = self.concurrent {
+                if let Some(device) = self.devices.get_mut(&id) {
+                    device.concurrent_write(byte);
+                }
+            } else if let Some(cassette) = self.cassette.as_mut() {
+                cassette.record_byte(byte, pokey.serial_bit_cycles());
+            }
+        }
+        if let Some(byte) = tape_byte {
+            pokey.serial_input(byte);
+        }
+
+        // A device in concurrent mode is asked for a byte once the last has
+        // gone, or a byte time after it last had none
+        if let (Some(id), true) = (self.concurrent, self.outgoing.is_empty()) {
+            if self.concurrent_wait > 0 {
+                self.concurrent_wait -= 1;
+            } else if let Some(device) = self.devices.get_mut(&id) {
+                let cycles = device.concurrent_byte_cycles().unwrap_or(BYTE_CYCLES);
+                match device.concurrent_read() {
+                    Some(byte) => self.outgoing.push_back((cycles, byte)),
+                    None => self.concurrent_wait = cycles,
+                }
+            }
+        }
+
+        if let Some((wait, byte)) = self.outgoing.front_mut() {
+            if *wait > 0 {
+                *wait -= 1;
+            } else {
+                pokey.serial_input(*byte);
+                self.outgoing.pop_front();
+            }
+        }
+
+        // The disk server's bytes come no faster than the line carries them
+        if let Some(bridge) = self.sio2pc.as_mut() {
+            if self.sio2pc_wait > 0 {
+                self.sio2pc_wait -= 1;
+            } else {
+                if let Some(byte) = bridge.receive() {
+                    pokey.serial_input(byte);
+                }
+                self.sio2pc_wait = 10 * pokey.serial_bit_cycles();
+            }
+        }
+
+        if !self.line_devices.is_empty() {
+            let data_out = pokey.serial_output_level();
+            let mut data_in = true;
+            for device in self.line_devices.values_mut() {
+                data_in &= device.tick(command_line, data_out);
+            }
+            self.receive_bit(pokey, data_in);
+        }
+    }
+
+    /// Frame the level line devices put on the data input line into bytes:
+    /// a falling edge starts a byte, and each bit is sampled in the middle
+    fn receive_bit(&mut self, pokey: &mut Pokey, level: bool) {
+        let bit_cycles = pokey.serial_bit_cycles();
+        match self.line_byte.as_mut() {
+            None if !level => self.line_byte = Some((bit_cycles * 3 / 2, 0, 0)),
+            None => {}
+            Some((wait, _, _)) if *wait > 1 => *wait -= 1,
+            Some((_, byte, 8)) => {
+                // The stop bit
+                pokey.serial_input(*byte);
+                self.line_byte = None;
+            }
+            Some((wait, byte, bits)) => {
+                *byte |= (level as u8) << *bits;
+                *bits += 1;
+                *wait = bit_cycles;
+            }
+        }
+    }
+
+    /// The command line went high: pass a complete command frame to its
+    /// device. Frames that are short or fail the checksum get no answer.
+    fn end_command(&mut self) {
+        let frame = std::mem::take(&mut self.frame);
+        if frame.len() != 5 || checksum(&frame[..4]) != frame[4] {
+            return;
+        }
+        let id = frame[0];
+        let (response, busy) = match self.devices.get_mut(&id) {
+            Some(device) => {
+                device.set_clock(self.clock);
+                (device.command(frame[1], frame[2], frame[3]), device.busy_cycles())
+            }
+            None => return,
+        };
+        self.respond(id, response, busy);
+        self.start_concurrent(id);
+    }
+
+    /// A data frame has arrived for device `id`
+    fn end_data(&mut self, id: u8) {
+        let frame = std::mem::take(&mut self.frame);
+        let (data, sum) = frame.split_at(frame.len() - 1);
+        let (response, busy) = match self.devices.get_mut(&id) {
+            Some(_) if checksum(data) != sum[0] => (SioResponse::Nak, 0),
+            Some(device) => {
+                device.set_clock(self.clock);
+                (device.data(data), device.busy_cycles())
+            }
+            None => return,
+        };
+        self.respond(id, response, busy);
+        self.start_concurrent(id);
+    }
+
+    /// Give device `id` the data lines if the command it has just answered
+    /// put it in concurrent mode. Its bytes follow the answer.
+    fn start_concurrent(&mut self, id: u8) {
+        let concurrent = self.devices.get(&id).and_then(|device| device.concurrent_byte_cycles());
+        if concurrent.is_some() {
+            self.concurrent = Some(id);
+            self.concurrent_wait = 0;
+        }
+    }
+
+    fn end_concurrent(&mut self) {
+        if let Some(id) = self.concurrent.take() {
+            if let Some(device) = self.devices.get_mut(&id) {
+                device.end_concurrent();
+            }
+        }
+    }
+
+    fn respond(&mut self, id: u8, response: SioResponse, busy: u64) {
+        match response {
+            SioResponse::Nak => self.send(ACK_DELAY, NAK),
+            SioResponse::Receive(len) => {
+                self.send(ACK_DELAY, ACK);
+                self.receiving = Some((id, len));
+            }
+            SioResponse::Complete(data) => self.finish(COMPLETE, data, busy),
+            SioResponse::Error(data) => self.finish(ERROR, data, busy),
+        }
+    }
+
+    fn finish(&mut self, status: u8, data: Vec<u8>, busy: u64) {
+        self.send(ACK_DELAY, ACK);
+        let work = busy.saturating_sub(ACK_DELAY as u64).max(COMPLETE_DELAY as u64);
+        self.send(work as u32, status);
+        if !data.is_empty() {
+            let sum = checksum(&data);
+            for byte in data.into_iter().chain(Some(sum)) {
+                self.send(BYTE_CYCLES, byte);
+            }
+        }
+    }
+
+    fn send(&mut self, wait: u32, byte: u8) {
+        self.outgoing.push_back((wait, byte));
+    }
+}
+
+impl Default for SioBus {
+    fn default() -> SioBus {
+        SioBus::new()
+    }
+}
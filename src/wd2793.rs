@@ -0,0 +1,654 @@
+use crate::atr::Density;
+use crate::atx::ROTATION_UNITS;
+use crate::disk_image::{DiskImage, SectorCopy};
+
+/// Controller cycles (1MHz) per 8µs unit of rotation
+const CYCLES_PER_UNIT: u64 = 8;
+
+/// Controller cycles per revolution at 288rpm
+const REVOLUTION_CYCLES: u64 = ROTATION_UNITS as u64 * CYCLES_PER_UNIT;
+
+/// Cycles for a byte to pass the head: 4µs a bit in FM, 2µs in MFM
+const FM_BYTE_CYCLES: u64 = 32;
+const MFM_BYTE_CYCLES: u64 = 16;
+
+/// The index pulse lasts this many units of each revolution
+const INDEX_UNITS: u64 = 500;
+
+/// Type I step rates (r1 r0), in cycles
+const STEP_CYCLES: [u64; 4] = [6_000, 12_000, 20_000, 30_000];
+
+/// Head settling time for the E flag and verify
+const SETTLE_CYCLES: u64 = 30_000;
+
+/// Revolutions spent looking for a sector before giving up
+const NOT_FOUND_REVOLUTIONS: u64 = 5;
+
+/// Byte times a write waits for its first byte before failing
+const WRITE_GRACE_BYTES: u64 = 2;
+
+/// Address marks in the byte stream of a write track
+const ID_MARK: u8 = 0xFE;
+const DATA_MARK: u8 = 0xFB;
+const DELETED_DATA_MARK: u8 = 0xF8;
+
+/// Status bits common to all commands
+const STATUS_BUSY: u8 = 0x01;
+const STATUS_WRITE_PROTECT: u8 = 0x40;
+const STATUS_NOT_READY: u8 = 0x80;
+
+/// Type I status bits
+const STATUS_INDEX: u8 = 0x02;
+const STATUS_TRACK_0: u8 = 0x04;
+const STATUS_SEEK_ERROR: u8 = 0x10;
+const STATUS_HEAD_LOADED: u8 = 0x20;
+
+/// Type II and III status bits
+const STATUS_DRQ: u8 = 0x02;
+const STATUS_LOST_DATA: u8 = 0x04;
+const STATUS_CRC_ERROR: u8 = 0x08;
+const STATUS_NOT_FOUND: u8 = 0x10;
+const STATUS_DELETED: u8 = 0x20;
+
+/// Bits of a sector copy's status the controller reports on reading it
+const COPY_STATUS: u8 = STATUS_LOST_DATA | STATUS_CRC_ERROR | STATUS_DELETED;
+
+/// Command flags
+const FLAG_UPDATE: u8 = 0x10;
+const FLAG_HEAD_LOAD: u8 = 0x08;
+const FLAG_VERIFY: u8 = 0x04;
+const FLAG_SETTLE: u8 = 0x04;
+
+/// The disk drive mechanism as the controller sees it: the disk, the head
+/// and the motor.
+///
+/// The drive moves the head and switches the motor and the recording mode;
+/// the controller only reads and writes what passes under the head. Time
+/// is kept in controller cycles, and the disk is at angle zero (the index
+/// hole) whenever the clock is a whole number of revolutions.
+///
+/// Each track holds sectors `1..=sectors_per_track` of the image in the
+/// image's density, FM for single density and MFM otherwise. Sectors go
+/// where the image has them (ATX) or are spread evenly round the track.
+pub struct Floppy {
+    disk: Option<Box<dyn DiskImage>>,
+    write_protect_tab: bool,
+    changed: bool,
+
+    /// Track under the head
+    pub head: usize,
+    pub motor: bool,
+    /// Recording in MFM (double density) rather than FM
+    pub mfm: bool,
+    /// Controller cycles since power-on
+    pub clock: u64,
+}
+
+impl Floppy {
+    pub fn new() -> Floppy {
+        Floppy {
+            disk: None,
+            write_protect_tab: false,
+            changed: false,
+            head: 0,
+            motor: false,
+            mfm: false,
+            clock: 0,
+        }
+    }
+
+    /// Put a disk in, replacing any already there. Read-only formats are
+    /// write protected.
+    pub fn insert(&mut self, disk: Box<dyn DiskImage>) {
+        self.write_protect_tab = disk.is_read_only();
+        self.disk = Some(disk);
+        self.changed = false;
+    }
+
+    pub fn eject(&mut self) -> Option<Box<dyn DiskImage>> {
+        self.disk.take()
+    }
+
+    pub fn disk(&self) -> Option<&dyn DiskImage> {
+        self.disk.as_deref()
+    }
+
+    /// Whether the disk's write protect notch is covered. With no disk in
+    /// the sensor sees the light and reports the drive writable.
+    pub fn write_protected(&self) -> bool {
+        self.disk.is_some() && self.write_protect_tab
+    }
+
+    /// Cover or uncover the notch. Read-only formats stay protected.
+    pub fn set_write_protected(&mut self, protected: bool) {
+        self.write_protect_tab = protected || self.disk.as_ref().is_some_and(|disk| disk.is_read_only());
+    }
+
+    /// Whether the disk has been written since this was last asked
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    fn ready(&self) -> bool {
+        self.disk.is_some() && self.motor
+    }
+
+    /// Angle of the disk in 8µs units from the index, at `time`
+    fn angle(time: u64) -> u64 {
+        time / CYCLES_PER_UNIT % ROTATION_UNITS as u64
+    }
+
+    fn index(&self) -> bool {
+        self.ready() && Floppy::angle(self.clock) < INDEX_UNITS
+    }
+
+    fn byte_cycles(&self) -> u64 {
+        if self.mfm {
+            MFM_BYTE_CYCLES
+        } else {
+            FM_BYTE_CYCLES
+        }
+    }
+
+    /// The sectors on the track under the head that can be read in the
+    /// current recording mode: position, sector number in the track, the
+    /// image's sector number and the copy
+    fn track_sectors(&self) -> Vec<(u64, u8, usize, SectorCopy)> {
+        let disk = match (&self.disk, self.motor) {
+            (Some(disk), true) => disk,
+            _ => return Vec::new(),
+        };
+        if (disk.density() != Density::Single) != self.mfm {
+            return Vec::new();
+        }
+        let per_track = disk.sectors_per_track();
+        let mut sectors = Vec::new();
+        for number in 1..=per_track {
+            let sector = self.head * per_track + number;
+            let default = ((number - 1) * ROTATION_UNITS as usize / per_track) as u16;
+            for copy in disk.copies(sector).unwrap_or_default() {
+                let position = copy.position.unwrap_or(default) as u64;
+                sectors.push((position, number as u8, sector, copy));
+            }
+        }
+        sectors
+    }
+}
+
+impl Default for Floppy {
+    fn default() -> Floppy {
+        Floppy::new()
+    }
+}
+
+/// What the controller is in the middle of
+enum Operation {
+    Idle,
+    /// A type I command, done at `at`
+    Seeking { at: u64, status: u8 },
+    /// Bytes coming off the disk, the next at `at`
+    Reading { data: Vec<u8>, next: usize, at: u64, status: u8 },
+    /// Sector `sector` of the image being written, the next byte due at
+    /// `at`. Nothing is asked for until the sector's ID passes.
+    Writing { sector: usize, size: usize, data: Vec<u8>, at: u64, started: bool, status: u8 },
+    /// A whole track being written from the index hole round to the next
+    WritingTrack { data: Vec<u8>, at: u64, end: u64, started: bool },
+    /// A command failing at `at`
+    Failing { at: u64, status: u8 },
+}
+
+impl Operation {
+    /// When the operation next has something to do
+    fn at(&self) -> u64 {
+        match self {
+            Operation::Idle => u64::MAX,
+            Operation::Seeking { at, .. }
+            | Operation::Reading { at, .. }
+            | Operation::Writing { at, .. }
+            | Operation::WritingTrack { at, .. }
+            | Operation::Failing { at, .. } => *at,
+        }
+    }
+}
+
+/// WD2793 floppy disk controller.
+///
+/// Registers at offsets 0-3: status (read) or command (write), track,
+/// sector and data. Type I commands (restore, seek, step) keep the track
+/// register and take the step time, but don't move the head: the drive
+/// moves it with its own stepper. Type II commands read and write sectors a
+/// byte at a time as they pass under the head, with DRQ asking for each
+/// byte and lost data reported if the CPU is too slow. Read address gives
+/// the next ID field to come round, write track formats a track, and force
+/// interrupt stops whatever is going on. Read track isn't supported and
+/// reports record not found.
+///
+/// Multiple-record reads and writes stop after the first record.
+pub struct Wd2793 {
+    track: u8,
+    sector: u8,
+    data: u8,
+    command: u8,
+
+    // Error bits of the last command, and whether it was type I
+    status: u8,
+    type_one: bool,
+
+    busy: bool,
+    drq: bool,
+    intrq: bool,
+
+    // Last step was towards the middle of the disk
+    step_in: bool,
+
+    operation: Operation,
+}
+
+impl Wd2793 {
+    pub fn new() -> Wd2793 {
+        Wd2793 {
+            track: 0,
+            sector: 1,
+            data: 0,
+            command: 0,
+            status: 0,
+            type_one: true,
+            busy: false,
+            drq: false,
+            intrq: false,
+            step_in: true,
+            operation: Operation::Idle,
+        }
+    }
+
+    /// Data request: a byte is waiting to be read, or wanted for writing
+    pub fn drq(&self) -> bool {
+        self.drq
+    }
+
+    /// Interrupt request: a command has finished
+    pub fn intrq(&self) -> bool {
+        self.intrq
+    }
+
+    pub fn busy(&self) -> bool {
+        self.busy
+    }
+
+    /// Read a register. Reading status clears the interrupt request, and
+    /// reading data the data request.
+    pub fn read(&mut self, addr: u16, floppy: &Floppy) -> u8 {
+        match addr & 0x03 {
+            0 => {
+                self.intrq = false;
+                self.status_register(floppy)
+            }
+            1 => self.track,
+            2 => self.sector,
+            _ => {
+                self.drq = false;
+                self.data
+            }
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8, floppy: &mut Floppy) {
+        match addr & 0x03 {
+            0 => self.start(val, floppy),
+            1 if !self.busy => self.track = val,
+            2 if !self.busy => self.sector = val,
+            1 | 2 => {}
+            _ => {
+                self.drq = false;
+                self.data = val;
+            }
+        }
+    }
+
+    fn status_register(&self, floppy: &Floppy) -> u8 {
+        let mut status = self.status;
+        if self.busy {
+            status |= STATUS_BUSY;
+        }
+        if !floppy.ready() {
+            status |= STATUS_NOT_READY;
+        }
+        if self.type_one {
+            if floppy.index() {
+                status |= STATUS_INDEX;
+            }
+            if floppy.head == 0 {
+                status |= STATUS_TRACK_0;
+            }
+            if floppy.write_protected() {
+                status |= STATUS_WRITE_PROTECT;
+            }
+        } else if self.drq {
+            status |= STATUS_DRQ;
+        }
+        status
+    }
+
+    fn start(&mut self, command: u8, floppy: &mut Floppy) {
+        if command & 0xF0 == 0xD0 {
+            self.force_interrupt(command);
+            return;
+        }
+        if self.busy {
+            return;
+        }
+        self.command = command;
+        self.busy = true;
+        self.drq = false;
+        self.intrq = false;
+        self.status = 0;
+        self.type_one = command & 0x80 == 0;
+        self.operation = if self.type_one {
+            self.seek(command, floppy)
+        } else {
+            self.access(command, floppy)
+        };
+    }
+
+    fn force_interrupt(&mut self, command: u8) {
+        if !self.busy {
+            self.type_one = true;
+            self.status = 0;
+        }
+        self.busy = false;
+        self.drq = false;
+        self.intrq = command & 0x0F != 0;
+        self.operation = Operation::Idle;
+    }
+
+    /// Start a type I command
+    fn seek(&mut self, command: u8, floppy: &Floppy) -> Operation {
+        let from = self.track;
+        let to = match command >> 4 {
+            0x0 => 0,
+            0x1 => self.data,
+            kind => {
+                match kind & 0x06 {
+                    0x04 => self.step_in = true,
+                    0x06 => self.step_in = false,
+                    _ => {}
+                }
+                let to = if self.step_in { from.wrapping_add(1) } else { from.wrapping_sub(1) };
+                if command & FLAG_UPDATE == 0 {
+                    from
+                } else {
+                    to
+                }
+            }
+        };
+        if command & 0xE0 == 0 && to != from {
+            self.step_in = to > from;
+        }
+        let steps = if command & 0xE0 == 0 { (to as i16 - from as i16).unsigned_abs() as u64 } else { 1 };
+        self.track = to;
+
+        let mut time = steps * STEP_CYCLES[(command & 0x03) as usize];
+        let mut status = 0;
+        if command & FLAG_HEAD_LOAD != 0 {
+            status |= STATUS_HEAD_LOADED;
+        }
+        if command & FLAG_VERIFY != 0 {
+            time += SETTLE_CYCLES;
+            status |= STATUS_HEAD_LOADED;
+            if floppy.head != self.track as usize || floppy.track_sectors().is_empty() {
+                status |= STATUS_SEEK_ERROR;
+            }
+        }
+        Operation::Seeking { at: floppy.clock + time, status }
+    }
+
+    /// Start a type II or III command
+    fn access(&mut self, command: u8, floppy: &Floppy) -> Operation {
+        let now = floppy.clock;
+        if !floppy.ready() {
+            return Operation::Failing { at: now, status: STATUS_NOT_READY };
+        }
+        let writes = matches!(command >> 4, 0xA | 0xB | 0xF);
+        if writes && floppy.write_protected() {
+            return Operation::Failing { at: now, status: STATUS_WRITE_PROTECT };
+        }
+        let start = if command & FLAG_SETTLE != 0 { now + SETTLE_CYCLES } else { now };
+        let not_found = Operation::Failing {
+            at: start + NOT_FOUND_REVOLUTIONS * REVOLUTION_CYCLES,
+            status: STATUS_NOT_FOUND,
+        };
+
+        match command >> 4 {
+            0x8 | 0x9 => match self.find(floppy, start, Some(self.sector)) {
+                Some((at, _, _, copy)) => Operation::Reading {
+                    data: weak_bits(&copy, now),
+                    next: 0,
+                    at,
+                    status: copy.status & COPY_STATUS,
+                },
+                None => not_found,
+            },
+            0xA | 0xB => match self.find(floppy, start, Some(self.sector)) {
+                Some((at, _, sector, copy)) => Operation::Writing {
+                    sector,
+                    size: copy.data.len(),
+                    data: Vec::new(),
+                    at,
+                    started: false,
+                    status: 0,
+                },
+                None => not_found,
+            },
+            0xC => match self.find(floppy, start, None) {
+                Some((at, number, _, copy)) => {
+                    let size_code = (copy.data.len() / 256) as u8;
+                    let mut id = vec![floppy.head as u8, 0, number, size_code];
+                    let crc = id_crc(&id, floppy.mfm);
+                    id.extend_from_slice(&crc.to_be_bytes());
+                    self.sector = floppy.head as u8;
+                    Operation::Reading { data: id, next: 0, at, status: 0 }
+                }
+                None => not_found,
+            },
+            0xF => {
+                let index = (start / REVOLUTION_CYCLES + 1) * REVOLUTION_CYCLES;
+                Operation::WritingTrack {
+                    data: Vec::new(),
+                    at: index,
+                    end: index + REVOLUTION_CYCLES,
+                    started: false,
+                }
+            }
+            _ => Operation::Failing { at: start + REVOLUTION_CYCLES, status: STATUS_NOT_FOUND },
+        }
+    }
+
+    /// The next sector to come past the head after `start` whose ID
+    /// matches the track register and `sector` (any sector if None), and
+    /// when it does, with its number in the track and in the image
+    fn find(&self, floppy: &Floppy, start: u64, sector: Option<u8>) -> Option<(u64, u8, usize, SectorCopy)> {
+        if sector.is_some() && self.track as usize != floppy.head {
+            return None;
+        }
+        let angle = Floppy::angle(start);
+        floppy
+            .track_sectors()
+            .into_iter()
+            .filter(|(_, number, _, copy)| {
+                sector.is_none_or(|sector| sector == *number) && copy.status & STATUS_NOT_FOUND == 0
+            })
+            .map(|(position, number, sector, copy)| {
+                let wait = (position + ROTATION_UNITS as u64 - angle) % ROTATION_UNITS as u64;
+                (start + wait * CYCLES_PER_UNIT, number, sector, copy)
+            })
+            .min_by_key(|(at, ..)| *at)
+    }
+
+    /// Run one controller cycle
+    pub fn tick(&mut self, floppy: &mut Floppy) {
+        let mut operation = std::mem::replace(&mut self.operation, Operation::Idle);
+        match self.advance(&mut operation, floppy) {
+            Some(status) => self.finish(status),
+            None => self.operation = operation,
+        }
+    }
+
+    /// Move an operation on to the current time. Returns the status to
+    /// finish with once it is over.
+    fn advance(&mut self, operation: &mut Operation, floppy: &mut Floppy) -> Option<u8> {
+        let now = floppy.clock;
+        if now < operation.at() {
+            return None;
+        }
+        let byte_cycles = floppy.byte_cycles();
+        match operation {
+            Operation::Idle => None,
+            Operation::Seeking { status, .. } | Operation::Failing { status, .. } => Some(*status),
+            Operation::Reading { data, next, at, status } => {
+                if *next == data.len() {
+                    return Some(*status);
+                }
+                if self.drq {
+                    *status |= STATUS_LOST_DATA;
+                }
+                self.data = data[*next];
+                self.drq = true;
+                *next += 1;
+                *at += byte_cycles;
+                None
+            }
+            Operation::Writing { sector, size, data, at, started, status } => {
+                if !*started {
+                    // The ID has passed: ask for the first byte
+                    *started = true;
+                    self.drq = true;
+                    *at += WRITE_GRACE_BYTES * byte_cycles;
+                    return None;
+                }
+                if self.drq && data.is_empty() {
+                    return Some(STATUS_LOST_DATA);
+                }
+                if self.drq {
+                    *status |= STATUS_LOST_DATA;
+                }
+                data.push(if self.drq { 0 } else { self.data });
+                if data.len() < *size {
+                    self.drq = true;
+                    *at += byte_cycles;
+                    return None;
+                }
+                let written = floppy.disk.as_mut().is_some_and(|disk| disk.write_sector(*sector, data));
+                floppy.changed |= written;
+                Some(if written { *status } else { STATUS_WRITE_PROTECT })
+            }
+            Operation::WritingTrack { data, at, end, started } => {
+                if now >= *end {
+                    format_track(floppy, data);
+                    return Some(if self.drq { STATUS_LOST_DATA } else { 0 });
+                }
+                if *started {
+                    data.push(if self.drq { 0 } else { self.data });
+                }
+                *started = true;
+                self.drq = true;
+                *at += byte_cycles;
+                None
+            }
+        }
+    }
+
+    fn finish(&mut self, status: u8) {
+        self.status = status;
+        self.busy = false;
+        self.drq = false;
+        self.intrq = true;
+        self.operation = Operation::Idle;
+    }
+}
+
+impl Default for Wd2793 {
+    fn default() -> Wd2793 {
+        Wd2793::new()
+    }
+}
+
+/// A sector's bytes as read at `time`, weak bits included
+fn weak_bits(copy: &SectorCopy, time: u64) -> Vec<u8> {
+    let mut data = copy.data.clone();
+    if let Some(offset) = copy.weak_offset {
+        let mut noise = time as u32 | 1;
+        for byte in data.iter_mut().skip(offset) {
+            noise ^= noise << 13;
+            noise ^= noise >> 17;
+            noise ^= noise << 5;
+            *byte = noise as u8;
+        }
+    }
+    data
+}
+
+/// CRC of an ID field as recorded: CCITT over the address mark and the
+/// four ID bytes, with the three $A1 sync bytes in front in MFM
+fn id_crc(id: &[u8], mfm: bool) -> u16 {
+    let sync: &[u8] = if mfm { &[0xA1, 0xA1, 0xA1] } else { &[] };
+    let mut crc = 0xFFFF_u16;
+    for &byte in sync.iter().chain(&[ID_MARK]).chain(id) {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Lay down the sectors a write track recorded on the track under the
+/// head. Each ID mark is followed by track, side, sector and size code,
+/// and each data mark by the sector's bytes. A track written in the other
+/// recording mode reformats the disk in a density to suit.
+fn format_track(floppy: &mut Floppy, stream: &[u8]) {
+    let mut sectors = Vec::new();
+    let mut i = 0;
+    while let Some(offset) = stream[i..].iter().position(|&byte| byte == ID_MARK) {
+        let id = &stream[i + offset + 1..];
+        if id.len() < 4 {
+            break;
+        }
+        let (number, size) = (id[2] as usize, 128 << (id[3] & 0x03));
+        i += offset + 5;
+        let mark = match stream[i..].iter().position(|&byte| byte == DATA_MARK || byte == DELETED_DATA_MARK) {
+            Some(mark) => i + mark + 1,
+            None => break,
+        };
+        if mark + size > stream.len() {
+            break;
+        }
+        if id[0] as usize == floppy.head {
+            sectors.push((number, stream[mark..mark + size].to_vec()));
+        }
+        i = mark + size;
+    }
+
+    let disk = match floppy.disk.as_mut() {
+        Some(disk) => disk,
+        None => return,
+    };
+    if (disk.density() != Density::Single) != floppy.mfm {
+        let density = match (floppy.mfm, sectors.iter().any(|(_, data)| data.len() == 256)) {
+            (false, _) => Density::Single,
+            (true, true) => Density::Double,
+            (true, false) => Density::Enhanced,
+        };
+        if !disk.format(density) {
+            return;
+        }
+        floppy.changed = true;
+    }
+    let per_track = disk.sectors_per_track();
+    for (number, data) in sectors {
+        if (1..=per_track).contains(&number) {
+            let sector = floppy.head * per_track + number;
+            let size = disk.sector_size(sector);
+            floppy.changed |= disk.write_sector(sector, &data[..size.min(data.len())]);
+        }
+    }
+}
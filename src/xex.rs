@@ -0,0 +1,116 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Run address, called once everything is loaded
+pub const RUNAD: u16 = 0x02E0;
+
+/// Init address, called after any segment that sets it
+pub const INITAD: u16 = 0x02E2;
+
+/// A block of bytes loaded at an address
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub start: u16,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    /// Whether loading this segment writes to `addr`
+    pub fn covers(&self, addr: u16) -> bool {
+        let addr = addr as usize;
+        let start = self.start as usize;
+        addr >= start && addr < start + self.data.len()
+    }
+}
+
+#[derive(Debug)]
+pub enum XexError {
+    Io(io::Error),
+    /// The file doesn't start with $FF $FF
+    NotXex,
+    /// A header or segment was cut short; `offset` is where it starts
+    Truncated { offset: usize },
+    /// A segment whose end address is below its start
+    BadSegment { offset: usize, start: u16, end: u16 },
+}
+
+impl fmt::Display for XexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XexError::Io(e) => write!(f, "{}", e),
+            XexError::NotXex => write!(f, "not an Atari binary load file (no $FFFF header)"),
+            XexError::Truncated { offset } => {
+                write!(f, "file ends inside the segment at offset {}", offset)
+            }
+            XexError::BadSegment { offset, start, end } => write!(
+                f,
+                "segment at offset {} ends (${:04X}) before it starts (${:04X})",
+                offset, end, start
+            ),
+        }
+    }
+}
+
+impl std::error::Error for XexError {}
+
+impl From<io::Error> for XexError {
+    fn from(e: io::Error) -> XexError {
+        XexError::Io(e)
+    }
+}
+
+/// An Atari DOS binary load file (.XEX, .COM, .EXE).
+///
+/// The file is a $FFFF marker followed by segments, each a start and end
+/// address (little endian, end inclusive) and the bytes between. Further
+/// $FFFF markers may appear before any segment header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Xex {
+    pub segments: Vec<Segment>,
+}
+
+impl Xex {
+    pub fn parse(data: &[u8]) -> Result<Xex, XexError> {
+        if data.len() < 2 || data[0..2] != [0xFF, 0xFF] {
+            return Err(XexError::NotXex);
+        }
+
+        let word = |at: usize| data[at] as u16 | (data[at + 1] as u16) << 8;
+        let mut segments = Vec::new();
+        let mut pos = 2;
+        while pos < data.len() {
+            let offset = pos;
+            if data.len() - pos >= 2 && word(pos) == 0xFFFF {
+                pos += 2;
+                continue;
+            }
+            if data.len() - pos < 4 {
+                return Err(XexError::Truncated { offset });
+            }
+            let start = word(pos);
+            let end = word(pos + 2);
+            if end < start {
+                return Err(XexError::BadSegment { offset, start, end });
+            }
+            pos += 4;
+
+            let len = (end - start) as usize + 1;
+            if data.len() - pos < len {
+                return Err(XexError::Truncated { offset });
+            }
+            segments.push(Segment { start, data: data[pos..pos + len].to_vec() });
+            pos += len;
+        }
+
+        if segments.is_empty() {
+            return Err(XexError::Truncated { offset: 2 });
+        }
+        Ok(Xex { segments })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Xex, XexError> {
+        Xex::parse(&fs::read(path)?)
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::atr::{Atr, Density};
+use crate::disk_image::{DiskImage, ImageFormat};
+
+/// Double density images: 128-byte boot sectors, or all 256 bytes
+const DOUBLE_SIZES: [usize; 2] = [3 * 128 + 717 * 256, 720 * 256];
+
+#[derive(Debug)]
+pub enum XfdError {
+    Io(io::Error),
+    /// The file isn't a whole number of sectors
+    BadSize(usize),
+}
+
+impl fmt::Display for XfdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XfdError::Io(e) => write!(f, "{}", e),
+            XfdError::BadSize(size) => write!(f, "{} bytes isn't a whole number of sectors", size),
+        }
+    }
+}
+
+impl std::error::Error for XfdError {}
+
+impl From<io::Error> for XfdError {
+    fn from(e: io::Error) -> XfdError {
+        XfdError::Io(e)
+    }
+}
+
+/// An XFD disk image: the sectors in order with no header. The size of the
+/// file gives the density: the two double density sizes, or else 128-byte
+/// sectors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Xfd {
+    sectors: Atr,
+}
+
+impl Xfd {
+    pub fn blank(density: Density) -> Xfd {
+        Xfd { sectors: Atr::blank(density) }
+    }
+
+    pub fn parse(image: &[u8]) -> Result<Xfd, XfdError> {
+        let sector_size = if DOUBLE_SIZES.contains(&image.len()) {
+            256
+        } else if !image.is_empty() && image.len().is_multiple_of(128) {
+            128
+        } else {
+            return Err(XfdError::BadSize(image.len()));
+        };
+
+        // Give the sectors an ATR header and let Atr sort out the layout
+        let paragraphs = image.len() / 16;
+        let mut atr_image = vec![0x96, 0x02];
+        atr_image.extend_from_slice(&(paragraphs as u16).to_le_bytes());
+        atr_image.extend_from_slice(&(sector_size as u16).to_le_bytes());
+        atr_image.push((paragraphs >> 16) as u8);
+        atr_image.resize(16, 0);
+        atr_image.extend_from_slice(image);
+        let sectors = Atr::parse(&atr_image).map_err(|_| XfdError::BadSize(image.len()))?;
+        Ok(Xfd { sectors })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Xfd, XfdError> {
+        Xfd::parse(&fs::read(path)?)
+    }
+
+    pub fn from_image(image: &dyn DiskImage) -> Xfd {
+        Xfd { sectors: Atr::from_image(image) }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, DiskImage::to_bytes(self))
+    }
+}
+
+impl DiskImage for Xfd {
+    fn image_format(&self) -> ImageFormat {
+        ImageFormat::Xfd
+    }
+
+    fn density(&self) -> Density {
+        self.sectors.density()
+    }
+
+    fn sector_count(&self) -> usize {
+        self.sectors.sector_count()
+    }
+
+    fn sector_size(&self, sector: usize) -> usize {
+        self.sectors.sector_size(sector)
+    }
+
+    fn read_sector(&self, sector: usize) -> Option<&[u8]> {
+        self.sectors.read_sector(sector)
+    }
+
+    fn write_sector(&mut self, sector: usize, data: &[u8]) -> bool {
+        self.sectors.write_sector(sector, data)
+    }
+
+    fn format(&mut self, density: Density) -> bool {
+        self.sectors.format(density);
+        true
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.sectors.to_bytes().split_off(16)
+    }
+}
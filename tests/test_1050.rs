@@ -0,0 +1,482 @@
+use atari800_rs::asm;
+use atari800_rs::atari800::Atari800;
+use atari800_rs::atr::{Atr, Density};
+use atari800_rs::bus::Bus;
+use atari800_rs::disk::DiskDrive;
+use atari800_rs::drive1050::{Drive1050, Drive1050Error, ROM_SIZE};
+use atari800_rs::riot::Riot;
+use atari800_rs::wd2793::{Floppy, Wd2793};
+
+// Drive firmware that answers 'S' and 'R' for D1: in single density,
+// bit-banging the serial lines at 19200 baud (52 drive cycles a bit). The
+// sector buffer is the 6810 RAM, seen at $0800 so indexing doesn't wrap in
+// page zero; the variables and the stack are in the RIOT RAM.
+const FIRMWARE: &str = "
+ORA = $0280
+DDRA = $0281
+ORB = $0282
+DDRB = $0283
+FDCCMD = $0400
+FDCTRK = $0401
+FDCSEC = $0402
+FDCDAT = $0403
+BUFFER = $0800
+frame = $80
+track = $85
+portb = $86
+sum = $87
+tmp = $88
+count = $8A
+phase = $8B
+target = $8C
+
+        .org $F000
+reset:  sei
+        cld
+        ldx #$FF
+        txs
+        ; Motor on, FM
+        lda #$28
+        sta DDRA
+        lda #$20
+        sta ORA
+        ; Data out idle high, stepper on phase 0
+        lda #$03
+        sta portb
+        sta ORB
+        lda #$1F
+        sta DDRB
+        lda #0
+        sta track
+        sta phase
+
+wait_cmd:
+        lda ORB
+        and #$40
+        bne wait_cmd
+        ldy #0
+cmd_byte:
+        jsr get_byte
+        sta frame,y
+        iny
+        cpy #5
+        bne cmd_byte
+wait_end:
+        lda ORB
+        and #$40
+        beq wait_end
+        lda frame
+        cmp #$31
+        bne wait_cmd
+        lda frame+1
+        cmp #'S'
+        beq status
+        cmp #'R'
+        beq read
+        lda #'N'
+        jsr put_byte
+        jmp wait_cmd
+
+status: lda #'A'
+        jsr put_byte
+        lda #'C'
+        jsr put_byte
+        ldy #3
+st_copy:
+        lda status_frame,y
+        sta BUFFER,y
+        dey
+        bpl st_copy
+        lda #4
+        jmp send
+
+read:   lda #'A'
+        jsr put_byte
+        ; Sector - 1 into track and sector
+        lda frame+2
+        sec
+        sbc #1
+        sta tmp
+        lda frame+3
+        sbc #0
+        sta tmp+1
+        ldx #0
+rd_div: lda tmp
+        sec
+        sbc #18
+        tay
+        lda tmp+1
+        sbc #0
+        bcc rd_found
+        sta tmp+1
+        sty tmp
+        inx
+        jmp rd_div
+rd_found:
+        stx target
+        jsr seek
+        lda target
+        sta FDCTRK
+        ldx tmp
+        inx
+        stx FDCSEC
+        lda #$80
+        sta FDCCMD
+        ldy #0
+rd_byte:
+        bit ORA
+        bvs rd_get
+        bpl rd_byte
+        jmp rd_end
+rd_get: lda FDCDAT
+        sta BUFFER,y
+        iny
+        jmp rd_byte
+rd_end: lda FDCCMD
+        and #$1C
+        beq rd_ok
+        lda #'E'
+        jmp rd_status
+rd_ok:  lda #'C'
+rd_status:
+        jsr put_byte
+        lda #128
+
+; Send A bytes from the buffer and their checksum
+send:   sta target
+        lda #0
+        sta sum
+        tay
+sd_byte:
+        lda BUFFER,y
+        clc
+        adc sum
+        adc #0
+        sta sum
+        lda BUFFER,y
+        jsr put_byte
+        iny
+        cpy target
+        bne sd_byte
+        lda sum
+        jsr put_byte
+        jmp wait_cmd
+
+; Step the head from track to target
+seek:   lda track
+        cmp target
+        beq sk_done
+        bcc sk_in
+        dec track
+        lda phase
+        sec
+        sbc #1
+        jmp sk_set
+sk_in:  inc track
+        lda phase
+        clc
+        adc #1
+sk_set: and #3
+        sta phase
+        tax
+        lda portb
+        and #$E1
+        ora phase_bits,x
+        sta portb
+        sta ORB
+        jmp seek
+sk_done:
+        rts
+
+; A byte from the computer
+get_byte:
+        lda ORB
+        bmi get_byte
+        lda #8
+        sta count
+        ldx #13
+gb_start:
+        dex
+        bne gb_start
+gb_bit: lda ORB
+        asl a
+        ror tmp
+        ldx #6
+gb_delay:
+        dex
+        bne gb_delay
+        nop
+        dec count
+        bne gb_bit
+gb_stop:
+        lda ORB
+        bpl gb_stop
+        lda tmp
+        rts
+
+; Send A to the computer
+put_byte:
+        sta tmp
+        lda portb
+        and #$FE
+        sta ORB
+        lda #8
+        sta count
+        ldx #6
+pb_start:
+        dex
+        bne pb_start
+pb_bit: lsr tmp
+        lda portb
+        and #$FE
+        adc #0
+        sta ORB
+        ldx #5
+pb_delay:
+        dex
+        bne pb_delay
+        nop
+        dec count
+        bne pb_bit
+        lda portb
+        ora #$01
+        sta ORB
+        ldx #10
+pb_stop:
+        dex
+        bne pb_stop
+        rts
+
+phase_bits:
+        .byte $02, $04, $08, $10
+status_frame:
+        .byte $10, $FF, $E0, $00
+
+        .org $FFFC
+        .word reset, reset
+";
+
+fn firmware() -> Vec<u8> {
+    asm::assemble(FIRMWARE, &[]).unwrap().image(0xF000, ROM_SIZE)
+}
+
+// Boot sector that loads at $0700, stores $A5 in $0601, and reads
+// sector 40 (track 2) to $0800 with the status in $0602
+fn boot_sector() -> Vec<u8> {
+    let source = "
+DOSVEC = $0A
+SIOV = $E459
+        .org $0700
+        .byte 0, 1
+        .word $0700, init
+        lda #$A5
+        sta $0601
+        ldx #11
+dcb:    lda request,x
+        sta $0300,x
+        dex
+        bpl dcb
+        jsr SIOV
+        sty $0602
+        lda #<main
+        sta DOSVEC
+        lda #>main
+        sta DOSVEC+1
+        clc
+        rts
+init:   rts
+main:   jmp main
+request:
+        .byte $31, 1, 'R', $40
+        .word $0800
+        .byte 7, 0
+        .word 128
+        .word 40
+";
+    asm::assemble(source, &[]).unwrap().image(0x0700, 128)
+}
+
+#[test]
+fn test_riot_timer_and_ports() {
+    let mut riot = Riot::new();
+
+    // Port A: low nibble output, high nibble input
+    riot.write_register(0x01, 0x0F);
+    riot.write_register(0x00, 0x5A);
+    riot.set_port_a_input(0x30);
+    assert_eq!(riot.read_register(0x00), 0x3A);
+
+    // Timer at /8 with its interrupt: 3 counts, then down a cycle at a time
+    riot.write_register(0x1D, 3);
+    for _ in 0..31 {
+        riot.tick();
+    }
+    assert!(!riot.irq());
+    riot.tick();
+    assert!(riot.irq());
+    assert_eq!(riot.read_register(0x05) & 0x80, 0x80, "timer flag");
+    riot.tick();
+    riot.tick();
+    assert_eq!(riot.read_register(0x0C), 0xFD, "counting every cycle");
+    assert!(!riot.irq(), "reading the timer clears the flag");
+
+    // Rising edge on PA7
+    riot.write_register(0x05, 0x01);
+    riot.set_port_a_input(0x80);
+    riot.tick();
+    assert_eq!(riot.read_register(0x05) & 0x40, 0x40);
+    assert_eq!(riot.read_register(0x05) & 0x40, 0x00, "reading clears it");
+}
+
+#[test]
+fn test_fdc_reads_sectors_as_they_come_round() {
+    let mut atr = Atr::blank(Density::Single);
+    let pattern: Vec<u8> = (0..128).collect();
+    atr.write_sector(20, &pattern);
+    let mut floppy = Floppy::new();
+    floppy.insert(Box::new(atr));
+    floppy.motor = true;
+    floppy.head = 1;
+    let mut fdc = Wd2793::new();
+
+    // Sector 2 of track 1 is an eighteenth of a revolution round
+    fdc.write(1, 1, &mut floppy);
+    fdc.write(2, 2, &mut floppy);
+    fdc.write(0, 0x80, &mut floppy);
+    let mut data = Vec::new();
+    let mut first = None;
+    while fdc.busy() {
+        floppy.clock += 1;
+        fdc.tick(&mut floppy);
+        if fdc.drq() {
+            first.get_or_insert(floppy.clock);
+            data.push(fdc.read(3, &floppy));
+        }
+    }
+    assert_eq!(data, pattern);
+    let first = first.unwrap();
+    assert!((11_000..12_000).contains(&first), "first byte after {} cycles", first);
+    assert!(fdc.intrq());
+    assert_eq!(fdc.read(0, &floppy) & 0x1C, 0, "read without errors");
+    assert!(!fdc.intrq(), "reading status clears INTRQ");
+
+    // Too slow to take the bytes: lost data
+    fdc.write(0, 0x80, &mut floppy);
+    while fdc.busy() {
+        floppy.clock += 1;
+        fdc.tick(&mut floppy);
+    }
+    assert_eq!(fdc.read(0, &floppy) & 0x04, 0x04);
+
+    // The track register doesn't match the head: not found after five
+    // revolutions
+    fdc.write(1, 5, &mut floppy);
+    fdc.write(0, 0x80, &mut floppy);
+    let start = floppy.clock;
+    while fdc.busy() {
+        floppy.clock += 1;
+        fdc.tick(&mut floppy);
+    }
+    assert!(floppy.clock - start > 1_000_000);
+    assert_eq!(fdc.read(0, &floppy) & 0x10, 0x10);
+
+    // Writing a protected disk fails at once
+    floppy.set_write_protected(true);
+    fdc.write(1, 1, &mut floppy);
+    fdc.write(0, 0xA0, &mut floppy);
+    floppy.clock += 1;
+    fdc.tick(&mut floppy);
+    assert!(!fdc.busy());
+    assert_eq!(fdc.read(0, &floppy) & 0x40, 0x40);
+}
+
+#[test]
+fn test_fdc_writes_a_sector() {
+    let mut floppy = Floppy::new();
+    floppy.insert(Box::new(Atr::blank(Density::Single)));
+    floppy.motor = true;
+    let mut fdc = Wd2793::new();
+
+    fdc.write(2, 3, &mut floppy);
+    fdc.write(0, 0xA0, &mut floppy);
+    let mut written = 0u8;
+    while fdc.busy() {
+        floppy.clock += 1;
+        fdc.tick(&mut floppy);
+        if fdc.drq() {
+            fdc.write(3, written, &mut floppy);
+            written += 1;
+        }
+    }
+    assert_eq!(written, 128);
+    assert_eq!(fdc.read(0, &floppy) & 0x5C, 0);
+    assert!(floppy.take_changed());
+    let expected: Vec<u8> = (0..128).collect();
+    assert_eq!(floppy.disk().unwrap().read_sector(3), Some(&expected[..]));
+}
+
+#[test]
+fn test_drive_switches_and_rom_size() {
+    assert_eq!(Drive1050::new(&[0; 0x1800], 1).err(), Some(Drive1050Error::WrongRomSize(0x1800)));
+    assert_eq!(Drive1050::new(&firmware(), 5).err(), Some(Drive1050Error::NoSuchUnit(5)));
+
+    let mut drive = Drive1050::new(&firmware()[..ROM_SIZE / 2], 2).unwrap();
+    assert_eq!(drive.device_id(), 0x32);
+    assert_eq!(drive.set_unit(0), Err(Drive1050Error::NoSuchUnit(0)));
+    assert!(drive.set_unit(4).is_ok());
+    assert_eq!(drive.device_id(), 0x34);
+}
+
+#[test]
+fn test_boots_through_drive_firmware() {
+    let mut atr = Atr::blank(Density::Single);
+    atr.write_sector(1, &boot_sector());
+    let pattern: Vec<u8> = (0..128u8).map(|i| i.wrapping_mul(3)).collect();
+    atr.write_sector(40, &pattern);
+
+    let mut drive = Drive1050::new(&firmware(), 1).unwrap();
+    drive.insert_disk(Box::new(atr));
+    let mut atari800 = Atari800::new();
+    // Commands for the drive have to go down the wire even with the patch
+    atari800.set_sio_patch(true);
+    atari800.attach_1050(drive);
+    for _ in 0..120 {
+        atari800.run_frame();
+        if atari800.read(0x0602) != 0 {
+            break;
+        }
+    }
+
+    assert_eq!(atari800.read(0x0601), 0xA5, "booted");
+    assert_eq!(atari800.read(0x0602), 0x01, "sector 40 read");
+    for i in 0..128u16 {
+        assert_eq!(atari800.read(0x0800 + i), pattern[i as usize]);
+    }
+    let drive = atari800.drive_1050(1).unwrap();
+    assert_eq!(drive.head_track(), 2, "the firmware stepped the head");
+    assert!(drive.motor_on());
+}
+
+#[test]
+fn test_1050s_move_but_are_never_replaced() {
+    let mut atari800 = Atari800::new();
+    atari800.attach_1050(Drive1050::new(&firmware(), 1).unwrap());
+    assert!(atari800.mount_disk(1, DiskDrive::new(Atr::blank(Density::Single))).is_err());
+    assert!(atari800.drive_1050(1).is_some(), "still there");
+
+    assert!(atari800.swap_disks(1, 2).is_ok());
+    assert!(atari800.drive_1050(1).is_none());
+    assert_eq!(atari800.drive_1050(2).unwrap().unit(), 2);
+
+    assert_eq!(atari800.swap_disks(2, 5), Err(5));
+    assert_eq!(atari800.swap_disks(6, 2), Err(6));
+    assert!(atari800.drive_1050(2).is_some(), "nothing moved");
+
+    // Changing places with a drive of the other kind
+    assert!(atari800.mount_disk(3, DiskDrive::new(Atr::blank(Density::Enhanced))).is_ok());
+    assert!(atari800.swap_disks(3, 2).is_ok());
+    assert_eq!(atari800.drive_1050(3).unwrap().unit(), 3);
+    assert!(atari800.disk(2).is_some());
+    assert!(atari800.unmount_disk(3).is_none(), "unmount leaves a 1050 alone");
+    assert!(atari800.drive_1050(3).is_some());
+}
@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::rc::Rc;
+use std::time::Duration;
+
+use atari800_rs::asm;
+use atari800_rs::atari800::Atari800;
+use atari800_rs::bus::Bus;
+use atari800_rs::interface850::{bootstrap, handler, HandlerError, SerialPort, R1};
+use atari800_rs::serial_bridge::{SerialBridge, TcpBridge};
+use atari800_rs::sio::{SioDevice, SioResponse};
+use atari800_rs::xex::{Segment, Xex, RUNAD};
+
+// The far end of a port, shared with the test
+#[derive(Clone, Default)]
+struct Line {
+    sent: Rc<RefCell<Vec<u8>>>,
+    waiting: Rc<RefCell<VecDeque<u8>>>,
+    hung_up: Rc<RefCell<bool>>,
+}
+
+impl SerialBridge for Line {
+    fn send(&mut self, byte: u8) {
+        self.sent.borrow_mut().push(byte);
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        self.waiting.borrow_mut().pop_front()
+    }
+
+    fn connected(&mut self) -> bool {
+        !*self.hung_up.borrow()
+    }
+
+    fn hang_up(&mut self) {
+        *self.hung_up.borrow_mut() = true;
+    }
+}
+
+#[test]
+fn test_port_commands() {
+    let line = Line::default();
+    let mut port = SerialPort::new(1, Some(Box::new(line.clone())));
+
+    // 9600 baud, 7 bits, one stop bit
+    assert!(matches!(port.command(b'B', 0x2E, 0x04), SioResponse::Complete(_)));
+    assert_eq!((port.baud(), port.word_bits()), (9600.0, 7));
+    match port.command(b'S', 0, 0) {
+        SioResponse::Complete(status) => assert_eq!(status, [0x00, 0xFC]),
+        _ => panic!("no status"),
+    }
+
+    assert!(matches!(port.command(b'W', 3, 0), SioResponse::Receive(32)));
+    let mut block = vec![b'H', b'I' | 0x80, b'\r'];
+    block.resize(32, b'X');
+    assert!(matches!(port.data(&block), SioResponse::Complete(_)));
+    assert_eq!(*line.sent.borrow(), b"HI\r");
+
+    assert_eq!(port.concurrent_byte_cycles(), None);
+    assert!(matches!(port.command(b'X', 0, 0), SioResponse::Complete(_)));
+    assert_eq!(port.concurrent_byte_cycles(), Some(1677));
+    line.waiting.borrow_mut().push_back(0xC1);
+    assert_eq!(port.concurrent_read(), Some(0x41));
+    port.end_concurrent();
+    assert!(!port.concurrent());
+
+    // Dropping DTR hangs up, and a line check then fails
+    assert!(matches!(port.command(b'A', 0x80, 0), SioResponse::Complete(_)));
+    assert!(!port.dtr() && *line.hung_up.borrow());
+    assert!(matches!(port.command(b'B', 0x0E, 0x04), SioResponse::Error(_)));
+    match port.command(b'S', 0, 0) {
+        SioResponse::Complete(status) => assert_eq!(status, [0x04, 0x00]),
+        _ => panic!("no status"),
+    }
+}
+
+#[test]
+fn test_boot_sequence_answers() {
+    let mut port = SerialPort::new(1, None);
+    let boot = bootstrap().unwrap();
+    match port.command(b'?', 0, 0) {
+        SioResponse::Complete(dcb) => {
+            assert_eq!(dcb[..4], [R1, 1, b'!', 0x40]);
+            assert_eq!(dcb[4..6], [0x00, 0x05]);
+            assert_eq!(u16::from_le_bytes([dcb[8], dcb[9]]) as usize, boot.len());
+        }
+        _ => panic!("no poll answer"),
+    }
+    assert!(matches!(port.command(b'!', 0, 0), SioResponse::Complete(data) if data == boot));
+
+    let low = handler(0x0700).unwrap();
+    let high = handler(0x8000).unwrap();
+    assert_eq!(low.len(), high.len());
+    assert_ne!(low, high, "built for where it goes");
+    assert!(matches!(port.command(b'&', 0x00, 0x07), SioResponse::Complete(data) if data == low));
+    assert!(matches!(port.command(b'&', 0x80, 0x00), SioResponse::Nak));
+    assert!(matches!(port.command(b'&', 0x00, 0xFF), SioResponse::Nak));
+    assert_eq!(handler(0xFF00), Err(HandlerError::NoRoom(0xFF00)));
+    assert_eq!(handler(0x0080), Err(HandlerError::NoRoom(0x0080)));
+
+    // Only port 1 boots
+    let mut port2 = SerialPort::new(2, None);
+    assert!(matches!(port2.command(b'?', 0, 0), SioResponse::Nak));
+}
+
+// CIO call on IOCB 1: `buffer` is a label, `len` the buffer length
+fn cio(command: u8, buffer: &str, len: u16, aux1: u8, result: u16) -> String {
+    format!(
+        "
+        ldx #$10
+        lda #{}
+        sta ICCOM,x
+        lda #<{}
+        sta ICBAL,x
+        lda #>{}
+        sta ICBAL+1,x
+        lda #<{}
+        sta ICBLL,x
+        lda #>{}
+        sta ICBLL+1,x
+        lda #{}
+        sta ICAX1,x
+        jsr CIOV
+        sty ${:04X}
+",
+        command, buffer, buffer, len, len, aux1, result
+    )
+}
+
+#[test]
+fn test_programs_load_r_and_talk_through_it() {
+    let bridge = TcpBridge::listen("127.0.0.1:0").unwrap();
+    let mut peer = TcpStream::connect(bridge.local_addr().unwrap()).unwrap();
+    peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    peer.write_all(b"OK\r").unwrap();
+
+    let calls = [
+        cio(3, "name", 0, 13, 0x0603),
+        // 9600 baud, 8 bits
+        cio(36, "name", 0, 0x3E, 0x0604),
+        // Light translation, LF after CR
+        cio(38, "name", 0, 0x40, 0x0605),
+        cio(9, "dial", 16, 0, 0x0606),
+        cio(40, "name", 0, 13, 0x0607),
+        cio(9, "text", 16, 0, 0x0608),
+        cio(5, "buffer", 16, 0, 0x0609),
+        "        lda ICBLL,x\n        sta $060A\n".to_string(),
+        cio(12, "name", 0, 0, 0x060B),
+    ];
+    let source = format!(
+        "
+CIOV = $E456
+SIOV = $E459
+ICCOM = $0342
+ICBAL = $0344
+ICBLL = $0348
+ICAX1 = $034A
+DDEVIC = $0300
+DUNIT = $0301
+DCOMND = $0302
+DSTATS = $0303
+DBUFLO = $0304
+DBUFHI = $0305
+DTIMLO = $0306
+DBYTLO = $0308
+DBYTHI = $0309
+MEMLO = $02E7
+        .org $2000
+        ; Poll for the bootstrap's DCB, fetch it and run it
+        lda #$50
+        sta DDEVIC
+        lda #1
+        sta DUNIT
+        lda #'?'
+        sta DCOMND
+        lda #$40
+        sta DSTATS
+        lda #<poll
+        sta DBUFLO
+        lda #>poll
+        sta DBUFHI
+        lda #12
+        sta DBYTLO
+        lda #0
+        sta DBYTHI
+        lda #8
+        sta DTIMLO
+        jsr SIOV
+        sty $0600
+        ldx #11
+copy:   lda poll,x
+        sta DDEVIC,x
+        dex
+        bpl copy
+        jsr SIOV
+        sty $0601
+        jsr $0506
+        lda #0
+        rol
+        sta $0602
+        lda MEMLO
+        sta $060C
+        lda MEMLO+1
+        sta $060D
+{}
+        lda #1
+        sta $06FF
+done:   jmp done
+
+name:   .byte \"R:\", $9B
+dial:   .byte \"ATDT\", $9B
+text:   .byte \"HELLO\", $9B
+buffer: .byte 0
+        .org buffer+16
+poll:   .byte 0
+",
+        calls.concat()
+    );
+    let code = asm::assemble(&source, &[]).unwrap();
+    let xex = Xex {
+        segments: vec![
+            Segment { start: 0x2000, data: code.image(0x2000, 0x400) },
+            Segment { start: RUNAD, data: vec![0x00, 0x20] },
+        ],
+    };
+
+    let mut atari800 = Atari800::new();
+    atari800.connect_850(Box::new(bridge));
+    atari800.load_xex(xex);
+    for _ in 0..300 {
+        atari800.run_frame();
+        if atari800.read(0x06FF) == 1 {
+            break;
+        }
+    }
+    assert_eq!(atari800.read(0x06FF), 1, "never finished");
+    assert_eq!(atari800.read(0x0600), 0x01, "poll");
+    assert_eq!(atari800.read(0x0601), 0x01, "bootstrap");
+    assert_eq!(atari800.read(0x0602), 0x00, "handler installed");
+    let memlo = u16::from_le_bytes([atari800.read(0x060C), atari800.read(0x060D)]);
+    assert!(memlo > 0x0700 + handler(0x0700).unwrap().len() as u16, "MEMLO past the handler");
+    for (addr, what) in [
+        (0x0603, "open"),
+        (0x0604, "baud rate"),
+        (0x0605, "translation"),
+        (0x0606, "block write"),
+        (0x0607, "concurrent mode"),
+        (0x0608, "concurrent write"),
+        (0x0609, "get record"),
+        (0x060B, "close"),
+    ] {
+        assert_eq!(atari800.read(addr), 0x01, "{}", what);
+    }
+    assert_eq!(atari800.read(0x060A), 3);
+    let buffer = code.symbol("buffer").unwrap();
+    let line: Vec<u8> = (buffer..buffer + 3).map(|addr| atari800.read(addr)).collect();
+    assert_eq!(line, [b'O', b'K', 0x9B]);
+
+    let mut received = [0; 13];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"ATDT\r\nHELLO\r\n");
+}
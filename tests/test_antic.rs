@@ -0,0 +1,365 @@
+use atari800_rs::antic::{Antic, HIRES_BACKGROUND, HIRES_PIXEL, NORMAL_PLAYFIELD_START};
+use atari800_rs::gtia::Gtia;
+use atari800_rs::mem::Mem;
+
+const DLIST: u16 = 0x1000;
+const SCREEN: u16 = 0x2000;
+
+// Memory holding a display list of `lines` (each with LMS on the first) and
+// a jump back, with DMA on at normal width and the character set at CHBASE
+fn setup(lines: &[u8], chbase: u8) -> (Antic, Mem) {
+    let mut mem = Mem::blank(0);
+    let mut addr = DLIST;
+    for (i, &line) in lines.iter().enumerate() {
+        if i == 0 {
+            mem.set_byte(addr, line | 0x40);
+            mem.set_word(addr + 1, SCREEN);
+            addr += 3;
+        } else {
+            mem.set_byte(addr, line);
+            addr += 1;
+        }
+    }
+    mem.set_byte(addr, 0x41);
+    mem.set_word(addr + 1, DLIST);
+
+    let mut antic = Antic::new();
+    antic.write_register(0xD402, DLIST as u8);
+    antic.write_register(0xD403, (DLIST >> 8) as u8);
+    antic.write_register(0xD409, chbase);
+    antic.write_register(0xD400, 0x22);
+    (antic, mem)
+}
+
+// Color indices of `count` pixels from `x` in the normal playfield
+fn pixels(antic: &Antic, x: usize, count: usize) -> Vec<u8> {
+    let start = NORMAL_PLAYFIELD_START + x;
+    antic.scanline_buffer[start..start + count].to_vec()
+}
+
+// Text mode pixels from bits: a set bit is a hi-res pixel, a clear one the
+// playfield behind it
+fn hires(bits: &[u8]) -> Vec<u8> {
+    bits.iter().map(|&bit| if bit != 0 { HIRES_PIXEL } else { HIRES_BACKGROUND }).collect()
+}
+
+#[test]
+fn test_mode3_descenders() {
+    let (mut antic, mut mem) = setup(&[0x03, 0x03], 0x30);
+    // 'A' ($21) and 'g' ($67): every row of both glyphs has its row
+    // number in it
+    for row in 0..8 {
+        mem.set_byte(0x3000 + 0x21 * 8 + row, 0x80 | row as u8);
+        mem.set_byte(0x3000 + 0x67 * 8 + row, 0x80 | row as u8);
+    }
+    mem.set_byte(SCREEN, 0x21);
+    mem.set_byte(SCREEN + 1, 0x67);
+    mem.set_byte(SCREEN + 40, 0x67);
+
+    let mut rows = Vec::new();
+    for _ in 0..10 {
+        antic.process_scanline(&mem);
+        rows.push((pixels(&antic, 0, 8), pixels(&antic, 8, 8)));
+    }
+    let glyph = |row: u8| -> Vec<u8> { hires(&(0..8).map(|bit| ((0x80 | row) >> (7 - bit)) & 1).collect::<Vec<_>>()) };
+    let blank = hires(&[0; 8]);
+    for line in 0..10u8 {
+        let (normal, descender) = &rows[line as usize];
+        assert_eq!(*normal, if line < 8 { glyph(line) } else { blank.clone() }, "line {}", line);
+        let expected = match line {
+            0..=1 => blank.clone(),
+            2..=7 => glyph(line),
+            _ => glyph(line - 8),
+        };
+        assert_eq!(*descender, expected, "descender line {}", line);
+    }
+
+    // Ten scanlines later the second mode line starts 40 bytes on
+    antic.process_scanline(&mem);
+    assert_eq!(pixels(&antic, 0, 8), blank);
+    antic.process_scanline(&mem);
+    antic.process_scanline(&mem);
+    assert_eq!(pixels(&antic, 0, 8), glyph(2));
+}
+
+#[test]
+fn test_mode4_multicolor_and_pf3() {
+    let (mut antic, mut mem) = setup(&[0x04], 0x30);
+    // Character 1: background, PF0, PF1, PF2 on every row
+    for row in 0..8 {
+        mem.set_byte(0x3000 + 8 + row, 0b00_01_10_11);
+    }
+    mem.set_byte(SCREEN, 0x01);
+    mem.set_byte(SCREEN + 1, 0x81);
+
+    antic.process_scanline(&mem);
+    assert_eq!(pixels(&antic, 0, 16), [0, 0, 1, 1, 2, 2, 3, 3, 0, 0, 1, 1, 2, 2, 4, 4]);
+}
+
+#[test]
+fn test_mode5_doubles_rows() {
+    let (mut antic, mut mem) = setup(&[0x05, 0x05], 0x30);
+    for row in 0..8 {
+        mem.set_byte(0x3000 + 8 + row, row as u8);
+    }
+    mem.set_byte(SCREEN, 0x01);
+    mem.set_byte(SCREEN + 40, 0x00);
+
+    let mut lines = Vec::new();
+    for _ in 0..17 {
+        antic.process_scanline(&mem);
+        lines.push(pixels(&antic, 0, 8));
+    }
+    assert_eq!(lines[4], [0, 0, 0, 0, 0, 0, 2, 2]);
+    assert_eq!(lines[5], [0, 0, 0, 0, 0, 0, 2, 2]);
+    assert_eq!(lines[6], [0, 0, 0, 0, 0, 0, 3, 3]);
+    assert_eq!(lines[16], [0; 8], "16 scanlines a mode line");
+}
+
+#[test]
+fn test_modes6_and_7_colors_and_alignment() {
+    // CHBASE $31 is the 512-byte set at $3000, rounded down
+    let (mut antic, mut mem) = setup(&[0x07, 0x06], 0x31);
+    for row in 0..8 {
+        mem.set_byte(0x3000 + 0x21 * 8 + row, 0xC0);
+    }
+    // 'A' in each of the four colors, then the next mode line
+    mem.set_byte(SCREEN, 0x21);
+    mem.set_byte(SCREEN + 1, 0x61);
+    mem.set_byte(SCREEN + 2, 0xA1);
+    mem.set_byte(SCREEN + 3, 0xE1);
+    mem.set_byte(SCREEN + 20, 0xA1);
+
+    antic.process_scanline(&mem);
+    for (col, color) in (1..=4).enumerate() {
+        let mut expected = vec![0; 16];
+        expected[..4].fill(color);
+        assert_eq!(pixels(&antic, col * 16, 16), expected, "color {}", color);
+    }
+
+    // Mode 7 takes 16 scanlines, then mode 6 shows the next 20 bytes
+    for _ in 0..16 {
+        antic.process_scanline(&mem);
+    }
+    assert_eq!(pixels(&antic, 0, 4), [3, 3, 3, 3]);
+}
+
+#[test]
+fn test_playfield_widths() {
+    let (mut antic, mut mem) = setup(&[0x02, 0x02], 0x30);
+    for row in 0..8 {
+        mem.set_byte(0x3000 + 8 + row, 0xFF);
+    }
+    // Narrow: 32 bytes a line, starting 32 pixels in
+    antic.write_register(0xD400, 0x21);
+    mem.set_byte(SCREEN, 0x01);
+    mem.set_byte(SCREEN + 32, 0x01);
+    antic.process_scanline(&mem);
+    assert_eq!(pixels(&antic, 0, 32), [0; 32]);
+    assert_eq!(pixels(&antic, 32, 8), [HIRES_PIXEL; 8]);
+    for _ in 0..8 {
+        antic.process_scanline(&mem);
+    }
+    assert_eq!(pixels(&antic, 32, 8), [HIRES_PIXEL; 8], "second line 32 bytes on");
+
+    // Wide: 48 bytes, starting 32 pixels left of the normal playfield
+    let (mut antic, mut mem) = setup(&[0x02], 0x30);
+    for row in 0..8 {
+        mem.set_byte(0x3000 + 8 + row, 0xFF);
+    }
+    antic.write_register(0xD400, 0x23);
+    mem.set_byte(SCREEN + 47, 0x01);
+    antic.process_scanline(&mem);
+    assert_eq!(antic.scanline_buffer[376..384], [HIRES_PIXEL; 8]);
+}
+
+#[test]
+fn test_bitmap_mode_geometry() {
+    // Mode, scanlines per mode line, bytes per line at normal width
+    let modes = [
+        (0x08, 8, 10),
+        (0x09, 4, 10),
+        (0x0A, 4, 20),
+        (0x0B, 2, 20),
+        (0x0C, 1, 20),
+        (0x0D, 2, 40),
+        (0x0E, 1, 40),
+        (0x0F, 1, 40),
+    ];
+    for &(mode, scanlines, bytes) in &modes {
+        for &(dmactl, width) in &[(0x21u8, 4u16), (0x22, 5), (0x23, 6)] {
+            let (mut antic, mut mem) = setup(&[mode, mode], 0x30);
+            antic.write_register(0xD400, dmactl);
+            let line_bytes = bytes * width / 5;
+            // The second mode line's first byte lights its leftmost pixel
+            mem.set_byte(SCREEN + line_bytes, 0xC0);
+
+            // Mode F's playfield is never the background color
+            let blank = |c: u8| c == 0 || mode == 0x0F && c == HIRES_BACKGROUND;
+            for line in 0..scanlines {
+                antic.process_scanline(&mem);
+                assert!(antic.scanline_buffer.iter().all(|&c| blank(c)), "mode {:X} line {}", mode, line);
+            }
+            antic.process_scanline(&mem);
+            let start = match dmactl {
+                0x21 => 64,
+                0x22 => 32,
+                _ => 0,
+            };
+            assert!(!blank(antic.scanline_buffer[start]), "mode {:X} DMACTL {:02X}", mode, dmactl);
+        }
+    }
+}
+
+#[test]
+fn test_bitmap_pixels() {
+    // Mode D: four 2-bit pixels a byte, each 2 wide
+    let (mut antic, mut mem) = setup(&[0x0D], 0x30);
+    mem.set_byte(SCREEN, 0b00_01_10_11);
+    antic.process_scanline(&mem);
+    assert_eq!(pixels(&antic, 0, 8), [0, 0, 1, 1, 2, 2, 3, 3]);
+
+    // Mode 8: four pixels a byte, each 8 wide
+    let (mut antic, mut mem) = setup(&[0x08], 0x30);
+    mem.set_byte(SCREEN, 0b11_00_10_01);
+    antic.process_scanline(&mem);
+    let expected: Vec<u8> = [3, 0, 2, 1].iter().flat_map(|&c| vec![c; 8]).collect();
+    assert_eq!(pixels(&antic, 0, 32), expected);
+
+    // Mode 9: eight 1-bit pixels, each 4 wide
+    let (mut antic, mut mem) = setup(&[0x09], 0x30);
+    mem.set_byte(SCREEN, 0b1010_0001);
+    antic.process_scanline(&mem);
+    let expected: Vec<u8> = [1, 0, 1, 0, 0, 0, 0, 1].iter().flat_map(|&c| vec![c; 4]).collect();
+    assert_eq!(pixels(&antic, 0, 32), expected);
+
+}
+
+#[test]
+fn test_mode_f_is_hi_res() {
+    // One pixel a bit, on a playfield of its own color
+    let (mut antic, mut mem) = setup(&[0x0F], 0x30);
+    mem.set_byte(SCREEN + 39, 0b0110_0001);
+    antic.process_scanline(&mem);
+    let (b, p) = (HIRES_BACKGROUND, HIRES_PIXEL);
+    assert_eq!(pixels(&antic, 312, 8), [b, p, p, b, b, b, b, p]);
+    assert_eq!(pixels(&antic, 0, 8), [b; 8]);
+
+    // The background is COLPF2, and set pixels COLPF1's luminance on
+    // COLPF2's hue
+    let mut gtia = Gtia::new();
+    gtia.write_register(0xD01A, 0x00);
+    gtia.write_register(0xD017, 0x3C);
+    gtia.write_register(0xD018, 0x94);
+    gtia.render_scanline(0, &antic.scanline_buffer);
+    let rgb = |x: usize| {
+        let pixel = &gtia.framebuffer.pixels[x * 3..x * 3 + 3];
+        (pixel[0], pixel[1], pixel[2])
+    };
+    assert_eq!(rgb(312), gtia.color_to_rgb(0x94));
+    assert_eq!(rgb(313), gtia.color_to_rgb(0x9C));
+    assert_ne!(rgb(312), rgb(313));
+}
+
+#[test]
+fn test_mode2_colors() {
+    let (mut antic, mut mem) = setup(&[0x02], 0x30);
+    mem.set_byte(0x3000 + 8, 0xF0);
+    mem.set_byte(SCREEN, 0x01);
+    antic.process_scanline(&mem);
+    let line = pixels(&antic, 0, 8);
+
+    // Text is COLPF1's luminance on COLPF2, whatever the background is
+    let mut gtia = Gtia::new();
+    gtia.write_register(0xD01A, 0x46);
+    gtia.write_register(0xD017, 0x0E);
+    gtia.write_register(0xD018, 0x94);
+    assert_eq!(gtia.get_color_for_index(line[0]), gtia.color_to_rgb(0x9E));
+    assert_eq!(gtia.get_color_for_index(line[7]), gtia.color_to_rgb(0x94));
+}
+
+#[test]
+fn test_screen_memory_wraps_at_4k() {
+    // A mode E line starting 8 bytes short of $3000 carries on from $2000
+    let mut mem = Mem::blank(0);
+    mem.set_byte(DLIST, 0x4E);
+    mem.set_word(DLIST + 1, 0x2FF8);
+    mem.set_byte(DLIST + 3, 0x0E);
+    mem.set_byte(DLIST + 4, 0x41);
+    mem.set_word(DLIST + 5, DLIST);
+    mem.set_byte(0x2000, 0xFF);
+    mem.set_byte(0x3000, 0x55);
+    mem.set_byte(0x2020, 0xAA);
+
+    let mut antic = Antic::new();
+    antic.write_register(0xD402, DLIST as u8);
+    antic.write_register(0xD403, (DLIST >> 8) as u8);
+    antic.write_register(0xD400, 0x22);
+    antic.process_scanline(&mem);
+    assert_eq!(pixels(&antic, 8 * 8, 8), [3; 8], "byte 8 fetched from $2000");
+
+    // The next line starts 40 bytes on, at $2020
+    antic.process_scanline(&mem);
+    assert_eq!(pixels(&antic, 0, 8), [2, 2, 2, 2, 2, 2, 2, 2]);
+}
+
+// The first `count` scanlines of a mode line showing character 1, whose
+// top row is $F0 and bottom row $0F, and the same with bit 7 set, as the
+// first 8 pixels of each
+fn chactl_lines(mode: u8, chactl: u8, count: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let (mut antic, mut mem) = setup(&[mode], 0x30);
+    mem.set_byte(0x3000 + 8, 0xF0);
+    mem.set_byte(0x3000 + 15, 0x0F);
+    mem.set_byte(SCREEN, 0x01);
+    mem.set_byte(SCREEN + 1, 0x81);
+    antic.write_register(0xD401, chactl);
+    (0..count)
+        .map(|_| {
+            antic.process_scanline(&mem);
+            (pixels(&antic, 0, 8), pixels(&antic, 8, 8))
+        })
+        .collect()
+}
+
+#[test]
+fn test_chactl_inverse_and_blank() {
+    let glyph = hires(&[1, 1, 1, 1, 0, 0, 0, 0]);
+    let inverse = hires(&[0, 0, 0, 0, 1, 1, 1, 1]);
+
+    // Bit 7 has no effect until CHACTL asks for it
+    assert_eq!(chactl_lines(0x02, 0x00, 1)[0], (glyph.clone(), glyph.clone()));
+    assert_eq!(chactl_lines(0x02, 0x02, 1)[0], (glyph.clone(), inverse.clone()), "inverse");
+    assert_eq!(chactl_lines(0x02, 0x01, 1)[0], (glyph.clone(), hires(&[0; 8])), "blank");
+    assert_eq!(chactl_lines(0x02, 0x03, 1)[0], (glyph.clone(), hires(&[1; 8])), "blanked then inverted");
+
+    // Mode 3's empty scanlines are inverted too, as for the cursor
+    let lines = chactl_lines(0x03, 0x02, 10);
+    assert_eq!(lines[0], (glyph, inverse));
+    assert_eq!(lines[9], (hires(&[0; 8]), hires(&[1; 8])));
+
+    // In mode 4 bit 7 picks PF3 instead
+    let lines = chactl_lines(0x04, 0x03, 1);
+    assert_eq!(lines[0], (vec![3, 3, 3, 3, 0, 0, 0, 0], vec![4, 4, 4, 4, 0, 0, 0, 0]));
+}
+
+#[test]
+fn test_chactl_vertical_reflect() {
+    let top = hires(&[1, 1, 1, 1, 0, 0, 0, 0]);
+    let bottom = hires(&[0, 0, 0, 0, 1, 1, 1, 1]);
+
+    let lines = chactl_lines(0x02, 0x04, 8);
+    assert_eq!(lines[0].0, bottom);
+    assert_eq!(lines[7].0, top);
+
+    // Mode 3 draws the reflected glyph in the same 8 scanlines
+    let lines = chactl_lines(0x03, 0x06, 10);
+    assert_eq!(lines[0], (bottom.clone(), top.clone()), "reflected, and inverted with bit 7");
+    assert_eq!(lines[7].0, top);
+    assert_eq!(lines[8].0, hires(&[0; 8]));
+
+    // Mode 5's doubled rows are reflected as a whole
+    let lines = chactl_lines(0x05, 0x04, 16);
+    assert_eq!(lines[0].0, [0, 0, 0, 0, 3, 3, 3, 3]);
+    assert_eq!(lines[1].0, [0, 0, 0, 0, 3, 3, 3, 3]);
+    assert_eq!(lines[15].0, [3, 3, 3, 3, 0, 0, 0, 0]);
+}
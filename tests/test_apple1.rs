@@ -0,0 +1,64 @@
+use atari800_rs::apple1::Apple1;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// Display output sink that the test can inspect
+#[derive(Clone)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// 256-byte monitor at $FF00 that sets up the PIA like WozMon and echoes keys
+fn echo_rom() -> Vec<u8> {
+    let code: [u8; 32] = [
+        0xA9, 0x7F,             // LDA #$7F
+        0x8D, 0x12, 0xD0,       // STA DSP (DDRB)
+        0xA9, 0xA7,             // LDA #$A7
+        0x8D, 0x11, 0xD0,       // STA KBDCR
+        0x8D, 0x13, 0xD0,       // STA DSPCR
+        0xAD, 0x11, 0xD0,       // LOOP: LDA KBDCR
+        0x10, 0xFB,             // BPL LOOP
+        0xAD, 0x10, 0xD0,       // LDA KBD
+        0x2C, 0x12, 0xD0,       // ECHO: BIT DSP
+        0x30, 0xFB,             // BMI ECHO
+        0x8D, 0x12, 0xD0,       // STA DSP
+        0x4C, 0x0D, 0xFF,       // JMP LOOP
+    ];
+
+    let mut rom = vec![0xEA_u8; 0x100];
+    rom[..code.len()].copy_from_slice(&code);
+    // NMI, RESET and IRQ vectors all point at $FF00
+    rom[0xFA..].copy_from_slice(&[0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF]);
+    rom
+}
+
+#[test]
+fn test_apple1_echoes_keys() {
+    let mut apple1 = Apple1::new(&echo_rom()).unwrap();
+    let output = SharedOutput(Rc::new(RefCell::new(Vec::new())));
+    apple1.set_output(Box::new(output.clone()));
+
+    apple1.run_cycles(100);
+    for &key in b"hi\n" {
+        apple1.key_press(key);
+        apple1.run_cycles(100);
+    }
+
+    assert_eq!(output.0.borrow().as_slice(), b"HI\n");
+}
+
+#[test]
+fn test_apple1_rejects_oversized_rom() {
+    assert!(Apple1::new(&[]).is_err());
+    assert!(Apple1::new(&vec![0; 0x4000]).is_err());
+}
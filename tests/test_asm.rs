@@ -0,0 +1,80 @@
+use atari800_rs::asm::{assemble, AsmError};
+
+#[test]
+fn test_addressing_modes() {
+    let source = "
+        .org $1000
+        lda #$12
+        lda $34
+        lda $1234,x
+        lda ($20),y
+        sta ($20,x)
+        jmp ($FFFC)
+        asl a
+        rts
+";
+    let asm = assemble(source, &[]).unwrap();
+    assert_eq!(
+        asm.image(0x1000, 15),
+        vec![0xA9, 0x12, 0xA5, 0x34, 0xBD, 0x34, 0x12, 0xB1, 0x20, 0x81, 0x20, 0x6C, 0xFC, 0xFF, 0x0A]
+    );
+}
+
+#[test]
+fn test_labels_and_branches() {
+    let source = "
+ZP = $80
+        .org $2000
+start:  ldx #3
+loop:   dex
+        bne loop
+        beq done
+        lda ZP
+done:   jmp start
+        .word start, done
+        .byte <done, >done, \"AB\"
+";
+    let asm = assemble(source, &[]).unwrap();
+    assert_eq!(asm.symbol("done"), Some(0x2009));
+    assert_eq!(
+        asm.image(0x2000, 20),
+        vec![
+            0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xF0, 0x02, 0xA5, 0x80, 0x4C, 0x00, 0x20, 0x00, 0x20, 0x09,
+            0x20, 0x09, 0x20, b'A', b'B',
+        ]
+    );
+    // Unassembled bytes read as unprogrammed ROM
+    assert_eq!(asm.image(0x1FFF, 1), vec![0xFF]);
+}
+
+#[test]
+fn test_forward_references_are_absolute() {
+    let asm = assemble(".org $0200\n lda later\nlater = $10\n", &[]).unwrap();
+    assert_eq!(asm.image(0x0200, 3), vec![0xAD, 0x10, 0x00]);
+}
+
+#[test]
+fn test_conditional_assembly() {
+    let source = "
+        .org $3000
+.if XL
+        .byte 1
+.else
+        .byte 2
+.endif
+";
+    assert_eq!(assemble(source, &[("XL", 1)]).unwrap().image(0x3000, 1), vec![1]);
+    assert_eq!(assemble(source, &[("XL", 0)]).unwrap().image(0x3000, 1), vec![2]);
+}
+
+#[test]
+fn test_errors_report_line() {
+    let err = assemble("\n        .org $1000\n        lda nowhere\n", &[]).err();
+    assert_eq!(
+        err,
+        Some(AsmError { line: 3, message: "undefined symbol in nowhere".to_string() })
+    );
+
+    let err = assemble(".org $1000\nhere: bne far\n.org $1100\nfar: rts\n", &[]).err();
+    assert_eq!(err.map(|e| e.line), Some(2));
+}
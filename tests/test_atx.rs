@@ -0,0 +1,228 @@
+use atari800_rs::asm;
+use atari800_rs::atari800::Atari800;
+use atari800_rs::atr::Density;
+use atari800_rs::atx::{Atx, AtxError};
+use atari800_rs::bus::Bus;
+use atari800_rs::disk::DiskDrive;
+use atari800_rs::sio::{SioDevice, SioResponse};
+
+// A sector for `atx_file`: number, controller status, angular position,
+// fill byte (None for no data) and weak bit offset
+type Sector = (u8, u8, u16, Option<u8>, Option<u16>);
+
+// Machine cycles for one 8µs unit of rotation, near enough
+const CYCLES_PER_UNIT: u64 = 14;
+
+// Single density ATX file with one track record per entry
+fn atx_file(tracks: &[(u8, Vec<Sector>)]) -> Vec<u8> {
+    let mut file = vec![0; 48];
+    file[0..4].copy_from_slice(b"AT8X");
+    file[4] = 1;
+    file[28..32].copy_from_slice(&48u32.to_le_bytes());
+
+    for (number, sectors) in tracks {
+        let list_size = 8 + sectors.len() * 8;
+        let data_start = 32 + list_size;
+        let with_data = sectors.iter().filter(|s| s.3.is_some()).count();
+        let weak_chunks = sectors.iter().filter(|s| s.4.is_some()).count();
+        let size = data_start + with_data * 128 + weak_chunks * 8 + 8;
+
+        let mut record = vec![0; 32];
+        record[0..4].copy_from_slice(&(size as u32).to_le_bytes());
+        record[8] = *number;
+        record[10..12].copy_from_slice(&(sectors.len() as u16).to_le_bytes());
+        record[20..24].copy_from_slice(&32u32.to_le_bytes());
+
+        // Sector list chunk
+        record.extend_from_slice(&(list_size as u32).to_le_bytes());
+        record.extend_from_slice(&[0x01, 0, 0, 0]);
+        let mut offset = data_start + weak_chunks * 8 + 8;
+        for &(number, status, position, fill, _) in sectors {
+            record.extend_from_slice(&[number, status]);
+            record.extend_from_slice(&position.to_le_bytes());
+            let start = if fill.is_some() { offset } else { 0 };
+            record.extend_from_slice(&(start as u32).to_le_bytes());
+            if fill.is_some() {
+                offset += 128;
+            }
+        }
+
+        // Weak sector chunks, then the terminator and the data
+        for (index, sector) in sectors.iter().enumerate() {
+            if let Some(weak) = sector.4 {
+                record.extend_from_slice(&8u32.to_le_bytes());
+                record.extend_from_slice(&[0x10, index as u8]);
+                record.extend_from_slice(&weak.to_le_bytes());
+            }
+        }
+        record.extend_from_slice(&[0; 8]);
+        for sector in sectors {
+            if let Some(fill) = sector.3 {
+                record.extend_from_slice(&[fill; 128]);
+            }
+        }
+        assert_eq!(record.len(), size);
+        file.extend_from_slice(&record);
+    }
+
+    let len = file.len() as u32;
+    file[32..36].copy_from_slice(&len.to_le_bytes());
+    file
+}
+
+// Send a command at a bus time, returning the response and the cycles the
+// drive was busy
+fn command_at(drive: &mut DiskDrive, cycle: u64, command: u8, sector: u16) -> (SioResponse, u64) {
+    drive.set_clock(cycle);
+    let [aux1, aux2] = sector.to_le_bytes();
+    let response = drive.command(command, aux1, aux2);
+    (response, drive.busy_cycles())
+}
+
+fn data(response: SioResponse) -> (bool, Vec<u8>) {
+    match response {
+        SioResponse::Complete(data) => (true, data),
+        SioResponse::Error(data) => (false, data),
+        _ => panic!("no data frame"),
+    }
+}
+
+#[test]
+fn test_parse_tracks() {
+    let file = atx_file(&[
+        (0, vec![(1, 0x00, 100, Some(0x11), None), (2, 0x10, 5000, None, None)]),
+        (1, vec![(1, 0x08, 200, Some(0x22), Some(64))]),
+    ]);
+    let atx = Atx::parse(&file).unwrap();
+    assert_eq!(atx.density, Density::Single);
+    assert_eq!(atx.sectors_per_track(), 18);
+    assert_eq!(atx.tracks.len(), 2);
+
+    let track0 = atx.track(0).unwrap();
+    assert_eq!(track0.sectors[0].data.as_deref(), Some(&[0x11; 128][..]));
+    assert_eq!(track0.sectors[1].position, 5000);
+    assert!(track0.sectors[1].data.is_none());
+
+    let weak = &atx.track(1).unwrap().sectors[0];
+    assert_eq!(weak.status, 0x08);
+    assert_eq!(weak.weak_offset, Some(64));
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(matches!(Atx::parse(b"ATR"), Err(AtxError::NotAtx)));
+
+    let mut file = atx_file(&[(0, vec![(1, 0, 0, Some(0), None)])]);
+    file[18] = 7;
+    assert!(matches!(Atx::parse(&file), Err(AtxError::BadDensity(7))));
+
+    let file = atx_file(&[(0, vec![(1, 0, 0, Some(0), None)])]);
+    let len = file.len() - 10;
+    let mut short = file[..len].to_vec();
+    short[32..36].copy_from_slice(&(len as u32).to_le_bytes());
+    assert!(matches!(Atx::parse(&short), Err(AtxError::Truncated { offset: 48 })));
+}
+
+#[test]
+fn test_duplicate_sectors_follow_rotation() {
+    let file = atx_file(&[(0, vec![(1, 0, 1000, Some(0xAA), None), (1, 0, 14000, Some(0xBB), None)])]);
+    let mut drive = DiskDrive::from_atx(Atx::parse(&file).unwrap());
+
+    // Near the index the first copy comes round first
+    let (response, busy) = command_at(&mut drive, 0, b'R', 1);
+    assert_eq!(data(response), (true, vec![0xAA; 128]));
+    assert!((1000 * CYCLES_PER_UNIT..3000 * CYCLES_PER_UNIT).contains(&busy), "busy {}", busy);
+
+    // Past it, the second copy is next
+    let (response, busy) = command_at(&mut drive, 5000 * CYCLES_PER_UNIT, b'R', 1);
+    assert_eq!(data(response), (true, vec![0xBB; 128]));
+    assert!((9000 * CYCLES_PER_UNIT..11000 * CYCLES_PER_UNIT).contains(&busy), "busy {}", busy);
+}
+
+#[test]
+fn test_bad_sectors_report_controller_status() {
+    let file = atx_file(&[
+        (0, vec![(1, 0x08, 0, Some(0x5A), None)]),
+        (1, vec![(1, 0x00, 0, Some(0x00), None)]),
+    ]);
+    let mut drive = DiskDrive::from_atx(Atx::parse(&file).unwrap());
+
+    // CRC error: the data still comes, with ERROR
+    let (response, _) = command_at(&mut drive, 0, b'R', 1);
+    assert_eq!(data(response), (false, vec![0x5A; 128]));
+    let (status, _) = command_at(&mut drive, 0, b'S', 0);
+    assert_eq!(data(status).1, [0x1C, !0x48, 0xE0, 0x00]);
+
+    // Sector 2 isn't on track 0: the drive looks for two revolutions
+    let (response, busy) = command_at(&mut drive, 0, b'R', 2);
+    assert!(!data(response).0);
+    assert!(busy >= 2 * 26042 * CYCLES_PER_UNIT, "busy {}", busy);
+    let (status, _) = command_at(&mut drive, 0, b'S', 0);
+    assert_eq!(data(status).1[1], !0x50);
+
+    // Sector 19 is on track 1, a step away
+    let (response, busy) = command_at(&mut drive, 0, b'R', 19);
+    assert!(data(response).0);
+    assert!(busy > 20_000, "busy {}", busy);
+}
+
+#[test]
+fn test_weak_bits_read_differently() {
+    let file = atx_file(&[(0, vec![(1, 0x08, 0, Some(0x33), Some(100))])]);
+    let mut drive = DiskDrive::from_atx(Atx::parse(&file).unwrap());
+
+    let (first, _) = command_at(&mut drive, 1000, b'R', 1);
+    let (second, _) = command_at(&mut drive, 900_000, b'R', 1);
+    let (first, second) = (data(first).1, data(second).1);
+    assert_eq!(first[..100], [0x33; 100][..]);
+    assert_eq!(second[..100], [0x33; 100][..]);
+    assert_ne!(first[100..], second[100..]);
+}
+
+#[test]
+fn test_atx_is_write_protected() {
+    let file = atx_file(&[(0, vec![(1, 0, 0, Some(0), None)])]);
+    let mut drive = DiskDrive::from_atx(Atx::parse(&file).unwrap());
+
+    let (response, _) = command_at(&mut drive, 0, b'W', 1);
+    assert!(matches!(response, SioResponse::Receive(128)));
+    assert!(matches!(drive.data(&[0; 128]), SioResponse::Error(_)));
+    let (status, _) = command_at(&mut drive, 0, b'S', 0);
+    assert_eq!(data(status).1[..2], [0x1C, !0x40]);
+}
+
+#[test]
+fn test_boots_from_atx() {
+    let code = asm::assemble(
+        "
+DOSVEC = $0A
+        .org $0700
+        .byte 0, 1
+        .word $0700, init
+        lda #$C3
+        sta $0601
+        lda #<main
+        sta DOSVEC
+        lda #>main
+        sta DOSVEC+1
+        clc
+        rts
+init:   rts
+main:   jmp main
+",
+        &[],
+    )
+    .unwrap();
+    let mut file = atx_file(&[(0, vec![(1, 0, 3000, Some(0), None)])]);
+
+    // Put the boot code in place of the sector's fill
+    let start = file.len() - 128;
+    file[start..].copy_from_slice(&code.image(0x0700, 128));
+
+    let mut atari800 = Atari800::new();
+    assert!(atari800.mount_disk(1, DiskDrive::from_atx(Atx::parse(&file).unwrap())).is_ok());
+    for _ in 0..60 {
+        atari800.run_frame();
+    }
+    assert_eq!(atari800.read(0x0601), 0xC3);
+}
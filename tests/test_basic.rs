@@ -0,0 +1,75 @@
+use atari800_rs::atari800::{Atari800, Atari800Config, Model};
+use atari800_rs::bus::Bus;
+use atari800_rs::rom::{RomError, RomKind};
+
+fn atari_with_basic(model: Model, basic_enabled: bool) -> Atari800 {
+    Atari800::with_config(Atari800Config {
+        model,
+        basic_rom: Some(vec![0xB5; 0x2000]),
+        basic_enabled,
+        ..Atari800Config::default()
+    }).unwrap()
+}
+
+// Program PORTB through the PIA: make all lines outputs, then write them
+fn write_portb(atari800: &mut Atari800, val: u8) {
+    atari800.write(0xD303, 0x38);   // PBCTL: select DDRB
+    atari800.write(0xD301, 0xFF);   // All outputs
+    atari800.write(0xD303, 0x3C);   // PBCTL: select PORTB
+    atari800.write(0xD301, val);
+}
+
+#[test]
+fn test_basic_is_a_cartridge_on_the_800() {
+    let mut atari800 = atari_with_basic(Model::Atari800, true);
+    assert_eq!(atari800.read(0xA000), 0xB5);
+    assert_eq!(atari800.read(0xD013), 1);
+
+    let mut atari800 = atari_with_basic(Model::Atari800, false);
+    atari800.write(0xA000, 0x12);
+    assert_eq!(atari800.read(0xA000), 0x12);
+    assert_eq!(atari800.read(0xD013), 0);
+}
+
+#[test]
+fn test_basic_follows_portb_on_xl() {
+    let mut atari800 = atari_with_basic(Model::Atari800Xl, true);
+
+    // PORTB lines float high at power-on, so BASIC starts out disabled
+    atari800.write(0xA000, 0x12);
+    assert_eq!(atari800.read(0xA000), 0x12);
+
+    write_portb(&mut atari800, 0xFD);
+    assert_eq!(atari800.read(0xA000), 0xB5);
+    // Built-in BASIC is not a cartridge
+    assert_eq!(atari800.read(0xD013), 0);
+
+    write_portb(&mut atari800, 0xFF);
+    assert_eq!(atari800.read(0xA000), 0x12);
+}
+
+#[test]
+fn test_option_held_on_boot_when_basic_disabled() {
+    let mut atari800 = atari_with_basic(Model::Atari800Xl, false);
+    assert_eq!(atari800.read(0xD01F), 0x03);
+
+    for _ in 0..60 {
+        atari800.trigger_vbi();
+    }
+    assert_eq!(atari800.read(0xD01F), 0x07);
+
+    let mut atari800 = atari_with_basic(Model::Atari800Xl, true);
+    assert_eq!(atari800.read(0xD01F), 0x07);
+}
+
+#[test]
+fn test_wrong_size_basic_is_an_error() {
+    let result = Atari800::with_config(Atari800Config {
+        basic_rom: Some(vec![0xB5; 0x1000]),
+        ..Atari800Config::default()
+    });
+    assert!(matches!(
+        result,
+        Err(RomError::WrongImageSize { kind: RomKind::Basic, expected: 0x2000, actual: 0x1000 })
+    ));
+}
@@ -0,0 +1,128 @@
+use atari800_rs::asm;
+use atari800_rs::atari800::{Atari800, Atari800Config, Model};
+use atari800_rs::builtin_os;
+use atari800_rs::bus::Bus;
+use atari800_rs::cartridge::Cartridge;
+
+fn run_frames(atari800: &mut Atari800, frames: u32) {
+    for _ in 0..frames {
+        atari800.run_frame();
+    }
+}
+
+// Text on a screen row, converted back from screen codes to ASCII
+fn screen_row(atari800: &mut Atari800, row: u16) -> String {
+    let savmsc = atari800.read_word(0x0058);
+    (0..40)
+        .map(|col| {
+            let code = atari800.read(savmsc + row * 40 + col) & 0x7F;
+            match code {
+                0x00..=0x3F => (code + 0x20) as char,
+                0x60..=0x7F => code as char,
+                _ => '?',
+            }
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+#[test]
+fn test_assembles_for_both_machines() {
+    for &(xl, size) in &[(false, 0x2800), (true, 0x4000)] {
+        let image = builtin_os::assemble(xl).unwrap_or_else(|e| panic!("XL={}: {}", xl, e));
+        assert_eq!(image.len(), size);
+        assert_eq!(builtin_os::os_rom(xl), image);
+    }
+}
+
+#[test]
+fn test_boots_to_memo_pad() {
+    let mut atari800 = Atari800::new();
+    run_frames(&mut atari800, 30);
+
+    // 48K: RAMTOP at $C0, screen just below it
+    assert_eq!(atari800.read(0x006A), 0xC0);
+    assert_eq!(atari800.read_word(0x0058), 0xBC40);
+    assert_eq!(screen_row(&mut atari800, 0), "  ATARI800-RS BUILT-IN OS");
+
+    // The VBI is running: RTCLOK counts frames
+    let clock = atari800.read(0x0014);
+    run_frames(&mut atari800, 5);
+    assert_eq!(atari800.read(0x0014), clock.wrapping_add(5));
+}
+
+#[test]
+fn test_typed_keys_are_echoed() {
+    let mut atari800 = Atari800::new();
+    run_frames(&mut atari800, 30);
+
+    // H, I, Return
+    for &code in &[0x39, 0x0D, 0x0C] {
+        atari800.press_key(code);
+        run_frames(&mut atari800, 2);
+        atari800.release_key();
+        run_frames(&mut atari800, 2);
+    }
+    // Caps lock is on at power-up
+    assert_eq!(screen_row(&mut atari800, 1), "  HI");
+    assert_eq!(atari800.read(0x0054), 2);
+}
+
+#[test]
+fn test_xl_boots_with_smaller_ram() {
+    let mut atari800 = Atari800::with_config(Atari800Config {
+        basic_enabled: false,
+        ..Atari800Config::for_model(Model::Atari600Xl)
+    }).unwrap();
+    run_frames(&mut atari800, 30);
+
+    assert_eq!(atari800.read(0x006A), 0x40);
+    assert_eq!(screen_row(&mut atari800, 0), "  ATARI800-RS BUILT-IN OS");
+}
+
+#[test]
+fn test_runs_cartridge_through_cio() {
+    // Prints a line with PUT RECORD via CIOV, then leaves a marker
+    let source = r#"
+ICCOM = $0342
+ICBAL = $0344
+ICBLL = $0348
+        .org $A000
+start:  ldx #0
+        lda #9
+        sta ICCOM,x
+        lda #<text
+        sta ICBAL,x
+        lda #>text
+        sta ICBAL+1,x
+        lda #40
+        sta ICBLL,x
+        lda #0
+        sta ICBLL+1,x
+        jsr $E456
+        sty $0600
+        lda #$A5
+        sta $0601
+loop:   jmp loop
+init:   rts
+text:   .byte "CART OK", $9B
+
+        .org $BFFA
+        .word start
+        .byte 0, $04
+        .word init
+"#;
+    let image = asm::assemble(source, &[]).unwrap().image(0xA000, 0x2000);
+    let mut atari800 = Atari800::with_config(Atari800Config {
+        cartridge: Some(Cartridge::from_bytes(&image).unwrap()),
+        ..Atari800Config::default()
+    }).unwrap();
+    run_frames(&mut atari800, 30);
+
+    assert_eq!(atari800.read(0x0601), 0xA5);
+    assert_eq!(atari800.read(0x0600), 1);
+    // 8K cartridge in: RAMTOP drops to $A0
+    assert_eq!(atari800.read(0x006A), 0xA0);
+    assert_eq!(screen_row(&mut atari800, 0), "  CART OK");
+}
@@ -0,0 +1,139 @@
+use atari800_rs::atari800::{Atari800, Atari800Config};
+use atari800_rs::bus::Bus;
+use atari800_rs::cartridge::{CartError, CartType, Cartridge};
+
+// Image whose every byte holds the number of the 8K bank it's in
+fn banked_image(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i / 0x2000) as u8).collect()
+}
+
+fn car_file(type_num: u32, image: &[u8]) -> Vec<u8> {
+    let checksum = image.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+    let mut data = b"CART".to_vec();
+    data.extend_from_slice(&type_num.to_be_bytes());
+    data.extend_from_slice(&checksum.to_be_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(image);
+    data
+}
+
+fn atari_with_cart(cart: Cartridge) -> Atari800 {
+    Atari800::with_config(Atari800Config {
+        cartridge: Some(cart),
+        ..Atari800Config::default()
+    }).unwrap()
+}
+
+#[test]
+fn test_car_header() {
+    let image = banked_image(0x10000);
+    let cart = Cartridge::from_bytes(&car_file(8, &image)).unwrap();
+    assert_eq!(cart.cart_type(), CartType::Williams64k);
+
+    let mut bad = car_file(8, &image);
+    bad[11] ^= 0xFF;
+    assert!(matches!(Cartridge::from_bytes(&bad), Err(CartError::BadChecksum { .. })));
+
+    assert!(matches!(
+        Cartridge::from_bytes(&car_file(99, &image)),
+        Err(CartError::UnknownType(99))
+    ));
+}
+
+#[test]
+fn test_raw_image_detection() {
+    assert_eq!(Cartridge::from_bytes(&[0; 0x2000]).unwrap().cart_type(), CartType::Std8k);
+    assert_eq!(Cartridge::from_bytes(&[0; 0x4000]).unwrap().cart_type(), CartType::Std16k);
+    assert!(matches!(Cartridge::from_bytes(&[0; 0x1234]), Err(CartError::UnknownSize(0x1234))));
+}
+
+#[test]
+fn test_std8k_maps_at_a000_and_sets_trig3() {
+    let mut atari800 = Atari800::new();
+    assert_eq!(atari800.read(0xD013), 0);
+
+    atari800.insert_cartridge(Cartridge::new(CartType::Std8k, &[0x42; 0x2000]).unwrap());
+    assert_eq!(atari800.read(0xA000), 0x42);
+    assert_eq!(atari800.read(0xBFFF), 0x42);
+    assert_eq!(atari800.read(0xD013), 1);
+
+    // Cart ROM ignores writes
+    atari800.write(0xA000, 0x00);
+    assert_eq!(atari800.read(0xA000), 0x42);
+
+    // Removing it uncovers RAM again
+    atari800.remove_cartridge();
+    atari800.write(0xA000, 0x00);
+    assert_eq!(atari800.read(0xA000), 0x00);
+    assert_eq!(atari800.read(0xD013), 0);
+}
+
+#[test]
+fn test_right_slot() {
+    let mut atari800 = Atari800::new();
+    atari800.insert_cartridge(Cartridge::new(CartType::Right8k, &[0x99; 0x2000]).unwrap());
+    assert_eq!(atari800.read(0x8000), 0x99);
+    assert_eq!(atari800.read(0xD013), 0);
+}
+
+#[test]
+fn test_xegs_banking() {
+    let cart = Cartridge::new(CartType::Xegs64k, &banked_image(0x10000)).unwrap();
+    let mut atari800 = atari_with_cart(cart);
+
+    // Last bank is fixed at $A000, bank 0 starts in the $8000 window
+    assert_eq!(atari800.read(0xA000), 7);
+    assert_eq!(atari800.read(0x8000), 0);
+
+    atari800.write(0xD500, 5);
+    assert_eq!(atari800.read(0x8000), 5);
+    assert_eq!(atari800.read(0xA000), 7);
+}
+
+#[test]
+fn test_williams_banking_and_disable() {
+    let cart = Cartridge::new(CartType::Williams64k, &banked_image(0x10000)).unwrap();
+    let mut atari800 = atari_with_cart(cart);
+    assert_eq!(atari800.read(0xA000), 0);
+
+    // Any access to $D500-$D507 selects a bank
+    atari800.read(0xD503);
+    assert_eq!(atari800.read(0xA000), 3);
+
+    // $D508-$D50F switches the cart off and RAM shows through
+    atari800.write(0xD508, 0);
+    atari800.write(0xA000, 0x77);
+    assert_eq!(atari800.read(0xA000), 0x77);
+    assert_eq!(atari800.read(0xD013), 0);
+}
+
+#[test]
+fn test_sdx_and_megacart_banking() {
+    let cart = Cartridge::new(CartType::Sdx64k, &banked_image(0x10000)).unwrap();
+    let mut atari800 = atari_with_cart(cart);
+    atari800.write(0xD5E2, 0);
+    assert_eq!(atari800.read(0xA000), 5);
+
+    let cart = Cartridge::new(CartType::Mega64k, &banked_image(0x10000)).unwrap();
+    let mut atari800 = atari_with_cart(cart);
+    atari800.write(0xD500, 2);
+    assert_eq!(atari800.read(0x8000), 4);
+    assert_eq!(atari800.read(0xA000), 5);
+}
+
+#[test]
+fn test_swapping_cartridges_reuses_rom_space() {
+    let mut atari800 = Atari800::new();
+    atari800.insert_cartridge(Cartridge::new(CartType::Std8k, &[0x11; 0x2000]).unwrap());
+    atari800.insert_cartridge(Cartridge::new(CartType::Right8k, &[0x99; 0x2000]).unwrap());
+
+    // The right cart's image was stored after the left one's, and moves
+    // down when that is freed
+    for i in 0..100 {
+        atari800.insert_cartridge(Cartridge::new(CartType::Std8k, &[i; 0x2000]).unwrap());
+        assert_eq!(atari800.read(0xA000), i);
+        assert_eq!(atari800.read(0x8000), 0x99);
+    }
+    atari800.remove_right_cartridge();
+    assert_eq!(atari800.read(0xA000), 99);
+}
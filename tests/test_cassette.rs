@@ -0,0 +1,307 @@
+use atari800_rs::asm;
+use atari800_rs::atari800::{Atari800, Atari800Config};
+use atari800_rs::bus::Bus;
+use atari800_rs::cas::{Cas, CasBlock, CasError};
+use atari800_rs::cassette::Cassette;
+use atari800_rs::xex::{Segment, Xex, RUNAD};
+
+mod common;
+use common::TempDir;
+
+// Machine cycles for one byte at 600 baud
+const BYTE_CYCLES_600: u32 = 29_829;
+
+fn block(baud: u16, gap_ms: u16, data: &[u8]) -> CasBlock {
+    CasBlock { baud, gap_ms, data: data.to_vec() }
+}
+
+// Run a program at $2000 with a tape in the recorder
+fn run(source: &str, cassette: Cassette, frames: u32) -> Atari800 {
+    let source = format!(
+        "
+PACTL = $D302
+AUDF3 = $D204
+AUDF4 = $D206
+AUDCTL = $D208
+SERIN = $D20D
+SEROUT = $D20D
+IRQEN = $D20E
+IRQST = $D20E
+RTCLOK = $12
+        .org $2000
+{}",
+        source
+    );
+    let code = asm::assemble(&source, &[]).unwrap();
+    let xex = Xex {
+        segments: vec![
+            Segment { start: 0x2000, data: code.image(0x2000, 0x100) },
+            Segment { start: RUNAD, data: vec![0x00, 0x20] },
+        ],
+    };
+
+    let mut atari800 = Atari800::new();
+    atari800.insert_tape(cassette);
+    atari800.load_xex(xex);
+    for _ in 0..frames {
+        atari800.run_frame();
+    }
+    atari800
+}
+
+#[test]
+fn test_cas_round_trip() {
+    let cas = Cas {
+        description: "Test tape".to_string(),
+        blocks: vec![block(600, 9000, &[0x55, 0x55, 0xFC]), block(600, 250, &[1]), block(1200, 3, &[])],
+    };
+    let image = cas.to_bytes();
+    assert_eq!(&image[0..8], b"FUJI\x09\x00\x00\x00");
+    assert_eq!(&image[17..25], b"baud\x00\x00\x58\x02");
+    assert_eq!(&image[25..33], b"data\x03\x00\x28\x23");
+    assert_eq!(Cas::parse(&image).unwrap(), cas);
+
+    // Unknown chunks are skipped; data before a baud chunk is 600 baud
+    let mut image = b"FUJI\x00\x00\x00\x00fsk \x02\x00\x00\x00\x10\x00".to_vec();
+    image.extend_from_slice(b"data\x01\x00\x64\x00\xAA");
+    assert_eq!(Cas::parse(&image).unwrap().blocks, [block(600, 100, &[0xAA])]);
+}
+
+#[test]
+fn test_cas_errors() {
+    assert!(matches!(Cas::parse(b"baud\x00\x00\x58\x02"), Err(CasError::NotCas)));
+
+    let mut image = Cas { description: String::new(), blocks: vec![block(600, 0, &[1, 2, 3])] }.to_bytes();
+    image.pop();
+    assert!(matches!(Cas::parse(&image), Err(CasError::Truncated { offset: 16 })));
+}
+
+#[test]
+fn test_tape_only_moves_with_the_motor() {
+    let tape = Cas { description: String::new(), blocks: vec![block(600, 10, &[0xAA, 0xBB])] };
+    let mut cassette = Cassette::new(tape);
+    for _ in 0..100_000 {
+        assert_eq!(cassette.tick(false), None);
+    }
+
+    // The gap, then a byte's time
+    let first = (1..).find(|_| cassette.tick(true).is_some()).unwrap();
+    assert_eq!(first, 10 * 1790 + BYTE_CYCLES_600);
+
+    // Stopping the motor half way through the next byte holds it back
+    for _ in 0..10_000 {
+        assert_eq!(cassette.tick(true), None);
+    }
+    for _ in 0..100_000 {
+        assert_eq!(cassette.tick(false), None);
+    }
+    let rest = (1..).find(|_| cassette.tick(true).is_some()).unwrap();
+    assert_eq!(rest + 10_000, BYTE_CYCLES_600);
+    assert!(cassette.at_end());
+
+    cassette.rewind();
+    assert!(!cassette.at_end());
+}
+
+#[test]
+fn test_plays_into_serial_input() {
+    // Poll for five bytes with the motor on, then stop it
+    let source = "
+        sei
+        lda #$34
+        sta PACTL
+        ldx #0
+next:   lda #$20
+        sta IRQEN
+wait:   lda IRQST
+        and #$20
+        bne wait
+        lda SERIN
+        sta $0680,x
+        lda #0
+        sta IRQEN
+        inx
+        cpx #5
+        bne next
+        lda #$3C
+        sta PACTL
+        stx $0601
+loop:   jmp loop
+";
+    let tape = Cas {
+        description: String::new(),
+        blocks: vec![block(600, 100, &[0x55, 0x55, 0xFC]), block(1200, 50, &[0x12, 0x34])],
+    };
+    let mut atari800 = run(source, Cassette::new(tape), 40);
+
+    assert_eq!(atari800.read(0x0601), 5);
+    let received: Vec<u8> = (0..5).map(|i| atari800.read(0x0680 + i)).collect();
+    assert_eq!(received, [0x55, 0x55, 0xFC, 0x12, 0x34]);
+    assert!(atari800.tape().unwrap().at_end());
+}
+
+#[test]
+fn test_records_serial_output_to_a_new_file() {
+    // Two records at 600 baud with a 100ms pause between them
+    let source = "
+        sei
+        lda #$34
+        sta PACTL
+        lda #$28
+        sta AUDCTL
+        lda #$CC
+        sta AUDF3
+        lda #$05
+        sta AUDF4
+        ldx #0
+        ldy #4
+        jsr send
+        lda RTCLOK+2
+        clc
+        adc #6
+pause:  cmp RTCLOK+2
+        bne pause
+        ldy #2
+        jsr send
+        lda #$3C
+        sta PACTL
+        sta $0601
+loop:   jmp loop
+
+; Send Y bytes from data+X, then wait for the last to go
+send:   lda #$10
+        sta IRQEN
+        lda data,x
+        sta SEROUT
+wait:   lda IRQST
+        and #$10
+        bne wait
+        lda #0
+        sta IRQEN
+        inx
+        dey
+        bne send
+done:   lda IRQST
+        and #$08
+        bne done
+        rts
+data:   .byte $55, $55, $FC, $01, $55, $55
+";
+    let dir = TempDir::new("tape");
+    let path = dir.join("TAPE.CAS");
+    let mut atari800 = run(source, Cassette::record_to(&path), 40);
+    let saved = Cas::from_file(&path);
+
+    assert_eq!(atari800.read(0x0601), 0x3C);
+    let cassette = atari800.eject_tape().unwrap();
+    assert!(cassette.is_recording());
+    let blocks = &cassette.tape().blocks;
+    assert_eq!(blocks.len(), 2);
+    assert_eq!((blocks[0].baud, &blocks[0].data[..]), (600, &[0x55, 0x55, 0xFC, 0x01][..]));
+    assert_eq!((blocks[1].baud, &blocks[1].data[..]), (600, &[0x55, 0x55][..]));
+    assert!((80..=110).contains(&blocks[1].gap_ms), "gap {}ms", blocks[1].gap_ms);
+    assert_eq!(&saved.unwrap(), cassette.tape(), "saved when the motor stops");
+}
+
+#[test]
+fn test_failed_save_is_kept_for_the_caller() {
+    // A directory can't be written over
+    let dir = TempDir::new("tape-error");
+    let mut cassette = Cassette::record_to(&*dir);
+    cassette.tick(true);
+    cassette.record_byte(0x55, 2982);
+    cassette.tick(false);
+    assert!(cassette.take_write_error().is_some());
+    assert!(cassette.take_write_error().is_none());
+    assert!(cassette.save().is_err(), "still unsaved");
+
+    let path = dir.join("TAPE.CAS");
+    let mut cassette = Cassette::record_to(&path);
+    cassette.tick(true);
+    cassette.record_byte(0x55, 2982);
+    cassette.save().unwrap();
+    assert_eq!(&Cas::from_file(&path).unwrap(), cassette.tape());
+}
+
+#[test]
+fn test_sio_patch_leaves_the_tape_to_the_os() {
+    // An OS whose SIOV reads DBYTLO bytes from tape to $0680, and which
+    // calls it for device $60 at reset
+    let source = "
+DDEVIC = $0300
+DUNIT = $0301
+DCOMND = $0302
+DSTATS = $0303
+DBYTLO = $0308
+PACTL = $D302
+SERIN = $D20D
+IRQEN = $D20E
+IRQST = $D20E
+SIOV = $E459
+        .org $D800
+reset:  sei
+        cld
+        ldx #$FF
+        txs
+        lda #$60
+        sta DDEVIC
+        lda #1
+        sta DUNIT
+        lda #'R'
+        sta DCOMND
+        lda #$40
+        sta DSTATS
+        lda #3
+        sta DBYTLO
+        jsr SIOV
+        sty $0601
+loop:   jmp loop
+
+tape:   lda #$34
+        sta PACTL
+        ldy #0
+next:   lda #$20
+        sta IRQEN
+wait:   lda IRQST
+        and #$20
+        bne wait
+        lda SERIN
+        sta $0680,y
+        lda #0
+        sta IRQEN
+        iny
+        cpy DBYTLO
+        bne next
+        lda #$3C
+        sta PACTL
+        ldy #1
+        sty DSTATS
+        rts
+
+        .org SIOV
+        jmp tape
+        .org $FFFA
+        .word reset, reset, reset
+";
+    let os = asm::assemble(source, &[]).unwrap().image(0xD800, 0x2800);
+    let tape = Cas { description: String::new(), blocks: vec![block(600, 100, &[0x55, 0x55, 0xFC])] };
+    let dir = TempDir::new("patched");
+    let path = dir.join("TAPE.CAS");
+    std::fs::write(&path, tape.to_bytes()).unwrap();
+    let cassette = Cassette::open(&path);
+
+    let mut atari800 = Atari800::with_config(Atari800Config {
+        os_rom: Some(os),
+        sio_patch: true,
+        ..Atari800Config::default()
+    })
+    .unwrap();
+    atari800.insert_tape(cassette.unwrap());
+    for _ in 0..40 {
+        atari800.run_frame();
+    }
+
+    assert_eq!(atari800.read(0x0601), 0x01, "the OS routine ran");
+    let received: Vec<u8> = (0..3).map(|i| atari800.read(0x0680 + i)).collect();
+    assert_eq!(received, [0x55, 0x55, 0xFC]);
+}
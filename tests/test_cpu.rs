@@ -10,7 +10,7 @@ struct TestBus {
 impl TestBus {
     fn new() -> TestBus {
         TestBus {
-            mem: Mem::new(0, false),
+            mem: Mem::blank(0),
         }
     }
 }
@@ -213,3 +213,31 @@ fn test_sbc_dec() {
     assert_eq!(cpu.z, true);
     assert_eq!(cpu.c, false);
 }
+
+// Cycles for one instruction at `pc`
+fn instruction_cycles(cpu: &mut Cpu, bus: &mut TestBus, pc: u16) -> u32 {
+    cpu.pc = pc;
+    cpu.cycles_remaining = 0;
+    let mut cycles = 1;
+    cpu.tick(bus);
+    while cpu.cycles_remaining > 0 {
+        cpu.tick(bus);
+        cycles += 1;
+    }
+    cycles
+}
+
+#[test]
+fn test_branch_cycles() {
+    let (mut cpu, mut bus) = get_cpu_bus();
+    bus.mem.ram[0x0880..0x0882].copy_from_slice(&[0xD0, 0x10]);     // BNE +16
+    bus.mem.ram[0x08F0..0x08F2].copy_from_slice(&[0xD0, 0x10]);     // BNE +16, to the next page
+
+    cpu.z = true;
+    assert_eq!(instruction_cycles(&mut cpu, &mut bus, 0x0880), 2, "not taken");
+    cpu.z = false;
+    assert_eq!(instruction_cycles(&mut cpu, &mut bus, 0x0880), 3, "taken");
+    assert_eq!(cpu.pc, 0x0892);
+    assert_eq!(instruction_cycles(&mut cpu, &mut bus, 0x08F0), 4, "taken across a page");
+    assert_eq!(cpu.pc, 0x0902);
+}
@@ -0,0 +1,449 @@
+use atari800_rs::asm;
+use atari800_rs::atari800::Atari800;
+use atari800_rs::atr::{Atr, AtrError, Density};
+use atari800_rs::atx::Atx;
+use atari800_rs::bus::Bus;
+use atari800_rs::disk::{DiskDrive, DiskError};
+use atari800_rs::sio::{checksum, SioDevice, SioResponse};
+
+mod common;
+use common::TempDir;
+
+// Boot sector that loads at $0700. `body` runs after the header; DOSINI
+// counts its calls in $0600, and DOSVEC ends up at a loop.
+fn boot_sector(body: &str) -> Vec<u8> {
+    let source = format!(
+        "
+DOSVEC = $0A
+        .org $0700
+        .byte 0, 1
+        .word $0700, init
+{}
+        lda #<main
+        sta DOSVEC
+        lda #>main
+        sta DOSVEC+1
+        clc
+        rts
+init:   inc $0600
+        rts
+main:   jmp main
+",
+        body
+    );
+    let code = asm::assemble(&source, &[]).unwrap();
+    assert!(code.symbol("main").unwrap() < 0x077D, "boot code doesn't fit in a sector");
+    code.image(0x0700, 128)
+}
+
+fn boot(drive: DiskDrive) -> Atari800 {
+    let mut atari800 = Atari800::new();
+    assert!(atari800.mount_disk(1, drive).is_ok());
+    for _ in 0..60 {
+        atari800.run_frame();
+    }
+    atari800
+}
+
+// Frames until the boot code has stored its result in $0601
+fn frames_to_boot(atari800: &mut Atari800) -> u32 {
+    (1..=60)
+        .find(|_| {
+            atari800.run_frame();
+            atari800.read(0x0601) != 0
+        })
+        .expect("never booted")
+}
+
+#[test]
+fn test_atr_layout() {
+    let single = Atr::blank(Density::Single);
+    assert_eq!(single.to_bytes().len(), 16 + 720 * 128);
+    assert_eq!(single.sector_count(), 720);
+    assert_eq!(single.density(), Density::Single);
+    assert_eq!(Atr::blank(Density::Enhanced).density(), Density::Enhanced);
+
+    // Double density keeps the three boot sectors at 128 bytes
+    let mut double = Atr::blank(Density::Double);
+    assert_eq!(double.to_bytes().len(), 16 + 3 * 128 + 717 * 256);
+    assert_eq!(double.sector_count(), 720);
+    assert_eq!(double.sector_size(3), 128);
+    assert_eq!(double.sector_size(4), 256);
+    assert!(double.write_sector(4, &[0x44; 256]));
+    assert!(!double.write_sector(4, &[0x44; 128]));
+    assert!(double.read_sector(721).is_none());
+
+    let reparsed = Atr::parse(&double.to_bytes()).unwrap();
+    assert_eq!(reparsed, double);
+    assert_eq!(reparsed.read_sector(4).unwrap(), &[0x44; 256][..]);
+}
+
+#[test]
+fn test_atr_errors() {
+    assert!(matches!(Atr::parse(&[0; 32]), Err(AtrError::NotAtr)));
+
+    let mut image = Atr::blank(Density::Single).to_bytes();
+    image[4] = 0;
+    image[5] = 2;
+    assert!(matches!(Atr::parse(&image), Err(AtrError::BadSectorSize(512))));
+
+    let image = Atr::blank(Density::Single).to_bytes();
+    assert!(matches!(
+        Atr::parse(&image[..1000]),
+        Err(AtrError::Truncated { expected: 92160, actual: 984 })
+    ));
+}
+
+#[test]
+fn test_checksum_wraps_carry() {
+    assert_eq!(checksum(&[0x31, 0x52, 0x01, 0x00]), 0x84);
+    assert_eq!(checksum(&[0xFF, 0x02]), 0x02);
+}
+
+#[test]
+fn test_boots_from_disk() {
+    let mut atr = Atr::blank(Density::Single);
+    atr.write_sector(1, &boot_sector("        lda #$A5\n        sta $0601\n"));
+    let mut atari800 = boot(DiskDrive::new(atr));
+
+    assert_eq!(atari800.read(0x0601), 0xA5);
+    assert_eq!(atari800.read(0x0600), 1, "DOSINI is called once");
+    assert_eq!(atari800.read(0x0009) & 0x01, 0x01, "BOOT? records the disk boot");
+}
+
+#[test]
+fn test_write_and_status_go_back_to_the_file() {
+    // Write the boot sector itself out to sector 10, then ask for the
+    // drive's status
+    let body = "
+DCOMND = $0302
+DBUFLO = $0304
+DAUX1 = $030A
+DAUX2 = $030B
+DSKINV = $E453
+        lda #'W'
+        sta DCOMND
+        lda #<$0700
+        sta DBUFLO
+        lda #>$0700
+        sta DBUFLO+1
+        lda #10
+        sta DAUX1
+        lda #0
+        sta DAUX2
+        jsr DSKINV
+        sty $0601
+        lda #'S'
+        sta DCOMND
+        lda #<$0610
+        sta DBUFLO
+        lda #>$0610
+        sta DBUFLO+1
+        jsr DSKINV
+        sty $0602
+";
+    let sector = boot_sector(body);
+    let mut atr = Atr::blank(Density::Enhanced);
+    atr.write_sector(1, &sector);
+
+    let dir = TempDir::new("disk");
+    let path = dir.join("DISK.ATR");
+    atr.save(&path).unwrap();
+    let mut atari800 = boot(DiskDrive::open(&path).unwrap());
+    let saved = Atr::from_file(&path);
+
+    assert_eq!(atari800.read(0x0601), 0x01, "write succeeds");
+    assert_eq!(atari800.read(0x0602), 0x01, "status succeeds");
+    let status: Vec<u8> = (0..4).map(|i| atari800.read(0x0610 + i)).collect();
+    assert_eq!(status, [0x90, 0xFF, 0xE0, 0x00], "motor on, enhanced density");
+    assert_eq!(saved.unwrap().read_sector(10).unwrap(), &sector[..]);
+}
+
+#[test]
+fn test_reads_double_density_sector_through_siov() {
+    let body = "
+SIOV = $E459
+        ldx #11
+dcb:    lda request,x
+        sta $0300,x
+        dex
+        bpl dcb
+        jsr SIOV
+        sty $0601
+        jmp done
+; D1: read sector 4, 256 bytes to $0800
+request:
+        .byte $31, 1, 'R', $40
+        .word $0800
+        .byte 7, 0
+        .word 256
+        .word 4
+done:
+";
+    let mut atr = Atr::blank(Density::Double);
+    atr.write_sector(1, &boot_sector(body));
+    let pattern: Vec<u8> = (0..=255).collect();
+    atr.write_sector(4, &pattern);
+    let mut atari800 = boot(DiskDrive::new(atr));
+
+    assert_eq!(atari800.read(0x0601), 0x01);
+    for i in 0..256u16 {
+        assert_eq!(atari800.read(0x0800 + i), i as u8);
+    }
+}
+
+#[test]
+fn test_missing_sector_is_refused() {
+    let body = "
+DCOMND = $0302
+DBUFLO = $0304
+DAUX1 = $030A
+DAUX2 = $030B
+DSKINV = $E453
+        lda #<$0800
+        sta DBUFLO
+        lda #>$0800
+        sta DBUFLO+1
+        lda #<1000
+        sta DAUX1
+        lda #>1000
+        sta DAUX2
+        jsr DSKINV
+        sty $0601
+";
+    let mut atr = Atr::blank(Density::Single);
+    atr.write_sector(1, &boot_sector(body));
+    let mut atari800 = boot(DiskDrive::new(atr));
+
+    assert_eq!(atari800.read(0x0601), 0x8B, "the drive NAKs the command");
+}
+
+#[test]
+fn test_write_back_failure_fails_the_command() {
+    let dir = TempDir::new("write-back");
+    let path = dir.join("DISK.ATR");
+    Atr::blank(Density::Single).save(&path).unwrap();
+    let mut drive = DiskDrive::open(&path).unwrap();
+
+    // A directory where the file was can't be written
+    std::fs::remove_file(&path).unwrap();
+    std::fs::create_dir(&path).unwrap();
+
+    assert!(matches!(drive.command(b'W', 10, 0), SioResponse::Receive(128)));
+    assert!(matches!(drive.data(&[0x55; 128]), SioResponse::Error(_)));
+    assert!(drive.take_write_error().is_some());
+    assert!(drive.take_write_error().is_none());
+    match drive.command(b'S', 0, 0) {
+        SioResponse::Complete(status) => assert_eq!(status[0] & 0x04, 0x04, "command failed"),
+        _ => panic!("status refused"),
+    }
+    assert!(matches!(drive.command(b'!', 0, 0), SioResponse::Error(_)));
+    assert!(drive.take_write_error().is_some());
+}
+
+#[test]
+fn test_sio_patch_serves_siov_directly() {
+    let mut atr = Atr::blank(Density::Single);
+    atr.write_sector(1, &boot_sector("        lda #$A5\n        sta $0601\n"));
+
+    let mut serial = Atari800::new();
+    assert!(serial.mount_disk(1, DiskDrive::new(atr.clone())).is_ok());
+    let serial_frames = frames_to_boot(&mut serial);
+
+    let mut patched = Atari800::new();
+    patched.set_sio_patch(true);
+    assert!(patched.sio_patch());
+    assert!(patched.mount_disk(1, DiskDrive::new(atr)).is_ok());
+    let patched_frames = frames_to_boot(&mut patched);
+
+    assert!(patched_frames < serial_frames, "{} frames patched, {} not", patched_frames, serial_frames);
+    assert_eq!(patched.read(0x0600), 1);
+}
+
+#[test]
+fn test_sio_patch_writes_and_reads_status() {
+    let body = "
+DCOMND = $0302
+DBUFLO = $0304
+DAUX1 = $030A
+DAUX2 = $030B
+DSKINV = $E453
+        lda #'P'
+        sta DCOMND
+        lda #7
+        sta DAUX1
+        lda #0
+        sta DAUX2
+        jsr DSKINV
+        sty $0602
+        lda #'S'
+        sta DCOMND
+        lda #<$0610
+        sta DBUFLO
+        lda #>$0610
+        sta DBUFLO+1
+        jsr DSKINV
+        sty $0603
+        lda #2
+        sta $0301
+        jsr DSKINV
+        sty $0604
+        lda #1
+        sta $0601
+";
+    // The boot loader leaves DBUF pointing at the loaded sector
+    let sector = boot_sector(body);
+    let mut atr = Atr::blank(Density::Single);
+    atr.write_sector(1, &sector);
+
+    let dir = TempDir::new("fast-sio");
+    let path = dir.join("DISK.ATR");
+    atr.save(&path).unwrap();
+    let mut atari800 = Atari800::new();
+    atari800.set_sio_patch(true);
+    assert!(atari800.mount_disk(1, DiskDrive::open(&path).unwrap()).is_ok());
+    frames_to_boot(&mut atari800);
+    let saved = Atr::from_file(&path);
+
+    assert_eq!(atari800.read(0x0602), 0x01, "put succeeds");
+    assert_eq!(atari800.read(0x0603), 0x01, "status succeeds");
+    assert_eq!(atari800.read(0x0610), 0x10, "motor on, single density");
+    assert_eq!(atari800.read(0x0604), 0x8A, "nothing answers as D2:");
+    assert_eq!(saved.unwrap().read_sector(7).unwrap(), &sector[..]);
+}
+
+// Disk whose boot code reads sector 4 of D1: into $0680 each time $06F0 is
+// set, leaving the status in $06F1. Sector 4 is filled with `fill`.
+fn reader_disk(fill: u8) -> DiskDrive {
+    let body = "
+DCOMND = $0302
+DBUFLO = $0304
+DAUX1 = $030A
+DAUX2 = $030B
+DSKINV = $E453
+wait:   lda $06F0
+        beq wait
+        lda #'R'
+        sta DCOMND
+        lda #<$0680
+        sta DBUFLO
+        lda #>$0680
+        sta DBUFLO+1
+        lda #4
+        sta DAUX1
+        lda #0
+        sta DAUX2
+        jsr DSKINV
+        sty $06F1
+        lda #0
+        sta $06F0
+        beq wait
+";
+    let mut atr = Atr::blank(Density::Single);
+    atr.write_sector(1, &boot_sector(body));
+    atr.write_sector(4, &[fill; 128]);
+    DiskDrive::new(atr)
+}
+
+// Have the boot code read sector 4 of D1: and give its first byte
+fn read_d1(atari800: &mut Atari800) -> u8 {
+    atari800.write(0x06F0, 1);
+    for _ in 0..30 {
+        atari800.run_frame();
+        if atari800.read(0x06F0) == 0 {
+            assert_eq!(atari800.read(0x06F1), 0x01, "read succeeds");
+            return atari800.read(0x0680);
+        }
+    }
+    panic!("sector never read");
+}
+
+#[test]
+fn test_disks_swap_while_running() {
+    let mut atari800 = Atari800::new();
+    assert!(atari800.mount_disk(1, reader_disk(0xAA)).is_ok());
+    assert!(atari800.mount_disk(2, reader_disk(0xBB)).is_ok());
+    for _ in 0..30 {
+        atari800.run_frame();
+    }
+    assert_eq!(read_d1(&mut atari800), 0xAA);
+
+    assert!(atari800.swap_disks(1, 2).is_ok());
+    assert_eq!(read_d1(&mut atari800), 0xBB);
+    assert_eq!(atari800.disk(2).unwrap().image().read_sector(4).unwrap()[0], 0xAA);
+
+    // Into an empty drive and back
+    assert!(atari800.swap_disks(1, 3).is_ok());
+    assert!(atari800.disk(1).is_none());
+    assert!(atari800.swap_disks(3, 1).is_ok());
+
+    let removed = atari800.unmount_disk(1).expect("a disk in D1:");
+    assert_eq!(removed.image().read_sector(4).unwrap()[0], 0xBB);
+    assert!(atari800.unmount_disk(1).is_none());
+    assert!(atari800.mount_disk(1, reader_disk(0xCC)).is_ok());
+    assert_eq!(read_d1(&mut atari800), 0xCC);
+
+    assert_eq!(atari800.swap_disks(1, 9), Err(9));
+    assert_eq!(read_d1(&mut atari800), 0xCC, "nothing moved");
+}
+
+#[test]
+fn test_write_protect_tab() {
+    let mut drive = DiskDrive::new(Atr::blank(Density::Single));
+    assert!(!drive.write_protected());
+    drive.set_write_protected(true);
+    match drive.command(b'S', 0, 0) {
+        SioResponse::Complete(status) => assert_eq!(status[0] & 0x08, 0x08),
+        _ => panic!("no status"),
+    }
+    assert!(matches!(drive.command(b'W', 5, 0), SioResponse::Receive(128)));
+    assert!(matches!(drive.data(&[0x55; 128]), SioResponse::Error(_)));
+
+    drive.set_write_protected(false);
+    assert!(matches!(drive.command(b'W', 5, 0), SioResponse::Receive(128)));
+    assert!(matches!(drive.data(&[0x55; 128]), SioResponse::Complete(_)));
+    assert_eq!(drive.image().read_sector(5).unwrap(), &[0x55; 128][..]);
+
+    // Copy-protected images can't be unprotected
+    let mut atx = DiskDrive::from_atx(Atx::from_image(&Atr::blank(Density::Single)));
+    atx.set_write_protected(false);
+    assert!(atx.write_protected());
+}
+
+#[test]
+fn test_create_blank_disks() {
+    let dir = TempDir::new("new");
+    for (density, size) in [
+        (Density::Single, 720 * 128),
+        (Density::Enhanced, 1040 * 128),
+        (Density::Double, 3 * 128 + 717 * 256),
+    ] {
+        let path = dir.join(format!("{:?}.ATR", density));
+        let mut drive = DiskDrive::create(&path, density).unwrap();
+        assert_eq!(drive.path(), Some(path.as_path()));
+        let sector_size = drive.image().sector_size(4);
+        assert!(matches!(drive.command(b'W', 4, 0), SioResponse::Receive(n) if n == sector_size));
+        drive.data(&vec![0x77; sector_size]);
+        let saved = std::fs::read(&path).unwrap();
+        assert_eq!(saved.len(), 16 + size, "{:?}", density);
+        let atr = Atr::parse(&saved).unwrap();
+        assert_eq!(atr.density(), density);
+        assert_eq!(atr.read_sector(4).unwrap(), &vec![0x77; sector_size][..]);
+
+        // Creating it again leaves it as it is
+        match DiskDrive::create(&path, Density::Single) {
+            Err(DiskError::Atr(AtrError::Io(e))) => assert_eq!(e.kind(), std::io::ErrorKind::AlreadyExists),
+            _ => panic!("{:?} disk replaced", density),
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), saved);
+    }
+}
+
+#[test]
+fn test_no_such_drive() {
+    let mut atari800 = Atari800::new();
+    let drive = atari800.mount_disk(9, reader_disk(0xAA)).expect_err("no D9:");
+    assert!(atari800.mount_disk(0, drive).is_err());
+    assert!(atari800.unmount_disk(9).is_none());
+    assert!(atari800.disk(0).is_none());
+}
@@ -0,0 +1,222 @@
+use atari800_rs::atr::{Atr, Density};
+use atari800_rs::atx::Atx;
+use atari800_rs::dcm::{Dcm, DcmError};
+use atari800_rs::disk::DiskDrive;
+use atari800_rs::disk_image::{convert, parse_image, DiskImage, ImageFormat};
+use atari800_rs::pro::{Pro, ProError};
+use atari800_rs::sio::{SioDevice, SioResponse};
+use atari800_rs::xfd::{Xfd, XfdError};
+
+mod common;
+use common::TempDir;
+
+// A disk with something different in every sector, and some left empty
+fn patterned(density: Density) -> Atr {
+    let mut atr = Atr::blank(density);
+    let mut seed = 0x1234_5678u32;
+    for sector in (1..=atr.sector_count()).filter(|n| n % 7 != 0) {
+        let data: Vec<u8> = (0..atr.sector_size(sector))
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                // Long runs as well as noise, so every DCM block type is used
+                if i % 64 < 40 { sector as u8 } else { (seed >> 16) as u8 }
+            })
+            .collect();
+        atr.write_sector(sector, &data);
+    }
+    atr
+}
+
+// PRO file: sector 5 has a CRC error and two phantom copies
+fn pro_file() -> Vec<u8> {
+    let mut file = vec![0; 16];
+    file[0..2].copy_from_slice(&722u16.to_be_bytes());
+    file[2..4].copy_from_slice(b"P2");
+    for record in 1..=722 {
+        let mut header = [0x10, 0xFF, 0, 0xE0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let fill = match record {
+            5 => {
+                header[1] = !0x08;
+                header[5] = 2;
+                header[6] = 1;
+                header[7] = 2;
+                0xA5
+            }
+            721 => 0xB1,
+            722 => 0xB2,
+            _ => record as u8,
+        };
+        file.extend_from_slice(&header);
+        file.extend_from_slice(&[fill; 128]);
+    }
+    file
+}
+
+fn read(drive: &mut DiskDrive, sector: u16) -> SioResponse {
+    let [aux1, aux2] = sector.to_le_bytes();
+    drive.command(b'R', aux1, aux2)
+}
+
+#[test]
+fn test_xfd_sizes() {
+    let single = Xfd::parse(&[0; 720 * 128]).unwrap();
+    assert_eq!(single.density(), Density::Single);
+    let enhanced = Xfd::parse(&[0; 1040 * 128]).unwrap();
+    assert_eq!(enhanced.density(), Density::Enhanced);
+
+    for size in [3 * 128 + 717 * 256, 720 * 256] {
+        let mut image = vec![0; size];
+        image[0] = 0x11;
+        let double = Xfd::parse(&image).unwrap();
+        assert_eq!(double.density(), Density::Double);
+        assert_eq!(double.sector_count(), 720);
+        assert_eq!(double.read_sector(1).unwrap()[0], 0x11);
+        assert_eq!(double.sector_size(4), 256);
+        assert_eq!(double.to_bytes(), image);
+    }
+
+    assert!(matches!(Xfd::parse(&[0; 1000]), Err(XfdError::BadSize(1000))));
+}
+
+#[test]
+fn test_dcm_block_types() {
+    let mut file = vec![0xFA, 0x81, 0x01, 0x00];
+    // Sector 1 whole, 2 the same
+    file.push(0xC7);
+    file.extend(0..128u8);
+    file.push(0xC6);
+    // Sector 3: change the first three bytes, then on to sector 10
+    file.extend_from_slice(&[0x41, 2, 0xCC, 0xBB, 0xAA, 10, 0]);
+    // Sector 10: change the last two bytes
+    file.extend_from_slice(&[0xC4, 126, 0xEE, 0xFF]);
+    // Sector 11: DOS sector
+    file.extend_from_slice(&[0xC2, 0x20, 1, 2, 3, 4, 5]);
+    // Sector 12: compressed
+    file.extend_from_slice(&[0xC3, 3, 9, 8, 7, 100, 0x55, 128]);
+    file.extend_from_slice(&[0x77; 28]);
+    file.push(0x45);
+
+    let dcm = Dcm::parse(&file).unwrap();
+    let counting: Vec<u8> = (0..128).collect();
+    assert_eq!(dcm.read_sector(1).unwrap(), &counting[..]);
+    assert_eq!(dcm.read_sector(2).unwrap(), &counting[..]);
+    assert_eq!(dcm.read_sector(3).unwrap()[..4], [0xAA, 0xBB, 0xCC, 3]);
+    assert_eq!(dcm.read_sector(3).unwrap()[4..], counting[4..]);
+    assert_eq!(dcm.read_sector(4).unwrap(), &[0; 128][..]);
+    assert_eq!(dcm.read_sector(10).unwrap()[124..], [124, 125, 0xEE, 0xFF]);
+    assert_eq!(dcm.read_sector(11).unwrap()[120..], [0x20, 0x20, 0x20, 1, 2, 3, 4, 5]);
+    let compressed = dcm.read_sector(12).unwrap();
+    assert_eq!(compressed[..4], [9, 8, 7, 0x55]);
+    assert_eq!(compressed[99..101], [0x55, 0x77]);
+    assert_eq!(compressed[127], 0x77);
+}
+
+#[test]
+fn test_dcm_passes_and_errors() {
+    let mut file = vec![0xFA, 0x01, 0x01, 0x00, 0xC7];
+    file.extend_from_slice(&[0x11; 128]);
+    file.extend_from_slice(&[0x45, 0xFA, 0x82, 0x05, 0x00, 0xC7]);
+    file.extend_from_slice(&[0x55; 128]);
+    file.push(0x45);
+    let dcm = Dcm::parse(&file).unwrap();
+    assert_eq!(dcm.read_sector(1).unwrap(), &[0x11; 128][..]);
+    assert_eq!(dcm.read_sector(5).unwrap(), &[0x55; 128][..]);
+
+    assert!(matches!(Dcm::parse(&[0x00, 0x81, 1, 0]), Err(DcmError::NotDcm)));
+    assert!(matches!(Dcm::parse(&file[..100]), Err(DcmError::Truncated { .. })));
+    assert!(matches!(
+        Dcm::parse(&[0xFA, 0x81, 1, 0, 0x48, 0x45]),
+        Err(DcmError::BadBlock { offset: 4, kind: 0x48 })
+    ));
+}
+
+#[test]
+fn test_dcm_round_trip() {
+    for density in [Density::Single, Density::Enhanced, Density::Double] {
+        let dcm = Dcm::from_image(&patterned(density));
+        let file = dcm.to_bytes();
+        assert!(file.len() < Atr::blank(density).to_bytes().len() / 2, "{:?} compresses", density);
+        assert_eq!(Dcm::parse(&file).unwrap(), dcm, "{:?}", density);
+    }
+}
+
+#[test]
+fn test_pro_status_and_phantoms() {
+    let pro = Pro::parse(&pro_file()).unwrap();
+    assert_eq!(pro.sector_count(), 720);
+    assert_eq!(pro.density(), Density::Single);
+    assert_eq!(pro.sector_status(5), 0x08);
+    assert_eq!(pro.sector_status(6), 0x00);
+    assert_eq!(pro.copies(5).unwrap().len(), 3);
+    assert_eq!(Pro::parse(&pro.to_bytes()).unwrap(), pro);
+
+    // The drive goes through the copies in turn
+    let mut drive = DiskDrive::with_image(Box::new(pro));
+    let mut fills = Vec::new();
+    for _ in 0..4 {
+        match read(&mut drive, 5) {
+            SioResponse::Complete(data) => fills.push((true, data[0])),
+            SioResponse::Error(data) => fills.push((false, data[0])),
+            _ => panic!("no data"),
+        }
+    }
+    assert_eq!(fills, [(false, 0xA5), (true, 0xB1), (true, 0xB2), (false, 0xA5)]);
+
+    let mut short = pro_file();
+    short.truncate(16 + 100 * 140);
+    assert!(matches!(Pro::parse(&short), Err(ProError::Truncated { expected: 722, actual: 100 })));
+}
+
+#[test]
+fn test_conversion_keeps_sectors() {
+    for density in [Density::Single, Density::Enhanced, Density::Double] {
+        let atr = patterned(density);
+        for format in [ImageFormat::Xfd, ImageFormat::Dcm, ImageFormat::Pro, ImageFormat::Atx] {
+            let converted = match convert(&atr, format) {
+                Some(converted) => converted,
+                None => {
+                    assert_eq!((density, format), (Density::Double, ImageFormat::Pro));
+                    continue;
+                }
+            };
+            assert_eq!(converted.image_format(), format);
+            assert_eq!(converted.density(), density);
+            let reparsed = parse_image(&converted.to_bytes(), Some(format)).unwrap();
+            assert_eq!(reparsed.image_format(), format);
+            assert_eq!(Atr::from_image(reparsed.as_ref()), atr, "{:?} {:?}", density, format);
+        }
+    }
+}
+
+#[test]
+fn test_conversion_keeps_status_and_copies() {
+    let pro = Pro::parse(&pro_file()).unwrap();
+    let atx = Atx::from_image(&pro);
+    let copies = atx.copies(5).unwrap();
+    assert_eq!(copies.iter().map(|c| (c.status, c.data[0])).collect::<Vec<_>>(), [
+        (0x08, 0xA5),
+        (0x00, 0xB1),
+        (0x00, 0xB2)
+    ]);
+    assert!(copies.iter().all(|c| c.position.is_some()));
+
+    let back = convert(&Atx::parse(&atx.to_bytes()).unwrap(), ImageFormat::Pro).unwrap();
+    assert_eq!(back.sector_status(5), 0x08);
+    assert_eq!(back.copies(5).unwrap().len(), 3);
+    assert_eq!(back.read_sector(6).unwrap(), &[6; 128][..]);
+}
+
+#[test]
+fn test_drive_writes_back_in_the_file_format() {
+    let dir = TempDir::new("xfd");
+    let path = dir.join("DISK.XFD");
+    Xfd::blank(Density::Single).save(&path).unwrap();
+    let mut drive = DiskDrive::open(&path).unwrap();
+    assert_eq!(drive.image().image_format(), ImageFormat::Xfd);
+
+    assert!(matches!(drive.command(b'W', 9, 0), SioResponse::Receive(128)));
+    assert!(matches!(drive.data(&[0x99; 128]), SioResponse::Complete(_)));
+    let saved = std::fs::read(&path).unwrap();
+    assert_eq!(saved.len(), 720 * 128);
+    assert_eq!(Xfd::parse(&saved).unwrap().read_sector(9).unwrap(), &[0x99; 128][..]);
+}
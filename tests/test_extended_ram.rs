@@ -0,0 +1,77 @@
+use atari800_rs::atari800::{Atari800, Atari800Config, ExtendedRam, Model};
+use atari800_rs::bus::Bus;
+use atari800_rs::mem::{Mem, Page};
+
+fn write_portb(atari800: &mut Atari800, val: u8) {
+    atari800.write(0xD303, 0x38);   // PBCTL: select DDRB
+    atari800.write(0xD301, 0xFF);   // All outputs
+    atari800.write(0xD303, 0x3C);   // PBCTL: select PORTB
+    atari800.write(0xD301, val);
+}
+
+#[test]
+fn test_130xe_banks() {
+    let mut atari800 = Atari800::with_config(Atari800Config::for_model(Model::Atari130Xe)).unwrap();
+    atari800.write(0x4000, 0xEE);
+
+    // Tag each bank with its number through the CPU window
+    for bank in 0..4u8 {
+        write_portb(&mut atari800, 0xE3 | (bank << 2));
+        atari800.write(0x4000, bank);
+    }
+    for bank in 0..4u8 {
+        write_portb(&mut atari800, 0xE3 | (bank << 2));
+        assert_eq!(atari800.read(0x4000), bank);
+    }
+
+    // Bit 4 high puts main memory back
+    write_portb(&mut atari800, 0xFF);
+    assert_eq!(atari800.read(0x4000), 0xEE);
+}
+
+#[test]
+fn test_extended_ram_window_is_16k() {
+    let mut atari800 = Atari800::with_config(Atari800Config::for_model(Model::Atari130Xe)).unwrap();
+    atari800.write(0x3FFF, 0x11);
+    atari800.write(0x8000, 0x22);
+
+    write_portb(&mut atari800, 0xE3);
+    atari800.write(0x3FFF, 0x33);
+    atari800.write(0x8000, 0x44);
+
+    write_portb(&mut atari800, 0xFF);
+    assert_eq!(atari800.read(0x3FFF), 0x33);
+    assert_eq!(atari800.read(0x8000), 0x44);
+}
+
+#[test]
+fn test_rambo_uses_bit_5_for_banking() {
+    let mut atari800 = Atari800::with_config(Atari800Config {
+        extended_ram: ExtendedRam::Rambo320,
+        ..Atari800Config::for_model(Model::Atari800Xl)
+    }).unwrap();
+
+    write_portb(&mut atari800, 0xE3);
+    atari800.write(0x4000, 0x01);
+    write_portb(&mut atari800, 0xC3);
+    atari800.write(0x4000, 0x02);
+
+    write_portb(&mut atari800, 0xE3);
+    assert_eq!(atari800.read(0x4000), 0x01);
+}
+
+#[test]
+fn test_separate_antic_view() {
+    let mut mem = Mem::blank(0);
+    mem.ram.resize(0x20000, 0);
+    mem.ram[0x4000] = 0x11;
+    mem.ram[0x10000] = 0x22;
+
+    // CPU in extended RAM, ANTIC still on main memory
+    mem.map_cpu(0x4000, 0x7FFF, Page::Ram(0x10000));
+    assert_eq!(mem.get_byte(0x4000), 0x22);
+    assert_eq!(mem.antic_read(0x4000), 0x11);
+
+    mem.map_antic(0x4000, 0x7FFF, Page::Ram(0x10000));
+    assert_eq!(mem.antic_read(0x4000), 0x22);
+}
@@ -0,0 +1,281 @@
+use std::fs;
+use std::path::Path;
+
+use atari800_rs::asm;
+use atari800_rs::atari800::Atari800;
+use atari800_rs::bus::Bus;
+use atari800_rs::host::{
+    HostDevice, STATUS_BAD_NAME, STATUS_END_OF_FILE, STATUS_FILE_EXISTS, STATUS_NOT_FOUND,
+    STATUS_NO_DEVICE, STATUS_OUTPUT_NOT_ALLOWED, STATUS_SUCCESS,
+};
+use atari800_rs::xex::{Segment, Xex, RUNAD};
+
+mod common;
+use common::TempDir;
+
+const EOL: u8 = 0x9B;
+
+fn spec(name: &str) -> Vec<u8> {
+    let mut spec = name.as_bytes().to_vec();
+    spec.push(EOL);
+    spec
+}
+
+fn read_all(device: &mut HostDevice, channel: usize) -> (Vec<u8>, u8) {
+    let mut data = Vec::new();
+    loop {
+        match device.get(channel) {
+            (STATUS_SUCCESS, byte) => data.push(byte),
+            (status, _) => return (data, status),
+        }
+    }
+}
+
+// CIO call on an IOCB: `buffer` is a label, `len` the buffer length
+fn cio(iocb: u8, command: u8, buffer: &str, len: u16, aux1: u8, result: u16) -> String {
+    format!(
+        "
+        ldx #${:02X}
+        lda #{}
+        sta ICCOM,x
+        lda #<{}
+        sta ICBAL,x
+        lda #>{}
+        sta ICBAL+1,x
+        lda #<{}
+        sta ICBLL,x
+        lda #>{}
+        sta ICBLL+1,x
+        lda #{}
+        sta ICAX1,x
+        jsr CIOV
+        sty ${:04X}
+",
+        iocb * 16,
+        command,
+        buffer,
+        buffer,
+        len,
+        len,
+        aux1,
+        result
+    )
+}
+
+// Load CIO calls as a binary load, with H: on `dir`
+fn start(dir: &Path, calls: &[String], data: &str) -> Atari800 {
+    let source = format!(
+        "
+CIOV = $E456
+ICCOM = $0342
+ICBAL = $0344
+ICBLL = $0348
+ICAX1 = $034A
+        .org $2000
+{}
+        lda #1
+        sta $06FF
+done:   jmp done
+{}
+",
+        calls.concat(),
+        data
+    );
+    let code = asm::assemble(&source, &[]).unwrap();
+    let xex = Xex {
+        segments: vec![
+            Segment { start: 0x2000, data: code.image(0x2000, 0x400) },
+            Segment { start: RUNAD, data: vec![0x00, 0x20] },
+        ],
+    };
+
+    let mut atari800 = Atari800::new();
+    atari800.set_host_dir(Some(dir.to_path_buf()));
+    atari800.load_xex(xex);
+    atari800
+}
+
+// Run frames until the program stores 1 at `flag`
+fn run_until(atari800: &mut Atari800, flag: u16) {
+    for _ in 0..120 {
+        atari800.run_frame();
+        if atari800.read(flag) == 1 {
+            return;
+        }
+    }
+    panic!("CIO calls never finished");
+}
+
+// Run CIO calls from a binary load, with H: on `dir`, until they are done
+fn run(dir: &Path, calls: &[String], data: &str) -> Atari800 {
+    let mut atari800 = start(dir, calls, data);
+    run_until(&mut atari800, 0x06FF);
+    atari800
+}
+
+#[test]
+fn test_write_and_read_back() {
+    let dir = TempDir::new("host-files");
+    let mut device = HostDevice::new(&*dir);
+
+    assert_eq!(device.open(1, &spec("H:HELLO.TXT"), 8), STATUS_SUCCESS);
+    for &byte in b"HI THERE" {
+        assert_eq!(device.put(1, byte), STATUS_SUCCESS);
+    }
+    assert_eq!(device.get(1).0, 0x83, "write only");
+    assert_eq!(device.close(1), STATUS_SUCCESS);
+    assert_eq!(fs::read(dir.join("HELLO.TXT")).unwrap(), b"HI THERE");
+
+    assert_eq!(device.open(1, &spec("H:HELLO.TXT"), 9), STATUS_SUCCESS);
+    assert_eq!(device.put(1, b'!'), STATUS_SUCCESS);
+    device.close(1);
+
+    // Names are matched whatever their case on the host
+    assert_eq!(device.open(2, &spec("H1:hello.txt"), 4), STATUS_SUCCESS);
+    assert_eq!(device.put(2, b'X'), STATUS_OUTPUT_NOT_ALLOWED);
+    assert_eq!(device.status(2), STATUS_SUCCESS);
+    assert_eq!(read_all(&mut device, 2), (b"HI THERE!".to_vec(), STATUS_END_OF_FILE));
+    assert_eq!(device.status(2), STATUS_END_OF_FILE);
+    device.close(2);
+}
+
+#[test]
+fn test_names_and_errors() {
+    let dir = TempDir::new("host-names");
+    fs::write(dir.join("game.xex"), [0xFF, 0xFF]).unwrap();
+    fs::write(dir.join("much-too-long-a-name.txt"), b"").unwrap();
+    let mut device = HostDevice::new(&*dir);
+
+    assert_eq!(device.open(1, &spec("H:MISSING"), 4), STATUS_NOT_FOUND);
+    assert_eq!(device.open(1, &spec("H:../ESCAPE"), 8), STATUS_BAD_NAME);
+    assert_eq!(device.open(1, &spec("H:TOOLONGNAME"), 8), STATUS_BAD_NAME);
+    assert_eq!(device.open(1, &spec("H:*.TXT"), 8), STATUS_BAD_NAME, "no wildcards creating a file");
+    assert_eq!(device.open(1, &spec("NAME"), 4), STATUS_BAD_NAME, "no device");
+
+    // A pattern opens the first match
+    assert_eq!(device.open(1, &spec("H:G*.*"), 4), STATUS_SUCCESS);
+    assert_eq!(read_all(&mut device, 1).0, [0xFF, 0xFF]);
+    device.close(1);
+}
+
+#[test]
+fn test_directory_listing() {
+    let dir = TempDir::new("host-listing");
+    fs::write(dir.join("BIG.DAT"), vec![0; 300]).unwrap();
+    fs::write(dir.join("autorun.sys"), vec![0; 10]).unwrap();
+    fs::write(dir.join("NOTES"), b"").unwrap();
+    fs::create_dir(dir.join("SUBDIR")).unwrap();
+    let mut device = HostDevice::new(&*dir);
+
+    assert_eq!(device.open(1, &spec("H:"), 6), STATUS_SUCCESS);
+    let (listing, status) = read_all(&mut device, 1);
+    assert_eq!(status, STATUS_END_OF_FILE);
+    let lines: Vec<&[u8]> = listing.split(|&c| c == EOL).collect();
+    assert_eq!(lines, [
+        &b"  AUTORUN SYS 001"[..],
+        b"  BIG     DAT 003",
+        b"  NOTES       000",
+        b"999 FREE SECTORS",
+        b""
+    ]);
+
+    assert_eq!(device.open(1, &spec("H:*.DAT"), 6), STATUS_SUCCESS);
+    let (listing, _) = read_all(&mut device, 1);
+    assert!(listing.starts_with(b"  BIG     DAT 003\x9B999 FREE"));
+}
+
+#[test]
+fn test_rename_delete_and_lock() {
+    let dir = TempDir::new("host-xio");
+    for name in ["ONE.TXT", "TWO.TXT", "KEEP.BAS"] {
+        fs::write(dir.join(name), name).unwrap();
+    }
+    let mut device = HostDevice::new(&*dir);
+
+    assert_eq!(device.special(32, &spec("H:ONE.TXT,FIRST.TXT")), STATUS_SUCCESS);
+    assert_eq!(fs::read(dir.join("FIRST.TXT")).unwrap(), b"ONE.TXT");
+    assert!(!dir.join("ONE.TXT").exists());
+    assert_eq!(device.special(32, &spec("H:FIRST.TXT,TWO.TXT")), STATUS_FILE_EXISTS);
+    assert_eq!(device.special(32, &spec("H:*.TXT,*.DOC")), STATUS_SUCCESS);
+    assert!(dir.join("FIRST.DOC").exists() && dir.join("TWO.DOC").exists());
+
+    assert_eq!(device.special(35, &spec("H:KEEP.BAS")), STATUS_SUCCESS);
+    assert_eq!(device.open(1, &spec("H:"), 6), STATUS_SUCCESS);
+    let (listing, _) = read_all(&mut device, 1);
+    assert!(listing.windows(11).any(|w| w == b"* KEEP    B"));
+    assert_eq!(device.special(36, &spec("H:KEEP.BAS")), STATUS_SUCCESS);
+
+    assert_eq!(device.special(33, &spec("H:*.DOC")), STATUS_SUCCESS);
+    assert_eq!(device.special(33, &spec("H:*.DOC")), STATUS_NOT_FOUND);
+    assert!(dir.join("KEEP.BAS").exists());
+    assert_eq!(device.special(32, &spec("H:KEEP.BAS")), STATUS_BAD_NAME);
+}
+
+#[test]
+fn test_programs_use_h_through_cio() {
+    let dir = TempDir::new("host-cio");
+    fs::write(dir.join("IN.DAT"), b"0123456789").unwrap();
+    fs::write(dir.join("OLD.TMP"), b"").unwrap();
+
+    let calls = [
+        cio(1, 3, "out_name", 0, 8, 0x0600),
+        cio(1, 9, "text", 64, 8, 0x0601),
+        cio(1, 12, "out_name", 0, 8, 0x0602),
+        cio(2, 3, "in_name", 0, 4, 0x0603),
+        cio(2, 7, "buffer", 16, 4, 0x0604),
+        "        lda ICBLL,x\n        sta $0605\n".to_string(),
+        cio(2, 12, "in_name", 0, 4, 0x0606),
+        // XIO on a closed IOCB
+        cio(3, 33, "old_name", 0, 0, 0x0607),
+        cio(3, 3, "old_name", 0, 4, 0x0608),
+    ];
+    let data = "
+out_name: .byte \"H:OUT.TXT\", $9B
+in_name:  .byte \"H:IN.DAT\", $9B
+old_name: .byte \"H:OLD.TMP\", $9B
+text:     .byte \"HELLO\", $9B
+buffer:   .byte 0
+";
+    let mut atari800 = run(&dir, &calls, data);
+
+    assert_eq!(atari800.read(0x0600), STATUS_SUCCESS, "open for writing");
+    assert_eq!(atari800.read(0x0601), STATUS_SUCCESS, "put record");
+    assert_eq!(atari800.read(0x0602), STATUS_SUCCESS, "close");
+    assert_eq!(fs::read(dir.join("OUT.TXT")).unwrap(), b"HELLO\x9B");
+
+    assert_eq!(atari800.read(0x0603), STATUS_SUCCESS, "open for reading");
+    assert_eq!(atari800.read(0x0604), STATUS_END_OF_FILE, "get characters runs out");
+    assert_eq!(atari800.read(0x0605), 10);
+    assert_eq!(atari800.read(0x0606), STATUS_SUCCESS);
+
+    assert_eq!(atari800.read(0x0607), STATUS_SUCCESS, "delete");
+    assert!(!dir.join("OLD.TMP").exists());
+    assert_eq!(atari800.read(0x0608), STATUS_NOT_FOUND);
+}
+
+#[test]
+fn test_switching_h_off_fails_calls() {
+    let dir = TempDir::new("host-off");
+
+    let calls = [
+        cio(1, 3, "out_name", 0, 8, 0x0600),
+        // Wait here while H: is switched off
+        "        lda #1\n        sta $06FD\nwait:   lda $06FE\n        beq wait\n".to_string(),
+        cio(1, 11, "text", 0, 0, 0x0601),
+        cio(2, 3, "out_name", 0, 8, 0x0602),
+    ];
+    let data = "
+out_name: .byte \"H:OUT.TXT\", $9B
+text:     .byte 0
+";
+    let mut atari800 = start(&dir, &calls, data);
+    run_until(&mut atari800, 0x06FD);
+    assert_eq!(atari800.read(0x0600), STATUS_SUCCESS);
+
+    atari800.set_host_dir(None);
+    atari800.write(0x06FE, 1);
+    run_until(&mut atari800, 0x06FF);
+
+    assert_eq!(atari800.read(0x0601), STATUS_NO_DEVICE, "put on a channel already open");
+    assert_eq!(atari800.read(0x0602), STATUS_NO_DEVICE, "open");
+}
@@ -0,0 +1,167 @@
+use atari800_rs::atari800::{Atari800, Atari800Config};
+use atari800_rs::bus::Bus;
+use atari800_rs::cpu::Cpu;
+use atari800_rs::mem::{LoadError, Mem, Page, RamFill, Region};
+
+mod common;
+use common::TempDir;
+
+// Test bus exposing a Mem with a custom page map
+struct TestBus {
+    mem: Mem,
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.mem.get_byte(addr)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.mem.set_byte(addr, val);
+    }
+}
+
+fn run(cpu: &mut Cpu, bus: &mut TestBus, code: &[u8]) {
+    bus.mem.ram[0x0800..(0x0800 + code.len())].copy_from_slice(code);
+    bus.mem.ram[0x0800 + code.len()] = 0x00;
+    cpu.pc = 0x0800;
+    cpu.cycles_remaining = 0;
+
+    for _ in 0..100 {
+        cpu.tick(bus);
+        if cpu.cycles_remaining == 0 && bus.read(cpu.pc) == 0 {
+            return;
+        }
+    }
+}
+
+#[test]
+fn test_rom_writes_are_ignored() {
+    let mut mem = Mem::blank(0xC000);
+    mem.load(Region::Rom, 0xC000, &[0x12]).unwrap();
+
+    mem.set_byte(0xC000, 0x34);
+    assert_eq!(mem.get_byte(0xC000), 0x12);
+}
+
+#[test]
+fn test_unmapped_read_returns_floating_bus() {
+    let mut bus = TestBus { mem: Mem::blank(0) };
+    bus.mem.map(0x2000, 0xBFFF, Page::Unmapped);
+    let mut cpu = Cpu::new();
+
+    // A RAM probe at $4000 sees the high byte of the LDA operand, not the
+    // value it just stored
+    let code: [u8; 8] = [
+        0xA9, 0x55,         // LDA #$55
+        0x8D, 0x00, 0x40,   // STA $4000
+        0xAD, 0x00, 0x40,   // LDA $4000
+    ];
+    run(&mut cpu, &mut bus, &code);
+    assert_eq!(cpu.a, 0x40);
+}
+
+#[test]
+fn test_atari800_ram_size() {
+    let mut atari800 = Atari800::with_config(Atari800Config {
+        ram_size_kb: 16,
+        ..Atari800Config::default()
+    }).unwrap();
+
+    // Last byte of installed RAM is writable
+    atari800.write(0x3FFF, 0xA5);
+    assert_eq!(atari800.read(0x3FFF), 0xA5);
+
+    // Above it nothing answers, so the last bus value comes back
+    atari800.write(0x3FFF, 0x5A);
+    atari800.write(0x4000, 0xC3);
+    atari800.read(0x3FFF);
+    assert_eq!(atari800.read(0x4000), 0x5A);
+
+    // The $C000-$CFFF hole floats the same way
+    atari800.read(0x3FFF);
+    assert_eq!(atari800.read(0xC800), 0x5A);
+}
+
+#[test]
+fn test_load_into_ram_and_rom() {
+    let mut mem = Mem::blank(0xC000);
+    mem.load(Region::Ram, 0x2000, &[1, 2, 3]).unwrap();
+    mem.load(Region::Rom, 0xFFFE, &[0x00, 0xE0]).unwrap();
+
+    assert_eq!(mem.get_byte(0x2001), 2);
+    assert_eq!(mem.get_word(0xFFFE), 0xE000);
+
+    // Loading into a store only shows where that store is mapped
+    mem.load(Region::Ram, 0xD000, &[0x55]).unwrap();
+    assert_eq!(mem.get_byte(0xD000), 0x00);
+}
+
+#[test]
+fn test_load_errors() {
+    let mut mem = Mem::blank(0);
+    match mem.load(Region::Ram, 0xFFFF, &[1, 2]) {
+        Err(LoadError::TooLarge { addr: 0xFFFF, len: 2 }) => {}
+        other => panic!("expected TooLarge, got {:?}", other),
+    }
+    match mem.load_file(Region::Ram, 0x0000, "no/such/image.bin") {
+        Err(LoadError::Io { .. }) => {}
+        other => panic!("expected Io error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_load_file() {
+    let dir = TempDir::new("load");
+    let path = dir.join("CODE.BIN");
+    std::fs::write(&path, [0xA9, 0x42]).unwrap();
+
+    let mut mem = Mem::blank(0);
+    assert_eq!(mem.load_file(Region::Ram, 0x0600, &path).unwrap(), 2);
+    assert_eq!(mem.get_word(0x0600), 0x42A9);
+}
+
+#[test]
+fn test_ram_fill_policies() {
+    let mut ram = vec![0x11; 0x100];
+
+    RamFill::Value(0xAA).fill(&mut ram);
+    assert!(ram.iter().all(|&b| b == 0xAA));
+
+    RamFill::Pattern.fill(&mut ram);
+    assert_eq!((ram[0x00], ram[0x3F], ram[0x40], ram[0x7F], ram[0x80]), (0x00, 0x00, 0xFF, 0xFF, 0x00));
+
+    RamFill::Zero.fill(&mut ram);
+    assert!(ram.iter().all(|&b| b == 0x00));
+}
+
+#[test]
+fn test_random_ram_fill_is_reproducible() {
+    let fill = RamFill::Random(0x1234_5678);
+    let mut first = Atari800::with_config(Atari800Config { ram_fill: fill, ..Atari800Config::default() }).unwrap();
+    let mut second = Atari800::with_config(Atari800Config { ram_fill: fill, ..Atari800Config::default() }).unwrap();
+    assert_eq!(first.ram_fill(), fill);
+
+    let sample = |atari800: &mut Atari800| (0x1000..0x1100).map(|a| atari800.read(a)).collect::<Vec<u8>>();
+    let contents = sample(&mut first);
+    assert_eq!(contents, sample(&mut second));
+    assert!(contents.iter().any(|&b| b != contents[0]));
+
+    let mut other = Atari800::with_config(Atari800Config {
+        ram_fill: RamFill::Random(0x8765_4321),
+        ..Atari800Config::default()
+    }).unwrap();
+    assert_ne!(contents, sample(&mut other));
+}
+
+#[test]
+fn test_remove_rom_image() {
+    let mut mem = Mem::blank(0xC000);
+    let first = mem.add_rom_image(&[1; 0x2000]);
+    let second = mem.add_rom_image(&[2; 0x1000]);
+    assert_eq!(second, first + 0x2000);
+
+    mem.remove_rom_image(first, 0x2000);
+    assert_eq!(mem.rom.len(), 0x11000);
+    assert_eq!(mem.rom[first], 2, "the second image moved down");
+}
@@ -0,0 +1,133 @@
+use std::fs;
+
+use atari800_rs::asm;
+use atari800_rs::atari800::Atari800;
+use atari800_rs::bus::Bus;
+use atari800_rs::printer::Printer;
+use atari800_rs::sio::{SioDevice, SioResponse};
+use atari800_rs::xex::{Segment, Xex, RUNAD};
+
+mod common;
+use common::TempDir;
+
+// A line as the OS sends it: padded with spaces to 40 bytes
+fn line(text: &[u8]) -> Vec<u8> {
+    let mut line = text.to_vec();
+    line.resize(40, b' ');
+    line
+}
+
+#[test]
+fn test_printer_commands() {
+    let dir = TempDir::new("printer");
+    let path = dir.join("PRINTOUT.TXT");
+    let mut printer = Printer::create(&path).unwrap();
+
+    assert!(matches!(printer.command(b'W', b'N', 0), SioResponse::Receive(40)));
+    assert!(matches!(printer.data(&line(b"TOTAL \xB1\xB2\xB3\x9B")), SioResponse::Complete(_)));
+    assert!(matches!(printer.command(b'W', b'S', 0), SioResponse::Receive(29)));
+    assert!(matches!(printer.command(b'W', b'D', 0), SioResponse::Receive(20)));
+    printer.data(&[b'A'; 20]);
+    printer.data(&line(b"B\x9B"));
+    match printer.command(b'S', 0, 0) {
+        SioResponse::Complete(status) => assert_eq!(status.len(), 4),
+        _ => panic!("no status"),
+    }
+    assert!(matches!(printer.command(b'R', 1, 0), SioResponse::Nak));
+
+    let output = fs::read(&path).unwrap();
+    assert_eq!(output, b"TOTAL 123\nAAAAAAAAAAAAAAAAAAAAB\n");
+}
+
+#[test]
+fn test_programs_print_through_p() {
+    let source = "
+CIOV = $E456
+ICCOM = $0342
+ICBAL = $0344
+ICBLL = $0348
+ICAX1 = $034A
+        .org $2000
+        ldx #$10
+        lda #3
+        sta ICCOM,x
+        lda #<name
+        sta ICBAL,x
+        lda #>name
+        sta ICBAL+1,x
+        lda #8
+        sta ICAX1,x
+        jsr CIOV
+        sty $0600
+
+        ldx #$10
+        lda #9
+        sta ICCOM,x
+        lda #<report
+        sta ICBAL,x
+        lda #>report
+        sta ICBAL+1,x
+        lda #<120
+        sta ICBLL,x
+        lda #>120
+        sta ICBLL+1,x
+        jsr CIOV
+        sty $0601
+
+        ldx #$10
+        lda #11
+        sta ICCOM,x
+        lda #<report
+        sta ICBAL,x
+        lda #>report
+        sta ICBAL+1,x
+        lda #5
+        sta ICBLL,x
+        lda #0
+        sta ICBLL+1,x
+        jsr CIOV
+
+        ldx #$10
+        lda #12
+        sta ICCOM,x
+        jsr CIOV
+        sty $0602
+        lda #1
+        sta $06FF
+done:   jmp done
+
+name:   .byte \"P:\", $9B
+report: .byte \"SALES \", $D4, $CF, $D4, $C1, $CC, \" 1000 1234567890123456789012345678901234567890\", $9B
+";
+    let code = asm::assemble(source, &[]).unwrap();
+    let xex = Xex {
+        segments: vec![
+            Segment { start: 0x2000, data: code.image(0x2000, 0x200) },
+            Segment { start: RUNAD, data: vec![0x00, 0x20] },
+        ],
+    };
+
+    let dir = TempDir::new("printer-cio");
+    let path = dir.join("PRINTOUT.TXT");
+    let mut atari800 = Atari800::new();
+    atari800.connect_printer(Printer::create(&path).unwrap());
+    atari800.load_xex(xex);
+    for _ in 0..300 {
+        atari800.run_frame();
+        if atari800.read(0x06FF) == 1 {
+            break;
+        }
+    }
+    let output = fs::read_to_string(&path);
+
+    assert_eq!(atari800.read(0x06FF), 1, "printing never finished");
+    assert_eq!(atari800.read(0x0600), 0x01, "open");
+    assert_eq!(atari800.read(0x0601), 0x01, "put record");
+    assert_eq!(atari800.read(0x0602), 0x01, "close prints the rest");
+    // A line longer than the printer's carries on where it left off
+    let expected = format!(
+        "SALES TOTAL 1000 1234567890123456789012345678901234567890\nSALES{}",
+        " ".repeat(35)
+    );
+    assert_eq!(output.unwrap(), expected);
+}
@@ -0,0 +1,89 @@
+use atari800_rs::atari800::{Atari800, Atari800Config, Model};
+use atari800_rs::rom::{crc32, RomError, RomKind, RomSet};
+use std::fs;
+
+mod common;
+use common::TempDir;
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0x00000000);
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+}
+
+#[test]
+fn test_missing_rom() {
+    let dir = TempDir::new("missing");
+    let roms = RomSet::with_search_dir(&dir);
+    assert!(matches!(roms.load(RomKind::Os800), Err(RomError::Missing { path: None, .. })));
+    assert!(roms.load_optional(RomKind::Basic).unwrap().is_none());
+
+    let roms = RomSet {
+        os_xl: Some("/nonexistent/ATARIXL.ROM".into()),
+        ..RomSet::default()
+    };
+    assert!(matches!(roms.load(RomKind::OsXl), Err(RomError::Missing { path: Some(_), .. })));
+}
+
+#[test]
+fn test_wrong_size_and_unknown_checksum() {
+    let dir = TempDir::new("checks");
+    fs::write(dir.join("ATARIBAS.ROM"), vec![0; 0x1000]).unwrap();
+    fs::write(dir.join("ATARIOSB.ROM"), vec![0; 0x2800]).unwrap();
+    let mut roms = RomSet::with_search_dir(&dir);
+
+    assert!(matches!(
+        roms.load(RomKind::Basic),
+        Err(RomError::WrongSize { expected: 0x2000, actual: 0x1000, .. })
+    ));
+    match roms.load(RomKind::Os800) {
+        Err(RomError::UnknownChecksum { crc32: crc, .. }) => {
+            assert_eq!(crc, crc32(&[0; 0x2800]))
+        }
+        other => panic!("expected unknown checksum, got {:?}", other),
+    }
+
+    roms.allow_unknown = true;
+    let os = roms.load(RomKind::Os800).unwrap();
+    assert_eq!(os.data.len(), 0x2800);
+    assert!(os.known.is_none());
+}
+
+#[test]
+fn test_any_self_test_image_is_taken() {
+    let dir = TempDir::new("selftest");
+    fs::write(dir.join("SELFTEST.ROM"), vec![0x5A; 0x800]).unwrap();
+    let roms = RomSet::with_search_dir(&dir);
+
+    let mut config = Atari800Config::for_model(Model::Atari800Xl);
+    config.load_roms(&roms).unwrap();
+    assert_eq!(config.self_test_rom, Some(vec![0x5A; 0x800]));
+}
+
+#[test]
+fn test_1050_firmware_in_either_size() {
+    let dir = TempDir::new("1050");
+    let roms = RomSet::with_search_dir(&dir);
+    for &(size, ok) in &[(0x800, true), (0x1000, true), (0x1800, false)] {
+        fs::write(dir.join("1050.ROM"), vec![0; size]).unwrap();
+        assert_eq!(roms.load(RomKind::Drive1050).is_ok(), ok, "{} bytes", size);
+    }
+}
+
+#[test]
+fn test_wrong_size_images_are_errors() {
+    let result = Atari800::with_config(Atari800Config {
+        os_rom: Some(vec![0; 0x4000]),
+        ..Atari800Config::default()
+    });
+    assert!(matches!(
+        result,
+        Err(RomError::WrongImageSize { kind: RomKind::Os800, expected: 0x2800, actual: 0x4000 })
+    ));
+
+    let result = Atari800::with_config(Atari800Config {
+        self_test_rom: Some(vec![0; 0x1000]),
+        ..Atari800Config::for_model(Model::Atari800Xl)
+    });
+    assert!(matches!(result, Err(RomError::WrongImageSize { kind: RomKind::SelfTest, .. })));
+}
@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use atari800_rs::asm;
+use atari800_rs::atari800::Atari800;
+use atari800_rs::atr::{Atr, Density};
+use atari800_rs::bus::Bus;
+use atari800_rs::serial_bridge::SerialBridge;
+use atari800_rs::sio::checksum;
+
+// A disk server serving D1: from an ATR the way servers on a real SIO2PC
+// cable do: the bytes sent while the handshake line (the command line) is
+// up are the command frame. It keeps everything it was sent, every command
+// frame and every frame it has answered, for the test to see.
+#[derive(Clone)]
+struct DiskServer {
+    disk: Rc<Atr>,
+    link: Rc<RefCell<Vec<u8>>>,
+    command: Option<Vec<u8>>,
+    commands: Rc<RefCell<Vec<Vec<u8>>>>,
+    frames: Rc<RefCell<Vec<Vec<u8>>>>,
+    replies: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl DiskServer {
+    fn new(disk: Atr) -> DiskServer {
+        DiskServer {
+            disk: Rc::new(disk),
+            link: Rc::default(),
+            command: None,
+            commands: Rc::default(),
+            frames: Rc::default(),
+            replies: Rc::default(),
+        }
+    }
+
+    fn answer(&mut self, frame: Vec<u8>) {
+        self.commands.borrow_mut().push(frame.clone());
+        if frame.len() != 5 || frame[0] != 0x31 || checksum(&frame[..4]) != frame[4] {
+            return;
+        }
+        self.frames.borrow_mut().push(frame.clone());
+        let mut replies = self.replies.borrow_mut();
+        let sector = u16::from_le_bytes([frame[2], frame[3]]) as usize;
+        match (frame[1], self.disk.read_sector(sector)) {
+            (b'R', Some(data)) => {
+                replies.extend(b"AC");
+                replies.extend(data);
+                replies.push_back(checksum(data));
+            }
+            _ => replies.push_back(b'N'),
+        }
+    }
+}
+
+impl SerialBridge for DiskServer {
+    fn send(&mut self, byte: u8) {
+        self.link.borrow_mut().push(byte);
+        if let Some(frame) = self.command.as_mut() {
+            frame.push(byte);
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        self.replies.borrow_mut().pop_front()
+    }
+
+    fn connected(&mut self) -> bool {
+        true
+    }
+
+    fn set_handshake(&mut self, on: bool) {
+        if on {
+            self.command = Some(Vec::new());
+        } else if let Some(frame) = self.command.take() {
+            self.answer(frame);
+        }
+    }
+}
+
+#[test]
+fn test_boots_from_a_disk_server() {
+    let source = "
+DOSVEC = $0A
+DUNIT = $0301
+DCOMND = $0302
+DBUFLO = $0304
+DAUX1 = $030A
+DAUX2 = $030B
+DSKINV = $E453
+        .org $0700
+        .byte 0, 2
+        .word $0700, init
+        lda #<main
+        sta DOSVEC
+        lda #>main
+        sta DOSVEC+1
+        lda tail
+        sta $0601
+        clc
+        rts
+init:   rts
+        .org $0780
+tail:   .byte $5A
+        ; Sector $1B, an ESC on the link
+main:   lda #1
+        sta DUNIT
+        lda #'R'
+        sta DCOMND
+        lda #$00
+        sta DBUFLO
+        lda #$08
+        sta DBUFLO+1
+        lda #$1B
+        sta DAUX1
+        lda #0
+        sta DAUX2
+        jsr DSKINV
+        lda $0800
+        sta $0602
+done:   jmp done
+";
+    let code = asm::assemble(source, &[]).unwrap();
+    let mut atr = Atr::blank(Density::Single);
+    atr.write_sector(1, &code.image(0x0700, 128));
+    atr.write_sector(2, &code.image(0x0780, 128));
+    atr.write_sector(0x1B, &[0x1B; 128]);
+    let server = DiskServer::new(atr);
+
+    let mut atari800 = Atari800::new();
+    // The patch can't serve D1:, so the OS sends the commands down the link
+    atari800.set_sio_patch(true);
+    atari800.connect_sio2pc(Box::new(server.clone()));
+    for _ in 0..120 {
+        atari800.run_frame();
+        if atari800.read(0x0602) != 0 {
+            break;
+        }
+    }
+
+    assert_eq!(atari800.read(0x0601), 0x5A, "booted both sectors");
+    assert_eq!(atari800.read(0x0602), 0x1B, "read sector $1B");
+    let frames = server.frames.borrow();
+    assert_eq!(frames[..2], [[0x31, b'R', 1, 0, 0x84], [0x31, b'R', 2, 0, 0x85]]);
+    assert_eq!(frames[2], [0x31, b'R', 0x1B, 0, 0x9E]);
+
+    // After what the OS sends at power-on, nothing but the command frames
+    // goes down the link, byte for byte
+    assert_eq!(*server.commands.borrow(), *frames);
+    assert!(server.link.borrow().ends_with(&frames.concat()));
+    assert!(atari800.disconnect_sio2pc().is_some());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tty_bridge_passes_bytes_untouched() {
+    use atari800_rs::serial_bridge::{ModemLine, PtyBridge, TtyBridge};
+
+    // A pty has no modem lines, so the handshake goes nowhere, but the
+    // bytes still get through as they are
+    let mut pty = PtyBridge::open().unwrap();
+    let mut tty = TtyBridge::open(pty.path(), ModemLine::Rts).unwrap();
+    tty.set_handshake(true);
+    assert!(tty.connected());
+    for byte in [0x31, 0x1B, 0x00, 0xFF] {
+        tty.send(byte);
+    }
+    pty.send(b'A');
+    let mut received = Vec::new();
+    for _ in 0..100 {
+        received.extend(pty.receive());
+        if received.len() == 4 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(received, [0x31, 0x1B, 0x00, 0xFF]);
+    let reply = (0..100).find_map(|_| {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        tty.receive()
+    });
+    assert_eq!(reply, Some(b'A'));
+}
@@ -0,0 +1,122 @@
+use atari800_rs::asm;
+use atari800_rs::atari800::Atari800;
+use atari800_rs::bus::Bus;
+use atari800_rs::cartridge::{CartType, Cartridge};
+use atari800_rs::xex::{Segment, Xex, XexError};
+
+// Binary load file from (start, bytes) segments
+fn xex_file(segments: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut file = vec![0xFF, 0xFF];
+    for &(start, data) in segments {
+        let end = start + data.len() as u16 - 1;
+        file.extend_from_slice(&start.to_le_bytes());
+        file.extend_from_slice(&end.to_le_bytes());
+        file.extend_from_slice(data);
+    }
+    file
+}
+
+#[test]
+fn test_parse_segments() {
+    let mut file = xex_file(&[(0x2000, &[1, 2, 3])]);
+    // A repeated $FFFF header before the next segment is allowed
+    file.extend_from_slice(&xex_file(&[(0x02E0, &[0x00, 0x20])]));
+
+    let xex = Xex::parse(&file).unwrap();
+    assert_eq!(
+        xex.segments,
+        vec![
+            Segment { start: 0x2000, data: vec![1, 2, 3] },
+            Segment { start: 0x02E0, data: vec![0x00, 0x20] },
+        ]
+    );
+    assert!(xex.segments[1].covers(0x02E1));
+    assert!(!xex.segments[1].covers(0x02E2));
+}
+
+#[test]
+fn test_malformed_files() {
+    assert!(matches!(Xex::parse(&[0x00, 0x20, 0x00, 0x20]), Err(XexError::NotXex)));
+    assert!(matches!(Xex::parse(&[0xFF, 0xFF]), Err(XexError::Truncated { offset: 2 })));
+
+    // Segment says 4 bytes, file has 2
+    let file = [0xFF, 0xFF, 0x00, 0x20, 0x03, 0x20, 0xEA, 0xEA];
+    assert!(matches!(Xex::parse(&file), Err(XexError::Truncated { offset: 2 })));
+
+    // Header cut short after a good segment
+    let file = [0xFF, 0xFF, 0x00, 0x20, 0x00, 0x20, 0xEA, 0x00, 0x30];
+    assert!(matches!(Xex::parse(&file), Err(XexError::Truncated { offset: 7 })));
+
+    let file = [0xFF, 0xFF, 0x00, 0x30, 0xFF, 0x2F];
+    match Xex::parse(&file) {
+        Err(e @ XexError::BadSegment { offset: 2, start: 0x3000, end: 0x2FFF }) => {
+            assert_eq!(e.to_string(), "segment at offset 2 ends ($2FFF) before it starts ($3000)")
+        }
+        other => panic!("expected BadSegment, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_boot_runs_init_then_run() {
+    // The init routine leaves a marker; the main program copies it and
+    // counts the calls it saw
+    let source = "
+        .org $2000
+init:   inc $0600
+        rts
+main:   lda $0600
+        sta $0601
+        lda #$5A
+        sta $0602
+loop:   jmp loop
+";
+    let code = asm::assemble(source, &[]).unwrap();
+    let image = code.image(0x2000, 18);
+    let init = code.symbol("init").unwrap().to_le_bytes();
+    let main = code.symbol("main").unwrap().to_le_bytes();
+
+    let file = xex_file(&[(0x2000, &image), (0x02E2, &init), (0x02E0, &main)]);
+    let mut atari800 = Atari800::new();
+    atari800.load_xex(Xex::parse(&file).unwrap());
+    assert!(atari800.xex_loading());
+
+    for _ in 0..40 {
+        atari800.run_frame();
+    }
+    assert!(!atari800.xex_loading());
+    assert_eq!(atari800.read(0x0600), 1);
+    assert_eq!(atari800.read(0x0601), 1);
+    assert_eq!(atari800.read(0x0602), 0x5A);
+}
+
+#[test]
+fn test_boot_without_runad_starts_first_segment() {
+    let code = asm::assemble(".org $3000\n lda #$77\n sta $0600\nloop: jmp loop\n", &[]).unwrap();
+    let file = xex_file(&[(0x3000, &code.image(0x3000, 8))]);
+
+    let mut atari800 = Atari800::new();
+    atari800.load_xex(Xex::parse(&file).unwrap());
+    for _ in 0..40 {
+        atari800.run_frame();
+    }
+    assert_eq!(atari800.read(0x0600), 0x77);
+}
+
+#[test]
+fn test_boot_pulls_a_cartridge_that_would_run_instead() {
+    // A cart that asks to be run and marks $0600 if it gets control
+    let cart_code = asm::assemble(".org $A000\nstart: lda #$EE\n sta $0600\nloop: jmp loop\ninit: rts\n", &[]).unwrap();
+    let mut image = vec![0; 0x2000];
+    image[..9].copy_from_slice(&cart_code.image(0xA000, 9));
+    image[0x1FFA..].copy_from_slice(&[0x00, 0xA0, 0x00, 0x04, 0x08, 0xA0]);
+
+    let mut atari800 = Atari800::new();
+    atari800.insert_cartridge(Cartridge::new(CartType::Std8k, &image).unwrap());
+    let code = asm::assemble(".org $3000\n lda #$77\n sta $0600\nloop: jmp loop\n", &[]).unwrap();
+    atari800.load_xex(Xex::parse(&xex_file(&[(0x3000, &code.image(0x3000, 8))])).unwrap());
+    for _ in 0..40 {
+        atari800.run_frame();
+    }
+    assert!(!atari800.xex_loading());
+    assert_eq!(atari800.read(0x0600), 0x77);
+}
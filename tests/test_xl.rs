@@ -0,0 +1,67 @@
+use atari800_rs::atari800::{Atari800, Atari800Config, Model};
+use atari800_rs::bus::Bus;
+
+fn write_portb(atari800: &mut Atari800, val: u8) {
+    atari800.write(0xD303, 0x38);   // PBCTL: select DDRB
+    atari800.write(0xD301, 0xFF);   // All outputs
+    atari800.write(0xD303, 0x3C);   // PBCTL: select PORTB
+    atari800.write(0xD301, val);
+}
+
+// True if a write to addr sticks (RAM) rather than being ignored (ROM or
+// nothing there). The write to $0000 keeps the floating bus from echoing
+// the value back.
+fn is_ram(atari800: &mut Atari800, addr: u16) -> bool {
+    let val = !atari800.read(addr);
+    atari800.write(addr, val);
+    atari800.write(0x0000, !val);
+    atari800.read(addr) == val
+}
+
+#[test]
+fn test_xl_os_rom_switching() {
+    let mut atari800 = Atari800::with_config(Atari800Config::for_model(Model::Atari800Xl)).unwrap();
+
+    // PORTB floats high at power-on: OS ROM in
+    assert!(!is_ram(&mut atari800, 0xC000));
+    assert!(!is_ram(&mut atari800, 0xE000));
+    assert!(is_ram(&mut atari800, 0xA000));
+
+    // Bit 0 low switches the OS out for the RAM beneath it
+    write_portb(&mut atari800, 0xFE);
+    assert!(is_ram(&mut atari800, 0xC000));
+    assert!(is_ram(&mut atari800, 0xE000));
+    assert!(is_ram(&mut atari800, 0xFFFF));
+
+    // ...which keeps its contents while the ROM is back in
+    atari800.write(0xE000, 0x5A);
+    write_portb(&mut atari800, 0xFF);
+    write_portb(&mut atari800, 0xFE);
+    assert_eq!(atari800.read(0xE000), 0x5A);
+}
+
+#[test]
+fn test_xl_self_test_rom() {
+    let mut atari800 = Atari800::with_config(Atari800Config::for_model(Model::Atari65Xe)).unwrap();
+    assert!(is_ram(&mut atari800, 0x5000));
+
+    write_portb(&mut atari800, 0x7F);
+    assert!(!is_ram(&mut atari800, 0x5000));
+    assert!(!is_ram(&mut atari800, 0x57FF));
+    assert!(is_ram(&mut atari800, 0x5800));
+
+    // Self-test only appears while the OS ROM is enabled
+    write_portb(&mut atari800, 0x7E);
+    assert!(is_ram(&mut atari800, 0x5000));
+}
+
+#[test]
+fn test_600xl_has_16k() {
+    let mut atari800 = Atari800::with_config(Atari800Config::for_model(Model::Atari600Xl)).unwrap();
+    assert!(is_ram(&mut atari800, 0x3FFF));
+    assert!(!is_ram(&mut atari800, 0x4000));
+
+    // Nothing beneath the OS either
+    write_portb(&mut atari800, 0xFE);
+    assert!(!is_ram(&mut atari800, 0xE000));
+}